@@ -3,40 +3,73 @@
 
 use anyhow::Result;
 use anyhow::{bail, Context};
-use clap::Parser;
+use chibiwasm::runtime::Runtime;
+use chibiwasm::structure::module::Module;
+use chibiwasm::structure::values::Value;
+use chibiwasm::text::Style;
+use clap::{Parser, Subcommand};
 use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, Read};
 use std::{env, result};
 
 #[derive(Debug, Parser)]
 #[clap(author, about, version)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    file: String,
-    func: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(required = true)]
+    file: Option<String>,
+    #[clap(required = true)]
+    func: Option<String>,
     func_args: Vec<i32>,
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print a module in the text format
+    Disasm {
+        file: String,
+        /// write instructions in the folded form
+        #[clap(long)]
+        folded: bool,
+    },
+}
+
 impl Args {
-    fn wasm_file(&self) -> io::Result<File> {
-        File::open(&self.file)
+    fn func_args(&self) -> Vec<Value> {
+        self.func_args.iter().map(|x| Value::I32(*x)).collect()
+    }
+}
+
+/// Load module with decoder, or with parser if the file is in the text format
+fn load_module(file: &str) -> Result<Module<'static>> {
+    if file.ends_with(".wat") {
+        let wat = fs::read_to_string(file)?;
+        chibiwasm::text::parse(&wat)
+    } else {
+        let mut reader = BufReader::new(File::open(file)?);
+        chibiwasm::binary::module::decode(&mut reader)
     }
-    // fn func_args(&self) -> Vec<Value> {
-    //     self.func_args.iter().map(|x| Value::I32(*x)).collect()
-    // }
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    //Load module with decoder
-    let mut file = args.wasm_file()?;
-    let mut reader = BufReader::new(file);
-    let mut module = chibiwasm::binary::module::decode(&mut reader)?;
+    if let Some(Command::Disasm { file, folded }) = &args.command {
+        let style = if *folded { Style::Folded } else { Style::Flat };
+        print!("{}", chibiwasm::text::print(&load_module(file)?, style));
+        return Ok(());
+    }
+
+    let module = load_module(args.file.as_deref().unwrap())?;
 
     //Execute with runtime
-    //    let mut runtime = Runtime::new(&mut module)?;
-    //    let result = runtime.invoke(&args.func, &args.func_args());
+    let mut runtime = Runtime::new(module)?;
+    let results = runtime.invoke(args.func.as_deref().unwrap(), &args.func_args())?;
 
-    //    println!("{}", result?.unwrap());
+    for result in results {
+        println!("{}", result);
+    }
     Ok(())
 }
@@ -1,3 +1,6 @@
 pub mod binary;
+pub mod features;
+pub mod runtime;
 pub mod structure;
-//pub mod runtime;
+pub mod text;
+pub mod validate;
@@ -1,4 +1,5 @@
 pub mod decode;
+pub mod encode;
 pub mod instructions;
 pub mod module;
 pub mod types;
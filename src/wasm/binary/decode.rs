@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::io::{BufRead, Read};
 
 /// Extensions for Read to help to parse wasm binary
@@ -9,9 +9,17 @@ pub trait WasmModuleBinaryRead: Read + BufRead {
         Ok(buf[0])
     }
 
+    /// The buffer grows as bytes arrive, so a huge `size` fails without allocating it up front.
     fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>> {
-        let mut buf = vec![0u8; size];
-        self.read_exact(&mut buf)?;
+        let mut buf = Vec::new();
+        (&mut *self).take(size as u64).read_to_end(&mut buf)?;
+        if buf.len() < size {
+            bail!(
+                "{} bytes are requested but only {} bytes remain",
+                size,
+                buf.len()
+            )
+        }
         Ok(buf)
     }
 
@@ -45,9 +53,52 @@ pub trait WasmModuleBinaryRead: Read + BufRead {
         let _ = self.read_to_end(&mut buff)?;
         Ok(buff)
     }
+
+    /// read UTF-8 string prefixed with its length
+    fn read_name(&mut self) -> Result<String> {
+        let len = self.read_u32()? as usize;
+        Ok(String::from_utf8(self.read_bytes(len)?)?)
+    }
+
+    /// make sure that all bytes have been consumed
+    fn ensure_end(&mut self) -> Result<()> {
+        let rest = self.fill_buf()?.len();
+        if rest > 0 {
+            bail!("{} bytes remain unread", rest)
+        }
+        Ok(())
+    }
 }
 impl<R: Read + BufRead> WasmModuleBinaryRead for R {}
 
+/// Extensions for bytes in memory to read parts of them without copying
+pub trait SliceRead<'a> {
+    fn read_slice(&mut self, size: usize) -> Result<&'a [u8]>;
+
+    /// read UTF-8 string prefixed with its length, borrowing it
+    fn read_name_slice(&mut self) -> Result<&'a str>;
+}
+
+impl<'a> SliceRead<'a> for &'a [u8] {
+    fn read_slice(&mut self, size: usize) -> Result<&'a [u8]> {
+        if size > self.len() {
+            bail!(
+                "{} bytes are requested but only {} bytes remain",
+                size,
+                self.len()
+            )
+        }
+        let (taken, rest) = self.split_at(size);
+        *self = rest;
+        Ok(taken)
+    }
+
+    fn read_name_slice(&mut self) -> Result<&'a str> {
+        let len = self.read_u32()? as usize;
+        Ok(std::str::from_utf8(self.read_slice(len)?)?)
+    }
+}
+
 #[cfg(test)]
 pub mod test_util {
 
@@ -148,7 +199,7 @@ mod tests {
     #[test]
     fn read_the_rest() {
         //Given
-        let bytes = vec![0x01u8, 0x02, 0x03, 0x04, 0x05];
+        let bytes = [0x01u8, 0x02, 0x03, 0x04, 0x05];
         let mut reader = &bytes[..];
         //When
         let first_read = reader.read_bytes(2).unwrap();
@@ -157,4 +208,37 @@ mod tests {
         assert_eq!(first_read, vec![0x01, 0x02]);
         assert_eq!(the_rest, vec![0x03, 0x04, 0x05]);
     }
+
+    #[test]
+    fn read_slice_without_copy() {
+        use super::SliceRead;
+        //Given
+        let bytes = [0x03u8, 0x61, 0x64, 0x64, 0x01, 0x02];
+        let mut reader = &bytes[..];
+        //When
+        let name = reader.read_name_slice().unwrap();
+        let rest = reader.read_slice(1).unwrap();
+        //Then
+        assert_eq!(name, "add");
+        assert!(std::ptr::eq(name.as_ptr(), &bytes[1]));
+        assert_eq!(rest, [0x01]);
+        assert_eq!(
+            reader.read_slice(2).unwrap_err().to_string(),
+            "2 bytes are requested but only 1 bytes remain"
+        );
+    }
+
+    #[test]
+    fn read_name_and_ensure_end() {
+        //Given
+        let bytes = [0x03u8, 0x61, 0x64, 0x64, 0x01];
+        let mut reader = &bytes[..];
+        //When
+        let name = reader.read_name().unwrap();
+        //Then
+        assert_eq!(name, "add");
+        assert!(reader.ensure_end().is_err());
+        let _ = reader.read_byte().unwrap();
+        assert!(reader.ensure_end().is_ok());
+    }
 }
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use std::io::Write;
+
+use crate::structure::module::Module;
+
+/// Extensions for Write to help to build wasm binary
+pub trait WasmModuleBinaryWrite: Write {
+    fn write_byte(&mut self, b: u8) -> Result<()> {
+        self.write_all(&[b])?;
+        Ok(())
+    }
+
+    fn write_u32_le(&mut self, n: u32) -> Result<()> {
+        self.write_all(&n.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn write_u64(&mut self, n: u64) -> Result<()> {
+        leb128::write::unsigned(self, n)?;
+        Ok(())
+    }
+
+    fn write_u32(&mut self, n: u32) -> Result<()> {
+        self.write_u64(n as u64)
+    }
+
+    fn write_i64(&mut self, n: i64) -> Result<()> {
+        leb128::write::signed(self, n)?;
+        Ok(())
+    }
+
+    fn write_i32(&mut self, n: i32) -> Result<()> {
+        self.write_i64(n as i64)
+    }
+
+    /// write bytes prefixed with their length
+    fn write_sized(&mut self, bytes: &[u8]) -> Result<()> {
+        self.write_u32(bytes.len().try_into()?)?;
+        self.write_all(bytes)?;
+        Ok(())
+    }
+
+    /// write UTF-8 string prefixed with its length
+    fn write_name(&mut self, name: &str) -> Result<()> {
+        self.write_sized(name.as_bytes())
+    }
+
+    /// write a vector prefixed with the number of its items
+    fn write_vec<T>(
+        &mut self,
+        items: &[T],
+        mut write_item: impl FnMut(&mut Self, &T) -> Result<()>,
+    ) -> Result<()> {
+        self.write_u32(items.len().try_into()?)?;
+        for item in items {
+            write_item(self, item)?;
+        }
+        Ok(())
+    }
+}
+impl<W: Write> WasmModuleBinaryWrite for W {}
+
+/// encode Module to wasm binary
+pub fn encode(module: &Module) -> Result<Vec<u8>> {
+    let mut buf = Vec::<u8>::new();
+    module::write_module(&mut buf, module)?;
+    Ok(buf)
+}
+
+mod instructions;
+mod module;
+mod types;
+
+pub use instructions::ExprWrite;
+pub use types::TypeWrite;
+
+#[cfg(test)]
+mod tests {
+    use super::WasmModuleBinaryWrite;
+    use crate::binary::decode::WasmModuleBinaryRead;
+
+    #[test]
+    fn write_and_read() {
+        //Given
+        let mut buf = Vec::<u8>::new();
+        //When
+        buf.write_u32(344865).unwrap();
+        buf.write_i32(-512).unwrap();
+        buf.write_name("add").unwrap();
+        buf.write_u32_le(0x04_03_02_01).unwrap();
+        //Then
+        let mut reader = &buf[..];
+        assert_eq!(reader.read_u32().unwrap(), 344865);
+        assert_eq!(reader.read_i32().unwrap(), -512);
+        assert_eq!(reader.read_name().unwrap(), "add");
+        assert_eq!(reader.read_u32_le().unwrap(), 0x04_03_02_01);
+        assert!(!reader.has_next().unwrap());
+    }
+}
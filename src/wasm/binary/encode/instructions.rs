@@ -0,0 +1,586 @@
+use super::{types::TypeWrite, WasmModuleBinaryWrite};
+use crate::structure::instructions::{
+    BlockType, Catch, Expr,
+    Instruction::{self, *},
+    MemArg,
+};
+use crate::structure::types::RefType;
+use anyhow::*;
+
+/// Extensions for WasmModuleBinaryWrite to encode instructions
+pub trait ExprWrite: WasmModuleBinaryWrite + Sized {
+    /// write instructions followed by the terminating End
+    fn write_expr(&mut self, expr: &[Instruction]) -> Result<()> {
+        self.write_instructions(expr)?;
+        self.write_byte(0x0B)
+    }
+
+    fn write_instructions(&mut self, insts: &[Instruction]) -> Result<()> {
+        for inst in insts {
+            self.write_instruction(inst)?;
+        }
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/instructions.html#control-instructions
+    fn write_block_type(&mut self, block_type: &BlockType) -> Result<()> {
+        match block_type {
+            BlockType::Empty => self.write_byte(0x40),
+            BlockType::ValType(v) => self.write_val_type(*v),
+            BlockType::TypeIdx(idx) => self.write_i64(*idx as i64),
+        }
+    }
+
+    fn write_block(&mut self, op: u8, block_type: &BlockType, insts: &Expr) -> Result<()> {
+        self.write_byte(op)?;
+        self.write_block_type(block_type)?;
+        self.write_expr(insts)
+    }
+
+    fn write_instruction(&mut self, inst: &Instruction) -> Result<()> {
+        match inst {
+            //Control Instructions
+            Unreachable => self.write_byte(0x00),
+            Nop => self.write_byte(0x01),
+            Block(block_type, insts) => self.write_block(0x02, block_type, insts),
+            Loop(block_type, insts) => self.write_block(0x03, block_type, insts),
+            If(block_type, then, else_) => {
+                self.write_byte(0x04)?;
+                self.write_block_type(block_type)?;
+                self.write_instructions(then)?;
+                if let Some(else_) = else_ {
+                    self.write_byte(0x05)?;
+                    self.write_instructions(else_)?;
+                }
+                self.write_byte(0x0B)
+            }
+            TryTable(block_type, catches, insts) => {
+                self.write_byte(0x1F)?;
+                self.write_block_type(block_type)?;
+                self.write_vec(catches, |w, catch| w.write_catch(catch))?;
+                self.write_expr(insts)
+            }
+            Throw(tag) => self.write_op_u32(0x08, *tag),
+            ThrowRef => self.write_byte(0x0A),
+            Else => self.write_byte(0x05),
+            End => self.write_byte(0x0B),
+            Br(label) => self.write_op_u32(0x0C, *label),
+            BrIf(label) => self.write_op_u32(0x0D, *label),
+            BrTable(labels, default) => {
+                self.write_byte(0x0E)?;
+                self.write_vec(labels, |w, label| w.write_u32(*label))?;
+                self.write_u32(*default)
+            }
+            Return => self.write_byte(0x0F),
+            Call(func) => self.write_op_u32(0x10, *func),
+            CallIndirect(table, type_) => {
+                self.write_op_u32(0x11, *type_)?;
+                self.write_u32(*table)
+            }
+            ReturnCall(func) => self.write_op_u32(0x12, *func),
+            ReturnCallIndirect(table, type_) => {
+                self.write_op_u32(0x13, *type_)?;
+                self.write_u32(*table)
+            }
+            CallRef(type_) => self.write_op_u32(0x14, *type_),
+            ReturnCallRef(type_) => self.write_op_u32(0x15, *type_),
+            BrOnNull(label) => self.write_op_u32(0xD5, *label),
+            BrOnNonNull(label) => self.write_op_u32(0xD6, *label),
+            BrOnCast(label, from, to) => self.write_br_on_cast(24, *label, from, to),
+            BrOnCastFail(label, from, to) => self.write_br_on_cast(25, *label, from, to),
+            //Reference Instructions
+            RefNull(heap) => {
+                self.write_byte(0xD0)?;
+                self.write_heap_type(*heap)
+            }
+            RefIsNull => self.write_byte(0xD1),
+            RefFunc(func) => self.write_op_u32(0xD2, *func),
+            RefAsNonNull => self.write_byte(0xD4),
+            RefEq => self.write_byte(0xD3),
+            RefTest(ref_type) => {
+                self.write_gc_op(if ref_type.nullable { 21 } else { 20 })?;
+                self.write_heap_type(ref_type.heap)
+            }
+            RefCast(ref_type) => {
+                self.write_gc_op(if ref_type.nullable { 23 } else { 22 })?;
+                self.write_heap_type(ref_type.heap)
+            }
+            //Aggregate Instructions
+            StructNew(type_) => self.write_gc_op_u32(0, *type_),
+            StructNewDefault(type_) => self.write_gc_op_u32(1, *type_),
+            StructGet(type_, field) => self.write_gc_op_u32_u32(2, *type_, *field),
+            StructGetS(type_, field) => self.write_gc_op_u32_u32(3, *type_, *field),
+            StructGetU(type_, field) => self.write_gc_op_u32_u32(4, *type_, *field),
+            StructSet(type_, field) => self.write_gc_op_u32_u32(5, *type_, *field),
+            ArrayNew(type_) => self.write_gc_op_u32(6, *type_),
+            ArrayNewDefault(type_) => self.write_gc_op_u32(7, *type_),
+            ArrayNewFixed(type_, n) => self.write_gc_op_u32_u32(8, *type_, *n),
+            ArrayNewData(type_, data) => self.write_gc_op_u32_u32(9, *type_, *data),
+            ArrayNewElem(type_, elem) => self.write_gc_op_u32_u32(10, *type_, *elem),
+            ArrayGet(type_) => self.write_gc_op_u32(11, *type_),
+            ArrayGetS(type_) => self.write_gc_op_u32(12, *type_),
+            ArrayGetU(type_) => self.write_gc_op_u32(13, *type_),
+            ArraySet(type_) => self.write_gc_op_u32(14, *type_),
+            ArrayLen => self.write_gc_op(15),
+            ArrayFill(type_) => self.write_gc_op_u32(16, *type_),
+            ArrayCopy(dst, src) => self.write_gc_op_u32_u32(17, *dst, *src),
+            ArrayInitData(type_, data) => self.write_gc_op_u32_u32(18, *type_, *data),
+            ArrayInitElem(type_, elem) => self.write_gc_op_u32_u32(19, *type_, *elem),
+            AnyConvertExtern => self.write_gc_op(26),
+            ExternConvertAny => self.write_gc_op(27),
+            RefI31 => self.write_gc_op(28),
+            I31GetS => self.write_gc_op(29),
+            I31GetU => self.write_gc_op(30),
+            //Parametric Instructions
+            Drop => self.write_byte(0x1A),
+            Select(None) => self.write_byte(0x1B),
+            Select(Some(val_types)) => {
+                self.write_byte(0x1C)?;
+                self.write_vec(val_types, |w, v| w.write_val_type(*v))
+            }
+            //Variable Instructions
+            LocalGet(idx) => self.write_op_u32(0x20, *idx),
+            LocalSet(idx) => self.write_op_u32(0x21, *idx),
+            LocalTee(idx) => self.write_op_u32(0x22, *idx),
+            GlobalGet(idx) => self.write_op_u32(0x23, *idx),
+            GlobalSet(idx) => self.write_op_u32(0x24, *idx),
+            //Table Instructions
+            TableGet(idx) => self.write_op_u32(0x25, *idx),
+            TableSet(idx) => self.write_op_u32(0x26, *idx),
+            TableInit(elem, table) => {
+                self.write_prefixed_op(12)?;
+                self.write_u32(*elem)?;
+                self.write_u32(*table)
+            }
+            TableDrop(elem) => {
+                self.write_prefixed_op(13)?;
+                self.write_u32(*elem)
+            }
+            TableCopy(dst, src) => {
+                self.write_prefixed_op(14)?;
+                self.write_u32(*dst)?;
+                self.write_u32(*src)
+            }
+            TableGrow(idx) => {
+                self.write_prefixed_op(15)?;
+                self.write_u32(*idx)
+            }
+            TableSize(idx) => {
+                self.write_prefixed_op(16)?;
+                self.write_u32(*idx)
+            }
+            TableFill(idx) => {
+                self.write_prefixed_op(17)?;
+                self.write_u32(*idx)
+            }
+            //Memory Instructions
+            Memory(op, memarg) => {
+                self.write_byte(*op as u8)?;
+                self.write_memarg(memarg)
+            }
+            MemorySize(idx) => self.write_op_u32(0x3F, *idx),
+            MemoryGrow(idx) => self.write_op_u32(0x40, *idx),
+            MemoryInit(data, idx) => {
+                self.write_prefixed_op(8)?;
+                self.write_u32(*data)?;
+                self.write_u32(*idx)
+            }
+            DataDrop(data) => {
+                self.write_prefixed_op(9)?;
+                self.write_u32(*data)
+            }
+            MemoryCopy(dst, src) => {
+                self.write_prefixed_op(10)?;
+                self.write_u32(*dst)?;
+                self.write_u32(*src)
+            }
+            MemoryFill(idx) => {
+                self.write_prefixed_op(11)?;
+                self.write_u32(*idx)
+            }
+            //Numeric Instructions
+            I32Const(n) => {
+                self.write_byte(0x41)?;
+                self.write_i32(*n)
+            }
+            I64Const(n) => {
+                self.write_byte(0x42)?;
+                self.write_i64(*n)
+            }
+            I32Eqz => self.write_byte(0x45),
+            I32Eq => self.write_byte(0x46),
+            I32Ne => self.write_byte(0x47),
+            I32LtS => self.write_byte(0x48),
+            I32LtU => self.write_byte(0x49),
+            I32GtS => self.write_byte(0x4A),
+            I32GtU => self.write_byte(0x4B),
+            I32LeS => self.write_byte(0x4C),
+            I32LeU => self.write_byte(0x4D),
+            I32GeS => self.write_byte(0x4E),
+            I32GeU => self.write_byte(0x4F),
+            I32Clz => self.write_byte(0x67),
+            I32Ctz => self.write_byte(0x68),
+            I32Popcnt => self.write_byte(0x69),
+            I32Add => self.write_byte(0x6A),
+            I32Sub => self.write_byte(0x6B),
+            I32Mul => self.write_byte(0x6C),
+            I32DivS => self.write_byte(0x6D),
+            I32DivU => self.write_byte(0x6E),
+            I32RemS => self.write_byte(0x6F),
+            I32RemU => self.write_byte(0x70),
+            I32And => self.write_byte(0x71),
+            I32Or => self.write_byte(0x72),
+            I32Xor => self.write_byte(0x73),
+            I32ShL => self.write_byte(0x74),
+            I32ShrS => self.write_byte(0x75),
+            I32ShrU => self.write_byte(0x76),
+            I32RtoL => self.write_byte(0x77),
+            I32RtoR => self.write_byte(0x78),
+            I64Add => self.write_byte(0x7C),
+            I64Sub => self.write_byte(0x7D),
+            I64Mul => self.write_byte(0x7E),
+            I32Extend8S => self.write_byte(0xC0),
+            I32Extend16S => self.write_byte(0xC1),
+            //Vector Instructions
+            V128Const(v) => {
+                self.write_vector_op(0x0C)?;
+                self.write_all(&v.to_le_bytes())?;
+                Ok(())
+            }
+            I8x16Shuffle(lanes) => {
+                self.write_vector_op(0x0D)?;
+                self.write_all(lanes)?;
+                Ok(())
+            }
+            Vector(op) => self.write_vector_op(*op as u32),
+            VectorLane(op, lane) => {
+                self.write_vector_op(*op as u32)?;
+                self.write_byte(*lane)
+            }
+            VectorMem(op, memarg) => {
+                self.write_vector_op(*op as u32)?;
+                self.write_memarg(memarg)
+            }
+            VectorMemLane(op, memarg, lane) => {
+                self.write_vector_op(*op as u32)?;
+                self.write_memarg(memarg)?;
+                self.write_byte(*lane)
+            }
+            //Atomic Memory Instructions
+            AtomicFence => {
+                self.write_atomic_op(0x03)?;
+                self.write_byte(0x00)
+            }
+            Atomic(op, memarg) => {
+                self.write_atomic_op(*op as u32)?;
+                self.write_memarg(memarg)
+            }
+            Void => bail!("Void is not a wasm instruction"),
+        }
+    }
+
+    /// https://webassembly.github.io/exception-handling/core/binary/instructions.html#control-instructions
+    fn write_catch(&mut self, catch: &Catch) -> Result<()> {
+        match catch {
+            Catch::Catch(tag, label) => {
+                self.write_op_u32(0x00, *tag)?;
+                self.write_u32(*label)
+            }
+            Catch::CatchRef(tag, label) => {
+                self.write_op_u32(0x01, *tag)?;
+                self.write_u32(*label)
+            }
+            Catch::CatchAll(label) => self.write_op_u32(0x02, *label),
+            Catch::CatchAllRef(label) => self.write_op_u32(0x03, *label),
+        }
+    }
+
+    fn write_op_u32(&mut self, op: u8, n: u32) -> Result<()> {
+        self.write_byte(op)?;
+        self.write_u32(n)
+    }
+
+    /// instructions prefixed with 0xFB
+    fn write_gc_op(&mut self, op: u32) -> Result<()> {
+        self.write_byte(0xFB)?;
+        self.write_u32(op)
+    }
+
+    fn write_gc_op_u32(&mut self, op: u32, n: u32) -> Result<()> {
+        self.write_gc_op(op)?;
+        self.write_u32(n)
+    }
+
+    fn write_gc_op_u32_u32(&mut self, op: u32, n: u32, m: u32) -> Result<()> {
+        self.write_gc_op_u32(op, n)?;
+        self.write_u32(m)
+    }
+
+    /// https://webassembly.github.io/gc/core/binary/instructions.html#control-instructions
+    fn write_br_on_cast(
+        &mut self,
+        op: u32,
+        label: u32,
+        from: &RefType,
+        to: &RefType,
+    ) -> Result<()> {
+        self.write_gc_op(op)?;
+        self.write_byte(from.nullable as u8 | (to.nullable as u8) << 1)?;
+        self.write_u32(label)?;
+        self.write_heap_type(from.heap)?;
+        self.write_heap_type(to.heap)
+    }
+
+    /// instructions prefixed with 0xFC
+    fn write_prefixed_op(&mut self, op: u32) -> Result<()> {
+        self.write_byte(0xFC)?;
+        self.write_u32(op)
+    }
+
+    /// instructions prefixed with 0xFD
+    fn write_vector_op(&mut self, op: u32) -> Result<()> {
+        self.write_byte(0xFD)?;
+        self.write_u32(op)
+    }
+
+    /// instructions prefixed with 0xFE
+    fn write_atomic_op(&mut self, op: u32) -> Result<()> {
+        self.write_byte(0xFE)?;
+        self.write_u32(op)
+    }
+
+    /// https://webassembly.github.io/multi-memory/core/binary/instructions.html#memory-instructions
+    /// https://webassembly.github.io/memory64/core/binary/instructions.html#memory-instructions
+    fn write_memarg(&mut self, memarg: &MemArg) -> Result<()> {
+        if memarg.memory == 0 {
+            self.write_u32(memarg.align)?;
+        } else {
+            self.write_u32(memarg.align | 0x40)?;
+            self.write_u32(memarg.memory)?;
+        }
+        self.write_u64(memarg.offset)
+    }
+}
+impl<W: WasmModuleBinaryWrite> ExprWrite for W {}
+
+#[cfg(all(
+    test,
+    feature = "simd",
+    feature = "threads",
+    feature = "exceptions",
+    feature = "gc"
+))]
+mod tests {
+    use anyhow::*;
+
+    use super::ExprWrite;
+    use crate::binary::instructions::decode_instructions;
+    use crate::structure::{
+        instructions::{
+            atomic::AtomicOp, memory::MemoryOp, vector::VectorOp, BlockType, Catch, Instruction::*,
+            MemArg,
+        },
+        types::{HeapType, NumType, RefType, ValType},
+    };
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        //Given
+        let expr = vec![
+            Unreachable,
+            Nop,
+            Block(
+                BlockType::Empty,
+                vec![Loop(
+                    BlockType::TypeIdx(64),
+                    vec![Br(1), BrIf(0), BrTable(vec![0, 1], 2)],
+                )],
+            ),
+            If(
+                BlockType::ValType(ValType::Number(NumType::I32)),
+                vec![I32Const(11)],
+                Some(vec![I64Const(-1), Drop, I32Const(0)]),
+            ),
+            If(BlockType::Empty, vec![Return], None),
+            Call(1),
+            CallIndirect(1, 2),
+            ReturnCall(1),
+            ReturnCallIndirect(1, 2),
+            TryTable(
+                BlockType::ValType(ValType::Ref(RefType::EXNREF)),
+                vec![
+                    Catch::Catch(0, 1),
+                    Catch::CatchRef(1, 2),
+                    Catch::CatchAll(3),
+                    Catch::CatchAllRef(0),
+                ],
+                vec![Throw(2), ThrowRef],
+            ),
+            TryTable(BlockType::Empty, vec![], vec![]),
+            Block(
+                BlockType::ValType(ValType::Ref(RefType::non_null(HeapType::Type(1)))),
+                vec![BrOnNull(0), BrOnNonNull(1), CallRef(1), ReturnCallRef(200)],
+            ),
+            RefNull(HeapType::Extern),
+            RefNull(HeapType::Type(300)),
+            RefIsNull,
+            RefFunc(3),
+            RefAsNonNull,
+            RefEq,
+            RefTest(RefType::null(HeapType::I31)),
+            RefCast(RefType::non_null(HeapType::Type(2))),
+            Block(
+                BlockType::ValType(ValType::Ref(RefType::null(HeapType::Any))),
+                vec![
+                    BrOnCast(
+                        0,
+                        RefType::null(HeapType::Any),
+                        RefType::non_null(HeapType::Struct),
+                    ),
+                    BrOnCastFail(
+                        0,
+                        RefType::non_null(HeapType::Any),
+                        RefType::null(HeapType::I31),
+                    ),
+                ],
+            ),
+            StructNew(1),
+            StructNewDefault(2),
+            StructGet(1, 0),
+            StructGetS(1, 1),
+            StructGetU(1, 2),
+            StructSet(1, 3),
+            ArrayNew(4),
+            ArrayNewDefault(4),
+            ArrayNewFixed(4, 5),
+            ArrayNewData(4, 1),
+            ArrayNewElem(4, 2),
+            ArrayGet(4),
+            ArrayGetS(5),
+            ArrayGetU(5),
+            ArraySet(4),
+            ArrayLen,
+            ArrayFill(4),
+            ArrayCopy(4, 6),
+            ArrayInitData(5, 0),
+            ArrayInitElem(4, 1),
+            AnyConvertExtern,
+            ExternConvertAny,
+            RefI31,
+            I31GetS,
+            I31GetU,
+            RefNull(HeapType::None),
+            Select(None),
+            Select(Some(vec![ValType::Number(NumType::F64)])),
+            Select(Some(vec![ValType::Ref(RefType::null(HeapType::Type(0)))])),
+            LocalGet(0),
+            LocalSet(1),
+            LocalTee(2),
+            GlobalGet(3),
+            GlobalSet(4),
+            TableGet(0),
+            TableSet(1),
+            TableInit(2, 3),
+            TableDrop(4),
+            TableCopy(5, 6),
+            TableGrow(7),
+            TableSize(8),
+            TableFill(9),
+            Memory(
+                MemoryOp::I64Load32S,
+                MemArg {
+                    align: 2,
+                    offset: 1 << 40,
+                    memory: 0,
+                },
+            ),
+            Memory(
+                MemoryOp::F32Store,
+                MemArg {
+                    align: 0,
+                    offset: 0,
+                    memory: 1,
+                },
+            ),
+            MemorySize(0),
+            MemoryGrow(1),
+            MemoryInit(2, 3),
+            DataDrop(4),
+            MemoryCopy(5, 6),
+            MemoryFill(7),
+            I32Eqz,
+            I32Eq,
+            I32Ne,
+            I32LtS,
+            I32LtU,
+            I32GtS,
+            I32GtU,
+            I32LeS,
+            I32LeU,
+            I32GeS,
+            I32GeU,
+            I32Clz,
+            I32Ctz,
+            I32Popcnt,
+            I32Add,
+            I32Sub,
+            I32Mul,
+            I32DivS,
+            I32DivU,
+            I32RemS,
+            I32RemU,
+            I32And,
+            I32Or,
+            I32Xor,
+            I32ShL,
+            I32ShrS,
+            I32ShrU,
+            I32RtoL,
+            I32RtoR,
+            I64Add,
+            I64Sub,
+            I64Mul,
+            I32Extend8S,
+            I32Extend16S,
+            V128Const(0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10),
+            I8x16Shuffle([0, 17, 2, 19, 4, 21, 6, 23, 8, 25, 10, 27, 12, 29, 14, 31]),
+            Vector(VectorOp::I8x16Swizzle),
+            Vector(VectorOp::F64x2ConvertLowI32x4U),
+            Vector(VectorOp::I32x4RelaxedDotI8x16I7x16AddS),
+            VectorLane(VectorOp::I16x8ReplaceLane, 7),
+            VectorMem(
+                VectorOp::V128Load,
+                MemArg {
+                    align: 4,
+                    offset: 16,
+                    memory: 0,
+                },
+            ),
+            VectorMemLane(
+                VectorOp::V128Store64Lane,
+                MemArg {
+                    align: 3,
+                    offset: 0,
+                    memory: 2,
+                },
+                1,
+            ),
+            AtomicFence,
+            Atomic(
+                AtomicOp::I64AtomicRmw32CmpxchgU,
+                MemArg {
+                    align: 2,
+                    offset: 8,
+                    memory: 1,
+                },
+            ),
+        ];
+        //When
+        let mut buf = Vec::<u8>::new();
+        buf.write_expr(&expr)?;
+        //Then
+        assert_eq!(decode_instructions(&buf)?, expr);
+        assert!(buf.write_instruction(&Void).is_err());
+        Ok(())
+    }
+}
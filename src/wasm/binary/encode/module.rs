@@ -0,0 +1,382 @@
+use super::{instructions::ExprWrite, types::TypeWrite, WasmModuleBinaryWrite};
+use crate::{
+    binary::module::parser::SectionID,
+    structure::{
+        instructions::{Expr, Instruction},
+        module::{Custom, Data, DataMode, Elem, ElemMode, ExportDesc, Func, ImportDesc, Module},
+        types::{RefType, ValType},
+    },
+};
+use anyhow::*;
+use std::io::Write;
+
+/// The order in which non-custom sections are written
+/// https://webassembly.github.io/spec/core/binary/modules.html#binary-module
+const SECTION_ORDER: [SectionID; 13] = [
+    SectionID::Type,
+    SectionID::Import,
+    SectionID::Function,
+    SectionID::Table,
+    SectionID::Memory,
+    SectionID::Tag,
+    SectionID::Global,
+    SectionID::Export,
+    SectionID::Start,
+    SectionID::Element,
+    SectionID::DataCount,
+    SectionID::Code,
+    SectionID::Data,
+];
+
+pub fn write_module(w: &mut impl WasmModuleBinaryWrite, module: &Module) -> Result<()> {
+    w.write_all(b"\0asm")?;
+    w.write_u32_le(module.version)?;
+    write_customs_after(w, &module.customs, None)?;
+    for section_id in SECTION_ORDER {
+        let id = section_id as u8;
+        let anchored = module.customs.iter().any(|c| c.after == Some(id));
+        // an empty section is kept only if a custom section has been placed after it
+        if let Some(content) = encode_section(module, section_id, anchored)? {
+            w.write_byte(id)?;
+            w.write_sized(&content)?;
+        }
+        write_customs_after(w, &module.customs, Some(id))?;
+    }
+    Ok(())
+}
+
+fn write_customs_after(
+    w: &mut impl WasmModuleBinaryWrite,
+    customs: &[Custom],
+    after: Option<u8>,
+) -> Result<()> {
+    for custom in customs.iter().filter(|c| c.after == after) {
+        let mut content = Vec::<u8>::new();
+        content.write_name(&custom.name)?;
+        content.write_all(&custom.data)?;
+        w.write_byte(SectionID::Custom as u8)?;
+        w.write_sized(&content)?;
+    }
+    Ok(())
+}
+
+/// encode the content of a section, None if it is empty and not forced
+fn encode_section(module: &Module, section_id: SectionID, force: bool) -> Result<Option<Vec<u8>>> {
+    let mut w = Vec::<u8>::new();
+    let is_empty = match section_id {
+        SectionID::Type => {
+            if module.rec_groups.iter().sum::<u32>() as usize != module.types.len() {
+                bail!("recursive type groups do not cover the types")
+            }
+            let mut types = &module.types[..];
+            w.write_vec(&module.rec_groups, |w, len| {
+                let (group, rest) = types.split_at(*len as usize);
+                types = rest;
+                w.write_rec_type(group)
+            })?;
+            module.types.is_empty()
+        }
+        SectionID::Import => {
+            w.write_vec(&module.imports, |w, import| {
+                w.write_name(&import.module)?;
+                w.write_name(&import.name)?;
+                match &import.desc {
+                    ImportDesc::Func(idx) => {
+                        w.write_byte(0x00)?;
+                        w.write_u32(*idx)
+                    }
+                    ImportDesc::Table(t) => {
+                        w.write_byte(0x01)?;
+                        w.write_table_type(t)
+                    }
+                    ImportDesc::Mem(m) => {
+                        w.write_byte(0x02)?;
+                        w.write_mem_type(m)
+                    }
+                    ImportDesc::Global(g) => {
+                        w.write_byte(0x03)?;
+                        w.write_global_type(g)
+                    }
+                    ImportDesc::Tag(type_) => {
+                        w.write_byte(0x04)?;
+                        w.write_byte(0x00)?;
+                        w.write_u32(*type_)
+                    }
+                }
+            })?;
+            module.imports.is_empty()
+        }
+        SectionID::Function => {
+            w.write_vec(&module.funcs, |w, f| w.write_u32(f.type_))?;
+            module.funcs.is_empty()
+        }
+        SectionID::Table => {
+            w.write_vec(&module.tables, |w, t| w.write_table_type(&t.type_))?;
+            module.tables.is_empty()
+        }
+        SectionID::Memory => {
+            w.write_vec(&module.mems, |w, m| w.write_mem_type(&m.type_))?;
+            module.mems.is_empty()
+        }
+        SectionID::Tag => {
+            w.write_vec(&module.tags, |w, t| {
+                w.write_byte(0x00)?;
+                w.write_u32(t.type_)
+            })?;
+            module.tags.is_empty()
+        }
+        SectionID::Global => {
+            w.write_vec(&module.globals, |w, g| {
+                w.write_global_type(&g.type_)?;
+                w.write_expr(&g.init)
+            })?;
+            module.globals.is_empty()
+        }
+        SectionID::Export => {
+            w.write_vec(&module.exports, |w, export| {
+                w.write_name(&export.name)?;
+                let (kind, idx) = match export.desc {
+                    ExportDesc::Func(idx) => (0x00, idx),
+                    ExportDesc::Table(idx) => (0x01, idx),
+                    ExportDesc::Mem(idx) => (0x02, idx),
+                    ExportDesc::Global(idx) => (0x03, idx),
+                    ExportDesc::Tag(idx) => (0x04, idx),
+                };
+                w.write_byte(kind)?;
+                w.write_u32(idx)
+            })?;
+            module.exports.is_empty()
+        }
+        SectionID::Start => match &module.start {
+            Some(start) => {
+                w.write_u32(start.func)?;
+                false
+            }
+            None if force => bail!("Start section cannot be empty"),
+            None => true,
+        },
+        SectionID::Element => {
+            w.write_vec(&module.elems, write_elem)?;
+            module.elems.is_empty()
+        }
+        // written only if the module declares it or the code needs it,
+        // so that modules without bulk memory instructions stay in WebAssembly 1.0
+        SectionID::DataCount => {
+            let needed = module.data_count.is_some()
+                || module
+                    .funcs
+                    .iter()
+                    .any(|func| func.body.expr().is_ok_and(refers_to_datas));
+            w.write_u32(module.datas.len().try_into()?)?;
+            !needed
+        }
+        SectionID::Code => {
+            w.write_vec(&module.funcs, write_code)?;
+            module.funcs.is_empty()
+        }
+        SectionID::Data => {
+            w.write_vec(&module.datas, write_data)?;
+            module.datas.is_empty()
+        }
+        SectionID::Custom => bail!("custom sections are written as they are"),
+    };
+    Ok(if is_empty && !force { None } else { Some(w) })
+}
+
+/// whether the instructions refer to data segments, which requires the DataCount section
+/// https://webassembly.github.io/spec/core/binary/modules.html#data-count-section
+fn refers_to_datas(expr: &Expr) -> bool {
+    expr.iter().any(|inst| match inst {
+        Instruction::MemoryInit(..)
+        | Instruction::DataDrop(_)
+        | Instruction::ArrayNewData(..)
+        | Instruction::ArrayInitData(..) => true,
+        Instruction::Block(_, body)
+        | Instruction::Loop(_, body)
+        | Instruction::TryTable(_, _, body) => refers_to_datas(body),
+        Instruction::If(_, then, else_) => {
+            refers_to_datas(then) || else_.as_ref().is_some_and(refers_to_datas)
+        }
+        _ => false,
+    })
+}
+
+/// Elements are always written as expressions, with the shortest flag that keeps the segment.
+/// https://webassembly.github.io/spec/core/binary/modules.html#element-section
+fn write_elem(w: &mut Vec<u8>, elem: &Elem) -> Result<()> {
+    match &elem.mode {
+        ElemMode::Active { table: 0, offset } if elem.type_ == RefType::FUNCREF => {
+            w.write_u32(0b100)?;
+            w.write_expr(offset)?;
+        }
+        ElemMode::Active { table, offset } => {
+            w.write_u32(0b110)?;
+            w.write_u32(*table)?;
+            w.write_expr(offset)?;
+            w.write_ref_type(elem.type_)?;
+        }
+        ElemMode::Passive => {
+            w.write_u32(0b101)?;
+            w.write_ref_type(elem.type_)?;
+        }
+        ElemMode::Declarative => {
+            w.write_u32(0b111)?;
+            w.write_ref_type(elem.type_)?;
+        }
+    }
+    w.write_vec(&elem.init, |w, init| w.write_expr(init))
+}
+
+/// https://webassembly.github.io/spec/core/binary/modules.html#code-section
+fn write_code(w: &mut Vec<u8>, func: &Func) -> Result<()> {
+    let mut code = Vec::<u8>::new();
+    let mut groups = Vec::<(u32, ValType)>::new();
+    for local in &func.locals {
+        match groups.last_mut() {
+            Some((n, val_type)) if val_type == local => *n += 1,
+            _ => groups.push((1, *local)),
+        }
+    }
+    code.write_vec(&groups, |w, (n, val_type)| {
+        w.write_u32(*n)?;
+        w.write_val_type(*val_type)
+    })?;
+    // the original bytes are reused as they are if the body has been decoded from a binary
+    if func.body.bytes().is_empty() {
+        code.write_expr(func.body.expr()?)?;
+    } else {
+        code.write_all(func.body.bytes())?;
+    }
+    w.write_sized(&code)
+}
+
+/// https://webassembly.github.io/spec/core/binary/modules.html#data-section
+fn write_data(w: &mut Vec<u8>, data: &Data) -> Result<()> {
+    match &data.mode {
+        DataMode::Active { memory: 0, offset } => {
+            w.write_u32(0x00)?;
+            w.write_expr(offset)?;
+        }
+        DataMode::Passive => w.write_u32(0x01)?,
+        DataMode::Active { memory, offset } => {
+            w.write_u32(0x02)?;
+            w.write_u32(*memory)?;
+            w.write_expr(offset)?;
+        }
+    }
+    w.write_sized(&data.init)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+    use wasmer::wat2wasm;
+
+    use crate::binary::{encode::encode, module::decode_slice};
+    use crate::structure::{instructions::Instruction::*, module::Body};
+
+    #[cfg(all(feature = "reference-types", feature = "bulk-memory"))]
+    fn round_trip(wat: &[u8]) -> Result<()> {
+        let wasm = wat2wasm(wat)?;
+        let module = decode_slice(&wasm)?;
+        let encoded = encode(&module)?;
+        assert_eq!(decode_slice(&encoded)?, module);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "reference-types", feature = "bulk-memory"))]
+    fn round_trip_modules() -> Result<()> {
+        round_trip(
+            br#"(module
+            (func $i32.add (param $lhs i32) (param $rhs i32) (result i32)
+                local.get $lhs
+                local.get $rhs
+                i32.add
+            )
+        )"#,
+        )?;
+        round_trip(
+            br#"(module
+            (memory 1 2)
+            (data (i32.const 16) "abc")
+        )"#,
+        )?;
+        round_trip(
+            br#"(module
+            (import "env" "print" (func $print (param i32)))
+            (import "env" "mem" (memory 1))
+            (import "env" "g" (global i64))
+            (import "env" "t" (table 2 externref))
+            (table $t2 1 funcref)
+            (global $g (mut i32) (i32.const 0))
+            (func $main (local i32 i32 i64)
+                (block (loop (br_if 1 (i32.eqz (global.get $g)))))
+                (call $print (global.get $g)))
+            (export "main" (func $main))
+            (start $main)
+            (elem (table $t2) (i32.const 0) func $main)
+            (elem func $main)
+            (elem declare func $main)
+            (data "passive")
+        )"#,
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_custom_sections() -> Result<()> {
+        //Given
+        let bytes = [
+            &b"\0asm\x01\0\0\0"[..],
+            &[0x00, 0x02, 0x01, 0x61],
+            // empty type section followed by a custom section
+            &[0x01, 0x01, 0x00],
+            &[0x00, 0x03, 0x01, 0x62, 0xFF],
+            &[0x00, 0x02, 0x01, 0x63],
+        ]
+        .concat();
+        let module = decode_slice(&bytes)?;
+        //When
+        let encoded = encode(&module)?;
+        //Then
+        assert_eq!(encoded, bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn encode_data_count_only_if_needed() -> Result<()> {
+        //Given
+        let wasm = wat2wasm(br#"(module (memory 1) (func) (data (i32.const 0) "a"))"#)?;
+        let mut module = decode_slice(&wasm)?;
+        //When
+        let mvp = encode(&module)?;
+        module.funcs[0].body = Body::from(vec![
+            I32Const(0),
+            I32Const(0),
+            I32Const(1),
+            MemoryInit(0, 0),
+        ]);
+        let bulk = encode(&module)?;
+        //Then
+        assert_eq!(mvp, *wasm);
+        assert_eq!(decode_slice(&mvp)?.data_count, None);
+        assert_eq!(decode_slice(&bulk)?.data_count, Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn encode_built_body() -> Result<()> {
+        //Given
+        let wasm = wat2wasm(br#"(module (func (result i32) i32.const 1))"#)?;
+        let mut module = decode_slice(&wasm)?;
+        module.funcs[0].body = Body::from(vec![I32Const(2)]);
+        module.funcs[0].locals = vec![];
+        //When
+        let encoded = encode(&module)?;
+        let decoded = decode_slice(&encoded)?;
+        //Then
+        assert_eq!(decoded.funcs[0].body.expr()?, &vec![I32Const(2)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use super::WasmModuleBinaryWrite;
+use crate::structure::types::{
+    CompositeType, FieldType, FuncType, GlobalType, HeapType, IndexType, Limits, MemType, Mut,
+    NumType, PackedType, RefType, ResultType, Share, StorageType, SubType, TableType, ValType,
+};
+use anyhow::*;
+
+/// Extensions for WasmModuleBinaryWrite to encode types
+pub trait TypeWrite: WasmModuleBinaryWrite {
+    fn write_val_type(&mut self, val_type: ValType) -> Result<()> {
+        match val_type {
+            ValType::Number(NumType::I32) => self.write_byte(0x7F),
+            ValType::Number(NumType::I64) => self.write_byte(0x7E),
+            ValType::Number(NumType::F32) => self.write_byte(0x7D),
+            ValType::Number(NumType::F64) => self.write_byte(0x7C),
+            ValType::Vec => self.write_byte(0x7B),
+            ValType::Ref(ref_type) => self.write_ref_type(ref_type),
+        }
+    }
+
+    /// nullable abstract types are written in their short forms, which are their heap types
+    /// https://webassembly.github.io/gc/core/binary/types.html#reference-types
+    fn write_ref_type(&mut self, ref_type: RefType) -> Result<()> {
+        match ref_type {
+            RefType {
+                nullable: true,
+                heap,
+            } if !matches!(heap, HeapType::Type(_)) => self.write_heap_type(heap),
+            RefType { nullable, heap } => {
+                self.write_byte(if nullable { 0x63 } else { 0x64 })?;
+                self.write_heap_type(heap)
+            }
+        }
+    }
+
+    /// https://webassembly.github.io/gc/core/binary/types.html#heap-types
+    fn write_heap_type(&mut self, heap: HeapType) -> Result<()> {
+        match heap {
+            HeapType::Func => self.write_byte(0x70),
+            HeapType::Extern => self.write_byte(0x6F),
+            HeapType::Exn => self.write_byte(0x69),
+            HeapType::Any => self.write_byte(0x6E),
+            HeapType::Eq => self.write_byte(0x6D),
+            HeapType::I31 => self.write_byte(0x6C),
+            HeapType::Struct => self.write_byte(0x6B),
+            HeapType::Array => self.write_byte(0x6A),
+            HeapType::None => self.write_byte(0x71),
+            HeapType::NoExtern => self.write_byte(0x72),
+            HeapType::NoFunc => self.write_byte(0x73),
+            HeapType::NoExn => self.write_byte(0x74),
+            HeapType::Type(idx) => self.write_i64(idx.into()),
+        }
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/types.html#result-types
+    fn write_result_type(&mut self, result_type: &ResultType) -> Result<()> {
+        self.write_vec(&result_type.0, |w, v| w.write_val_type(*v))
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/types.html#function-types
+    fn write_func_type(&mut self, func_type: &FuncType) -> Result<()> {
+        self.write_byte(0x60)?;
+        self.write_result_type(&func_type.0)?;
+        self.write_result_type(&func_type.1)
+    }
+
+    /// a group of a single type is written without `rec`
+    /// https://webassembly.github.io/gc/core/binary/types.html#recursive-types
+    fn write_rec_type(&mut self, sub_types: &[SubType]) -> Result<()> {
+        match sub_types {
+            [sub_type] => self.write_sub_type(sub_type),
+            _ => {
+                self.write_byte(0x4E)?;
+                self.write_vec(sub_types, |w, t| w.write_sub_type(t))
+            }
+        }
+    }
+
+    /// final types without supertypes are written without `sub`
+    fn write_sub_type(&mut self, sub_type: &SubType) -> Result<()> {
+        if !sub_type.is_final || !sub_type.supers.is_empty() {
+            self.write_byte(if sub_type.is_final { 0x4F } else { 0x50 })?;
+            self.write_vec(&sub_type.supers, |w, idx| w.write_u32(*idx))?;
+        }
+        match &sub_type.composite {
+            CompositeType::Func(func_type) => self.write_func_type(func_type),
+            CompositeType::Struct(fields) => {
+                self.write_byte(0x5F)?;
+                self.write_vec(fields, |w, field| w.write_field_type(field))
+            }
+            CompositeType::Array(field) => {
+                self.write_byte(0x5E)?;
+                self.write_field_type(field)
+            }
+        }
+    }
+
+    /// https://webassembly.github.io/gc/core/binary/types.html#aggregate-types
+    fn write_field_type(&mut self, FieldType(mutability, storage): &FieldType) -> Result<()> {
+        match storage {
+            StorageType::Val(val_type) => self.write_val_type(*val_type)?,
+            StorageType::Packed(PackedType::I8) => self.write_byte(0x78)?,
+            StorageType::Packed(PackedType::I16) => self.write_byte(0x77)?,
+        }
+        self.write_byte(match mutability {
+            Mut::Const => 0x00,
+            Mut::Var => 0x01,
+        })
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/types.html#limits
+    fn write_limits(&mut self, limits: &Limits) -> Result<()> {
+        self.write_limits_with(limits, 0x00)
+    }
+
+    /// write limits whose flag has the bits of `shared` set
+    /// https://webassembly.github.io/memory64/core/binary/types.html#limits
+    fn write_limits_with(&mut self, limits: &Limits, shared: u8) -> Result<()> {
+        let flag = match limits.index {
+            IndexType::I32 => shared,
+            IndexType::I64 => shared | 0x04,
+        };
+        match limits.max {
+            None => {
+                self.write_byte(flag)?;
+                self.write_u64(limits.min)
+            }
+            Some(max) => {
+                self.write_byte(0x01 | flag)?;
+                self.write_u64(limits.min)?;
+                self.write_u64(max)
+            }
+        }
+    }
+
+    /// https://webassembly.github.io/threads/core/binary/types.html#memory-types
+    fn write_mem_type(&mut self, MemType(limits, share): &MemType) -> Result<()> {
+        match share {
+            Share::Unshared => self.write_limits(limits),
+            Share::Shared => self.write_limits_with(limits, 0x02),
+        }
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/types.html#table-types
+    fn write_table_type(&mut self, table_type: &TableType) -> Result<()> {
+        self.write_ref_type(table_type.1)?;
+        self.write_limits(&table_type.0)
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/types.html#global-types
+    fn write_global_type(&mut self, global_type: &GlobalType) -> Result<()> {
+        self.write_val_type(global_type.1)?;
+        self.write_byte(match global_type.0 {
+            Mut::Const => 0x00,
+            Mut::Var => 0x01,
+        })
+    }
+}
+impl<W: WasmModuleBinaryWrite> TypeWrite for W {}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use super::TypeWrite;
+    use crate::binary::types::TypeRead;
+    use crate::structure::types::{
+        GlobalType, IndexType, Limits, MemType, Mut, NumType, RefType, Share, TableType, ValType,
+    };
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        //Given
+        let mem_type = MemType(
+            Limits {
+                min: 1,
+                max: Some(1 << 40),
+                index: IndexType::I64,
+            },
+            Share::Shared,
+        );
+        let table_type = TableType(
+            Limits {
+                min: 3,
+                max: Some(16),
+                index: IndexType::I32,
+            },
+            RefType::EXTERNREF,
+        );
+        let global_type = GlobalType(Mut::Var, ValType::Number(NumType::I64));
+        //When
+        let mut buf = Vec::<u8>::new();
+        buf.write_mem_type(&mem_type)?;
+        buf.write_table_type(&table_type)?;
+        buf.write_global_type(&global_type)?;
+        //Then
+        let mut reader = &buf[..];
+        assert_eq!(reader.read_mem_type()?, mem_type);
+        assert_eq!(reader.read_table_type()?, table_type);
+        assert_eq!(reader.read_global_type()?, global_type);
+        Ok(())
+    }
+}
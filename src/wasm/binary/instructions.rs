@@ -1,31 +1,303 @@
-use super::decode::WasmModuleBinaryRead;
+use super::{decode::WasmModuleBinaryRead, types::TypeRead};
+#[cfg(feature = "threads")]
+use crate::structure::instructions::atomic::AtomicOp;
+#[cfg(feature = "simd")]
+use crate::structure::instructions::vector::VectorOp;
+#[cfg(feature = "exceptions")]
+use crate::structure::instructions::Catch;
+#[cfg(feature = "gc")]
+use crate::structure::types::RefType;
 use crate::structure::{
-    instructions::Instruction::{self, *},
+    instructions::{
+        memory::MemoryOp,
+        BlockType, Expr,
+        Instruction::{self, *},
+        MemArg,
+    },
     types::ValType,
 };
 use anyhow::*;
+use num::FromPrimitive;
 
-pub fn decode_instructions(bytes: Vec<u8>) -> Result<Vec<Instruction>> {
-    block::InstructionArrayWrapper::try_from(bytes).map(|x| x.0)
+/// blocks nested deeper than this are rejected, so that decoding doesn't overflow the stack
+const MAX_NESTING: usize = 256;
+
+/// decode instructions until the terminating End, which has to be the last byte, or the end of the bytes
+pub fn decode_instructions(mut bytes: &[u8]) -> Result<Vec<Instruction>> {
+    let r: &mut dyn WasmModuleBinaryRead = &mut bytes;
+    let mut insts = Vec::<Instruction>::new();
+    while r.has_next()? {
+        let inst = read_instruction(r, 0)?;
+        if inst == End {
+            r.ensure_end()
+                .context("instructions follow the end of the expression")?;
+            break;
+        }
+        insts.push(inst);
+    }
+    Ok(insts)
+}
+
+/// Extensions for WasmModuleBinaryRead to decode expressions embedded in other sections
+pub trait ExprRead: WasmModuleBinaryRead + Sized {
+    /// read instructions until the terminating End
+    fn read_expr(&mut self) -> Result<Expr> {
+        match read_sequence(self, 0)? {
+            (expr, End) => Ok(expr),
+            _ => bail!("else is not allowed outside of if"),
+        }
+    }
+}
+impl<R: WasmModuleBinaryRead> ExprRead for R {}
+
+/// `depth` is the number of blocks which the instruction is nested in
+fn read_instruction(r: &mut dyn WasmModuleBinaryRead, depth: usize) -> Result<Instruction> {
+    let b = r.read_byte()?;
+    // loads and stores, which are numbered by their opcodes
+    if let Some(op) = MemoryOp::from_u8(b) {
+        return Ok(Memory(op, read_memarg(r)?));
+    }
+    if let Some(inst) = read_structured(r, b, depth + 1)? {
+        return Ok(inst);
+    }
+    choose_inst_factory(b)?(r)
+}
+
+/// read instructions until End or Else, which is returned together
+fn read_sequence(
+    r: &mut dyn WasmModuleBinaryRead,
+    depth: usize,
+) -> Result<(Vec<Instruction>, Instruction)> {
+    if depth > MAX_NESTING {
+        bail!("blocks are nested deeper than {}", MAX_NESTING)
+    }
+    let mut insts = Vec::<Instruction>::new();
+    loop {
+        match read_instruction(r, depth)? {
+            terminator @ (End | Else) => return Ok((insts, terminator)),
+            inst => insts.push(inst),
+        }
+    }
 }
 
-type FactoryMethod = fn(reader: &mut Box<dyn WasmModuleBinaryRead>) -> Result<Instruction>;
+/// https://webassembly.github.io/spec/core/binary/instructions.html#control-instructions
+fn read_block_type(r: &mut dyn WasmModuleBinaryRead) -> Result<BlockType> {
+    let b = *r.fill_buf()?.first().context("block type is missing")?;
+    if b == 0x40 {
+        r.read_byte()?;
+        Ok(BlockType::Empty)
+    } else if b == 0x63 || b == 0x64 {
+        Ok(BlockType::ValType(r.read_val_type()?))
+    } else if let Result::Ok(v) = ValType::try_from(b) {
+        r.read_byte()?;
+        Ok(BlockType::ValType(v))
+    } else {
+        // type indices are encoded as positive signed 33 bit integers
+        let idx = r.read_i64()?;
+        if !(0..=u32::MAX as i64).contains(&idx) {
+            bail!("invalid block type {}", idx)
+        }
+        Ok(BlockType::TypeIdx(idx as u32))
+    }
+}
+
+fn read_block(
+    r: &mut dyn WasmModuleBinaryRead,
+    depth: usize,
+) -> Result<(BlockType, Vec<Instruction>)> {
+    let block_type = read_block_type(r)?;
+    match read_sequence(r, depth)? {
+        (insts, End) => Ok((block_type, insts)),
+        _ => bail!("else is allowed only in if"),
+    }
+}
+
+/// instructions containing sequences of instructions, which are read `depth` blocks deep,
+/// or None for the other opcodes
+fn read_structured(
+    r: &mut dyn WasmModuleBinaryRead,
+    b: u8,
+    depth: usize,
+) -> Result<Option<Instruction>> {
+    Ok(Some(match b {
+        0x02 => {
+            let (block_type, insts) = read_block(r, depth)?;
+            Block(block_type, insts)
+        }
+        0x03 => {
+            let (block_type, insts) = read_block(r, depth)?;
+            Loop(block_type, insts)
+        }
+        0x04 => {
+            let block_type = read_block_type(r)?;
+            match read_sequence(r, depth)? {
+                (then, End) => If(block_type, then, None),
+                (then, _) => match read_sequence(r, depth)? {
+                    (else_, End) => If(block_type, then, Some(else_)),
+                    _ => bail!("if must not have more than one else"),
+                },
+            }
+        }
+        #[cfg(feature = "exceptions")]
+        0x1F => {
+            let block_type = read_block_type(r)?;
+            let len = r.read_u32()?;
+            let mut catches = Vec::<Catch>::new();
+            for _ in 0..len {
+                catches.push(read_catch(r)?);
+            }
+            match read_sequence(r, depth)? {
+                (insts, End) => TryTable(block_type, catches, insts),
+                _ => bail!("else is allowed only in if"),
+            }
+        }
+        _ => return Ok(None),
+    }))
+}
+
+/// https://webassembly.github.io/exception-handling/core/binary/instructions.html#control-instructions
+#[cfg(feature = "exceptions")]
+fn read_catch(r: &mut dyn WasmModuleBinaryRead) -> Result<Catch> {
+    Ok(match r.read_byte()? {
+        0x00 => Catch::Catch(r.read_u32()?, r.read_u32()?),
+        0x01 => Catch::CatchRef(r.read_u32()?, r.read_u32()?),
+        0x02 => Catch::CatchAll(r.read_u32()?),
+        0x03 => Catch::CatchAllRef(r.read_u32()?),
+        b => bail!("invalid catch clause {:#x}", b),
+    })
+}
+
+/// the bit 6 of the alignment tells that the memory index follows,
+/// and the offset is u64 to address 64 bit memories
+/// https://webassembly.github.io/multi-memory/core/binary/instructions.html#memory-instructions
+/// https://webassembly.github.io/memory64/core/binary/instructions.html#memory-instructions
+fn read_memarg(r: &mut dyn WasmModuleBinaryRead) -> Result<MemArg> {
+    let align = r.read_u32()?;
+    let memory = if align & 0x40 != 0 { r.read_u32()? } else { 0 };
+    Ok(MemArg {
+        align: align & !0x40,
+        offset: r.read_u64()?,
+        memory,
+    })
+}
+
+/// https://webassembly.github.io/spec/core/binary/instructions.html#vector-instructions
+#[cfg(feature = "simd")]
+fn read_vector_instruction(r: &mut dyn WasmModuleBinaryRead) -> Result<Instruction> {
+    let op = r.read_u32()?;
+    match op {
+        0x0C => {
+            let bytes = r.read_bytes(16)?.try_into().unwrap();
+            return Ok(V128Const(u128::from_le_bytes(bytes)));
+        }
+        0x0D => return Ok(I8x16Shuffle(r.read_bytes(16)?.try_into().unwrap())),
+        _ => {}
+    }
+    let op =
+        VectorOp::from_u32(op).with_context(|| format!("0xFD {} is undefined instruction.", op))?;
+    let kind = op.kind();
+    Ok(match (kind.width(), kind.lanes()) {
+        (Some(_), Some(_)) => {
+            let memarg = read_memarg(r)?;
+            VectorMemLane(op, memarg, r.read_byte()?)
+        }
+        (Some(_), None) => VectorMem(op, read_memarg(r)?),
+        (None, Some(_)) => VectorLane(op, r.read_byte()?),
+        (None, None) => Vector(op),
+    })
+}
+
+/// https://webassembly.github.io/threads/core/binary/instructions.html#atomic-memory-instructions
+#[cfg(feature = "threads")]
+fn read_atomic_instruction(r: &mut dyn WasmModuleBinaryRead) -> Result<Instruction> {
+    let op = r.read_u32()?;
+    if op == 0x03 {
+        match r.read_byte()? {
+            0x00 => return Ok(AtomicFence),
+            b => bail!("invalid flags of atomic.fence {:#x}", b),
+        }
+    }
+    let op =
+        AtomicOp::from_u32(op).with_context(|| format!("0xFE {} is undefined instruction.", op))?;
+    Ok(Atomic(op, read_memarg(r)?))
+}
+
+/// https://webassembly.github.io/gc/core/binary/instructions.html#aggregate-instructions
+#[cfg(feature = "gc")]
+fn read_gc_instruction(r: &mut dyn WasmModuleBinaryRead) -> Result<Instruction> {
+    let op = r.read_u32()?;
+    Ok(match op {
+        0 => StructNew(r.read_u32()?),
+        1 => StructNewDefault(r.read_u32()?),
+        2 => StructGet(r.read_u32()?, r.read_u32()?),
+        3 => StructGetS(r.read_u32()?, r.read_u32()?),
+        4 => StructGetU(r.read_u32()?, r.read_u32()?),
+        5 => StructSet(r.read_u32()?, r.read_u32()?),
+        6 => ArrayNew(r.read_u32()?),
+        7 => ArrayNewDefault(r.read_u32()?),
+        8 => ArrayNewFixed(r.read_u32()?, r.read_u32()?),
+        9 => ArrayNewData(r.read_u32()?, r.read_u32()?),
+        10 => ArrayNewElem(r.read_u32()?, r.read_u32()?),
+        11 => ArrayGet(r.read_u32()?),
+        12 => ArrayGetS(r.read_u32()?),
+        13 => ArrayGetU(r.read_u32()?),
+        14 => ArraySet(r.read_u32()?),
+        15 => ArrayLen,
+        16 => ArrayFill(r.read_u32()?),
+        17 => ArrayCopy(r.read_u32()?, r.read_u32()?),
+        18 => ArrayInitData(r.read_u32()?, r.read_u32()?),
+        19 => ArrayInitElem(r.read_u32()?, r.read_u32()?),
+        20 => RefTest(RefType::non_null(r.read_heap_type()?)),
+        21 => RefTest(RefType::null(r.read_heap_type()?)),
+        22 => RefCast(RefType::non_null(r.read_heap_type()?)),
+        23 => RefCast(RefType::null(r.read_heap_type()?)),
+        // the bits of the flags tell whether the types are nullable
+        24 | 25 => {
+            let flags = r.read_byte()?;
+            if flags > 3 {
+                bail!("invalid flags of br_on_cast {:#x}", flags)
+            }
+            let label = r.read_u32()?;
+            let from = RefType {
+                nullable: flags & 1 != 0,
+                heap: r.read_heap_type()?,
+            };
+            let to = RefType {
+                nullable: flags & 2 != 0,
+                heap: r.read_heap_type()?,
+            };
+            if op == 24 {
+                BrOnCast(label, from, to)
+            } else {
+                BrOnCastFail(label, from, to)
+            }
+        }
+        26 => AnyConvertExtern,
+        27 => ExternConvertAny,
+        28 => RefI31,
+        29 => I31GetS,
+        30 => I31GetU,
+        _ => bail!("0xFB {} is undefined instruction.", op),
+    })
+}
+
+type FactoryMethod = fn(reader: &mut dyn WasmModuleBinaryRead) -> Result<Instruction>;
 fn choose_inst_factory(b: u8) -> Result<FactoryMethod> {
     Ok(match b {
         //Control Instructions
         0x00 => |_| Ok(Unreachable),
         0x01 => |_| Ok(Nop),
-        0x02 => |r| {
-            let block = block::Block::try_from(r)?;
-            Ok(Block(block.block_type, block.first))
-        },
-        0x03 => |r| {
-            let block = block::Block::try_from(r)?;
-            Ok(Loop(block.block_type, block.first))
-        },
-        0x04 => |r| {
-            let block = block::Block::try_from(r)?;
-            Ok(If(block.block_type, block.first, block.second))
+        0x05 => |_| Ok(Else),
+        #[cfg(feature = "exceptions")]
+        0x08 => |r| Ok(Throw(r.read_u32()?)),
+        #[cfg(feature = "exceptions")]
+        0x0A => |_| Ok(ThrowRef),
+        #[cfg(not(feature = "exceptions"))]
+        0x08 | 0x0A | 0x1F => |_| {
+            bail!(
+                "feature not enabled: {}",
+                crate::features::Feature::Exceptions
+            )
         },
         0x0C => |r| Ok(Br(r.read_u32()?)),
         0x0D => |r| Ok(BrIf(r.read_u32()?)),
@@ -39,17 +311,25 @@ fn choose_inst_factory(b: u8) -> Result<FactoryMethod> {
         },
         0x0F => |_| Ok(Return),
         0x10 => |r| Ok(Call(r.read_u32()?)),
-        0x11 => |r| Ok(CallIndirect(r.read_u32()?, r.read_u32()?)),
-        //[Reference Instructions]
-        0xD0 => |r| {
-            if let ValType::Ref(r) = ValType::try_from(r.read_byte()?)? {
-                Ok(RefNull(r))
-            } else {
-                bail!("the byte is not RefType.")
-            }
+        0x11 => |r| {
+            let type_idx = r.read_u32()?;
+            Ok(CallIndirect(r.read_u32()?, type_idx))
+        },
+        0x12 => |r| Ok(ReturnCall(r.read_u32()?)),
+        0x13 => |r| {
+            let type_idx = r.read_u32()?;
+            Ok(ReturnCallIndirect(r.read_u32()?, type_idx))
         },
+        0x14 => |r| Ok(CallRef(r.read_u32()?)),
+        0x15 => |r| Ok(ReturnCallRef(r.read_u32()?)),
+        0xD5 => |r| Ok(BrOnNull(r.read_u32()?)),
+        0xD6 => |r| Ok(BrOnNonNull(r.read_u32()?)),
+        //[Reference Instructions]
+        0xD0 => |r| Ok(RefNull(r.read_heap_type()?)),
         0xD1 => |_| Ok(RefIsNull),
         0xD2 => |r| Ok(RefFunc(r.read_u32()?)),
+        0xD3 => |_| Ok(RefEq),
+        0xD4 => |_| Ok(RefAsNonNull),
         //Parametric Instructions
         0x1A => |_| Ok(Drop),
         0x1B => |_| Ok(Select(None)),
@@ -57,7 +337,7 @@ fn choose_inst_factory(b: u8) -> Result<FactoryMethod> {
             let len = r.read_u32()?;
             let mut valtypes = Vec::<ValType>::new();
             for _ in 0..len {
-                valtypes.push(ValType::try_from(r.read_byte()?)?);
+                valtypes.push(r.read_val_type()?);
             }
             Ok(Select(Some(valtypes)))
         },
@@ -67,15 +347,86 @@ fn choose_inst_factory(b: u8) -> Result<FactoryMethod> {
         0x22 => |r| Ok(LocalTee(r.read_u32()?)),
         0x23 => |r| Ok(GlobalGet(r.read_u32()?)),
         0x24 => |r| Ok(GlobalSet(r.read_u32()?)),
+        //Table Instructions
+        0x25 => |r| Ok(TableGet(r.read_u32()?)),
+        0x26 => |r| Ok(TableSet(r.read_u32()?)),
+        //Memory Instructions
+        0x3F => |r| Ok(MemorySize(r.read_u32()?)),
+        0x40 => |r| Ok(MemoryGrow(r.read_u32()?)),
+        0xFC => |r| {
+            let op = r.read_u32()?;
+            Ok(match op {
+                8 => {
+                    let data = r.read_u32()?;
+                    MemoryInit(data, r.read_u32()?)
+                }
+                9 => DataDrop(r.read_u32()?),
+                10 => MemoryCopy(r.read_u32()?, r.read_u32()?),
+                11 => MemoryFill(r.read_u32()?),
+                12 => {
+                    let elem = r.read_u32()?;
+                    TableInit(elem, r.read_u32()?)
+                }
+                13 => TableDrop(r.read_u32()?),
+                14 => TableCopy(r.read_u32()?, r.read_u32()?),
+                15 => TableGrow(r.read_u32()?),
+                16 => TableSize(r.read_u32()?),
+                17 => TableFill(r.read_u32()?),
+                _ => bail!("0xFC {} is undefined instruction.", op),
+            })
+        },
         //Numeric Instructions
         0x41 => |r| Ok(I32Const(r.read_i32()?)),
+        0x42 => |r| Ok(I64Const(r.read_i64()?)),
+        0x45 => |_| Ok(I32Eqz),
+        0x46 => |_| Ok(I32Eq),
+        0x47 => |_| Ok(I32Ne),
+        0x48 => |_| Ok(I32LtS),
+        0x49 => |_| Ok(I32LtU),
+        0x4A => |_| Ok(I32GtS),
+        0x4B => |_| Ok(I32GtU),
+        0x4C => |_| Ok(I32LeS),
+        0x4D => |_| Ok(I32LeU),
+        0x4E => |_| Ok(I32GeS),
+        0x4F => |_| Ok(I32GeU),
+        0x67 => |_| Ok(I32Clz),
+        0x68 => |_| Ok(I32Ctz),
+        0x69 => |_| Ok(I32Popcnt),
         0x6A => |_| Ok(I32Add),
         0x6B => |_| Ok(I32Sub),
         0x6C => |_| Ok(I32Mul),
         0x6D => |_| Ok(I32DivS),
-        0x6F => |_| Ok(I32DivU),
+        0x6E => |_| Ok(I32DivU),
+        0x6F => |_| Ok(I32RemS),
+        0x70 => |_| Ok(I32RemU),
         0x71 => |_| Ok(I32And),
         0x72 => |_| Ok(I32Or),
+        0x73 => |_| Ok(I32Xor),
+        0x74 => |_| Ok(I32ShL),
+        0x75 => |_| Ok(I32ShrS),
+        0x76 => |_| Ok(I32ShrU),
+        0x77 => |_| Ok(I32RtoL),
+        0x78 => |_| Ok(I32RtoR),
+        0x7C => |_| Ok(I64Add),
+        0x7D => |_| Ok(I64Sub),
+        0x7E => |_| Ok(I64Mul),
+        0xC0 => |_| Ok(I32Extend8S),
+        0xC1 => |_| Ok(I32Extend16S),
+        //Aggregate Instructions
+        #[cfg(feature = "gc")]
+        0xFB => read_gc_instruction,
+        #[cfg(not(feature = "gc"))]
+        0xFB => |_| bail!("feature not enabled: {}", crate::features::Feature::Gc),
+        //Vector Instructions
+        #[cfg(feature = "simd")]
+        0xFD => read_vector_instruction,
+        #[cfg(not(feature = "simd"))]
+        0xFD => |_| bail!("feature not enabled: {}", crate::features::Feature::Simd),
+        //Atomic Memory Instructions
+        #[cfg(feature = "threads")]
+        0xFE => read_atomic_instruction,
+        #[cfg(not(feature = "threads"))]
+        0xFE => |_| bail!("feature not enabled: {}", crate::features::Feature::Threads),
         0x0B => |_| Ok(End),
         _ => bail!("{:#X} is undefined instruction.", b),
     })
@@ -83,12 +434,28 @@ fn choose_inst_factory(b: u8) -> Result<FactoryMethod> {
 
 #[cfg(test)]
 mod tests {
-    use crate::structure::instructions::{BlockType, Instruction};
+    use crate::structure::{
+        instructions::{BlockType, Instruction},
+        types::{NumType, ValType},
+    };
+
+    #[test]
+    fn read_expr() {
+        use super::ExprRead;
+        //Given
+        let bytes = [0x41u8, 0x0B, 0x0B, 0x01];
+        let mut reader = &bytes[..];
+        //When
+        let expr = reader.read_expr().unwrap();
+        //Then
+        assert_eq!(expr, vec![Instruction::I32Const(11)]);
+        assert_eq!(reader, &[0x01]);
+    }
 
     #[test]
     fn decode_instructions() {
         assert_eq!(
-            super::decode_instructions(vec![0x20u8, 0x44, 0x20, 0x33, 0x6A]).unwrap(),
+            super::decode_instructions(&[0x20u8, 0x44, 0x20, 0x33, 0x6A]).unwrap(),
             vec![
                 Instruction::LocalGet(68),
                 Instruction::LocalGet(51),
@@ -97,7 +464,7 @@ mod tests {
         );
 
         assert_eq!(
-            super::decode_instructions(vec![
+            super::decode_instructions(&[
                 0x6Au8, 0x04, 0xA1, 0x86, 0x15, 0x6B, 0x6C, 0x05, 0x71, 0x72, 0x0B
             ])
             .unwrap(),
@@ -112,199 +479,88 @@ mod tests {
         );
 
         assert_eq!(
-            super::decode_instructions(vec![0x0Eu8, 0x03, 0x01, 0x02, 0x03, 0x0F]).unwrap(),
+            super::decode_instructions(&[0x0Eu8, 0x03, 0x01, 0x02, 0x03, 0x0F]).unwrap(),
             vec![Instruction::BrTable(vec![0x01, 0x02, 0x03], 0x0F)]
         );
     }
-}
-
-mod block {
-    use std::io::Cursor;
-
-    use crate::{
-        binary::decode::WasmModuleBinaryRead,
-        structure::{
-            instructions::{BlockType, Instruction},
-            types::ValType,
-        },
-    };
-    use anyhow::*;
-
-    /// Block structure for Control instructions
-    /// https://webassembly.github.io/spec/core/binary/instructions.html#control-instructions
-    pub struct Block {
-        pub block_type: BlockType,
-        pub first: Vec<Instruction>,
-        pub second: Option<Vec<Instruction>>,
-    }
-    impl TryFrom<&mut Box<dyn WasmModuleBinaryRead>> for Block {
-        type Error = anyhow::Error;
 
-        fn try_from(reader: &mut Box<dyn WasmModuleBinaryRead>) -> Result<Self> {
-            let (first_bytes, second_bytes) = reader.read_and_split_else()?;
-            let block_type = BlockType::try_from(&first_bytes[..])?;
-            let first_bytes = first_bytes[block_type.bytes_len()..].to_vec();
-            Ok(Self {
-                block_type,
-                first: super::decode_instructions(first_bytes)?,
-                second: second_bytes.map(super::decode_instructions).transpose()?,
-            })
-        }
-    }
-
-    /// NewType to Vec<Instruction> try_from
-    pub struct InstructionArrayWrapper(pub Vec<Instruction>);
-    impl TryFrom<Vec<u8>> for InstructionArrayWrapper {
-        type Error = anyhow::Error;
-
-        fn try_from(bytes: Vec<u8>) -> Result<Self> {
-            let mut r: Box<dyn WasmModuleBinaryRead> = Box::new(Cursor::new(bytes));
-            let mut inst_array = Vec::<Instruction>::new();
-            while r.has_next()? {
-                let b = r.read_byte()?;
-                let factory_method = super::choose_inst_factory(b)?;
-                let inst = factory_method(&mut r)?;
-                if inst == Instruction::End {
-                    break;
-                }
-                inst_array.push(inst);
-            }
-            Ok(Self(inst_array))
-        }
-    }
-
-    /// Extensions methods to decode Block parts
-    trait BlockInstRead: WasmModuleBinaryRead {
-        /// read "^(...)0x0B"
-        fn read_until_end_marker(&mut self) -> Result<Vec<u8>> {
-            let mut buf = Vec::<u8>::new();
-            let _ = self.read_until(0x0B, &mut buf)?;
-            let _ = buf.remove(buf.len() - 1);
-            Ok(buf)
-        }
-
-        /// read "^(...)0x05(...)0x0B"
-        fn read_and_split_else(&mut self) -> Result<(Vec<u8>, Option<Vec<u8>>)> {
-            let bytes = self.read_until_end_marker()?;
-            let mut iter = bytes.split(|b| b == &0x05);
-            Ok((
-                iter.next()
-                    .context("First block must not be empty.")?
-                    .to_vec(),
-                iter.next().map(|val| val.to_vec()),
-            ))
-        }
+    #[test]
+    fn decode_instructions_after_end() {
+        // unreachable end nop end
+        let err = super::decode_instructions(&[0x00u8, 0x0B, 0x01, 0x0B]).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "instructions follow the end of the expression: 2 bytes remain unread"
+        );
+        assert!(super::decode_instructions(&[0x00u8, 0x0B]).is_ok());
     }
-    impl<R: WasmModuleBinaryRead> BlockInstRead for R {}
 
-    impl TryFrom<&[u8]> for BlockType {
-        type Error = anyhow::Error;
-
-        fn try_from(mut bytes: &[u8]) -> Result<Self> {
-            let b = bytes[0];
-            if b == 0x40 {
-                Ok(Self::Empty)
-            } else if let Result::Ok(v) = ValType::try_from(b) {
-                Ok(Self::ValType(v))
-            } else {
-                Ok(Self::TypeIdx(bytes.read_u32()?))
-            }
-        }
+    #[test]
+    fn decode_nested_blocks() {
+        // block (loop (i32.const 11) end) end
+        assert_eq!(
+            super::decode_instructions(&[0x02u8, 0x40, 0x03, 0x7F, 0x41, 0x0B, 0x0B, 0x0B, 0x01])
+                .unwrap(),
+            vec![
+                Instruction::Block(
+                    BlockType::Empty,
+                    vec![Instruction::Loop(
+                        BlockType::ValType(ValType::Number(NumType::I32)),
+                        vec![Instruction::I32Const(11)]
+                    )]
+                ),
+                Instruction::Nop
+            ]
+        );
+        // else outside of if
+        assert!(super::decode_instructions(&[0x02u8, 0x40, 0x05, 0x0B]).is_err());
+        // missing end
+        assert!(super::decode_instructions(&[0x02u8, 0x40, 0x01]).is_err());
     }
 
-    impl BlockType {
-        pub fn bytes_len(&self) -> usize {
-            match self {
-                Self::Empty => 1,
-                Self::ValType(_) => 1,
-                Self::TypeIdx(n) => num_of_leb128_bytes(*n),
-            }
-        }
+    #[test]
+    #[cfg(not(feature = "simd"))]
+    fn decode_compiled_out_instructions() {
+        // v128.const
+        let err = super::decode_instructions(&[0xFDu8, 0x0C]).unwrap_err();
+        assert_eq!(err.to_string(), "feature not enabled: simd");
     }
 
-    fn num_of_leb128_bytes(mut n: u32) -> usize {
-        if n == 0 {
-            1
-        } else {
-            let mut i = 0;
-            while n > 0 {
-                n /= 128; //means 2^7
-                i += 1;
-            }
-            i
-        }
+    #[test]
+    fn decode_deeply_nested_blocks() {
+        // blocks nested as deeply as allowed, and one more
+        let nested = |n: usize| [[0x02u8, 0x40].repeat(n), vec![0x0Bu8; n]].concat();
+        assert!(super::decode_instructions(&nested(super::MAX_NESTING)).is_ok());
+        let err = super::decode_instructions(&nested(super::MAX_NESTING + 1)).unwrap_err();
+        assert_eq!(err.to_string(), "blocks are nested deeper than 256");
+        // far more blocks than the stack can hold are rejected as well
+        assert!(super::decode_instructions(&nested(200_000)).is_err());
     }
 
-    #[cfg(test)]
-    mod tests {
-        use crate::structure::{
-            instructions::{BlockType, Instruction},
-            types::{NumType, ValType},
-        };
-
-        use super::{num_of_leb128_bytes, BlockInstRead};
-
-        #[test]
-        fn block_type_try_from() {
-            let bytes = vec![0x40u8, 0x01, 0x02];
-            assert_eq!(BlockType::try_from(&bytes[..]).unwrap(), BlockType::Empty);
-
-            let bytes = vec![0x7Du8, 0x01, 0x02];
-            assert_eq!(
-                BlockType::try_from(&bytes[..]).unwrap(),
-                BlockType::ValType(ValType::Number(NumType::F32))
-            );
-
-            let bytes = vec![0xA1_u8, 0x86, 0x15];
-            assert_eq!(
-                BlockType::try_from(&bytes[..]).unwrap(),
-                BlockType::TypeIdx(344865)
-            );
-        }
-
-        #[test]
-        fn instructions_try_from() {
-            let bytes = vec![0x20u8, 0x44, 0x20, 0x33, 0x6A];
-            let super::InstructionArrayWrapper(insts) =
-                super::InstructionArrayWrapper::try_from(bytes).unwrap();
+    #[test]
+    fn read_block_type() {
+        use super::read_block_type;
 
-            assert_eq!(
-                insts,
-                vec![
-                    Instruction::LocalGet(68),
-                    Instruction::LocalGet(51),
-                    Instruction::I32Add
-                ]
-            );
-        }
+        let bytes = [0x40u8, 0x01, 0x02];
+        assert_eq!(read_block_type(&mut &bytes[..]).unwrap(), BlockType::Empty);
 
-        #[test]
-        fn block_inst_read() {
-            //Given
-            let bytes = vec![0xA1_u8, 0x86, 0x15, 0x05, 0x01, 0x02, 0x0b];
-            //When
-            let (first, second) = (&bytes[..]).read_and_split_else().unwrap();
-            //Then
-            assert_eq!(first, vec![0xA1, 0x86, 0x15]);
-            assert_eq!(second, Some(vec![0x01, 0x02]));
+        let bytes = [0x7Du8, 0x01, 0x02];
+        assert_eq!(
+            read_block_type(&mut &bytes[..]).unwrap(),
+            BlockType::ValType(ValType::Number(NumType::F32))
+        );
 
-            //Given
-            let bytes = vec![0xA1_u8, 0x86, 0x15, 0x0b];
-            //When
-            let (first, second) = (&bytes[..]).read_and_split_else().unwrap();
-            //Then
-            assert_eq!(first, vec![0xA1, 0x86, 0x15]);
-            assert_eq!(second, None);
-        }
+        let bytes = [0xA1_u8, 0x86, 0x15];
+        assert_eq!(
+            read_block_type(&mut &bytes[..]).unwrap(),
+            BlockType::TypeIdx(344865)
+        );
 
-        #[test]
-        fn calc_num_of_leb128_byte() {
-            assert_eq!(num_of_leb128_bytes(0), 1);
-            assert_eq!(num_of_leb128_bytes(1), 1);
-            assert_eq!(num_of_leb128_bytes(127), 1);
-            assert_eq!(num_of_leb128_bytes(128), 2);
-            assert_eq!(num_of_leb128_bytes(512), 2);
-            assert_eq!(num_of_leb128_bytes(16384), 3);
-        }
+        // 64 is encoded in 2 bytes since it is signed
+        let bytes = [0xC0_u8, 0x00];
+        assert_eq!(
+            read_block_type(&mut &bytes[..]).unwrap(),
+            BlockType::TypeIdx(64)
+        );
     }
 }
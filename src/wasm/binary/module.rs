@@ -1,23 +1,57 @@
+pub mod parser;
 mod section;
 
 use anyhow::*;
 
-use crate::structure::module::{Func, Module};
+use crate::features::WasmFeatures;
+use crate::structure::module::{Custom, Func, ImportDesc, Module, Names};
 
+use self::parser::{Parser, Payload};
 use self::section::Sections;
 
 use super::decode::WasmModuleBinaryRead;
 use section::ModuleSectionRead;
 
 /// decode binary read to Module
-pub fn decode(reader: &mut impl WasmModuleBinaryRead) -> Result<Module> {
-    let (version, sections) = (reader.decode_header()?, reader.decode_sections()?);
-    Module::try_from((version, sections))
+pub fn decode(reader: &mut impl WasmModuleBinaryRead) -> Result<Module<'static>> {
+    decode_with_features(reader, &WasmFeatures::default())
+}
+
+/// decode binary read to Module, rejecting proposals not enabled in `features`
+pub fn decode_with_features(
+    reader: &mut impl WasmModuleBinaryRead,
+    features: &WasmFeatures,
+) -> Result<Module<'static>> {
+    let (version, sections) = (reader.decode_header()?, reader.decode_sections(features)?);
+    let module = Module::try_from((version, sections))?;
+    features.check_module(&module)?;
+    Ok(module)
+}
+
+/// decode a module in memory such as a memory-mapped file without copying each section,
+/// where function bodies, data segments, names of imports and exports, and custom sections
+/// borrow from `bytes`
+pub fn decode_slice(bytes: &[u8]) -> Result<Module<'_>> {
+    decode_slice_with_features(bytes, &WasmFeatures::default())
+}
+
+pub fn decode_slice_with_features<'a>(
+    bytes: &'a [u8],
+    features: &WasmFeatures,
+) -> Result<Module<'a>> {
+    let mut parser = Parser::from_slice(bytes);
+    let version = match parser.next().transpose()? {
+        Some(Payload::Header(version)) => version,
+        _ => bail!("the module must begin with its header"),
+    };
+    let module = Module::try_from((version, Sections::collect(parser, features)?))?;
+    features.check_module(&module)?;
+    Ok(module)
 }
 
 // decode header
 const MAGIC_NUMBER: &[u8] = b"\0asm";
-type Version = u32;
+pub type Version = u32;
 trait ModuleHeaderRead {
     fn decode_header(&mut self) -> Result<Version>;
 }
@@ -37,31 +71,90 @@ impl<R: WasmModuleBinaryRead> ModuleHeaderRead for R {
     }
 }
 
-impl TryFrom<(Version, Sections)> for Module {
+impl<'a> TryFrom<(Version, Sections<'a>)> for Module<'a> {
     type Error = anyhow::Error;
 
-    fn try_from(value: (Version, Sections)) -> Result<Self, Self::Error> {
+    fn try_from(value: (Version, Sections<'a>)) -> Result<Self, Self::Error> {
         let (version, sections) = value;
+        if let Some(count) = sections.data_count_section {
+            if count as usize != sections.data_section.len() {
+                bail!("data_count_section should equal to data_section.len()")
+            }
+        }
+        let names = decode_names(&sections.custom_sections);
+        let num_of_imported_funcs = sections
+            .import_section
+            .iter()
+            .filter(|import| matches!(import.desc, ImportDesc::Func(_)))
+            .count();
+        let funcs = try_merge_to_funcs(sections.function_section, sections.code_section)?;
+        let rec_groups = sections
+            .type_section
+            .iter()
+            .map(|group| group.len() as u32)
+            .collect();
+        let types: Vec<_> = sections.type_section.into_iter().flatten().collect();
+        for (i, func) in funcs.iter().enumerate() {
+            if func.type_ as usize >= types.len() {
+                bail!(
+                    "{} refers to {} which is not defined",
+                    names.func((num_of_imported_funcs + i) as u32),
+                    names.type_(func.type_)
+                )
+            }
+        }
+        if let Some(start) = &sections.start_section {
+            if start.func as usize >= num_of_imported_funcs + funcs.len() {
+                bail!("start function {} is not defined", names.func(start.func))
+            }
+        }
         let module = Module {
             version,
-            types: sections.type_section,
-            funcs: try_merge_to_funcs(sections.function_section, sections.code_section)?,
+            types,
+            rec_groups,
+            funcs,
+            tables: sections.table_section,
+            mems: sections.memory_section,
+            tags: sections.tag_section,
+            globals: sections.global_section,
+            elems: sections.element_section,
+            datas: sections.data_section,
+            data_count: sections.data_count_section,
+            start: sections.start_section,
+            imports: sections.import_section,
             exports: sections.export_section,
+            customs: sections.custom_sections,
+            names,
         };
         Ok(module)
     }
 }
 
-fn try_merge_to_funcs(
+/// A malformed name section does not invalidate the module, so its names are just dropped.
+/// https://webassembly.github.io/spec/core/appendix/custom.html#custom-sections
+fn decode_names(customs: &[Custom]) -> Names {
+    customs
+        .iter()
+        .find(|custom| custom.name == section::NAME_SECTION)
+        .map(|custom| {
+            section::decode_names(&custom.data).unwrap_or_else(|e| {
+                log::warn!("name section is ignored: {}", e);
+                Names::default()
+            })
+        })
+        .unwrap_or_default()
+}
+
+fn try_merge_to_funcs<'a>(
     function_section: section::FunctionContent,
-    code_section: section::CodeContent,
-) -> Result<Vec<Func>> {
+    code_section: section::CodeContent<'a>,
+) -> Result<Vec<Func<'a>>> {
     if code_section.len() != function_section.len() {
         bail!("code_section.len() should equal to function_section.len()")
     }
     let funcs: Vec<Func> = code_section
         .into_iter()
-        .zip(function_section.into_iter())
+        .zip(function_section)
         .map(|(code, idx)| Func {
             type_: idx,
             locals: code.locals,
@@ -120,7 +213,8 @@ mod tests {
                     ValType::Number(NumType::I32)
                 ]),
                 ResultType(vec![ValType::Number(NumType::I32)])
-            )]
+            )
+            .into()]
         );
         assert_eq!(
             module.funcs,
@@ -132,9 +226,236 @@ mod tests {
                     Instruction::LocalGet(1),
                     Instruction::I32Add
                 ]
+                .into()
             }]
         );
 
         Ok(())
     }
+
+    #[test]
+    fn decode_slice() -> Result<()> {
+        //Given
+        let wat = br#"(module
+            (import "env" "g" (global i32))
+            (memory 1)
+            (func $add (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+            (export "add" (func $add))
+            (data (i32.const 0) "abc")
+        )"#;
+        let wasm = wasmer::wat2wasm(wat)?;
+        //When
+        let module = super::decode_slice(&wasm)?;
+        //Then
+        assert_eq!(module, super::decode(&mut &wasm[..])?);
+        assert_eq!(module.names.func(0), "$add");
+        // the body, the data, the names and the name section are borrowed from the binary
+        let input = wasm.as_ptr_range();
+        assert!(input.contains(&module.funcs[0].body.bytes().as_ptr()));
+        assert!(input.contains(&module.datas[0].init.as_ptr()));
+        assert!(input.contains(&module.imports[0].module.as_ptr()));
+        assert!(input.contains(&module.exports[0].name.as_ptr()));
+        assert!(module
+            .customs
+            .iter()
+            .all(|custom| input.contains(&custom.data.as_ptr())));
+        assert!(!module.customs.is_empty());
+        assert!(super::decode_slice(&wasm[..4]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_memory_and_data() -> Result<()> {
+        use crate::structure::{
+            instructions::Instruction,
+            module::{Data, DataMode, Mem},
+            types::{IndexType, Limits, MemType, Share},
+        };
+        //Given
+        let wat = br#"(module
+            (memory 1 2)
+            (data (i32.const 16) "abc")
+        )"#;
+        let mut reader = test_util::wasm_reader(wat);
+        //when
+        let module = super::decode(&mut reader)?;
+        //then
+        assert_eq!(
+            module.mems,
+            vec![Mem {
+                type_: MemType(
+                    Limits {
+                        min: 1,
+                        max: Some(2),
+                        index: IndexType::I32,
+                    },
+                    Share::Unshared
+                )
+            }]
+        );
+        assert_eq!(
+            module.datas,
+            vec![Data {
+                init: b"abc"[..].into(),
+                mode: DataMode::Active {
+                    memory: 0,
+                    offset: vec![Instruction::I32Const(16)]
+                }
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_names() -> Result<()> {
+        //Given
+        let wat = br#"(module $m
+            (func $i32.add (param $lhs i32) (param $rhs i32) (result i32)
+                local.get $lhs
+                local.get $rhs
+                i32.add
+            )
+        )"#;
+        let mut reader = test_util::wasm_reader(wat);
+        //when
+        let module = super::decode(&mut reader)?;
+        //then
+        assert_eq!(module.names.module, Some("m".to_string()));
+        assert_eq!(module.names.func(0), "$i32.add");
+        assert_eq!(module.names.local(0, 1), "$rhs");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_undefined_type_shows_name() {
+        let bytes = [
+            &b"\0asm\x01\0\0\0"[..],
+            // Function section refers to type 0 while no Type section exists
+            &[0x03, 0x02, 0x01, 0x00],
+            &[0x0A, 0x04, 0x01, 0x02, 0x00, 0x0B],
+            // name section giving "f" to func 0
+            &[0x00, 0x0B, 0x04],
+            b"name",
+            &[0x01, 0x04, 0x01, 0x00, 0x01, 0x66],
+        ]
+        .concat();
+        let err = super::decode(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.to_string(), "$f refers to type[0] which is not defined");
+    }
+
+    #[test]
+    fn decode_bodies_lazily() -> Result<()> {
+        use crate::structure::instructions::Instruction;
+        //Given
+        let wat = br#"(module
+            (func $one (result i32) i32.const 1)
+            (func $two (result i32) i32.const 2)
+        )"#;
+        let mut wasm = wasmer::wat2wasm(wat)?.to_vec();
+        // replace `i32.const 2` with an undefined instruction
+        let pos = wasm
+            .windows(3)
+            .position(|w| w == [0x41, 0x02, 0x0B])
+            .unwrap();
+        wasm[pos] = 0xFF;
+        //When
+        let module = super::decode(&mut &wasm[..])?;
+        //Then
+        assert!(!module.funcs[0].body.is_decoded());
+        assert_eq!(
+            module.funcs[0].body.expr()?,
+            &vec![Instruction::I32Const(1)]
+        );
+        assert!(module.funcs[0].body.is_decoded());
+        let range = module.funcs[1].body.range().unwrap();
+        assert_eq!(&wasm[range.clone()], module.funcs[1].body.bytes());
+        // errors are kept as the decoder returned them
+        let chain = |err: anyhow::Error| err.chain().map(|e| e.to_string()).collect::<Vec<_>>();
+        let expected =
+            chain(crate::binary::instructions::decode_instructions(&wasm[range]).unwrap_err());
+        assert_eq!(chain(module.funcs[1].body.expr().unwrap_err()), expected);
+        assert_eq!(chain(module.funcs[1].body.expr().unwrap_err()), expected);
+        // comparing bodies doesn't decode them
+        let other = super::decode(&mut &wasm[..])?;
+        assert_eq!(module.funcs, other.funcs);
+        assert!(!other.funcs[1].body.is_decoded());
+        let errors = module.decode_bodies();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_bodies_parallel() -> Result<()> {
+        use crate::structure::instructions::Instruction;
+        //Given
+        let funcs: String = (0..50)
+            .map(|i| {
+                format!(
+                    "(func (result i32) (i32.add (i32.const {}) (i32.const 1)))",
+                    i
+                )
+            })
+            .collect();
+        let wasm = wasmer::wat2wasm(format!("(module {})", funcs).as_bytes())?.to_vec();
+        let serial = super::decode(&mut &wasm[..])?;
+        let parallel = super::decode(&mut &wasm[..])?;
+        let default = super::decode(&mut &wasm[..])?;
+        //When
+        let serial_errors = serial.decode_bodies();
+        let parallel_errors = parallel.decode_bodies_parallel(Some(4));
+        let default_errors = default.decode_bodies_parallel(None);
+        //Then
+        assert!(
+            serial_errors.is_empty() && parallel_errors.is_empty() && default_errors.is_empty()
+        );
+        // every body has been cached by the passes before `expr` is called
+        assert!(serial.funcs.iter().all(|f| f.body.is_decoded()));
+        assert!(parallel.funcs.iter().all(|f| f.body.is_decoded()));
+        assert!(default.funcs.iter().all(|f| f.body.is_decoded()));
+        for i in 0..serial.funcs.len() {
+            let expected = serial.funcs[i].body.expr()?;
+            assert_eq!(parallel.funcs[i].body.expr()?, expected);
+            assert_eq!(default.funcs[i].body.expr()?, expected);
+        }
+        assert_eq!(
+            serial.funcs[7].body.expr()?,
+            &vec![
+                Instruction::I32Const(7),
+                Instruction::I32Const(1),
+                Instruction::I32Add
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_body_with_instructions_after_end() {
+        //Given
+        let bytes = [
+            &b"\0asm\x01\0\0\0"[..],
+            &[0x01, 0x04, 0x01, 0x60, 0x00, 0x00],
+            &[0x03, 0x02, 0x01, 0x00],
+            // unreachable end nop end
+            &[0x0A, 0x07, 0x01, 0x05, 0x00, 0x00, 0x0B, 0x01, 0x0B],
+        ]
+        .concat();
+        //When
+        let module = super::decode_slice(&bytes).unwrap();
+        //Then
+        let errors = module.decode_bodies();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].1.to_string(),
+            "instructions follow the end of the expression"
+        );
+    }
+
+    #[test]
+    fn decode_data_count_mismatch() {
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+        // DataCount section declaring 1 segment without Data section
+        bytes.extend([0x0C, 0x01, 0x01]);
+        assert!(super::decode(&mut &bytes[..]).is_err());
+    }
 }
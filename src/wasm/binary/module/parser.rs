@@ -0,0 +1,435 @@
+use std::borrow::Cow;
+use std::io::{BufRead, Read};
+use std::ops::Range;
+
+use anyhow::*;
+
+use super::section::{self, check_section_order, decode_section_header};
+use super::{ModuleHeaderRead, Version};
+use crate::{
+    binary::decode::WasmModuleBinaryRead, features::WasmFeatures, structure::module::Custom,
+};
+
+pub use super::section::{Code, SectionContent, SectionID};
+
+/// An item of a module yielded by [`Parser`] in order of appearance.
+/// Ranges are byte offsets from the beginning of the module.
+/// Bytes borrow from the input when it is parsed by [`Parser::from_slice`].
+#[derive(Debug, PartialEq)]
+pub enum Payload<'a> {
+    Header(Version),
+    /// a non-custom section other than the code section, not decoded yet
+    Section(Section<'a>),
+    CustomSection(CustomSection<'a>),
+    /// followed by `count` CodeEntry payloads
+    CodeSectionStart {
+        count: u32,
+        range: Range<usize>,
+    },
+    CodeEntry(FunctionBody<'a>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Section<'a> {
+    pub id: SectionID,
+    /// the range of the section content, excluding its id and size
+    pub range: Range<usize>,
+    pub content: Cow<'a, [u8]>,
+}
+
+impl<'a> Section<'a> {
+    /// the names and data segments keep borrowing from the input
+    pub fn decode(&self) -> Result<SectionContent<'a>> {
+        match &self.content {
+            Cow::Borrowed(bytes) => SectionContent::decode(self.id, bytes),
+            Cow::Owned(bytes) => SectionContent::decode(self.id, bytes).map(|c| c.into_owned()),
+        }
+        .with_context(|| format!("failed to decode {:?} section", self.id))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CustomSection<'a> {
+    pub name: Cow<'a, str>,
+    pub data: Cow<'a, [u8]>,
+    /// id of the last non-custom section preceding this one, None if it precedes all of them
+    pub after: Option<u8>,
+    /// the range of the section content, excluding its id and size
+    pub range: Range<usize>,
+}
+
+impl<'a> CustomSection<'a> {
+    /// the custom section of a module, which keeps borrowing from the input
+    pub fn into_custom(self) -> Custom<'a> {
+        Custom {
+            name: self.name,
+            data: self.data,
+            after: self.after,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FunctionBody<'a> {
+    /// index of the body in the code section, which excludes imported functions
+    pub index: u32,
+    /// the range of the body, excluding its size
+    pub range: Range<usize>,
+    pub bytes: Cow<'a, [u8]>,
+}
+
+impl<'a> FunctionBody<'a> {
+    /// decode the locals, the instructions are decoded on first use of `Code::expr`
+    pub fn decode(self) -> Result<Code<'a>> {
+        self.decode_with_features(&WasmFeatures::default())
+    }
+
+    /// the instructions keep borrowing from the input
+    pub fn decode_with_features(self, features: &WasmFeatures) -> Result<Code<'a>> {
+        Code::decode_lazily(self.bytes, self.range, features)
+            .with_context(|| format!("failed to decode function body {}", self.index))
+    }
+}
+
+/// Pull parser yielding a module piece by piece without building a whole `Module`
+///
+/// ```
+/// use chibiwasm::binary::module::parser::{Parser, Payload};
+///
+/// let wasm = b"\0asm\x01\0\0\0\x01\x04\x01\x60\x00\x00";
+/// for payload in Parser::from_slice(wasm) {
+///     if let Payload::Section(section) = payload.unwrap() {
+///         println!("{:?} at {:?}", section.id, section.range);
+///     }
+/// }
+/// ```
+pub struct Parser<'a, R: WasmModuleBinaryRead> {
+    input: Input<'a, R>,
+    state: State,
+    last_id: Option<SectionID>,
+}
+
+enum State {
+    Header,
+    Sections,
+    Code { index: u32, count: u32, end: usize },
+    Done,
+}
+
+impl<R: WasmModuleBinaryRead> Parser<'static, R> {
+    /// parse a reader, copying each payload out of it
+    pub fn new(reader: R) -> Self {
+        Self::with_input(Input::Reader { reader, pos: 0 }, State::Header)
+    }
+
+    /// start with the first section of a reader whose header has already been read
+    pub(super) fn after_header(reader: R) -> Self {
+        Self::with_input(Input::Reader { reader, pos: 8 }, State::Sections)
+    }
+}
+
+impl<'a> Parser<'a, &'a [u8]> {
+    /// parse a module in memory, whose payloads borrow from `bytes`
+    pub fn from_slice(bytes: &'a [u8]) -> Self {
+        Self::with_input(Input::Slice { bytes, pos: 0 }, State::Header)
+    }
+}
+
+impl<'a, R: WasmModuleBinaryRead> Parser<'a, R> {
+    fn with_input(input: Input<'a, R>, state: State) -> Self {
+        Self {
+            input,
+            state,
+            last_id: None,
+        }
+    }
+
+    fn next_payload(&mut self) -> Result<Option<Payload<'a>>> {
+        match self.state {
+            State::Header => {
+                let version = self.input.decode_header()?;
+                self.state = State::Sections;
+                Ok(Some(Payload::Header(version)))
+            }
+            State::Sections => {
+                if !self.input.has_next()? {
+                    self.state = State::Done;
+                    return Ok(None);
+                }
+                self.next_section().map(Some)
+            }
+            State::Code { index, count, end } => {
+                if index == count {
+                    if self.input.pos() != end {
+                        bail!("Code section does not end at its declared size")
+                    }
+                    self.state = State::Sections;
+                    return self.next_payload();
+                }
+                let size = self.input.read_u32()? as usize;
+                let start = self.input.pos();
+                if start + size > end {
+                    bail!("function body {} exceeds the Code section", index)
+                }
+                let bytes = self.input.take_bytes(size)?;
+                self.state = State::Code {
+                    index: index + 1,
+                    count,
+                    end,
+                };
+                Ok(Some(Payload::CodeEntry(FunctionBody {
+                    index,
+                    range: start..start + size,
+                    bytes,
+                })))
+            }
+            State::Done => Ok(None),
+        }
+    }
+
+    fn next_section(&mut self) -> Result<Payload<'a>> {
+        let (section_id, length) = decode_section_header(&mut self.input)?;
+        let start = self.input.pos();
+        let range = start..start + length;
+        if section_id == SectionID::Code {
+            self.check_order(section_id)?;
+            let count = self.input.read_u32()?;
+            if self.input.pos() > range.end {
+                bail!("Code section is shorter than its vector size")
+            }
+            self.state = State::Code {
+                index: 0,
+                count,
+                end: range.end,
+            };
+            return Ok(Payload::CodeSectionStart { count, range });
+        }
+
+        let content = self.input.take_bytes(length).with_context(|| {
+            format!("{:?} section is shorter than {} bytes", section_id, length)
+        })?;
+        if section_id == SectionID::Custom {
+            let after = self.last_id.map(|id| id as u8);
+            let (name, data) = match content {
+                Cow::Borrowed(bytes) => {
+                    let (name, data) = section::decode_custom(bytes)?;
+                    (Cow::Borrowed(name), Cow::Borrowed(data))
+                }
+                Cow::Owned(bytes) => {
+                    let (name, data) = section::decode_custom(&bytes)?;
+                    (Cow::Owned(name.to_string()), Cow::Owned(data.to_vec()))
+                }
+            };
+            return Ok(Payload::CustomSection(CustomSection {
+                name,
+                data,
+                after,
+                range,
+            }));
+        }
+        self.check_order(section_id)?;
+        Ok(Payload::Section(Section {
+            id: section_id,
+            range,
+            content,
+        }))
+    }
+
+    fn check_order(&mut self, section_id: SectionID) -> Result<()> {
+        check_section_order(self.last_id, section_id)?;
+        self.last_id = Some(section_id);
+        Ok(())
+    }
+}
+
+impl<'a, R: WasmModuleBinaryRead> Iterator for Parser<'a, R> {
+    type Item = Result<Payload<'a>>;
+
+    /// stops after the first error
+    fn next(&mut self) -> Option<Self::Item> {
+        let payload = self.next_payload();
+        if payload.is_err() {
+            self.state = State::Done;
+        }
+        payload.transpose()
+    }
+}
+
+/// Input of the parser which keeps the offset of the next byte
+enum Input<'a, R> {
+    Reader { reader: R, pos: usize },
+    Slice { bytes: &'a [u8], pos: usize },
+}
+
+impl<'a, R: WasmModuleBinaryRead> Input<'a, R> {
+    fn pos(&self) -> usize {
+        match self {
+            Self::Reader { pos, .. } | Self::Slice { pos, .. } => *pos,
+        }
+    }
+
+    /// borrow the next `size` bytes from a slice or copy them from a reader
+    fn take_bytes(&mut self, size: usize) -> Result<Cow<'a, [u8]>> {
+        match self {
+            Self::Reader { .. } => Ok(Cow::Owned(self.read_bytes(size)?)),
+            Self::Slice { bytes, pos } => {
+                let rest = bytes.len() - *pos;
+                if rest < size {
+                    bail!(
+                        "{} bytes are requested but only {} bytes remain",
+                        size,
+                        rest
+                    )
+                }
+                let taken = &bytes[*pos..*pos + size];
+                *pos += size;
+                Ok(Cow::Borrowed(taken))
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for Input<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = match self {
+            Self::Reader { reader, .. } => reader.read(buf)?,
+            Self::Slice { bytes, pos } => (&bytes[*pos..]).read(buf)?,
+        };
+        match self {
+            Self::Reader { pos, .. } | Self::Slice { pos, .. } => *pos += n,
+        }
+        std::io::Result::Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Input<'_, R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self {
+            Self::Reader { reader, .. } => reader.fill_buf(),
+            Self::Slice { bytes, pos } => std::io::Result::Ok(&bytes[*pos..]),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Self::Reader { reader, pos } => {
+                reader.consume(amt);
+                *pos += amt;
+            }
+            Self::Slice { pos, .. } => *pos += amt,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use anyhow::*;
+    use wasmer::wat2wasm;
+
+    use super::{Parser, Payload, SectionContent, SectionID};
+
+    #[test]
+    fn parse() -> Result<()> {
+        //Given
+        let wasm = wat2wasm(
+            br#"(module
+            (func $one (result i32) i32.const 1)
+            (func $two (result i32) i32.const 2)
+        )"#,
+        )?;
+        //When
+        let payloads = Parser::new(&wasm[..]).collect::<Result<Vec<_>>>()?;
+        //Then
+        assert_eq!(payloads[0], Payload::Header(1));
+        match &payloads[1] {
+            Payload::Section(section) => {
+                assert_eq!(
+                    (section.id, section.range.clone()),
+                    (SectionID::Type, 10..15)
+                );
+                assert_eq!(&wasm[section.range.clone()], &section.content[..]);
+            }
+            p => bail!("unexpected payload {:?}", p),
+        }
+        assert!(matches!(&payloads[2], Payload::Section(s) if s.id == SectionID::Function));
+        assert!(matches!(
+            &payloads[3],
+            Payload::CodeSectionStart { count: 2, .. }
+        ));
+        for (i, payload) in payloads[4..6].iter().enumerate() {
+            match payload {
+                Payload::CodeEntry(body) => {
+                    assert_eq!(body.index, i as u32);
+                    assert_eq!(&wasm[body.range.clone()], &body.bytes[..]);
+                }
+                p => bail!("unexpected payload {:?}", p),
+            }
+        }
+        assert!(matches!(&payloads[6], Payload::CustomSection(custom) if custom.name == "name"));
+        assert_eq!(payloads.len(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_slice_without_copy() -> Result<()> {
+        //Given
+        let wasm = wat2wasm(br#"(module (func $f))"#)?;
+        //When
+        let payloads = Parser::from_slice(&wasm).collect::<Result<Vec<_>>>()?;
+        //Then
+        let from_reader = Parser::new(&wasm[..]).collect::<Result<Vec<_>>>()?;
+        assert_eq!(payloads, from_reader);
+        for payload in payloads {
+            match payload {
+                Payload::Section(section) => {
+                    assert!(matches!(section.content, Cow::Borrowed(_)))
+                }
+                Payload::CodeEntry(body) => assert!(matches!(body.bytes, Cow::Borrowed(_))),
+                Payload::CustomSection(custom) => {
+                    assert!(matches!(custom.name, Cow::Borrowed("name")));
+                    assert!(matches!(custom.data, Cow::Borrowed(_)));
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_and_decode_section() -> Result<()> {
+        let wasm = b"\0asm\x01\0\0\0\x01\x04\x01\x60\x00\x00";
+        let mut parser = Parser::new(&wasm[..]);
+        assert_eq!(parser.next().transpose()?, Some(Payload::Header(1)));
+        match parser.next().transpose()? {
+            Some(Payload::Section(section)) => {
+                assert!(matches!(section.decode()?, SectionContent::Type(t) if t.len() == 1))
+            }
+            p => bail!("unexpected payload {:?}", p),
+        }
+        assert!(parser.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_stops_at_error() {
+        // code section declaring 2 bodies but containing only 1
+        let wasm = b"\0asm\x01\0\0\0\x0A\x04\x02\x02\x00\x0B";
+        let mut parser = Parser::from_slice(wasm);
+        assert!(matches!(
+            parser.next(),
+            Some(Result::Ok(Payload::Header(1)))
+        ));
+        assert!(matches!(
+            parser.next(),
+            Some(Result::Ok(Payload::CodeSectionStart { count: 2, .. }))
+        ));
+        assert!(matches!(
+            parser.next(),
+            Some(Result::Ok(Payload::CodeEntry(_)))
+        ));
+        assert!(matches!(parser.next(), Some(Err(_))));
+        assert!(parser.next().is_none());
+    }
+}
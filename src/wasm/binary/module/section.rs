@@ -1,22 +1,41 @@
+use std::borrow::Cow;
+
 use anyhow::*;
 use num::FromPrimitive;
 use num_derive::FromPrimitive;
 
 use super::super::decode::WasmModuleBinaryRead;
-pub use code::Content as CodeContent;
+use super::parser::{Parser, Payload};
+use crate::{
+    features::WasmFeatures,
+    structure::module::{Custom, Data, Export, Import, Name},
+};
+pub use code::{Content as CodeContent, Func as Code};
+pub use custom::decode as decode_custom;
 pub use function::Content as FunctionContent;
+pub use name::{decode as decode_names, SECTION_NAME as NAME_SECTION};
 
 #[derive(Default)]
-pub struct Sections {
+pub struct Sections<'a> {
     pub type_section: types::Content,
+    pub import_section: import::Content<'a>,
     pub function_section: function::Content,
-    pub code_section: code::Content,
-    pub export_section: export::Content,
+    pub table_section: table::Content,
+    pub memory_section: memory::Content,
+    pub tag_section: tag::Content,
+    pub global_section: global::Content,
+    pub export_section: export::Content<'a>,
+    pub start_section: start::Content,
+    pub element_section: element::Content,
+    pub data_count_section: data_count::Content,
+    pub code_section: code::Content<'a>,
+    pub data_section: data::Content<'a>,
+    pub custom_sections: Vec<Custom<'a>>,
 }
 
-#[derive(Debug, FromPrimitive, PartialEq)]
+#[derive(Debug, FromPrimitive, PartialEq, Eq, Clone, Copy)]
 // Refer to : https://webassembly.github.io/spec/core/binary/modules.html#sections
-enum SectionID {
+pub enum SectionID {
     Custom = 0x00,
     Type,
     Import,
@@ -30,42 +49,214 @@ enum SectionID {
     Code,
     Data,
     DataCount,
+    /// https://webassembly.github.io/exception-handling/core/binary/modules.html#tag-section
+    Tag,
+}
+
+impl SectionID {
+    /// The position where the section must appear in a module.
+    /// Note that DataCount and Tag are placed earlier than their ids tell.
+    /// https://webassembly.github.io/spec/core/binary/modules.html#binary-module
+    /// https://webassembly.github.io/exception-handling/core/binary/modules.html#binary-module
+    fn order(&self) -> u8 {
+        match self {
+            Self::Custom => 0,
+            Self::Type => 1,
+            Self::Import => 2,
+            Self::Function => 3,
+            Self::Table => 4,
+            Self::Memory => 5,
+            Self::Tag => 6,
+            Self::Global => 7,
+            Self::Export => 8,
+            Self::Start => 9,
+            Self::Element => 10,
+            Self::DataCount => 11,
+            Self::Code => 12,
+            Self::Data => 13,
+        }
+    }
+}
+
+/// decoded content of a non-custom section except for the code section,
+/// whose function bodies are yielded one by one by the parser
+#[derive(Debug, PartialEq)]
+pub enum SectionContent<'a> {
+    Type(types::Content),
+    Import(import::Content<'a>),
+    Function(function::Content),
+    Table(table::Content),
+    Memory(memory::Content),
+    Tag(tag::Content),
+    Global(global::Content),
+    Export(export::Content<'a>),
+    Start(start::Content),
+    Element(element::Content),
+    DataCount(data_count::Content),
+    Data(data::Content<'a>),
+}
+
+impl<'a> SectionContent<'a> {
+    /// names and data segments borrow from `bytes`
+    pub fn decode(section_id: SectionID, bytes: &'a [u8]) -> Result<Self> {
+        Ok(match section_id {
+            SectionID::Type => Self::Type(types::decode(bytes)?),
+            SectionID::Import => Self::Import(import::decode(bytes)?),
+            SectionID::Function => Self::Function(function::decode(bytes)?),
+            SectionID::Table => Self::Table(table::decode(bytes)?),
+            SectionID::Memory => Self::Memory(memory::decode(bytes)?),
+            SectionID::Tag => Self::Tag(tag::decode(bytes)?),
+            SectionID::Global => Self::Global(global::decode(bytes)?),
+            SectionID::Export => Self::Export(export::decode(bytes)?),
+            SectionID::Start => Self::Start(start::decode(bytes)?),
+            SectionID::Element => Self::Element(element::decode(bytes)?),
+            SectionID::DataCount => Self::DataCount(data_count::decode(bytes)?),
+            SectionID::Data => Self::Data(data::decode(bytes)?),
+            SectionID::Custom | SectionID::Code => {
+                bail!("{:?} section is not decoded as a whole", section_id)
+            }
+        })
+    }
+
+    /// the content copying what it borrows, for sections read from a stream
+    pub fn into_owned(self) -> SectionContent<'static> {
+        let owned = |name: Name| Cow::Owned(name.into_owned());
+        match self {
+            Self::Type(c) => SectionContent::Type(c),
+            Self::Import(c) => SectionContent::Import(
+                c.into_iter()
+                    .map(|import| Import {
+                        module: owned(import.module),
+                        name: owned(import.name),
+                        desc: import.desc,
+                    })
+                    .collect(),
+            ),
+            Self::Function(c) => SectionContent::Function(c),
+            Self::Table(c) => SectionContent::Table(c),
+            Self::Memory(c) => SectionContent::Memory(c),
+            Self::Tag(c) => SectionContent::Tag(c),
+            Self::Global(c) => SectionContent::Global(c),
+            Self::Export(c) => SectionContent::Export(
+                c.into_iter()
+                    .map(|export| Export {
+                        name: owned(export.name),
+                        desc: export.desc,
+                    })
+                    .collect(),
+            ),
+            Self::Start(c) => SectionContent::Start(c),
+            Self::Element(c) => SectionContent::Element(c),
+            Self::DataCount(c) => SectionContent::DataCount(c),
+            Self::Data(c) => SectionContent::Data(
+                c.into_iter()
+                    .map(|data| Data {
+                        init: Cow::Owned(data.init.into_owned()),
+                        mode: data.mode,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<'a> Sections<'a> {
+    fn set(&mut self, content: SectionContent<'a>) {
+        match content {
+            SectionContent::Type(c) => self.type_section = c,
+            SectionContent::Import(c) => self.import_section = c,
+            SectionContent::Function(c) => self.function_section = c,
+            SectionContent::Table(c) => self.table_section = c,
+            SectionContent::Memory(c) => self.memory_section = c,
+            SectionContent::Tag(c) => self.tag_section = c,
+            SectionContent::Global(c) => self.global_section = c,
+            SectionContent::Export(c) => self.export_section = c,
+            SectionContent::Start(c) => self.start_section = c,
+            SectionContent::Element(c) => self.element_section = c,
+            SectionContent::DataCount(c) => self.data_count_section = c,
+            SectionContent::Data(c) => self.data_section = c,
+        }
+    }
 }
 
 pub trait ModuleSectionRead {
-    fn decode_sections(&mut self) -> Result<Sections>;
+    fn decode_sections(&mut self, features: &WasmFeatures) -> Result<Sections<'static>>;
 }
 impl<R: WasmModuleBinaryRead> ModuleSectionRead for R {
-    fn decode_sections(&mut self) -> Result<Sections> {
+    fn decode_sections(&mut self, features: &WasmFeatures) -> Result<Sections<'static>> {
+        Sections::collect(Parser::after_header(self), features)
+    }
+}
+
+impl<'a> Sections<'a> {
+    /// decode the rest of the payloads, the header must have already been yielded
+    pub(super) fn collect<R: WasmModuleBinaryRead>(
+        parser: Parser<'a, R>,
+        features: &WasmFeatures,
+    ) -> Result<Self> {
         let mut sections: Sections = Default::default();
-        while self.has_next()? {
-            let (section_id, content) = decode_section_type(self)?;
-
-            match section_id {
-                SectionID::Custom => (),
-                SectionID::Type => sections.type_section = types::decode(content)?,
-                SectionID::Function => sections.function_section = function::decode(content)?,
-                SectionID::Export => sections.export_section = export::decode(content)?,
-                SectionID::Code => sections.code_section = code::decode(content)?,
-                _ => bail!("uninplemented section_id {:?}", section_id),
-            };
+        for payload in parser {
+            match payload? {
+                Payload::Header(_) => bail!("the header must precede all sections"),
+                Payload::Section(section) => sections.set(section.decode()?),
+                Payload::CustomSection(custom) => {
+                    sections.custom_sections.push(custom.into_custom())
+                }
+                Payload::CodeSectionStart { count, .. } => {
+                    sections.code_section = Vec::with_capacity((count as usize).min(1024))
+                }
+                Payload::CodeEntry(body) => sections
+                    .code_section
+                    .push(body.decode_with_features(features)?),
+            }
         }
         Ok(sections)
     }
 }
 
-fn decode_section_type(reader: &mut impl WasmModuleBinaryRead) -> Result<(SectionID, Vec<u8>)> {
+/// Non-custom sections must appear at most once and in the order the spec prescribes.
+pub(super) fn check_section_order(last: Option<SectionID>, current: SectionID) -> Result<()> {
+    match last {
+        Some(last) if last == current => bail!("duplicate {:?} section", current),
+        Some(last) if last.order() > current.order() => {
+            bail!(
+                "{:?} section must not appear after {:?} section",
+                current,
+                last
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+/// read the id of a section and the size of its content
+pub(super) fn decode_section_header(
+    reader: &mut impl WasmModuleBinaryRead,
+) -> Result<(SectionID, usize)> {
     let section_id = reader.read_byte()?;
-    let length = reader.read_u64()? as usize;
+    let length = reader.read_u64()?;
     let section_id = SectionID::from_u8(section_id)
         .ok_or_else(|| anyhow!("unknown section_id {}", section_id))?;
-    let content = reader.read_bytes(length)?;
-    Ok((section_id, content))
+    if length > u32::MAX as u64 {
+        bail!("{:?} section size {} exceeds u32", section_id, length)
+    }
+    Ok((section_id, length as usize))
 }
 
 mod code;
+mod custom;
+mod data;
+mod data_count;
+mod element;
 mod export;
 mod function;
+mod global;
+mod import;
+mod memory;
+mod name;
+mod start;
+mod table;
+mod tag;
 mod types;
 
 #[cfg(test)]
@@ -77,7 +268,7 @@ mod tests {
     use wasmer::wat2wasm;
 
     #[test]
-    fn decode_section_type_test() {
+    fn decode_section_header_test() {
         //Given
         let mut reader = test_util::wasm_reader(
             br#"(module
@@ -90,19 +281,21 @@ mod tests {
         );
         let _ = reader.read_bytes(8);
         //When
-        let (sec, content) = super::decode_section_type(&mut reader).unwrap();
+        let (sec, len) = super::decode_section_header(&mut reader).unwrap();
         //Then
-        assert_eq!((sec, content.len()), (SectionID::Type, 7));
+        assert_eq!((sec, len), (SectionID::Type, 7));
+        let _ = reader.read_bytes(len);
 
         //When
-        let (sec, content) = decode_section_type(&mut reader).unwrap();
+        let (sec, len) = decode_section_header(&mut reader).unwrap();
         //Then
-        assert_eq!((sec, content.len()), (SectionID::Function, 2));
+        assert_eq!((sec, len), (SectionID::Function, 2));
+        let _ = reader.read_bytes(len);
 
         //When
-        let (sec, content) = decode_section_type(&mut reader).unwrap();
+        let (sec, len) = decode_section_header(&mut reader).unwrap();
         //Then
-        assert_eq!((sec, content.len()), (SectionID::Code, 9));
+        assert_eq!((sec, len), (SectionID::Code, 9));
     }
 
     #[test]
@@ -120,7 +313,7 @@ mod tests {
         let _ = reader.read_bytes(8);
 
         //When
-        let sections = reader.decode_sections()?;
+        let sections = reader.decode_sections(&WasmFeatures::default())?;
 
         //Then
         assert_eq!(sections.type_section.len(), 1);
@@ -129,4 +322,131 @@ mod tests {
         assert_eq!(sections.code_section.len(), 1);
         Ok(())
     }
+
+    #[test]
+    fn decode_all_sections() -> Result<()> {
+        //Given
+        let wat = br#"(module
+            (import "env" "print" (func $print (param i32)))
+            (table 1 funcref)
+            (memory 1)
+            (global $g (mut i32) (i32.const 0))
+            (func $main (call $print (global.get $g)))
+            (export "main" (func $main))
+            (start $main)
+            (elem (i32.const 0) $main)
+            (data (i32.const 0) "hello")
+        )"#;
+        let mut reader = Cursor::new(wat2wasm(wat)?);
+        let _ = reader.read_bytes(8);
+
+        //When
+        let sections = reader.decode_sections(&WasmFeatures::default())?;
+
+        //Then
+        assert_eq!(sections.type_section.len(), 2);
+        assert_eq!(sections.import_section.len(), 1);
+        assert_eq!(sections.function_section.len(), 1);
+        assert_eq!(sections.table_section.len(), 1);
+        assert_eq!(sections.memory_section.len(), 1);
+        assert_eq!(sections.global_section.len(), 1);
+        assert_eq!(sections.export_section.len(), 1);
+        assert!(sections.start_section.is_some());
+        assert_eq!(sections.element_section.len(), 1);
+        assert_eq!(sections.code_section.len(), 1);
+        assert_eq!(sections.data_section.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_section_order() {
+        let type_section = [0x01u8, 0x01, 0x00];
+        let function_section = [0x03u8, 0x01, 0x00];
+        let custom_section = [0x00u8, 0x02, 0x01, 0x61];
+        let data_count_section = [0x0Cu8, 0x01, 0x00];
+        let code_section = [0x0Au8, 0x01, 0x00];
+        let decode = |sections: &[&[u8]]| {
+            sections
+                .concat()
+                .as_slice()
+                .decode_sections(&WasmFeatures::default())
+        };
+
+        // in order with custom sections anywhere
+        assert!(decode(&[&custom_section, &type_section, &custom_section]).is_ok());
+        assert!(decode(&[&type_section, &function_section, &custom_section]).is_ok());
+        // DataCount section precedes Code section in spite of its id
+        assert!(decode(&[&data_count_section, &code_section]).is_ok());
+        assert!(decode(&[&code_section, &data_count_section]).is_err());
+        // out of order
+        assert!(decode(&[&function_section, &type_section]).is_err());
+        // duplicated
+        assert!(decode(&[&type_section, &type_section]).is_err());
+    }
+
+    #[test]
+    fn decode_custom_sections() -> Result<()> {
+        use crate::{features::WasmFeatures, structure::module::Custom};
+        //Given
+        let bytes = [
+            &[0x00u8, 0x02, 0x01, 0x61][..],
+            &[0x01, 0x01, 0x00],
+            &[0x00, 0x03, 0x01, 0x62, 0xFF],
+            &[0x00, 0x02, 0x01, 0x63],
+        ]
+        .concat();
+        //When
+        let sections = (&bytes[..]).decode_sections(&WasmFeatures::default())?;
+        //Then
+        assert_eq!(
+            sections.custom_sections,
+            vec![
+                Custom {
+                    name: "a".into(),
+                    data: vec![].into(),
+                    after: None
+                },
+                Custom {
+                    name: "b".into(),
+                    data: vec![0xFF].into(),
+                    after: Some(SectionID::Type as u8)
+                },
+                Custom {
+                    name: "c".into(),
+                    data: vec![].into(),
+                    after: Some(SectionID::Type as u8)
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_section_size_mismatch() {
+        // type section declaring 1 byte but its vector continues beyond it
+        let bytes = [0x01u8, 0x01, 0x01, 0x60, 0x00, 0x00];
+        assert!((&bytes[..])
+            .decode_sections(&WasmFeatures::default())
+            .is_err());
+        // type section declaring 4 bytes but the module ends earlier
+        let bytes = [0x01u8, 0x04, 0x00];
+        assert!((&bytes[..])
+            .decode_sections(&WasmFeatures::default())
+            .is_err());
+        // type section containing an extra byte after its vector
+        let bytes = [0x01u8, 0x02, 0x00, 0x00];
+        assert!((&bytes[..])
+            .decode_sections(&WasmFeatures::default())
+            .is_err());
+        // code section declaring 2 bodies but containing only 1
+        let bytes = [0x0Au8, 0x04, 0x02, 0x02, 0x00, 0x0B];
+        assert!((&bytes[..])
+            .decode_sections(&WasmFeatures::default())
+            .is_err());
+        // code section declaring 1 body but containing 2
+        let bytes = [0x0Au8, 0x07, 0x01, 0x02, 0x00, 0x0B, 0x02, 0x00, 0x0B];
+        assert!((&bytes[..])
+            .decode_sections(&WasmFeatures::default())
+            .is_err());
+    }
 }
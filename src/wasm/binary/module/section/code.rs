@@ -1,60 +1,87 @@
-use std::io::Read;
+use std::borrow::Cow;
+use std::ops::Range;
 
 use crate::{
-    binary::{decode::*, instructions::decode_instructions},
-    structure::{instructions::Expr, types::ValType},
+    binary::{decode::*, types::TypeRead},
+    features::WasmFeatures,
+    structure::{module::Body, types::ValType},
 };
 use anyhow::*;
 
-pub type Content = Vec<Code>;
-pub type Code = Func;
+pub type Content<'a> = Vec<Code<'a>>;
+pub type Code<'a> = Func<'a>;
 #[derive(PartialEq, Eq, Debug)]
-pub struct Func {
+pub struct Func<'a> {
     pub locals: Vec<ValType>,
-    pub expr: Expr,
+    pub expr: Body<'a>,
 }
 
-pub fn decode(bytes: Vec<u8>) -> Result<Content> {
-    let mut reader = &bytes[..];
-    let num_of_funcs = reader.read_u32()? as usize;
-    let mut content = Vec::<Func>::with_capacity(num_of_funcs);
-    while reader.has_next()? {
-        let size = reader.read_u32()? as usize;
-        let func_bytes = reader.read_bytes(size)?;
-        content.push(Func::try_from(func_bytes)?);
+impl<'a> Func<'a> {
+    /// decode the locals and leave the instructions to be decoded on first use
+    /// `range` is where `bytes` are placed in the module
+    pub fn decode_lazily(
+        bytes: Cow<'a, [u8]>,
+        range: Range<usize>,
+        features: &WasmFeatures,
+    ) -> Result<Self> {
+        let (locals, len) = decode_locals(&bytes)?;
+        // the instructions follow the locals in the same buffer
+        let remainings = match bytes {
+            Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[len..]),
+            Cow::Owned(mut bytes) => {
+                bytes.drain(..len);
+                Cow::Owned(bytes)
+            }
+        };
+        for local in &locals {
+            features.check_val_type(*local)?;
+        }
+        if remainings.last() != Some(&0x0B) {
+            bail!("function body must be terminated with end");
+        }
+        let start = range.end - remainings.len();
+        Ok(Func {
+            locals,
+            expr: Body::lazy(remainings, Some(start..range.end), *features),
+        })
     }
-    Ok(content)
 }
 
-impl TryFrom<Vec<u8>> for Func {
+impl TryFrom<Vec<u8>> for Func<'static> {
     type Error = anyhow::Error;
 
     fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
-        let (locals, remainings) = decode_locals(bytes)?;
-        let expr = decode_instructions(remainings)?;
-        Ok(Func { locals, expr })
+        let len = bytes.len();
+        let func = Func::decode_lazily(bytes.into(), 0..len, &WasmFeatures::default())?;
+        func.expr.expr()?;
+        Ok(func)
     }
 }
 
-fn decode_locals(bytes: Vec<u8>) -> Result<(Vec<ValType>, Vec<u8>)> {
-    let mut reader = &bytes[..];
+/// The same limit as the major engines, which keeps a few bytes of locals from exhausting memory.
+const MAX_LOCALS: usize = 50_000;
+
+/// returns the locals and the number of bytes which they take
+fn decode_locals(bytes: &[u8]) -> Result<(Vec<ValType>, usize)> {
+    let mut reader = bytes;
     let num_of_locals = reader.read_u32()?;
     let mut locals = Vec::<ValType>::new();
     for _ in 0..num_of_locals {
-        let num_of_valtypes = reader.read_u32()?;
-        for _ in 0..num_of_valtypes {
-            locals.push(ValType::try_from(reader.read_byte()?)?);
+        let num_of_valtypes = reader.read_u32()? as usize;
+        let val_type = reader.read_val_type()?;
+        if num_of_valtypes > MAX_LOCALS - locals.len() {
+            bail!("too many locals")
         }
+        locals.resize(locals.len() + num_of_valtypes, val_type);
     }
-    let mut remainings = Vec::<u8>::new();
-    let _ = reader.read_to_end(&mut remainings);
-    Ok((locals, remainings))
+    Ok((locals, bytes.len() - reader.len()))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::binary::module::section::code::Func;
     use crate::structure::instructions::Instruction::*;
+    use crate::structure::types::{NumType, ValType};
 
     #[test]
     fn decode_func() {
@@ -64,8 +91,32 @@ mod tests {
             f,
             Func {
                 locals: vec![],
-                expr: vec![LocalGet(0), LocalGet(1), I32Add]
+                expr: vec![LocalGet(0), LocalGet(1), I32Add].into()
+            }
+        );
+    }
+
+    #[test]
+    fn decode_func_with_locals() {
+        let bytes = vec![0x02u8, 0x02, 0x7F, 0x01, 0x7E, 0x20, 0x02, 0x0b];
+        let f = super::Func::try_from(bytes).unwrap();
+        assert_eq!(
+            f,
+            Func {
+                locals: vec![
+                    ValType::Number(NumType::I32),
+                    ValType::Number(NumType::I32),
+                    ValType::Number(NumType::I64)
+                ],
+                expr: vec![LocalGet(2)].into()
             }
         );
     }
+
+    #[test]
+    fn decode_without_end() {
+        // body without end
+        let bytes = vec![0x00u8, 0x01];
+        assert!(super::Func::try_from(bytes).is_err());
+    }
 }
@@ -0,0 +1,33 @@
+use anyhow::*;
+
+use crate::binary::decode::WasmModuleBinaryRead;
+
+/// split the content of a custom section into its name and data, both borrowing from `bytes`
+pub fn decode(bytes: &[u8]) -> Result<(&str, &[u8])> {
+    let mut reader = bytes;
+    let len = reader.read_u32()? as usize;
+    if len > reader.len() {
+        bail!("custom section name is longer than the section")
+    }
+    let (name, data) = reader.split_at(len);
+    Ok((std::str::from_utf8(name)?, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![0x03u8, 0x61, 0x62, 0x63, 0x01, 0x02];
+        //when
+        let (name, data) = super::decode(&bytes)?;
+        //then
+        assert_eq!((name, data), ("abc", &[0x01u8, 0x02][..]));
+        // the name must be valid UTF-8 and fit in the section
+        assert!(super::decode(&[0x01u8, 0xFF]).is_err());
+        assert!(super::decode(&[0x03u8, 0x61]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::*;
+
+use crate::{
+    binary::{
+        decode::{SliceRead, WasmModuleBinaryRead},
+        instructions::ExprRead,
+    },
+    structure::module::{Data, DataMode},
+};
+
+pub type Content<'a> = Vec<Data<'a>>;
+
+/// the initial bytes borrow from `bytes`
+pub fn decode(bytes: &[u8]) -> Result<Content<'_>> {
+    let mut reader = bytes;
+    let num_of_datas = reader.read_u32()? as usize;
+    let mut datas = Vec::<Data>::with_capacity(num_of_datas.min(reader.len()));
+    for _ in 0..num_of_datas {
+        let flag = reader.read_u32()?;
+        let mode = match flag {
+            0x00 => DataMode::Active {
+                memory: 0,
+                offset: reader.read_expr()?,
+            },
+            0x01 => DataMode::Passive,
+            0x02 => DataMode::Active {
+                memory: reader.read_u32()?,
+                offset: reader.read_expr()?,
+            },
+            _ => bail!("invalid data segment flag: {}", flag),
+        };
+        let len = reader.read_u32()? as usize;
+        let init = reader.read_slice(len)?.into();
+        datas.push(Data { init, mode });
+    }
+    reader.ensure_end()?;
+    Ok(datas)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::structure::{
+        instructions::Instruction,
+        module::{Data, DataMode},
+    };
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![
+            0x02u8, 0x00, 0x41, 0x08, 0x0B, 0x02, 0x68, 0x69, 0x01, 0x01, 0x21,
+        ];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(
+            x,
+            vec![
+                Data {
+                    init: b"hi"[..].into(),
+                    mode: DataMode::Active {
+                        memory: 0,
+                        offset: vec![Instruction::I32Const(8)]
+                    }
+                },
+                Data {
+                    init: b"!"[..].into(),
+                    mode: DataMode::Passive
+                }
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+use anyhow::*;
+
+use crate::binary::decode::WasmModuleBinaryRead;
+
+pub type Content = Option<u32>;
+
+pub fn decode(bytes: &[u8]) -> Result<Content> {
+    let mut reader = bytes;
+    let count = reader.read_u32()?;
+    reader.ensure_end()?;
+    Ok(Some(count))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![0x02u8];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(x, Some(2));
+        assert!(super::decode(&[0x02u8, 0x00]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::*;
+
+use crate::{
+    binary::{decode::WasmModuleBinaryRead, instructions::ExprRead, types::TypeRead},
+    structure::{
+        instructions::{Expr, Instruction},
+        module::{Elem, ElemMode},
+        types::RefType,
+    },
+};
+
+pub type Content = Vec<Elem>;
+
+pub fn decode(bytes: &[u8]) -> Result<Content> {
+    let mut reader = bytes;
+    let num_of_elems = reader.read_u32()? as usize;
+    let mut elems = Vec::<Elem>::with_capacity(num_of_elems.min(reader.len()));
+    for _ in 0..num_of_elems {
+        elems.push(decode_elem(&mut reader)?);
+    }
+    reader.ensure_end()?;
+    Ok(elems)
+}
+
+/// https://webassembly.github.io/spec/core/binary/modules.html#element-section
+/// The bit 0 of the flag distinguishes passive/declarative from active,
+/// the bit 1 indicates an explicit table index or declarative,
+/// and the bit 2 indicates that elements are given as expressions instead of function indices.
+fn decode_elem(reader: &mut impl WasmModuleBinaryRead) -> Result<Elem> {
+    let flag = reader.read_u32()?;
+    if flag > 7 {
+        bail!("invalid element segment flag: {}", flag)
+    }
+    let mode = match flag & 0b011 {
+        0b000 => ElemMode::Active {
+            table: 0,
+            offset: reader.read_expr()?,
+        },
+        0b010 => ElemMode::Active {
+            table: reader.read_u32()?,
+            offset: reader.read_expr()?,
+        },
+        0b001 => ElemMode::Passive,
+        _ => ElemMode::Declarative,
+    };
+    let uses_exprs = flag & 0b100 != 0;
+    let type_ = match (flag & 0b011 == 0, uses_exprs) {
+        (true, _) => RefType::FUNCREF,
+        (false, true) => reader.read_ref_type()?,
+        (false, false) => decode_elem_kind(reader)?,
+    };
+    let num_of_inits = reader.read_u32()? as usize;
+    let mut init = Vec::<Expr>::new();
+    for _ in 0..num_of_inits {
+        init.push(if uses_exprs {
+            reader.read_expr()?
+        } else {
+            vec![Instruction::RefFunc(reader.read_u32()?)]
+        });
+    }
+    Ok(Elem { type_, init, mode })
+}
+
+fn decode_elem_kind(reader: &mut impl WasmModuleBinaryRead) -> Result<RefType> {
+    match reader.read_byte()? {
+        0x00 => Ok(RefType::FUNCREF),
+        b => bail!("invalid elemkind: {:#x}", b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::structure::{
+        instructions::Instruction::*,
+        module::{Elem, ElemMode},
+        types::{HeapType, RefType},
+    };
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![
+            0x03u8, // 3 segments
+            0x00, 0x41, 0x01, 0x0B, 0x02, 0x00, 0x01, // active with funcidx
+            0x05, 0x70, 0x01, 0xD0, 0x70, 0x0B, // passive with expr
+            0x03, 0x00, 0x01, 0x02, // declarative with funcidx
+        ];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(
+            x,
+            vec![
+                Elem {
+                    type_: RefType::FUNCREF,
+                    init: vec![vec![RefFunc(0)], vec![RefFunc(1)]],
+                    mode: ElemMode::Active {
+                        table: 0,
+                        offset: vec![I32Const(1)]
+                    }
+                },
+                Elem {
+                    type_: RefType::FUNCREF,
+                    init: vec![vec![RefNull(HeapType::Func)]],
+                    mode: ElemMode::Passive
+                },
+                Elem {
+                    type_: RefType::FUNCREF,
+                    init: vec![vec![RefFunc(2)]],
+                    mode: ElemMode::Declarative
+                },
+            ]
+        );
+        Ok(())
+    }
+}
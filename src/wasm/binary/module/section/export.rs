@@ -1,19 +1,19 @@
 use anyhow::*;
 
 use crate::{
-    binary::decode::WasmModuleBinaryRead,
+    binary::decode::{SliceRead, WasmModuleBinaryRead},
     structure::module::{Export, ExportDesc},
 };
 
-pub type Content = Vec<Export>;
+pub type Content<'a> = Vec<Export<'a>>;
 
-pub fn decode(bytes: Vec<u8>) -> Result<Content> {
-    let mut reader = &bytes[..];
+/// the names borrow from `bytes`
+pub fn decode(bytes: &[u8]) -> Result<Content<'_>> {
+    let mut reader = bytes;
     let num_of_export = reader.read_u32()? as usize;
-    let mut exports = Vec::<Export>::with_capacity(num_of_export);
+    let mut exports = Vec::<Export>::with_capacity(num_of_export.min(reader.len()));
     for _ in 0..num_of_export {
-        let n = reader.read_u32()? as usize;
-        let name = String::from_utf8(reader.read_bytes(n)?)?;
+        let name = reader.read_name_slice()?.into();
 
         let export_type = reader.read_byte()?;
         let idx = reader.read_u32()?;
@@ -22,11 +22,13 @@ pub fn decode(bytes: Vec<u8>) -> Result<Content> {
             0x01 => ExportDesc::Table(idx),
             0x02 => ExportDesc::Mem(idx),
             0x03 => ExportDesc::Global(idx),
+            0x04 => ExportDesc::Tag(idx),
             _ => bail!("invalid export desc: {:x}", export_type),
         };
 
         exports.push(Export { name, desc });
     }
+    reader.ensure_end()?;
     Ok(exports)
 }
 
@@ -41,13 +43,13 @@ mod tests {
         //given
         let bytes = vec![0x01u8, 0x03, 0x61, 0x64, 0x64, 0x00, 0x00];
         //when
-        let x = super::decode(bytes)?;
+        let x = super::decode(&bytes)?;
         //then
         assert_eq!(x.len(), 1);
         assert_eq!(
             x[0],
             Export {
-                name: "add".to_string(),
+                name: "add".into(),
                 desc: ExportDesc::Func(0)
             }
         );
@@ -0,0 +1,50 @@
+use anyhow::*;
+
+use crate::{
+    binary::{decode::WasmModuleBinaryRead, instructions::ExprRead, types::TypeRead},
+    structure::module::Global,
+};
+
+pub type Content = Vec<Global>;
+
+pub fn decode(bytes: &[u8]) -> Result<Content> {
+    let mut reader = bytes;
+    let num_of_globals = reader.read_u32()? as usize;
+    let mut globals = Vec::<Global>::with_capacity(num_of_globals.min(reader.len()));
+    for _ in 0..num_of_globals {
+        globals.push(Global {
+            type_: reader.read_global_type()?,
+            init: reader.read_expr()?,
+        });
+    }
+    reader.ensure_end()?;
+    Ok(globals)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::structure::{
+        instructions::Instruction,
+        module::Global,
+        types::{GlobalType, Mut, NumType, ValType},
+    };
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![0x01u8, 0x7F, 0x01, 0x41, 0x2A, 0x0B];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(
+            x,
+            vec![Global {
+                type_: GlobalType(Mut::Var, ValType::Number(NumType::I32)),
+                init: vec![Instruction::I32Const(42)]
+            }]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::*;
+
+use crate::{
+    binary::{
+        decode::{SliceRead, WasmModuleBinaryRead},
+        types::TypeRead,
+    },
+    structure::module::{Import, ImportDesc},
+};
+
+pub type Content<'a> = Vec<Import<'a>>;
+
+/// the names borrow from `bytes`
+pub fn decode(bytes: &[u8]) -> Result<Content<'_>> {
+    let mut reader = bytes;
+    let num_of_imports = reader.read_u32()? as usize;
+    let mut imports = Vec::<Import>::with_capacity(num_of_imports.min(reader.len()));
+    for _ in 0..num_of_imports {
+        let module = reader.read_name_slice()?.into();
+        let name = reader.read_name_slice()?.into();
+
+        let import_type = reader.read_byte()?;
+        let desc = match import_type {
+            0x00 => ImportDesc::Func(reader.read_u32()?),
+            0x01 => ImportDesc::Table(reader.read_table_type()?),
+            0x02 => ImportDesc::Mem(reader.read_mem_type()?),
+            0x03 => ImportDesc::Global(reader.read_global_type()?),
+            0x04 => ImportDesc::Tag(super::tag::read_tag(&mut reader)?.type_),
+            _ => bail!("invalid import desc: {:x}", import_type),
+        };
+
+        imports.push(Import { module, name, desc });
+    }
+    reader.ensure_end()?;
+    Ok(imports)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::structure::{
+        module::{Import, ImportDesc},
+        types::{IndexType, Limits, MemType, Share},
+    };
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![
+            0x02u8, 0x02, 0x6a, 0x73, 0x03, 0x6c, 0x6f, 0x67, 0x00, 0x01, 0x02, 0x6a, 0x73, 0x03,
+            0x6d, 0x65, 0x6d, 0x02, 0x00, 0x01,
+        ];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(
+            x,
+            vec![
+                Import {
+                    module: "js".into(),
+                    name: "log".into(),
+                    desc: ImportDesc::Func(1)
+                },
+                Import {
+                    module: "js".into(),
+                    name: "mem".into(),
+                    desc: ImportDesc::Mem(MemType(
+                        Limits {
+                            min: 1,
+                            max: None,
+                            index: IndexType::I32,
+                        },
+                        Share::Unshared
+                    ))
+                }
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::*;
+
+use crate::{
+    binary::{decode::WasmModuleBinaryRead, types::TypeRead},
+    structure::module::Mem,
+};
+
+pub type Content = Vec<Mem>;
+
+pub fn decode(bytes: &[u8]) -> Result<Content> {
+    let mut reader = bytes;
+    let num_of_mems = reader.read_u32()? as usize;
+    let mut mems = Vec::<Mem>::with_capacity(num_of_mems.min(reader.len()));
+    for _ in 0..num_of_mems {
+        mems.push(Mem {
+            type_: reader.read_mem_type()?,
+        });
+    }
+    reader.ensure_end()?;
+    Ok(mems)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::structure::{
+        module::Mem,
+        types::{IndexType, Limits, MemType, Share},
+    };
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![0x01u8, 0x00, 0x01];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(
+            x,
+            vec![Mem {
+                type_: MemType(
+                    Limits {
+                        min: 1,
+                        max: None,
+                        index: IndexType::I32,
+                    },
+                    Share::Unshared
+                )
+            }]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::*;
+
+use crate::{
+    binary::decode::WasmModuleBinaryRead,
+    structure::module::{IndirectNameMap, NameMap, Names},
+};
+
+/// The name of the custom section holding debug names
+pub const SECTION_NAME: &str = "name";
+
+/// decode the payload of the name section
+/// https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+pub fn decode(bytes: &[u8]) -> Result<Names> {
+    let mut reader = bytes;
+    let mut names = Names::default();
+    let mut last_id: Option<u8> = None;
+    while reader.has_next()? {
+        let id = reader.read_byte()?;
+        if last_id.is_some_and(|last| last >= id) {
+            bail!("name subsection {} is out of order", id)
+        }
+        last_id = Some(id);
+        let size = reader.read_u32()? as usize;
+        let mut sub = &reader.read_bytes(size)?[..];
+        match id {
+            0x00 => names.module = Some(sub.read_name()?),
+            0x01 => names.funcs = read_name_map(&mut sub)?,
+            0x02 => names.locals = read_indirect_name_map(&mut sub)?,
+            0x03 => names.labels = read_indirect_name_map(&mut sub)?,
+            0x04 => names.types = read_name_map(&mut sub)?,
+            0x05 => names.tables = read_name_map(&mut sub)?,
+            0x06 => names.mems = read_name_map(&mut sub)?,
+            0x07 => names.globals = read_name_map(&mut sub)?,
+            0x08 => names.elems = read_name_map(&mut sub)?,
+            0x09 => names.datas = read_name_map(&mut sub)?,
+            0x0A => names.fields = read_indirect_name_map(&mut sub)?,
+            0x0B => names.tags = read_name_map(&mut sub)?,
+            // subsections of later proposals are skipped
+            _ => continue,
+        }
+        sub.ensure_end()?;
+    }
+    Ok(names)
+}
+
+fn read_name_map(reader: &mut impl WasmModuleBinaryRead) -> Result<NameMap> {
+    let len = reader.read_u32()?;
+    let mut map = NameMap::new();
+    let mut last_idx: Option<u32> = None;
+    for _ in 0..len {
+        let idx = reader.read_u32()?;
+        if last_idx.is_some_and(|last| last >= idx) {
+            bail!("indices in a name map must be in increasing order")
+        }
+        last_idx = Some(idx);
+        map.insert(idx, reader.read_name()?);
+    }
+    Ok(map)
+}
+
+fn read_indirect_name_map(reader: &mut impl WasmModuleBinaryRead) -> Result<IndirectNameMap> {
+    let len = reader.read_u32()?;
+    let mut map = IndirectNameMap::new();
+    let mut last_idx: Option<u32> = None;
+    for _ in 0..len {
+        let idx = reader.read_u32()?;
+        if last_idx.is_some_and(|last| last >= idx) {
+            bail!("indices in an indirect name map must be in increasing order")
+        }
+        last_idx = Some(idx);
+        map.insert(idx, read_name_map(reader)?);
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![
+            // module name "m"
+            0x00u8, 0x02, 0x01, 0x6D, //
+            // function 1 is "f"
+            0x01, 0x04, 0x01, 0x01, 0x01, 0x66, //
+            // local 0 of function 1 is "x"
+            0x02, 0x06, 0x01, 0x01, 0x01, 0x00, 0x01, 0x78, //
+            // unknown subsection
+            0x0F, 0x01, 0x00, //
+        ];
+        //when
+        let names = super::decode(&bytes)?;
+        //then
+        assert_eq!(names.module, Some("m".to_string()));
+        assert_eq!(names.func(1), "$f");
+        assert_eq!(names.func(0), "func[0]");
+        assert_eq!(names.local(1, 0), "$x");
+        assert_eq!(names.local(0, 0), "local[0]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_out_of_order() {
+        // function names followed by module name
+        let bytes = vec![0x01u8, 0x01, 0x00, 0x00, 0x02, 0x01, 0x6D];
+        assert!(super::decode(&bytes).is_err());
+        // function names not sorted by index
+        let bytes = vec![0x01u8, 0x07, 0x02, 0x01, 0x01, 0x66, 0x00, 0x01, 0x67];
+        assert!(super::decode(&bytes).is_err());
+    }
+}
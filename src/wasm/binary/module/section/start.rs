@@ -0,0 +1,30 @@
+use anyhow::*;
+
+use crate::{binary::decode::WasmModuleBinaryRead, structure::module::Start};
+
+pub type Content = Option<Start>;
+
+pub fn decode(bytes: &[u8]) -> Result<Content> {
+    let mut reader = bytes;
+    let func = reader.read_u32()?;
+    reader.ensure_end()?;
+    Ok(Some(Start { func }))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::structure::module::Start;
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![0x03u8];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(x, Some(Start { func: 3 }));
+        Ok(())
+    }
+}
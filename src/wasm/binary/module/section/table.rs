@@ -0,0 +1,54 @@
+use anyhow::*;
+
+use crate::{
+    binary::{decode::WasmModuleBinaryRead, types::TypeRead},
+    structure::module::Table,
+};
+
+pub type Content = Vec<Table>;
+
+pub fn decode(bytes: &[u8]) -> Result<Content> {
+    let mut reader = bytes;
+    let num_of_tables = reader.read_u32()? as usize;
+    let mut tables = Vec::<Table>::with_capacity(num_of_tables.min(reader.len()));
+    for _ in 0..num_of_tables {
+        tables.push(Table {
+            type_: reader.read_table_type()?,
+        });
+    }
+    reader.ensure_end()?;
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::structure::{
+        module::Table,
+        types::{IndexType, Limits, RefType, TableType},
+    };
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![0x01u8, 0x70, 0x01, 0x02, 0x0A];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(
+            x,
+            vec![Table {
+                type_: TableType(
+                    Limits {
+                        min: 2,
+                        max: Some(10),
+                        index: IndexType::I32,
+                    },
+                    RefType::FUNCREF
+                )
+            }]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use anyhow::*;
+
+use crate::{binary::decode::WasmModuleBinaryRead, structure::module::Tag};
+
+pub type Content = Vec<Tag>;
+
+pub fn decode(bytes: &[u8]) -> Result<Content> {
+    let mut reader = bytes;
+    let num_of_tags = reader.read_u32()? as usize;
+    let mut tags = Vec::<Tag>::with_capacity(num_of_tags.min(reader.len()));
+    for _ in 0..num_of_tags {
+        tags.push(read_tag(&mut reader)?);
+    }
+    reader.ensure_end()?;
+    Ok(tags)
+}
+
+/// the attribute 0 for exceptions followed by the type index
+/// https://webassembly.github.io/exception-handling/core/binary/types.html#tag-types
+pub fn read_tag(reader: &mut impl WasmModuleBinaryRead) -> Result<Tag> {
+    match reader.read_byte()? {
+        0x00 => Ok(Tag {
+            type_: reader.read_u32()?,
+        }),
+        b => bail!("invalid tag attribute {:#x}", b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::structure::module::Tag;
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        //given
+        let bytes = vec![0x02u8, 0x00, 0x01, 0x00, 0x00];
+        //when
+        let x = super::decode(&bytes)?;
+        //then
+        assert_eq!(x, vec![Tag { type_: 1 }, Tag { type_: 0 }]);
+        assert!(super::decode(&[0x01u8, 0x01, 0x00]).is_err());
+        Ok(())
+    }
+}
@@ -1,42 +1,120 @@
 use crate::{
-    binary::decode::WasmModuleBinaryRead,
-    structure::types::{FuncType, ResultType},
+    binary::{decode::WasmModuleBinaryRead, types::TypeRead},
+    structure::types::{
+        CompositeType, FieldType, FuncType, Mut, PackedType, ResultType, StorageType, SubType,
+    },
 };
 use anyhow::*;
 
-pub type Content = Vec<FuncType>;
-pub fn decode(bytes: Vec<u8>) -> Result<Content> {
-    let mut reader = &bytes[..];
-    let num_of_functype = reader.read_u32()? as usize;
-    let mut functypes = Vec::<FuncType>::with_capacity(num_of_functype);
-    for _ in 0..num_of_functype {
-        let func_type = decode_func_type(&mut reader)?;
-        functypes.push(func_type);
+/// the types in their recursive type groups
+pub type Content = Vec<Vec<SubType>>;
+pub fn decode(bytes: &[u8]) -> Result<Content> {
+    let mut reader = bytes;
+    let num_of_groups = reader.read_u32()? as usize;
+    let mut groups = Vec::<Vec<SubType>>::with_capacity(num_of_groups.min(reader.len()));
+    for _ in 0..num_of_groups {
+        groups.push(decode_rec_type(&mut reader)?);
     }
-    Ok(functypes)
+    reader.ensure_end()?;
+    Ok(groups)
 }
 
-fn decode_func_type(reader: &mut impl WasmModuleBinaryRead) -> Result<FuncType> {
-    if reader.read_byte()? != 0x60 {
-        bail!("functype have to start with 0x60");
+/// a single type outside of `rec` forms a group of its own
+/// https://webassembly.github.io/gc/core/binary/types.html#recursive-types
+fn decode_rec_type(reader: &mut impl WasmModuleBinaryRead) -> Result<Vec<SubType>> {
+    if reader.fill_buf()?.first() != Some(&0x4E) {
+        return Ok(vec![decode_sub_type(reader)?]);
     }
-    Ok(FuncType(
-        decode_result_type(reader)?,
-        decode_result_type(reader)?,
-    ))
+    reader.read_byte()?;
+    let len = reader.read_u32()? as usize;
+    let mut sub_types = Vec::with_capacity(len.min(reader.fill_buf()?.len()));
+    for _ in 0..len {
+        sub_types.push(decode_sub_type(reader)?);
+    }
+    Ok(sub_types)
+}
+
+/// composite types without `sub` are final without supertypes
+fn decode_sub_type(reader: &mut impl WasmModuleBinaryRead) -> Result<SubType> {
+    let is_final = match reader.fill_buf()?.first() {
+        Some(0x50) => false,
+        Some(0x4F) => true,
+        _ => {
+            return Ok(SubType {
+                is_final: true,
+                supers: vec![],
+                composite: decode_composite_type(reader)?,
+            })
+        }
+    };
+    reader.read_byte()?;
+    let len = reader.read_u32()? as usize;
+    let mut supers = Vec::with_capacity(len.min(reader.fill_buf()?.len()));
+    for _ in 0..len {
+        supers.push(reader.read_u32()?);
+    }
+    Ok(SubType {
+        is_final,
+        supers,
+        composite: decode_composite_type(reader)?,
+    })
+}
+
+/// https://webassembly.github.io/gc/core/binary/types.html#composite-types
+fn decode_composite_type(reader: &mut impl WasmModuleBinaryRead) -> Result<CompositeType> {
+    Ok(match reader.read_byte()? {
+        0x60 => CompositeType::Func(FuncType(
+            decode_result_type(reader)?,
+            decode_result_type(reader)?,
+        )),
+        0x5F => {
+            let len = reader.read_u32()? as usize;
+            let mut fields = Vec::with_capacity(len.min(reader.fill_buf()?.len()));
+            for _ in 0..len {
+                fields.push(decode_field_type(reader)?);
+            }
+            CompositeType::Struct(fields)
+        }
+        0x5E => CompositeType::Array(decode_field_type(reader)?),
+        b => bail!("invalid composite type {:#x}", b),
+    })
+}
+
+/// https://webassembly.github.io/gc/core/binary/types.html#aggregate-types
+fn decode_field_type(reader: &mut impl WasmModuleBinaryRead) -> Result<FieldType> {
+    let storage = match reader.fill_buf()?.first() {
+        Some(0x78) => StorageType::Packed(PackedType::I8),
+        Some(0x77) => StorageType::Packed(PackedType::I16),
+        _ => StorageType::Val(reader.read_val_type()?),
+    };
+    if matches!(storage, StorageType::Packed(_)) {
+        reader.read_byte()?;
+    }
+    let mutability = match reader.read_byte()? {
+        0x00 => Mut::Const,
+        0x01 => Mut::Var,
+        b => bail!("invalid mutability {:#x}", b),
+    };
+    Ok(FieldType(mutability, storage))
 }
 
 fn decode_result_type(reader: &mut impl WasmModuleBinaryRead) -> Result<ResultType> {
     let len = reader.read_u32()? as usize;
-    let bytes = reader.read_bytes(len)?;
-    ResultType::try_from(bytes)
+    let mut val_types = Vec::with_capacity(len.min(reader.fill_buf()?.len()));
+    for _ in 0..len {
+        val_types.push(reader.read_val_type()?);
+    }
+    Ok(ResultType(val_types))
 }
 
 #[cfg(test)]
 mod tests {
     use anyhow::*;
 
-    use crate::structure::types::{FuncType, NumType, ResultType, ValType};
+    use crate::structure::types::{
+        CompositeType, FieldType, FuncType, HeapType, Mut, NumType, PackedType, RefType,
+        ResultType, StorageType, SubType, ValType,
+    };
 
     #[test]
     fn test() -> Result<()> {
@@ -44,20 +122,60 @@ mod tests {
         // See: https://qiita.com/kgtkr/items/f4b3e2d83c7067f3cfcb#%E3%83%90%E3%82%A4%E3%83%8A%E3%83%AA%E3%82%92%E8%AA%AD%E3%82%93%E3%81%A7%E3%81%BF%E3%82%88%E3%81%86
         let bytes = vec![0x01u8, 0x60, 0x02, 0x7f, 0x7C, 0x01, 0x7b];
         //when
-        let content = super::decode(bytes)?;
+        let content = super::decode(&bytes)?;
         //then
         assert_eq!(content.len(), 1);
         assert_eq!(
             content[0],
-            FuncType(
+            vec![SubType::from(FuncType(
                 ResultType(vec![
                     ValType::Number(NumType::I32),
                     ValType::Number(NumType::F64)
                 ]),
                 ResultType(vec![ValType::Vec])
-            )
+            ))]
         );
 
         Ok(())
     }
+
+    #[test]
+    fn decode_rec_group() -> Result<()> {
+        //given
+        // (rec (type (sub (struct (field (mut i8)) (field (ref null 1)))))
+        //      (type (sub final 0 (array i32))))
+        let bytes = vec![
+            0x01u8, 0x4E, 0x02, 0x50, 0x00, 0x5F, 0x02, 0x78, 0x01, 0x63, 0x01, 0x00, 0x4F, 0x01,
+            0x00, 0x5E, 0x7F, 0x00,
+        ];
+        //when
+        let content = super::decode(&bytes)?;
+        //then
+        assert_eq!(
+            content,
+            vec![vec![
+                SubType {
+                    is_final: false,
+                    supers: vec![],
+                    composite: CompositeType::Struct(vec![
+                        FieldType(Mut::Var, StorageType::Packed(PackedType::I8)),
+                        FieldType(
+                            Mut::Const,
+                            StorageType::Val(ValType::Ref(RefType::null(HeapType::Type(1))))
+                        ),
+                    ]),
+                },
+                SubType {
+                    is_final: true,
+                    supers: vec![0],
+                    composite: CompositeType::Array(FieldType(
+                        Mut::Const,
+                        StorageType::Val(ValType::Number(NumType::I32))
+                    )),
+                },
+            ]]
+        );
+        assert!(super::decode(&[0x01, 0x5D]).is_err());
+        Ok(())
+    }
 }
@@ -1,4 +1,8 @@
-use crate::structure::types::{NumType, RefType, ResultType, ValType};
+use super::decode::WasmModuleBinaryRead;
+use crate::structure::types::{
+    GlobalType, HeapType, IndexType, Limits, MemType, Mut, NumType, RefType, ResultType, Share,
+    TableType, ValType,
+};
 use anyhow::*;
 
 impl TryFrom<u8> for ValType {
@@ -11,8 +15,19 @@ impl TryFrom<u8> for ValType {
             0x7D => ValType::Number(NumType::F32),
             0x7C => ValType::Number(NumType::F64),
             0x7B => ValType::Vec,
-            0x70 => ValType::Ref(RefType::FuncRef),
-            0x6F => ValType::Ref(RefType::ExternRef),
+            0x70 => ValType::Ref(RefType::FUNCREF),
+            0x6F => ValType::Ref(RefType::EXTERNREF),
+            0x69 => ValType::Ref(RefType::EXNREF),
+            // https://webassembly.github.io/gc/core/binary/types.html#reference-types
+            0x6E => ValType::Ref(RefType::null(HeapType::Any)),
+            0x6D => ValType::Ref(RefType::null(HeapType::Eq)),
+            0x6C => ValType::Ref(RefType::null(HeapType::I31)),
+            0x6B => ValType::Ref(RefType::null(HeapType::Struct)),
+            0x6A => ValType::Ref(RefType::null(HeapType::Array)),
+            0x71 => ValType::Ref(RefType::null(HeapType::None)),
+            0x72 => ValType::Ref(RefType::null(HeapType::NoExtern)),
+            0x73 => ValType::Ref(RefType::null(HeapType::NoFunc)),
+            0x74 => ValType::Ref(RefType::null(HeapType::NoExn)),
             _ => bail!("unknown ValType {}", value),
         })
     }
@@ -27,6 +42,103 @@ impl TryFrom<Vec<u8>> for ResultType {
     }
 }
 
+/// Extensions for WasmModuleBinaryRead to decode types
+pub trait TypeRead: WasmModuleBinaryRead {
+    /// `(ref null? ht)` takes a heap type after 0x63 or 0x64
+    /// https://webassembly.github.io/function-references/core/binary/types.html#reference-types
+    fn read_val_type(&mut self) -> Result<ValType> {
+        Ok(match self.read_byte()? {
+            0x63 => ValType::Ref(RefType::null(self.read_heap_type()?)),
+            0x64 => ValType::Ref(RefType::non_null(self.read_heap_type()?)),
+            b => ValType::try_from(b)?,
+        })
+    }
+
+    /// abstract heap types are negative and type indices are positive signed 33 bit integers
+    /// https://webassembly.github.io/gc/core/binary/types.html#heap-types
+    fn read_heap_type(&mut self) -> Result<HeapType> {
+        Ok(match self.read_i64()? {
+            -0x0C => HeapType::NoExn,
+            -0x0D => HeapType::NoFunc,
+            -0x0E => HeapType::NoExtern,
+            -0x0F => HeapType::None,
+            -0x10 => HeapType::Func,
+            -0x11 => HeapType::Extern,
+            -0x12 => HeapType::Any,
+            -0x13 => HeapType::Eq,
+            -0x14 => HeapType::I31,
+            -0x15 => HeapType::Struct,
+            -0x16 => HeapType::Array,
+            -0x17 => HeapType::Exn,
+            idx @ 0..=0xFFFF_FFFF => HeapType::Type(idx as u32),
+            idx => bail!("invalid heap type {}", idx),
+        })
+    }
+
+    fn read_ref_type(&mut self) -> Result<RefType> {
+        match self.read_val_type()? {
+            ValType::Ref(r) => Ok(r),
+            v => bail!("{:?} is not RefType", v),
+        }
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/types.html#limits
+    fn read_limits(&mut self) -> Result<Limits> {
+        let flag = self.read_byte()?;
+        self.read_limits_of(flag)
+    }
+
+    /// the third bit of the flag tells 64 bit indices, with which the limits are u64
+    /// https://webassembly.github.io/memory64/core/binary/types.html#limits
+    fn read_limits_of(&mut self, flag: u8) -> Result<Limits> {
+        let index = if flag & 0x04 == 0 {
+            IndexType::I32
+        } else {
+            IndexType::I64
+        };
+        let read = |r: &mut Self| match index {
+            IndexType::I32 => r.read_u32().map(u64::from),
+            IndexType::I64 => r.read_u64(),
+        };
+        let (min, max) = match flag & !0x04 {
+            0x00 => (read(self)?, None),
+            0x01 => (read(self)?, Some(read(self)?)),
+            _ => bail!("invalid limits flag {:#x}", flag),
+        };
+        Ok(Limits { min, max, index })
+    }
+
+    /// the second bit of the limits flag tells a shared memory
+    /// https://webassembly.github.io/threads/core/binary/types.html#memory-types
+    fn read_mem_type(&mut self) -> Result<MemType> {
+        let flag = self.read_byte()?;
+        let share = if flag & 0x02 == 0 {
+            Share::Unshared
+        } else {
+            Share::Shared
+        };
+        Ok(MemType(self.read_limits_of(flag & !0x02)?, share))
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/types.html#table-types
+    fn read_table_type(&mut self) -> Result<TableType> {
+        let ref_type = self.read_ref_type()?;
+        Ok(TableType(self.read_limits()?, ref_type))
+    }
+
+    /// https://webassembly.github.io/spec/core/binary/types.html#global-types
+    fn read_global_type(&mut self) -> Result<GlobalType> {
+        let val_type = self.read_val_type()?;
+        let mutability = match self.read_byte()? {
+            0x00 => Mut::Const,
+            0x01 => Mut::Var,
+            b => bail!("invalid mutability {:#x}", b),
+        };
+        Ok(GlobalType(mutability, val_type))
+    }
+}
+impl<R: WasmModuleBinaryRead + ?Sized> TypeRead for R {}
+
 #[cfg(test)]
 mod test {
     use anyhow::*;
@@ -43,7 +155,7 @@ mod test {
         assert_eq!(x.len(), 3);
         assert_eq!(x[0], ValType::Number(NumType::I32));
         assert_eq!(x[1], ValType::Vec);
-        assert_eq!(x[2], ValType::Ref(RefType::ExternRef));
+        assert_eq!(x[2], ValType::Ref(RefType::EXTERNREF));
         Ok(())
     }
 
@@ -57,4 +169,93 @@ mod test {
         assert!(x.is_err());
         Ok(())
     }
+
+    #[test]
+    fn decode_ref_types() -> Result<()> {
+        use super::TypeRead;
+        use crate::structure::types::HeapType;
+        //given
+        let bytes = [0x63u8, 0x70, 0x64, 0x05, 0x63, 0x6F, 0x64, 0x7F];
+        let mut reader = &bytes[..];
+        //when
+        let types = [
+            reader.read_val_type()?,
+            reader.read_val_type()?,
+            reader.read_val_type()?,
+        ];
+        let invalid = reader.read_val_type();
+        //then
+        assert_eq!(
+            types,
+            [
+                ValType::Ref(RefType::FUNCREF),
+                ValType::Ref(RefType::non_null(HeapType::Type(5))),
+                ValType::Ref(RefType::EXTERNREF),
+            ]
+        );
+        assert_eq!(invalid.unwrap_err().to_string(), "invalid heap type -1");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_limits_and_types() -> Result<()> {
+        use super::TypeRead;
+        use crate::structure::types::{
+            GlobalType, IndexType, Limits, MemType, Mut, Share, TableType,
+        };
+        //given
+        let bytes = vec![
+            0x00u8, 0x01, 0x01, 0x02, 0x10, 0x70, 0x00, 0x03, 0x7E, 0x01, 0x07, 0x01, 0x80, 0x80,
+            0x80, 0x80, 0x10,
+        ];
+        let mut reader = &bytes[..];
+        //when then
+        assert_eq!(
+            reader.read_mem_type()?,
+            MemType(
+                Limits {
+                    min: 1,
+                    max: None,
+                    index: IndexType::I32,
+                },
+                Share::Unshared
+            )
+        );
+        assert_eq!(
+            reader.read_limits()?,
+            Limits {
+                min: 2,
+                max: Some(16),
+                index: IndexType::I32,
+            }
+        );
+        assert_eq!(
+            reader.read_table_type()?,
+            TableType(
+                Limits {
+                    min: 3,
+                    max: None,
+                    index: IndexType::I32,
+                },
+                RefType::FUNCREF
+            )
+        );
+        assert_eq!(
+            reader.read_global_type()?,
+            GlobalType(Mut::Var, ValType::Number(NumType::I64))
+        );
+        // 64 bit indices with limits beyond u32
+        assert_eq!(
+            reader.read_mem_type()?,
+            MemType(
+                Limits {
+                    min: 1,
+                    max: Some(1 << 32),
+                    index: IndexType::I64,
+                },
+                Share::Shared
+            )
+        );
+        Ok(())
+    }
 }
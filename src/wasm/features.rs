@@ -0,0 +1,610 @@
+use std::fmt;
+
+use anyhow::*;
+
+use crate::structure::{
+    instructions::{BlockType, Expr, Instruction},
+    module::{DataMode, ElemMode, ImportDesc, Module},
+    types::{
+        CompositeType, FieldType, FuncType, HeapType, IndexType, Limits, MemType, RefType,
+        ResultType, Share, SubType, TableType, ValType,
+    },
+};
+
+/// Post-MVP proposals which a module may use
+/// https://github.com/WebAssembly/proposals/blob/main/finished-proposals.md
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    SignExtension,
+    MultiValue,
+    ReferenceTypes,
+    BulkMemory,
+    Simd,
+    RelaxedSimd,
+    TailCall,
+    MultiMemory,
+    Memory64,
+    Threads,
+    Exceptions,
+    FunctionReferences,
+    Gc,
+    ExtendedConst,
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Feature::SignExtension => "sign-extension",
+            Feature::MultiValue => "multi-value",
+            Feature::ReferenceTypes => "reference-types",
+            Feature::BulkMemory => "bulk-memory",
+            Feature::Simd => "simd",
+            Feature::RelaxedSimd => "relaxed-simd",
+            Feature::TailCall => "tail-call",
+            Feature::MultiMemory => "multi-memory",
+            Feature::Memory64 => "memory64",
+            Feature::Threads => "threads",
+            Feature::Exceptions => "exceptions",
+            Feature::FunctionReferences => "function-references",
+            Feature::Gc => "gc",
+            Feature::ExtendedConst => "extended-const",
+        })
+    }
+}
+
+/// Proposals enabled for decoding and validation.
+/// The default enables the ones standardized in WebAssembly 2.0.
+/// A proposal compiled out by its cargo feature is never enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WasmFeatures {
+    pub sign_extension: bool,
+    pub multi_value: bool,
+    pub reference_types: bool,
+    pub bulk_memory: bool,
+    pub simd: bool,
+    pub relaxed_simd: bool,
+    pub tail_call: bool,
+    pub multi_memory: bool,
+    pub memory64: bool,
+    pub threads: bool,
+    pub exceptions: bool,
+    pub function_references: bool,
+    pub gc: bool,
+    pub extended_const: bool,
+}
+
+impl Default for WasmFeatures {
+    fn default() -> Self {
+        Self {
+            sign_extension: true,
+            multi_value: true,
+            reference_types: true,
+            bulk_memory: true,
+            simd: true,
+            relaxed_simd: false,
+            tail_call: false,
+            multi_memory: false,
+            memory64: false,
+            threads: false,
+            exceptions: false,
+            function_references: false,
+            gc: false,
+            extended_const: false,
+        }
+    }
+}
+
+impl WasmFeatures {
+    /// WebAssembly 1.0 without any proposal
+    pub fn mvp() -> Self {
+        Self {
+            sign_extension: false,
+            multi_value: false,
+            reference_types: false,
+            bulk_memory: false,
+            simd: false,
+            relaxed_simd: false,
+            tail_call: false,
+            multi_memory: false,
+            memory64: false,
+            threads: false,
+            exceptions: false,
+            function_references: false,
+            gc: false,
+            extended_const: false,
+        }
+    }
+
+    pub fn all() -> Self {
+        Self {
+            sign_extension: true,
+            multi_value: true,
+            reference_types: true,
+            bulk_memory: true,
+            simd: true,
+            relaxed_simd: true,
+            tail_call: true,
+            multi_memory: true,
+            memory64: true,
+            threads: true,
+            exceptions: true,
+            function_references: true,
+            gc: true,
+            extended_const: true,
+        }
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::SignExtension => cfg!(feature = "sign-extension") && self.sign_extension,
+            Feature::MultiValue => cfg!(feature = "multi-value") && self.multi_value,
+            Feature::ReferenceTypes => cfg!(feature = "reference-types") && self.reference_types,
+            Feature::BulkMemory => cfg!(feature = "bulk-memory") && self.bulk_memory,
+            Feature::Simd => cfg!(feature = "simd") && self.simd,
+            Feature::RelaxedSimd => {
+                cfg!(feature = "relaxed-simd")
+                    && self.is_enabled(Feature::Simd)
+                    && self.relaxed_simd
+            }
+            Feature::TailCall => cfg!(feature = "tail-call") && self.tail_call,
+            Feature::MultiMemory => cfg!(feature = "multi-memory") && self.multi_memory,
+            Feature::Memory64 => cfg!(feature = "memory64") && self.memory64,
+            Feature::Threads => cfg!(feature = "threads") && self.threads,
+            Feature::Exceptions => cfg!(feature = "exceptions") && self.exceptions,
+            Feature::FunctionReferences => {
+                cfg!(feature = "function-references") && self.function_references
+            }
+            Feature::Gc => {
+                cfg!(feature = "gc") && self.is_enabled(Feature::FunctionReferences) && self.gc
+            }
+            Feature::ExtendedConst => cfg!(feature = "extended-const") && self.extended_const,
+        }
+    }
+
+    pub fn check(&self, feature: Feature) -> Result<()> {
+        if !self.is_enabled(feature) {
+            bail!("feature not enabled: {}", feature)
+        }
+        Ok(())
+    }
+
+    pub fn check_val_type(&self, val_type: ValType) -> Result<()> {
+        match val_type {
+            ValType::Number(_) => Ok(()),
+            ValType::Vec => self.check(Feature::Simd),
+            ValType::Ref(RefType { nullable, heap }) => {
+                self.check(Feature::ReferenceTypes)?;
+                if !nullable || matches!(heap, HeapType::Type(_)) {
+                    self.check(Feature::FunctionReferences)?;
+                }
+                if matches!(heap, HeapType::Exn | HeapType::NoExn) {
+                    self.check(Feature::Exceptions)?;
+                }
+                if !matches!(
+                    heap,
+                    HeapType::Func | HeapType::Extern | HeapType::Exn | HeapType::Type(_)
+                ) {
+                    self.check(Feature::Gc)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn check_func_type(
+        &self,
+        FuncType(ResultType(params), ResultType(results)): &FuncType,
+    ) -> Result<()> {
+        if results.len() > 1 {
+            self.check(Feature::MultiValue)?;
+        }
+        for val_type in params.iter().chain(results) {
+            self.check_val_type(*val_type)?;
+        }
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/gc/core/syntax/types.html#recursive-types
+    fn check_sub_type(&self, sub_type: &SubType) -> Result<()> {
+        if !sub_type.is_final || !sub_type.supers.is_empty() {
+            self.check(Feature::Gc)?;
+        }
+        match &sub_type.composite {
+            CompositeType::Func(func_type) => self.check_func_type(func_type),
+            CompositeType::Struct(fields) => {
+                self.check(Feature::Gc)?;
+                for FieldType(_, storage) in fields {
+                    self.check_val_type(storage.unpack())?;
+                }
+                Ok(())
+            }
+            CompositeType::Array(FieldType(_, storage)) => {
+                self.check(Feature::Gc)?;
+                self.check_val_type(storage.unpack())
+            }
+        }
+    }
+
+    fn check_limits(&self, limits: &Limits) -> Result<()> {
+        if limits.index == IndexType::I64 {
+            self.check(Feature::Memory64)?;
+        }
+        Ok(())
+    }
+
+    fn check_table_type(&self, TableType(limits, ref_type): &TableType) -> Result<()> {
+        if *ref_type != RefType::FUNCREF {
+            self.check_val_type(ValType::Ref(*ref_type))?;
+        }
+        self.check_limits(limits)
+    }
+
+    fn check_mem_type(&self, MemType(limits, share): &MemType) -> Result<()> {
+        if *share == Share::Shared {
+            self.check(Feature::Threads)?;
+        }
+        self.check_limits(limits)
+    }
+
+    /// check an instruction itself, excluding the instructions nested in it
+    pub fn check_instruction(&self, instr: &Instruction) -> Result<()> {
+        use Instruction::*;
+        if instr.memarg().is_some_and(|memarg| memarg.memory != 0) {
+            self.check(Feature::MultiMemory)?;
+        }
+        match instr {
+            I32Extend8S | I32Extend16S => self.check(Feature::SignExtension),
+            Block(BlockType::TypeIdx(_), _)
+            | Loop(BlockType::TypeIdx(_), _)
+            | If(BlockType::TypeIdx(_), _, _) => self.check(Feature::MultiValue),
+            Block(BlockType::ValType(val_type), _)
+            | Loop(BlockType::ValType(val_type), _)
+            | If(BlockType::ValType(val_type), _, _) => self.check_val_type(*val_type),
+            TryTable(block_type, _, _) => {
+                self.check(Feature::Exceptions)?;
+                match block_type {
+                    BlockType::TypeIdx(_) => self.check(Feature::MultiValue),
+                    BlockType::ValType(val_type) => self.check_val_type(*val_type),
+                    BlockType::Empty => Ok(()),
+                }
+            }
+            Throw(_) | ThrowRef => self.check(Feature::Exceptions),
+            RefNull(heap) => self.check_val_type(ValType::Ref(RefType::null(*heap))),
+            Select(Some(types)) => {
+                self.check(Feature::ReferenceTypes)?;
+                types.iter().try_for_each(|t| self.check_val_type(*t))
+            }
+            CallRef(_) | RefAsNonNull | BrOnNull(_) | BrOnNonNull(_) => {
+                self.check(Feature::FunctionReferences)
+            }
+            ReturnCallRef(_) => {
+                self.check(Feature::TailCall)?;
+                self.check(Feature::FunctionReferences)
+            }
+            BrOnCast(_, from, to) | BrOnCastFail(_, from, to) => {
+                self.check(Feature::Gc)?;
+                self.check_val_type(ValType::Ref(*from))?;
+                self.check_val_type(ValType::Ref(*to))
+            }
+            RefTest(ref_type) | RefCast(ref_type) => {
+                self.check(Feature::Gc)?;
+                self.check_val_type(ValType::Ref(*ref_type))
+            }
+            RefEq | StructNew(_) | StructNewDefault(_) | StructGet(..) | StructGetS(..)
+            | StructGetU(..) | StructSet(..) | ArrayNew(_) | ArrayNewDefault(_)
+            | ArrayNewFixed(..) | ArrayNewData(..) | ArrayNewElem(..) | ArrayGet(_)
+            | ArrayGetS(_) | ArrayGetU(_) | ArraySet(_) | ArrayLen | ArrayFill(_)
+            | ArrayCopy(..) | ArrayInitData(..) | ArrayInitElem(..) | AnyConvertExtern
+            | ExternConvertAny | RefI31 | I31GetS | I31GetU => self.check(Feature::Gc),
+            RefIsNull | RefFunc(_) | TableGet(_) | TableSet(_) | TableSize(_) | TableGrow(_)
+            | TableFill(_) => self.check(Feature::ReferenceTypes),
+            CallIndirect(table, _) if *table != 0 => self.check(Feature::ReferenceTypes),
+            ReturnCallIndirect(table, _) if *table != 0 => {
+                self.check(Feature::TailCall)?;
+                self.check(Feature::ReferenceTypes)
+            }
+            ReturnCall(_) | ReturnCallIndirect(..) => self.check(Feature::TailCall),
+            Vector(op) if op.is_relaxed() => self.check(Feature::RelaxedSimd),
+            V128Const(_) | I8x16Shuffle(_) | Vector(_) | VectorLane(..) | VectorMem(..)
+            | VectorMemLane(..) => self.check(Feature::Simd),
+            AtomicFence | Atomic(..) => self.check(Feature::Threads),
+            MemorySize(memory) | MemoryGrow(memory) if *memory != 0 => {
+                self.check(Feature::MultiMemory)
+            }
+            MemoryInit(_, memory)
+            | MemoryCopy(memory, _)
+            | MemoryCopy(_, memory)
+            | MemoryFill(memory)
+                if *memory != 0 =>
+            {
+                self.check(Feature::BulkMemory)?;
+                self.check(Feature::MultiMemory)
+            }
+            MemoryInit(..) | DataDrop(_) | MemoryCopy(..) | MemoryFill(_) => {
+                self.check(Feature::BulkMemory)
+            }
+            TableInit(_, table) | TableCopy(table, _) | TableCopy(_, table) if *table != 0 => {
+                self.check(Feature::BulkMemory)?;
+                self.check(Feature::ReferenceTypes)
+            }
+            TableInit(..) | TableCopy(..) | TableDrop(_) => self.check(Feature::BulkMemory),
+            _ => Ok(()),
+        }
+    }
+
+    /// check instructions including nested ones
+    pub fn check_expr(&self, expr: &Expr) -> Result<()> {
+        for instr in expr {
+            self.check_instruction(instr)?;
+            match instr {
+                Instruction::Block(_, body)
+                | Instruction::Loop(_, body)
+                | Instruction::TryTable(_, _, body) => self.check_expr(body)?,
+                Instruction::If(_, then, else_) => {
+                    self.check_expr(then)?;
+                    if let Some(else_) = else_ {
+                        self.check_expr(else_)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// check a constant expression, where the arithmetic instructions are an extension
+    /// https://webassembly.github.io/extended-const/core/valid/instructions.html#constant-expressions
+    fn check_const_expr(&self, expr: &Expr) -> Result<()> {
+        use Instruction::*;
+        if expr
+            .iter()
+            .any(|instr| matches!(instr, I32Add | I32Sub | I32Mul | I64Add | I64Sub | I64Mul))
+        {
+            self.check(Feature::ExtendedConst)?;
+        }
+        self.check_expr(expr)
+    }
+
+    /// check the components of a module other than function bodies,
+    /// which are checked when they are decoded or validated
+    pub fn check_module(&self, module: &Module) -> Result<()> {
+        if module.rec_groups.iter().any(|size| *size != 1) {
+            self.check(Feature::Gc)?;
+        }
+        for sub_type in &module.types {
+            self.check_sub_type(sub_type)?;
+        }
+        for import in &module.imports {
+            match &import.desc {
+                ImportDesc::Table(table_type) => self.check_table_type(table_type)?,
+                ImportDesc::Mem(mem_type) => self.check_mem_type(mem_type)?,
+                ImportDesc::Global(global_type) => self.check_val_type(global_type.1)?,
+                ImportDesc::Tag(_) => self.check(Feature::Exceptions)?,
+                ImportDesc::Func(_) => {}
+            }
+        }
+        let num_of_tables = module
+            .imports
+            .iter()
+            .filter(|i| matches!(i.desc, ImportDesc::Table(_)))
+            .count()
+            + module.tables.len();
+        if num_of_tables > 1 {
+            self.check(Feature::ReferenceTypes)?;
+        }
+        for table in &module.tables {
+            self.check_table_type(&table.type_)?;
+        }
+        let num_of_mems = module
+            .imports
+            .iter()
+            .filter(|i| matches!(i.desc, ImportDesc::Mem(_)))
+            .count()
+            + module.mems.len();
+        if num_of_mems > 1 {
+            self.check(Feature::MultiMemory)?;
+        }
+        for mem in &module.mems {
+            self.check_mem_type(&mem.type_)?;
+        }
+        for func in &module.funcs {
+            for local in &func.locals {
+                self.check_val_type(*local)?;
+            }
+        }
+        if !module.tags.is_empty() {
+            self.check(Feature::Exceptions)?;
+        }
+        for global in &module.globals {
+            self.check_val_type(global.type_.1)?;
+            self.check_const_expr(&global.init)?;
+        }
+        for elem in &module.elems {
+            match &elem.mode {
+                ElemMode::Active { table, offset } => {
+                    if *table != 0 {
+                        self.check(Feature::ReferenceTypes)?;
+                    }
+                    self.check_const_expr(offset)?;
+                }
+                ElemMode::Passive | ElemMode::Declarative => self.check(Feature::BulkMemory)?,
+            }
+            // function indices are represented by `ref.func` which is allowed in the MVP
+            let uses_exprs = elem
+                .init
+                .iter()
+                .any(|init| !matches!(init.as_slice(), [Instruction::RefFunc(_)]));
+            if elem.type_ != RefType::FUNCREF {
+                self.check_val_type(ValType::Ref(elem.type_))?;
+            } else if uses_exprs {
+                self.check(Feature::ReferenceTypes)?;
+            }
+            if uses_exprs {
+                for init in &elem.init {
+                    self.check_const_expr(init)?;
+                }
+            }
+        }
+        for data in &module.datas {
+            match &data.mode {
+                DataMode::Active { offset, .. } => self.check_const_expr(offset)?,
+                DataMode::Passive => self.check(Feature::BulkMemory)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use super::{Feature, WasmFeatures};
+    use crate::binary::module::decode_slice_with_features;
+
+    #[test]
+    #[cfg(all(
+        feature = "multi-value",
+        feature = "reference-types",
+        feature = "bulk-memory"
+    ))]
+    fn decode_rejects_disabled_features() -> Result<()> {
+        let cases = [
+            (
+                "(func (result i32 i32) i32.const 0 i32.const 1)",
+                "feature not enabled: multi-value",
+            ),
+            (
+                r#"(memory 1) (data "abc")"#,
+                "feature not enabled: bulk-memory",
+            ),
+            (
+                "(table 1 externref)",
+                "feature not enabled: reference-types",
+            ),
+            (
+                "(global (mut funcref) (ref.null func))",
+                "feature not enabled: reference-types",
+            ),
+        ];
+        for (wat, expected) in cases {
+            //Given
+            let wasm = wasmer::wat2wasm(wat.as_bytes())?;
+            //When
+            let err = decode_slice_with_features(&wasm, &WasmFeatures::mvp()).unwrap_err();
+            //Then
+            assert_eq!(err.to_string(), expected, "{}", wat);
+            assert!(decode_slice_with_features(&wasm, &WasmFeatures::default()).is_ok());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_disabled_instructions_in_bodies() -> Result<()> {
+        //Given
+        let wasm = wasmer::wat2wasm(b"(func (result i32) i32.const 1 i32.extend8_s)")?;
+        //When
+        let module = decode_slice_with_features(&wasm, &WasmFeatures::mvp())?;
+        let errors = module.decode_bodies();
+        //Then
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].1.to_string(),
+            "feature not enabled: sign-extension"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "sign-extension", feature = "bulk-memory"))]
+    fn validate_rejects_disabled_instructions() -> Result<()> {
+        use crate::{text::parse, validate::validate_with_features};
+        //Given
+        let module = parse(
+            "(table 2 funcref) (func (param i32) (result i32) local.get 0 i32.extend16_s)
+             (func (table.copy (i32.const 0) (i32.const 0) (i32.const 1)))
+             (elem (i32.const 0) func 0 1)",
+        )?;
+        let features = WasmFeatures {
+            sign_extension: false,
+            ..WasmFeatures::mvp()
+        };
+        //When
+        let err = validate_with_features(&module, &features).unwrap_err();
+        //Then
+        assert_eq!(
+            err.to_string(),
+            "func[0]: instruction 1 (i32.extend16_s): feature not enabled: sign-extension"
+        );
+        let features = WasmFeatures {
+            sign_extension: true,
+            ..features
+        };
+        let err = validate_with_features(&module, &features).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "func[1]: instruction 3 (table.copy 0 0): feature not enabled: bulk-memory"
+        );
+        assert!(validate_with_features(
+            &module,
+            &WasmFeatures {
+                bulk_memory: true,
+                ..features
+            }
+        )
+        .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "extended-const")]
+    fn check_extended_constant_expressions() -> Result<()> {
+        use crate::{text::parse, validate::validate_with_features};
+        //Given
+        let module = parse(
+            r#"(import "env" "base" (global $base i32))
+               (memory 1)
+               (global i64 (i64.mul (i64.const 2) (i64.const 3)))
+               (data (i32.add (global.get $base) (i32.const 4)) "a")"#,
+        )?;
+        let features = WasmFeatures {
+            extended_const: true,
+            ..WasmFeatures::default()
+        };
+        //When
+        let err = validate_with_features(&module, &WasmFeatures::default()).unwrap_err();
+        //Then
+        assert_eq!(err.to_string(), "feature not enabled: extended-const");
+        assert!(validate_with_features(&module, &features).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn features_not_in_default() {
+        let features = WasmFeatures::default();
+        assert!(!features.is_enabled(Feature::TailCall));
+        assert!(!features.is_enabled(Feature::Threads));
+        assert!(!features.is_enabled(Feature::RelaxedSimd));
+        assert!(!features.is_enabled(Feature::MultiMemory));
+        assert!(!features.is_enabled(Feature::Memory64));
+        assert!(!features.is_enabled(Feature::Exceptions));
+        assert!(!features.is_enabled(Feature::FunctionReferences));
+        assert!(!features.is_enabled(Feature::Gc));
+        assert!(!features.is_enabled(Feature::ExtendedConst));
+        assert!(!WasmFeatures {
+            function_references: false,
+            ..WasmFeatures::all()
+        }
+        .is_enabled(Feature::Gc));
+        assert!(!WasmFeatures {
+            simd: false,
+            ..WasmFeatures::all()
+        }
+        .is_enabled(Feature::RelaxedSimd));
+        // proposals compiled out are never enabled
+        assert_eq!(
+            WasmFeatures::all().is_enabled(Feature::Threads),
+            cfg!(feature = "threads")
+        );
+    }
+}
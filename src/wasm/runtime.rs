@@ -1,377 +1,1088 @@
-use crate::module::Module;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[cfg(feature = "threads")]
+mod atomic;
+mod exception;
+mod gc;
+mod memory;
+mod trap;
+#[cfg(feature = "simd")]
+mod vector;
+
+pub use exception::Exception;
+use gc::{Heap, Object};
+pub use memory::SharedMemory;
+use memory::{address, effective, Memory, PAGE_SIZE};
+pub use trap::Trap;
 
-use super::grammer::{
-    instruction::Instruction,
-    section::ExportDesc,
-    types::FuncType,
-    value::{Function, Value},
-};
 use anyhow::{bail, Context, Result};
-use std::collections::HashMap;
 
-use std::ops::{Shl, Shr};
+use crate::features::WasmFeatures;
+use crate::structure::{
+    instructions::{
+        memory::{MemoryKind, MemoryOp},
+        BlockType, Catch, Expr, Instruction, MemArg,
+    },
+    module::{
+        indices::{FuncIdx, TypeIdx},
+        DataMode, ElemMode, ExportDesc, Import, ImportDesc, Module,
+    },
+    types::{FuncType, GlobalType, Limits, Mut, NumType, RefType, ResultType, TableType, ValType},
+    values::Value,
+};
+
+/// Frames live on the heap rather than the Rust stack,
+/// so that the limit only bounds the memory which runaway recursion takes
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1 << 16;
+
+/// the number of elements beyond which `table.grow` fails, even when tables have no maximum
+const MAX_TABLE_SIZE: u32 = 10_000_000;
+
+macro_rules! unop {
+    ($self:expr, $variant:ident, $f:expr) => {{
+        let value = match $self.stack_pop()? {
+            Value::$variant(v) => $f(v),
+            v => bail!("unexpected value: {:?}", v),
+        };
+        $self.stack.push(Value::from(value));
+    }};
+}
 
-#[macro_export]
 macro_rules! binop {
-    ($self:expr, $f:expr, $ty:ty) => {{
-        let b = $self.stack_pop()?;
-        let a = $self.stack_pop()?;
-
-        let result = match (a, b) {
-            (Value::I32(lhs), Value::I32(rhs)) => $f(lhs as $ty, rhs as $ty) as $ty,
-            (Value::I64(lhs), Value::I64(rhs)) => $f(lhs as $ty, rhs as $ty) as $ty,
-            (Value::F32(lhs), Value::F32(rhs)) => $f(lhs as $ty, rhs as $ty) as $ty,
-            (Value::F64(lhs), Value::F64(rhs)) => $f(lhs as $ty, rhs as $ty) as $ty,
-            _ => panic!("Unsupported opration"),
+    ($self:expr, $variant:ident, $f:expr) => {{
+        let (b, a) = ($self.stack_pop()?, $self.stack_pop()?);
+        let value = match (a, b) {
+            (Value::$variant(lhs), Value::$variant(rhs)) => $f(lhs, rhs),
+            (a, b) => bail!("unexpected values: {:?}, {:?}", a, b),
         };
-        $self.stack.push(result.into());
-        Ok::<(), anyhow::Error>(())
+        $self.stack.push(Value::from(value));
     }};
 }
 
-#[derive(Debug, Default)]
-pub struct Runtime {
-    exports: HashMap<String, ExportDesc>,
-    functions: Vec<Function>, // for fetch instructions of function
-    frames: Vec<Frame>,       // stack frame
-    stack: Vec<Value>,        // value stack
+/// Options of execution which don't change the validity of modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeOptions {
+    /// run relaxed SIMD instructions with the deterministic semantics the spec defines,
+    /// instead of the cheaper choices that x86 hardware makes
+    pub deterministic: bool,
+    /// the number of nested calls beyond which a call traps with "call stack exhausted"
+    pub max_call_depth: usize,
+    /// validate function bodies when they are called first instead of at instantiation,
+    /// so that the bodies never called are not decoded,
+    /// while a module with an invalid body is instantiated until the body is called
+    pub lazy_validation: bool,
 }
 
-impl Runtime {
-    pub fn new(module: &mut Module) -> Result<Self> {
-        let functions = new_functions(module)?;
-        let mut exports = HashMap::<String, ExportDesc>::new();
-        for ex in module
-            .export_section
-            .as_ref()
-            .context("not found export section")?
-            .iter()
-        {
-            exports.insert(ex.name.clone(), ex.desc.clone());
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        Self {
+            deterministic: false,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            lazy_validation: false,
         }
-        Ok(Self {
-            exports,
-            functions,
-            frames: vec![],
-            stack: vec![],
-        })
     }
+}
 
-    pub fn invoke(&mut self, func_name: &String, args: &[Value]) -> Result<Option<Value>> {
-        let func = self.resolve_func(func_name)?;
-        let frame = Frame::new(func.body.clone(), args);
-        self.frames.push(frame);
-        self.execute()
+/// External values which imports are resolved to, which can be memories
+/// and values of immutable globals only
+#[derive(Debug, Clone, Default)]
+pub struct Imports {
+    mems: HashMap<(String, String), SharedMemory>,
+    globals: HashMap<(String, String), Value>,
+}
+
+impl Imports {
+    /// provide a memory, which is shared or created by `SharedMemory::unshared`
+    /// to match the type of the import
+    pub fn add_memory(&mut self, module: &str, name: &str, memory: SharedMemory) {
+        self.mems.insert((module.into(), name.into()), memory);
     }
 
-    fn resolve_func(&mut self, func_name: &String) -> Result<&Function> {
-        let desc = self.exports.get(func_name).context("not found function")?;
-        let idx = match desc {
-            ExportDesc::Func(i) => *i,
-            _ => bail!("invalid export desc: {:?}", desc),
-        };
-        self.functions.get(idx as usize).context("")
+    /// provide the value of an immutable global, such as `__memory_base` of dynamic linking
+    pub fn add_global(&mut self, module: &str, name: &str, value: Value) {
+        self.globals.insert((module.into(), name.into()), value);
     }
+}
 
-    fn stack_pop(&mut self) -> Result<Value> {
-        self.stack.pop().context("not found variable from stack")
+fn resolve<'a, T>(externs: &'a HashMap<(String, String), T>, import: &Import) -> Result<&'a T> {
+    externs
+        .get(&(import.module.to_string(), import.name.to_string()))
+        .with_context(|| format!("unknown import {}.{}", import.module, import.name))
+}
+
+/// A tree-walking interpreter of a module instance
+/// https://webassembly.github.io/spec/core/exec/index.html
+#[derive(Debug)]
+pub struct Runtime<'m> {
+    module: Rc<Module<'m>>,
+    tables: Vec<Table>,
+    mems: Vec<Memory>,
+    globals: Vec<Value>,
+    /// element segments, emptied when they are dropped
+    elems: Vec<Vec<Value>>,
+    /// data segments, emptied when they are dropped
+    datas: Vec<Vec<u8>>,
+    /// caught exceptions which `exnref` values point to
+    exceptions: Heap<Exception>,
+    /// structures and arrays
+    heap: Heap<Object>,
+    /// references returned to the host, which are roots until the host releases them
+    pinned: Vec<Value>,
+    stack: Vec<Value>, // value stack
+    /// locals of the active calls, which frames point into
+    locals: Vec<Value>,
+    options: RuntimeOptions,
+    /// the proposals which function bodies are validated against when they are called first,
+    /// if validation is lazy
+    features: WasmFeatures,
+    /// whether the body of each function has been validated
+    validated: Vec<bool>,
+}
+
+#[derive(Debug)]
+struct Table {
+    elems: Vec<Value>,
+    max: Option<u32>,
+}
+
+/// An activation of a function on the call stack, which the interpreter keeps on the heap
+/// https://webassembly.github.io/spec/core/exec/runtime.html#activations-and-frames
+#[derive(Debug)]
+pub struct Frame<'a> {
+    /// the function being called, which is none for constant expressions
+    func: Option<FuncIdx>,
+    /// the index of the first local in the locals of the runtime,
+    /// which are kept there so that collections can trace them
+    base: usize,
+    /// the height of the value stack below the arguments
+    height: usize,
+    /// the number of results
+    arity: usize,
+    /// the blocks being executed, from the body of the function to the innermost one
+    labels: Vec<Label<'a>>,
+}
+
+/// A block being executed with the position of its next instruction
+/// https://webassembly.github.io/spec/core/exec/runtime.html#labels
+#[derive(Debug)]
+struct Label<'a> {
+    kind: LabelKind<'a>,
+    instrs: &'a [Instruction],
+    pc: usize,
+    /// the height of the value stack below the parameters
+    height: usize,
+    /// the number of values which a branch to the label passes
+    arity: usize,
+}
+
+#[derive(Debug)]
+enum LabelKind<'a> {
+    Block,
+    /// branches continue the loop
+    Loop,
+    /// exceptions thrown inside are caught by the clauses
+    #[cfg_attr(not(feature = "exceptions"), allow(dead_code))]
+    TryTable(&'a [Catch]),
+}
+
+impl<'m> Runtime<'m> {
+    /// validate and instantiate the module, running its start function
+    /// https://webassembly.github.io/spec/core/exec/modules.html#instantiation
+    pub fn new(module: Module<'m>) -> Result<Self> {
+        Self::new_with_features(module, &WasmFeatures::default())
     }
 
-    fn execute(&mut self) -> Result<Option<Value>> {
-        while let Some(inst) = self.instruction()? {
-            self.frame_pc_inc()?;
-            match inst {
-                Instruction::LocalGet(idx) => {
-                    let value = self
-                        .current_frame()?
-                        .local_stack
-                        .get(idx as usize)
-                        .context("not found local variable")?;
-                    self.stack.push(value.clone());
-                }
-                Instruction::I32Add => {
-                    binop!(self, |a, b| a + b, i32)?;
-                }
-                Instruction::I32Sub => {
-                    binop!(self, |a, b| a - b, i32)?;
-                }
-                Instruction::I32Mul => {
-                    binop!(self, |a, b| a * b, i32)?;
-                }
-                Instruction::I32Clz => {
-                    let v = self.stack_pop()?;
-                    match v {
-                        Value::I32(v) => self.stack.push(Value::I32(v.leading_zeros() as i32)),
-                        _ => bail!("unexpected value"),
-                    }
-                }
-                Instruction::I32Ctz => {
-                    let v = self.stack_pop()?;
-                    match v {
-                        Value::I32(v) => self.stack.push(Value::I32(v.trailing_zeros() as i32)),
-                        _ => bail!("unexpected value"),
-                    }
-                }
-                Instruction::I32DivU => {
-                    binop!(self, |a, b| a / b, i32)?;
-                }
-                Instruction::I32DivS => {
-                    binop!(self, |a, b| a / b, i32)?;
-                }
-                Instruction::I32Eq => {
-                    binop!(self, |a, b| a == b, i32)?;
-                }
-                Instruction::I32Eqz => {
-                    let v = self.stack_pop()?;
-                    self.stack.push(i32::from(v == Value::from(0)).into());
-                }
-                Instruction::I32Ne => {
-                    binop!(self, |a, b| a != b, i32)?;
-                }
-                Instruction::I32LtS => {
-                    binop!(self, |a, b| a < b, i32)?;
-                }
-                Instruction::I32LtU => {
-                    binop!(self, |a, b| a < b, i32)?;
-                }
-                Instruction::I32GtS => {
-                    binop!(self, |a, b| a > b, i32)?;
-                }
-                Instruction::I32GtU => {
-                    binop!(self, |a, b| a > b, i32)?;
-                }
-                Instruction::I32LeS => {
-                    binop!(self, |a, b| a <= b, i32)?;
-                }
-                Instruction::I32LeU => {
-                    binop!(self, |a, b| a <= b, i32)?;
-                }
-                Instruction::I32GeS => {
-                    binop!(self, |a, b| a >= b, i32)?;
-                }
-                Instruction::I32GeU => {
-                    binop!(self, |a, b| a >= b, i32)?;
-                }
-                Instruction::I32Popcnt => {
-                    let value = self.stack_pop()?;
-                    match value {
-                        Value::I32(v) => self.stack.push(v.count_ones().into()),
-                        _ => bail!("unexpected value"),
-                    }
-                }
-                Instruction::I32RemU => {
-                    binop!(self, |a, b| a % b, i32)?;
-                }
-                Instruction::I32RemS => {
-                    binop!(self, |a, b| a % b, i32)?;
-                }
-                Instruction::I32And => {
-                    binop!(self, |a: i32, b: i32| a & b, i32)?;
-                }
-                Instruction::I32Or => {
-                    binop!(self, |a: i32, b: i32| a | b, i32)?;
-                }
-                Instruction::I32Xor => {
-                    binop!(self, |a: i32, b: i32| a ^ b, i32)?;
-                }
-                Instruction::I32ShL => {
-                    binop!(self, |a: i32, b: i32| a.shl(b), i32)?;
-                }
-                Instruction::I32ShrU => {
-                    binop!(self, |a: i32, b: i32| a.shr(b), i32)?;
-                }
-                Instruction::I32ShrS => {
-                    binop!(self, |a: i32, b: i32| a.shr(b), i32)?;
-                }
-                Instruction::I32RtoL => {
-                    binop!(self, |a: i32, b: i32| a.rotate_left(b as u32), i32)?;
-                }
-                Instruction::I32RtoR => {
-                    binop!(self, |a: i32, b: i32| a.rotate_right(b as u32), i32)?;
-                }
-                Instruction::I32Extend8S => {
-                    let value = self.stack_pop()?;
-                    match value {
-                        Value::I32(v) => {
-                            let result = v << 24 >> 24;
-                            self.stack.push(result.into());
-                        }
-                        _ => bail!("unexpected value type"),
-                    }
-                }
-                Instruction::I32Extend16S => {
-                    let value = self.stack_pop()?;
-                    match value {
-                        Value::I32(v) => {
-                            let result = v << 16 >> 16;
-                            self.stack.push(result.into());
-                        }
-                        _ => bail!("unexpected value type"),
-                    }
-                }
-                Instruction::I32Const(v) => {
-                    self.stack.push(v.into());
-                }
-                Instruction::Return => {
-                    self.frames.pop();
-                }
-                Instruction::Void | Instruction::End => {
-                    // do nothing
+    /// the same as `new` but validates the module against `features`
+    pub fn new_with_features(module: Module<'m>, features: &WasmFeatures) -> Result<Self> {
+        Self::new_with_options(module, features, RuntimeOptions::default())
+    }
+
+    /// the same as `new_with_features` but executes the module with `options`
+    pub fn new_with_options(
+        module: Module<'m>,
+        features: &WasmFeatures,
+        options: RuntimeOptions,
+    ) -> Result<Self> {
+        Self::new_with_imports(module, features, options, &Imports::default())
+    }
+
+    /// the same as `new_with_options` but resolves imports to `imports`.
+    /// Tags can't be imported, since exceptions are identified by the tags of the instance.
+    pub fn new_with_imports(
+        module: Module<'m>,
+        features: &WasmFeatures,
+        options: RuntimeOptions,
+        imports: &Imports,
+    ) -> Result<Self> {
+        if options.lazy_validation {
+            crate::validate::validate_declarations(&module, features)?;
+        } else {
+            crate::validate::validate_with_features(&module, features)?;
+        }
+        let num_of_funcs = module.funcs.len();
+        let mut runtime = Self {
+            module: Rc::new(module),
+            tables: vec![],
+            mems: vec![],
+            globals: vec![],
+            elems: vec![],
+            datas: vec![],
+            exceptions: Heap::default(),
+            heap: Heap::default(),
+            pinned: vec![],
+            stack: vec![],
+            locals: vec![],
+            options,
+            features: *features,
+            validated: vec![!options.lazy_validation; num_of_funcs],
+        };
+        let module = runtime.module.clone();
+        for import in &module.imports {
+            match &import.desc {
+                ImportDesc::Mem(type_) => {
+                    let memory = resolve(&imports.mems, import)?;
+                    memory.matches(type_)?;
+                    runtime.mems.push(Memory::Shared(memory.clone()));
                 }
-                Instruction::If => {
-                    let v = self.stack_pop()?;
-                    if v != 1.into() {
-                        loop {
-                            let ins = self.instruction()?.context("not found instruction")?;
-                            if ins == Instruction::End || ins == Instruction::Else {
-                                self.frame_pc_inc()?;
-                                break;
-                            }
-                            self.frame_pc_inc()?;
-                        }
+                ImportDesc::Global(GlobalType(Mut::Const, val_type)) => {
+                    let value = *resolve(&imports.globals, import)?;
+                    if !runtime.is_instance(&value, *val_type)? {
+                        bail!(
+                            "incompatible import type: expected {}, found {}",
+                            val_type,
+                            value.type_()
+                        )
                     }
+                    runtime.globals.push(value);
                 }
-                Instruction::Call(func_idx) => {
-                    let func = self
-                        .functions
-                        .get(func_idx as usize)
-                        .context("not found function with index")?;
-                    let body = func.body.clone();
-
-                    let mut args = vec![];
-                    for _ in 0..func.func_type.params.len() {
-                        args.push(self.stack_pop()?);
-                    }
-                    let frame = Frame::new(body, &args);
-                    self.frames.push(frame);
-                    let result = self.execute()?;
-                    if let Some(value) = result {
-                        self.stack.push(value);
-                    }
-                }
-                _ => {
-                    dbg!(inst);
-                    dbg!(self.instructions()?);
-                    unimplemented!()
+                ImportDesc::Tag(_) => bail!("tag imports are not supported"),
+                _ => bail!("imports other than memories and immutable globals are not supported"),
+            }
+        }
+        // constant expressions may refer to the imported globals and the ones defined before
+        for global in &module.globals {
+            let value = runtime.eval_const(&global.init)?;
+            runtime.globals.push(value);
+        }
+        for table in &module.tables {
+            let TableType(Limits { min, max, .. }, ref_type) = table.type_;
+            runtime.tables.push(Table {
+                elems: vec![runtime.default_of(ValType::Ref(ref_type)); min as usize],
+                max: max.map(|max| max as u32),
+            });
+        }
+        for mem in &module.mems {
+            runtime.mems.push(Memory::new(&mem.type_));
+        }
+        for elem in &module.elems {
+            let refs = elem
+                .init
+                .iter()
+                .map(|init| runtime.eval_const(init))
+                .collect::<Result<_>>()?;
+            runtime.elems.push(refs);
+        }
+        for (idx, elem) in module.elems.iter().enumerate() {
+            match &elem.mode {
+                ElemMode::Active { table, offset } => {
+                    let offset = runtime.eval_const(offset)?;
+                    let n = elem.init.len() as i32;
+                    runtime.stack.extend([offset, Value::I32(0), Value::I32(n)]);
+                    runtime.table_init(idx as u32, *table)?;
+                    runtime.elems[idx].clear();
                 }
-            };
+                ElemMode::Declarative => runtime.elems[idx].clear(),
+                ElemMode::Passive => {}
+            }
+        }
+        runtime.datas = module.datas.iter().map(|data| data.init.to_vec()).collect();
+        for (idx, data) in module.datas.iter().enumerate() {
+            if let DataMode::Active { memory, offset } = &data.mode {
+                let offset = runtime.eval_const(offset)?;
+                let n = data.init.len() as i32;
+                runtime.stack.extend([offset, Value::I32(0), Value::I32(n)]);
+                runtime.memory_init(idx as u32, *memory)?;
+                runtime.datas[idx].clear();
+            }
         }
-        Ok(self.stack.pop())
+        if let Some(start) = &module.start {
+            runtime.call(start.func)?;
+        }
+        Ok(runtime)
     }
 
-    fn instruction(&mut self) -> Result<Option<Instruction>> {
-        loop {
-            let frame = self.frames.last();
-
-            if frame.is_none() {
-                return Ok(None);
+    /// call an exported function, returning its results.
+    /// References in the results and in uncaught exceptions stay alive until they are released.
+    pub fn invoke(&mut self, func_name: &str, args: &[Value]) -> Result<Vec<Value>> {
+        let idx = self.resolve_func(func_name)?;
+        let FuncType(ResultType(params), ResultType(results)) = self.func_type(idx);
+        let is_valid = args.len() == params.len()
+            && args
+                .iter()
+                .zip(params)
+                .map(|(arg, param)| self.is_instance(arg, *param))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .all(|is_instance| is_instance);
+        if !is_valid {
+            let arg_types: Vec<ValType> = args.iter().map(Value::type_).collect();
+            bail!(
+                "function {} expects {:?} but got {:?}",
+                func_name,
+                params,
+                arg_types
+            )
+        }
+        let num_of_results = results.len();
+        self.stack.clear();
+        self.stack.extend_from_slice(args);
+        if let Err(err) = self.call(idx) {
+            if let Some(exception) = err.downcast_ref::<Exception>() {
+                self.pin(&exception.values);
             }
-            let insts = self.instructions()?;
-            let inst = insts.get(self.frame_pc()?);
+            return Err(err);
+        }
+        let results = self.stack.split_off(self.stack.len() - num_of_results);
+        self.stack.clear();
+        self.pin(&results);
+        Ok(results)
+    }
 
-            if inst.is_some() {
-                return Ok(inst.cloned());
+    /// whether the value has the type, where functions and objects have the types of their definitions
+    /// https://webassembly.github.io/gc/core/valid/matching.html#reference-types
+    fn is_instance(&self, value: &Value, val_type: ValType) -> Result<bool> {
+        let ValType::Ref(RefType { nullable, heap }) = val_type else {
+            return Ok(value.type_() == val_type);
+        };
+        Ok(match self.heap_type_of(value)? {
+            Some(actual) => self.module.matches_heap(actual, heap),
+            None => {
+                let ValType::Ref(RefType { heap: top, .. }) = value.type_() else {
+                    return Ok(false);
+                };
+                nullable && top == self.module.top_heap(heap)
             }
-            self.frames.pop();
+        })
+    }
+
+    /// nulls belong to the hierarchies of their types
+    /// https://webassembly.github.io/spec/core/exec/runtime.html#default-val
+    fn default_of(&self, val_type: ValType) -> Value {
+        match val_type {
+            ValType::Ref(ref_type) => Value::null(self.module.top_heap(ref_type.heap)),
+            _ => Value::default_of(val_type),
+        }
+    }
+
+    fn resolve_func(&self, func_name: &str) -> Result<FuncIdx> {
+        let export = self.module.exports.iter().find(|e| e.name == func_name);
+        match &export.context("not found function")?.desc {
+            ExportDesc::Func(idx) => Ok(*idx),
+            desc => bail!("invalid export desc: {:?}", desc),
+        }
+    }
+
+    /// an exported memory to share with instances on other threads,
+    /// or with other instances when it is imported
+    pub fn shared_memory(&self, name: &str) -> Result<SharedMemory> {
+        let export = self.module.exports.iter().find(|e| e.name == name);
+        match &export.context("not found memory")?.desc {
+            ExportDesc::Mem(idx) => match &self.mems[*idx as usize] {
+                Memory::Shared(memory) => Ok(memory.clone()),
+                Memory::Unshared(_) => bail!("memory {} is not shared", name),
+            },
+            desc => bail!("invalid export desc: {:?}", desc),
+        }
+    }
+
+    /// the memory which `memarg` accesses
+    fn memory(&mut self, memarg: &MemArg) -> &mut Memory {
+        &mut self.mems[memarg.memory as usize]
+    }
+
+    fn func_type(&self, idx: FuncIdx) -> &FuncType {
+        self.type_(self.module.funcs[idx as usize].type_)
+    }
+
+    fn type_(&self, idx: TypeIdx) -> &FuncType {
+        self.module.func_type(idx).expect("validated function type")
+    }
+
+    fn stack_pop(&mut self) -> Result<Value> {
+        self.stack.pop().context("not found variable from stack")
+    }
+
+    fn pop_i32(&mut self) -> Result<i32> {
+        match self.stack_pop()? {
+            Value::I32(v) => Ok(v),
+            v => bail!("unexpected value: {:?}", v),
+        }
+    }
+
+    /// an address or a size into a memory, which is i64 with 64 bit indices
+    fn pop_addr(&mut self) -> Result<u64> {
+        match self.stack_pop()? {
+            Value::I32(v) => Ok(v as u32 as u64),
+            Value::I64(v) => Ok(v as u64),
+            v => bail!("unexpected value: {:?}", v),
         }
     }
 
-    fn instructions(&mut self) -> Result<Vec<Instruction>> {
-        let insts = self
-            .frames
-            .last()
-            .context("not found frame")?
-            .instructions
-            .clone();
-        Ok(insts)
+    /// keep the top `arity` values, dropping the values between them and `height`
+    fn unwind(&mut self, height: usize, arity: usize) {
+        let values = self.stack.split_off(self.stack.len() - arity);
+        self.stack.truncate(height);
+        self.stack.extend(values);
     }
 
-    fn current_frame(&self) -> Result<&Frame> {
-        self.frames.last().context("not found frame")
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#function-calls
+    fn call(&mut self, idx: FuncIdx) -> Result<()> {
+        let module = self.module.clone();
+        let frame = self.frame(&module, idx)?;
+        self.run(&module, vec![frame])
     }
 
-    fn frame_pc(&mut self) -> Result<usize> {
-        Ok(self.frames.last_mut().context("not found frame")?.pc as usize)
+    /// move the arguments on the stack into the locals of a new frame
+    fn frame<'a>(&mut self, module: &'a Module, idx: FuncIdx) -> Result<Frame<'a>> {
+        let func = &module.funcs[idx as usize];
+        if !self.validated[idx as usize] {
+            crate::validate::validate_func_body(module, &self.features, idx as usize)?;
+            self.validated[idx as usize] = true;
+        }
+        let body = func.body.expr()?;
+        let FuncType(ResultType(params), ResultType(results)) = module
+            .func_type(func.type_)
+            .expect("validated function type");
+        let height = self.stack.len() - params.len();
+        let base = self.locals.len();
+        self.locals.extend(self.stack.drain(height..));
+        for local in &func.locals {
+            self.locals.push(self.default_of(*local));
+        }
+        Ok(Frame {
+            func: Some(idx),
+            base,
+            height,
+            arity: results.len(),
+            labels: vec![Label {
+                kind: LabelKind::Block,
+                instrs: body,
+                pc: 0,
+                height,
+                arity: results.len(),
+            }],
+        })
+    }
+
+    /// execute the frames until the call stack is empty,
+    /// in a loop which keeps nested calls off the Rust stack
+    fn run<'a>(&mut self, module: &'a Module, mut frames: Vec<Frame<'a>>) -> Result<()> {
+        let base = frames.first().map_or(self.locals.len(), |frame| frame.base);
+        let result = self.dispatch(module, &mut frames);
+        self.locals.truncate(base);
+        result.map_err(|err| {
+            // uncaught exceptions have unwound the frames and stay distinct from traps
+            let funcs: Vec<FuncIdx> = frames.iter().filter_map(|frame| frame.func).collect();
+            if err.is::<Exception>() || funcs.is_empty() {
+                return err;
+            }
+            Trap::new(err, &funcs, &module.names).into()
+        })
+    }
+
+    fn dispatch<'a>(&mut self, module: &'a Module, frames: &mut Vec<Frame<'a>>) -> Result<()> {
+        loop {
+            let Some(frame) = frames.last_mut() else {
+                return Ok(());
+            };
+            let Some(label) = frame.labels.last_mut() else {
+                // the body of the function has ended or has been returned from
+                self.unwind(frame.height, frame.arity);
+                self.locals.truncate(frame.base);
+                frames.pop();
+                continue;
+            };
+            let instrs = label.instrs;
+            let Some(inst) = instrs.get(label.pc) else {
+                frame.labels.pop();
+                continue;
+            };
+            label.pc += 1;
+            if let Err(err) = self.step(module, frames, inst) {
+                self.catch(frames, err)?;
+            }
+        }
     }
 
-    fn frame_pc_inc(&mut self) -> Result<()> {
-        self.frames.last_mut().context("not found frame")?.pc += 1;
+    /// push a frame for a call on the call stack
+    fn push_frame<'a>(
+        &mut self,
+        module: &'a Module,
+        frames: &mut Vec<Frame<'a>>,
+        idx: FuncIdx,
+    ) -> Result<()> {
+        if frames.len() >= self.options.max_call_depth {
+            bail!("call stack exhausted")
+        }
+        let frame = self.frame(module, idx)?;
+        frames.push(frame);
         Ok(())
     }
-}
 
-pub type Exports = HashMap<String, ExportDesc>;
+    /// replace the current frame by the one of the callee, so that tail calls don't grow the call stack
+    /// https://webassembly.github.io/tail-call/core/exec/instructions.html#exec-return-call
+    #[cfg(feature = "tail-call")]
+    fn tail_call<'a>(
+        &mut self,
+        module: &'a Module,
+        frames: &mut Vec<Frame<'a>>,
+        idx: FuncIdx,
+    ) -> Result<()> {
+        let frame = frames.pop().expect("active frame");
+        let FuncType(ResultType(params), _) = self.func_type(idx);
+        self.unwind(frame.height, params.len());
+        self.locals.truncate(frame.base);
+        let frame = self.frame(module, idx)?;
+        frames.push(frame);
+        Ok(())
+    }
 
-#[derive(Debug)]
-pub struct Frame {
-    local_stack: Vec<Value>,
-    pc: u32,
-    instructions: Vec<Instruction>,
-}
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-call-indirect
+    fn indirect_callee(&mut self, table: u32, type_: u32) -> Result<FuncIdx> {
+        let i = self.pop_i32()? as u32 as usize;
+        let elem = self.tables[table as usize].elems.get(i);
+        let func_idx = match elem.context("undefined element")? {
+            Value::FuncRef(Some(idx)) => *idx,
+            Value::FuncRef(None) => bail!("uninitialized element {}", i),
+            v => bail!("unexpected value: {:?}", v),
+        };
+        if !self
+            .module
+            .is_subtype(self.module.funcs[func_idx as usize].type_, type_)
+        {
+            bail!("indirect call type mismatch")
+        }
+        Ok(func_idx)
+    }
 
-impl Frame {
-    pub fn new(instructions: Vec<Instruction>, args: &[Value]) -> Self {
-        let stack = args.to_owned();
-        Self {
-            local_stack: stack,
+    /// https://webassembly.github.io/function-references/core/exec/instructions.html#exec-call-ref
+    #[cfg(feature = "function-references")]
+    fn ref_callee(&mut self) -> Result<FuncIdx> {
+        match self.stack_pop()? {
+            Value::FuncRef(Some(idx)) => Ok(idx),
+            Value::FuncRef(None) => bail!("null function reference"),
+            v => bail!("unexpected value: {:?}", v),
+        }
+    }
+
+    /// the numbers of parameters and results of a block
+    fn block_arity(&self, block_type: &BlockType) -> (usize, usize) {
+        match block_type {
+            BlockType::Empty => (0, 0),
+            BlockType::ValType(_) => (0, 1),
+            BlockType::TypeIdx(idx) => {
+                let FuncType(ResultType(params), ResultType(results)) = self.type_(*idx);
+                (params.len(), results.len())
+            }
+        }
+    }
+
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-block
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-loop
+    fn enter<'a>(
+        &self,
+        frame: &mut Frame<'a>,
+        block_type: &BlockType,
+        body: &'a Expr,
+        kind: LabelKind<'a>,
+    ) {
+        let (params, results) = self.block_arity(block_type);
+        let arity = match kind {
+            LabelKind::Loop => params,
+            _ => results,
+        };
+        frame.labels.push(Label {
+            kind,
+            instrs: body,
             pc: 0,
-            instructions,
+            height: self.stack.len() - params,
+            arity,
+        });
+    }
+
+    /// branching to a loop continues it, and to another block exits it
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-br
+    fn branch(&mut self, frame: &mut Frame, depth: u32) {
+        let idx = frame.labels.len() - 1 - depth as usize;
+        let label = &mut frame.labels[idx];
+        self.unwind(label.height, label.arity);
+        if let LabelKind::Loop = label.kind {
+            label.pc = 0;
+            frame.labels.truncate(idx + 1);
+        } else {
+            frame.labels.truncate(idx);
         }
     }
 
-    pub fn inc(&mut self) {
-        self.pc += 1;
+    /// execute an instruction, where control instructions move between labels and frames
+    fn step<'a>(
+        &mut self,
+        module: &'a Module,
+        frames: &mut Vec<Frame<'a>>,
+        inst: &'a Instruction,
+    ) -> Result<()> {
+        let frame = frames.last_mut().expect("active frame");
+        match inst {
+            Instruction::Unreachable => bail!("unreachable"),
+            Instruction::Nop => {}
+            Instruction::Block(block_type, body) => {
+                self.enter(frame, block_type, body, LabelKind::Block)
+            }
+            Instruction::Loop(block_type, body) => {
+                self.enter(frame, block_type, body, LabelKind::Loop)
+            }
+            Instruction::If(block_type, then, else_) => {
+                if self.pop_i32()? != 0 {
+                    self.enter(frame, block_type, then, LabelKind::Block);
+                } else if let Some(else_) = else_ {
+                    self.enter(frame, block_type, else_, LabelKind::Block);
+                }
+            }
+            Instruction::Br(depth) => self.branch(frame, *depth),
+            Instruction::BrIf(depth) => {
+                if self.pop_i32()? != 0 {
+                    self.branch(frame, *depth);
+                }
+            }
+            Instruction::BrTable(depths, default) => {
+                let i = self.pop_i32()? as u32 as usize;
+                self.branch(frame, *depths.get(i).unwrap_or(default));
+            }
+            Instruction::Return => frame.labels.clear(),
+            Instruction::Call(func_idx) => self.push_frame(module, frames, *func_idx)?,
+            Instruction::CallIndirect(table, type_) => {
+                let func_idx = self.indirect_callee(*table, *type_)?;
+                self.push_frame(module, frames, func_idx)?;
+            }
+            #[cfg(feature = "tail-call")]
+            Instruction::ReturnCall(func_idx) => self.tail_call(module, frames, *func_idx)?,
+            #[cfg(feature = "tail-call")]
+            Instruction::ReturnCallIndirect(table, type_) => {
+                let func_idx = self.indirect_callee(*table, *type_)?;
+                self.tail_call(module, frames, func_idx)?;
+            }
+            #[cfg(feature = "function-references")]
+            Instruction::CallRef(_) => {
+                let func_idx = self.ref_callee()?;
+                self.push_frame(module, frames, func_idx)?;
+            }
+            #[cfg(all(feature = "tail-call", feature = "function-references"))]
+            Instruction::ReturnCallRef(_) => {
+                let func_idx = self.ref_callee()?;
+                self.tail_call(module, frames, func_idx)?;
+            }
+            #[cfg(feature = "function-references")]
+            Instruction::BrOnNull(depth) => {
+                let value = self.stack_pop()?;
+                if value.is_null() {
+                    self.branch(frame, *depth);
+                } else {
+                    self.stack.push(value);
+                }
+            }
+            #[cfg(feature = "function-references")]
+            Instruction::BrOnNonNull(depth) => {
+                let value = self.stack_pop()?;
+                if !value.is_null() {
+                    self.stack.push(value);
+                    self.branch(frame, *depth);
+                }
+            }
+            #[cfg(feature = "exceptions")]
+            Instruction::TryTable(block_type, catches, body) => {
+                self.enter(frame, block_type, body, LabelKind::TryTable(catches))
+            }
+            #[cfg(feature = "gc")]
+            Instruction::BrOnCast(depth, _, ref_type)
+            | Instruction::BrOnCastFail(depth, _, ref_type) => {
+                let value = self.stack.last().context("empty stack")?;
+                let is_instance = self.is_instance(value, ValType::Ref(*ref_type))?;
+                if is_instance == matches!(inst, Instruction::BrOnCast(..)) {
+                    self.branch(frame, *depth);
+                }
+            }
+            #[cfg(feature = "exceptions")]
+            Instruction::Throw(tag) => self.throw(*tag)?,
+            #[cfg(feature = "exceptions")]
+            Instruction::ThrowRef => self.throw_ref()?,
+            _ => self.instruction(inst, frame)?,
+        };
+        Ok(())
     }
-}
 
-fn new_functions(module: &mut Module) -> Result<Vec<Function>> {
-    let mut functions: Vec<Function> = vec![];
-    // 'idx' is index of function table
-    // 'func_sing_idx' is indx of function signature
-    for (idx, func_sig_idx) in module
-        .function_section
-        .as_ref()
-        .context("not noud function section")?
-        .iter()
-        .enumerate()
-    {
-        let t = module
-            .type_section
-            .as_ref()
-            .context("not found type section")?;
-        let t = t
-            .get(*func_sig_idx as usize)
-            .context("cannot get type section")?;
-
-        let func_type = FuncType {
-            params: t.params.clone(),
-            results: t.results.clone(),
+    /// execute an instruction other than control instructions
+    fn instruction(&mut self, inst: &Instruction, frame: &Frame) -> Result<()> {
+        match inst {
+            Instruction::RefNull(heap) => {
+                let null = self.default_of(ValType::Ref(RefType::null(*heap)));
+                self.stack.push(null);
+            }
+            Instruction::RefIsNull => {
+                let is_null = self.stack_pop()?.is_null();
+                self.stack.push(is_null.into());
+            }
+            Instruction::RefFunc(idx) => self.stack.push(Value::FuncRef(Some(*idx))),
+            #[cfg(feature = "function-references")]
+            Instruction::RefAsNonNull => {
+                if self.stack.last().is_some_and(Value::is_null) {
+                    bail!("null reference")
+                }
+            }
+            Instruction::Drop => {
+                self.stack_pop()?;
+            }
+            Instruction::Select(_) => {
+                let c = self.pop_i32()?;
+                let (b, a) = (self.stack_pop()?, self.stack_pop()?);
+                self.stack.push(if c != 0 { a } else { b });
+            }
+            Instruction::LocalGet(idx) => {
+                let value = self
+                    .locals
+                    .get(frame.base + *idx as usize)
+                    .context("not found local variable")?;
+                self.stack.push(*value);
+            }
+            Instruction::LocalSet(idx) => {
+                self.locals[frame.base + *idx as usize] = self.stack_pop()?;
+            }
+            Instruction::LocalTee(idx) => {
+                self.locals[frame.base + *idx as usize] =
+                    *self.stack.last().context("empty stack")?;
+            }
+            Instruction::GlobalGet(idx) => self.stack.push(self.globals[*idx as usize]),
+            Instruction::GlobalSet(idx) => self.globals[*idx as usize] = self.stack_pop()?,
+            Instruction::TableGet(table) => {
+                let i = self.pop_i32()? as u32 as usize;
+                let elems = &self.tables[*table as usize].elems;
+                let value = *elems.get(i).context("out of bounds table access")?;
+                self.stack.push(value);
+            }
+            Instruction::TableSet(table) => {
+                let value = self.stack_pop()?;
+                let i = self.pop_i32()? as u32 as usize;
+                let elems = &mut self.tables[*table as usize].elems;
+                *elems.get_mut(i).context("out of bounds table access")? = value;
+            }
+            Instruction::TableSize(table) => {
+                let size = self.tables[*table as usize].elems.len() as u32;
+                self.stack.push(size.into());
+            }
+            Instruction::TableGrow(table) => {
+                let n = self.pop_i32()? as u32;
+                let value = self.stack_pop()?;
+                let table = &mut self.tables[*table as usize];
+                let size = table.elems.len() as u32;
+                let max = table.max.unwrap_or(MAX_TABLE_SIZE).min(MAX_TABLE_SIZE);
+                match size.checked_add(n) {
+                    // failing to allocate the elements is a failure of growing rather than an abort
+                    Some(new_size)
+                        if new_size <= max && table.elems.try_reserve(n as usize).is_ok() =>
+                    {
+                        table.elems.resize(new_size as usize, value);
+                        self.stack.push(size.into());
+                    }
+                    _ => self.stack.push(Value::I32(-1)),
+                }
+            }
+            Instruction::TableFill(table) => {
+                let n = self.pop_i32()? as u32 as usize;
+                let value = self.stack_pop()?;
+                let i = self.pop_i32()? as u32 as usize;
+                let elems = &mut self.tables[*table as usize].elems;
+                elems
+                    .get_mut(i..i + n)
+                    .context("out of bounds table access")?
+                    .fill(value);
+            }
+            Instruction::TableCopy(dst, src) => {
+                let n = self.pop_i32()? as u32 as usize;
+                let s = self.pop_i32()? as u32 as usize;
+                let d = self.pop_i32()? as u32 as usize;
+                let values = self.tables[*src as usize]
+                    .elems
+                    .get(s..s + n)
+                    .context("out of bounds table access")?
+                    .to_vec();
+                self.tables[*dst as usize]
+                    .elems
+                    .get_mut(d..d + n)
+                    .context("out of bounds table access")?
+                    .copy_from_slice(&values);
+            }
+            Instruction::TableInit(elem, table) => self.table_init(*elem, *table)?,
+            Instruction::TableDrop(elem) => self.elems[*elem as usize].clear(),
+            Instruction::Memory(op, memarg) => self.load_store(*op, memarg)?,
+            Instruction::MemorySize(memory) => {
+                let memory = &mut self.mems[*memory as usize];
+                let size = address(memory.index(), memory.size());
+                self.stack.push(size);
+            }
+            Instruction::MemoryGrow(memory) => {
+                let delta = self.pop_addr()?;
+                let memory = &mut self.mems[*memory as usize];
+                let old = memory.grow(delta).unwrap_or(u64::MAX);
+                self.stack.push(address(memory.index(), old));
+            }
+            Instruction::MemoryFill(memory) => {
+                let n = self.pop_addr()?;
+                let value = self.pop_i32()?;
+                let d = self.pop_addr()?;
+                self.mems[*memory as usize].fill(d, value as u8, n)?;
+            }
+            Instruction::MemoryCopy(dst, src) => self.memory_copy(*dst, *src)?,
+            Instruction::MemoryInit(data, memory) => self.memory_init(*data, *memory)?,
+            Instruction::DataDrop(data) => self.datas[*data as usize].clear(),
+            Instruction::I32Const(v) => self.stack.push(Value::I32(*v)),
+            Instruction::I64Const(v) => self.stack.push(Value::I64(*v)),
+            Instruction::I32Add => binop!(self, I32, i32::wrapping_add),
+            Instruction::I32Sub => binop!(self, I32, i32::wrapping_sub),
+            Instruction::I32Mul => binop!(self, I32, i32::wrapping_mul),
+            Instruction::I64Add => binop!(self, I64, i64::wrapping_add),
+            Instruction::I64Sub => binop!(self, I64, i64::wrapping_sub),
+            Instruction::I64Mul => binop!(self, I64, i64::wrapping_mul),
+            Instruction::I32Clz => unop!(self, I32, i32::leading_zeros),
+            Instruction::I32Ctz => unop!(self, I32, i32::trailing_zeros),
+            Instruction::I32Popcnt => unop!(self, I32, i32::count_ones),
+            Instruction::I32DivS => {
+                let (b, a) = (self.pop_i32()?, self.pop_i32()?);
+                if b == 0 {
+                    bail!("integer divide by zero")
+                }
+                let value = a.checked_div(b).context("integer overflow")?;
+                self.stack.push(value.into());
+            }
+            Instruction::I32DivU => {
+                let (b, a) = (self.pop_i32()? as u32, self.pop_i32()? as u32);
+                let value = a.checked_div(b).context("integer divide by zero")?;
+                self.stack.push(value.into());
+            }
+            Instruction::I32RemS => {
+                let (b, a) = (self.pop_i32()?, self.pop_i32()?);
+                if b == 0 {
+                    bail!("integer divide by zero")
+                }
+                self.stack.push(a.wrapping_rem(b).into());
+            }
+            Instruction::I32RemU => {
+                let (b, a) = (self.pop_i32()? as u32, self.pop_i32()? as u32);
+                let value = a.checked_rem(b).context("integer divide by zero")?;
+                self.stack.push(value.into());
+            }
+            Instruction::I32Eqz => unop!(self, I32, |v| v == 0),
+            Instruction::I32Eq => binop!(self, I32, |a, b| a == b),
+            Instruction::I32Ne => binop!(self, I32, |a, b| a != b),
+            Instruction::I32LtS => binop!(self, I32, |a, b| a < b),
+            Instruction::I32LtU => binop!(self, I32, |a, b| (a as u32) < (b as u32)),
+            Instruction::I32GtS => binop!(self, I32, |a, b| a > b),
+            Instruction::I32GtU => binop!(self, I32, |a, b| (a as u32) > (b as u32)),
+            Instruction::I32LeS => binop!(self, I32, |a, b| a <= b),
+            Instruction::I32LeU => binop!(self, I32, |a, b| (a as u32) <= (b as u32)),
+            Instruction::I32GeS => binop!(self, I32, |a, b| a >= b),
+            Instruction::I32GeU => binop!(self, I32, |a, b| (a as u32) >= (b as u32)),
+            Instruction::I32And => binop!(self, I32, |a, b| a & b),
+            Instruction::I32Or => binop!(self, I32, |a, b| a | b),
+            Instruction::I32Xor => binop!(self, I32, |a, b| a ^ b),
+            Instruction::I32ShL => binop!(self, I32, |a: i32, b| a.wrapping_shl(b as u32)),
+            Instruction::I32ShrS => binop!(self, I32, |a: i32, b| a.wrapping_shr(b as u32)),
+            Instruction::I32ShrU => {
+                binop!(self, I32, |a, b| (a as u32).wrapping_shr(b as u32))
+            }
+            Instruction::I32RtoL => binop!(self, I32, |a: i32, b| a.rotate_left(b as u32)),
+            Instruction::I32RtoR => binop!(self, I32, |a: i32, b| a.rotate_right(b as u32)),
+            Instruction::I32Extend8S => unop!(self, I32, |v| v as i8 as i32),
+            Instruction::I32Extend16S => unop!(self, I32, |v| v as i16 as i32),
+            #[cfg(feature = "simd")]
+            Instruction::V128Const(v) => self.stack.push(Value::V128(*v)),
+            #[cfg(feature = "simd")]
+            Instruction::I8x16Shuffle(lanes) => self.shuffle(lanes)?,
+            #[cfg(feature = "simd")]
+            Instruction::Vector(op) => self.vector(*op)?,
+            #[cfg(feature = "simd")]
+            Instruction::VectorLane(op, lane) => self.vector_lane(*op, *lane)?,
+            #[cfg(feature = "simd")]
+            Instruction::VectorMem(op, memarg) => self.vector_mem(*op, memarg, None)?,
+            #[cfg(feature = "simd")]
+            Instruction::VectorMemLane(op, memarg, lane) => {
+                self.vector_mem(*op, memarg, Some(*lane))?
+            }
+            #[cfg(feature = "threads")]
+            Instruction::AtomicFence => {}
+            #[cfg(feature = "threads")]
+            Instruction::Atomic(op, memarg) => self.atomic(*op, memarg)?,
+            #[cfg(feature = "gc")]
+            Instruction::RefEq
+            | Instruction::RefTest(_)
+            | Instruction::RefCast(_)
+            | Instruction::StructNew(_)
+            | Instruction::StructNewDefault(_)
+            | Instruction::StructGet(..)
+            | Instruction::StructGetS(..)
+            | Instruction::StructGetU(..)
+            | Instruction::StructSet(..)
+            | Instruction::ArrayNew(_)
+            | Instruction::ArrayNewDefault(_)
+            | Instruction::ArrayNewFixed(..)
+            | Instruction::ArrayNewData(..)
+            | Instruction::ArrayNewElem(..)
+            | Instruction::ArrayGet(_)
+            | Instruction::ArrayGetS(_)
+            | Instruction::ArrayGetU(_)
+            | Instruction::ArraySet(_)
+            | Instruction::ArrayLen
+            | Instruction::ArrayFill(_)
+            | Instruction::ArrayCopy(..)
+            | Instruction::ArrayInitData(..)
+            | Instruction::ArrayInitElem(..)
+            | Instruction::AnyConvertExtern
+            | Instruction::ExternConvertAny
+            | Instruction::RefI31
+            | Instruction::I31GetS
+            | Instruction::I31GetU => self.gc(inst)?,
+            _ => bail!("unexpected instruction: {:?}", inst),
         };
+        Ok(())
+    }
 
-        let func_body = module
-            .code_section
-            .as_ref()
-            .context("not found code section")?;
-        let func_body = func_body.get(idx).context("not found function body")?;
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-table-init
+    fn table_init(&mut self, elem: u32, table: u32) -> Result<()> {
+        let n = self.pop_i32()? as u32 as usize;
+        let s = self.pop_i32()? as u32 as usize;
+        let d = self.pop_i32()? as u32 as usize;
+        let values = self.elems[elem as usize]
+            .get(s..s + n)
+            .context("out of bounds table access")?;
+        self.tables[table as usize]
+            .elems
+            .get_mut(d..d + n)
+            .context("out of bounds table access")?
+            .copy_from_slice(values);
+        Ok(())
+    }
 
-        let func = Function {
-            func_type,
-            body: func_body.code.clone(),
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-load
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-store
+    fn load_store(&mut self, op: MemoryOp, memarg: &MemArg) -> Result<()> {
+        match op.kind() {
+            MemoryKind::Load(t, width, signed) => {
+                let ea = effective(self.pop_addr()?, memarg)?;
+                let bytes = self.memory(memarg).read(ea, width as usize)?;
+                let mut bits = bytes.iter().rev().fold(0, |bits, b| bits << 8 | *b as u64);
+                if signed {
+                    let shift = 64 - width * 8;
+                    bits = ((bits << shift) as i64 >> shift) as u64;
+                }
+                self.stack.push(match t {
+                    NumType::I32 => Value::I32(bits as i32),
+                    NumType::I64 => Value::I64(bits as i64),
+                    NumType::F32 => Value::F32(f32::from_bits(bits as u32)),
+                    NumType::F64 => Value::F64(f64::from_bits(bits)),
+                });
+            }
+            MemoryKind::Store(_, width) => {
+                let bits = match self.stack_pop()? {
+                    Value::I32(v) => v as u32 as u64,
+                    Value::I64(v) => v as u64,
+                    Value::F32(v) => v.to_bits() as u64,
+                    Value::F64(v) => v.to_bits(),
+                    v => bail!("unexpected value: {:?}", v),
+                };
+                let ea = effective(self.pop_addr()?, memarg)?;
+                self.memory(memarg)
+                    .write(ea, &bits.to_le_bytes()[..width as usize])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// copy page by page, backwards if the destination is after the source,
+    /// so that neither overlapping bytes nor a large length need a buffer of the whole
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-memory-copy
+    fn memory_copy(&mut self, dst: u32, src: u32) -> Result<()> {
+        let n = self.pop_addr()?;
+        let s = self.pop_addr()?;
+        let d = self.pop_addr()?;
+        self.mems[src as usize].check(s, n)?;
+        self.mems[dst as usize].check(d, n)?;
+        let mut copied = 0;
+        while copied < n {
+            let len = (n - copied).min(PAGE_SIZE as u64);
+            let offset = if d > s { n - copied - len } else { copied };
+            let bytes = self.mems[src as usize].read(s + offset, len as usize)?;
+            self.mems[dst as usize].write(d + offset, &bytes)?;
+            copied += len;
+        }
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-memory-init
+    fn memory_init(&mut self, data: u32, memory: u32) -> Result<()> {
+        let n = self.pop_i32()? as u32 as usize;
+        let s = self.pop_i32()? as u32 as usize;
+        let d = self.pop_addr()?;
+        let bytes = self.datas[data as usize]
+            .get(s..s + n)
+            .context("out of bounds memory access")?;
+        self.mems[memory as usize].write(d, bytes)
+    }
+
+    /// https://webassembly.github.io/spec/core/valid/instructions.html#constant-expressions
+    fn eval_const(&mut self, expr: &Expr) -> Result<Value> {
+        let module = self.module.clone();
+        let height = self.stack.len();
+        let frame = Frame {
+            func: None,
+            base: self.locals.len(),
+            height,
+            arity: 1,
+            labels: vec![Label {
+                kind: LabelKind::Block,
+                instrs: expr,
+                pc: 0,
+                height,
+                arity: 1,
+            }],
         };
-        functions.push(func);
+        self.run(&module, vec![frame])?;
+        self.stack_pop()
     }
-    Ok(functions)
 }
 
 #[cfg(test)]
-mod test {
-
-    use super::Runtime;
+mod tests {
+    use super::{Imports, Runtime, RuntimeOptions, SharedMemory, Trap};
+    use crate::binary::module::{decode, decode_slice};
+    use crate::features::WasmFeatures;
+    use crate::structure::values::Value;
     use anyhow::Result;
-    use std::io::Cursor;
     use wasmer::wat2wasm;
 
+    /// the message of an error without the backtrace of a trap
+    fn message(err: anyhow::Error) -> String {
+        match err.downcast_ref::<Trap>() {
+            Some(trap) => trap.message(),
+            None => err.to_string(),
+        }
+    }
+
+    fn instantiate(wat: &[u8]) -> Result<Runtime<'static>> {
+        let wasm = wat2wasm(wat)?;
+        Runtime::new(decode(&mut &wasm[..])?)
+    }
+
     #[test]
+    #[cfg(feature = "sign-extension")]
     fn invoke() -> Result<()> {
         let wat_code = br#"
 (module
@@ -542,10 +1253,7 @@ mod test {
   (export "if_else" (func $if_else))
 )
 "#;
-        let wasm = wat2wasm(wat_code)?;
-        let mut reader = Cursor::new(wasm);
-        let mut module = Module::decode(&mut reader)?;
-        let mut runtime = Runtime::new(&mut module)?;
+        let mut runtime = instantiate(wat_code)?;
 
         let tests = [
             ("i32.add", vec![10, 11], 21),
@@ -641,8 +1349,8 @@ mod test {
             let args = test.1.into_iter().map(Value::from).collect::<Vec<Value>>();
             let result = runtime.invoke(&func_name, &args)?;
             assert_eq!(
-                result.unwrap(),
-                Value::from(test.2),
+                result,
+                vec![Value::from(test.2)],
                 "func {}: {:?}",
                 func_name,
                 args
@@ -650,4 +1358,1100 @@ mod test {
         }
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "multi-value")]
+    fn invoke_multi_value() -> Result<()> {
+        //Given
+        let mut runtime = instantiate(
+            br#"
+(module
+  (func $swap (export "swap") (param i32 i32) (result i32 i32)
+    local.get 1
+    local.get 0)
+  (func (export "call_swap") (param i32 i32) (result i32 i32 i32)
+    (call $swap (local.get 0) (local.get 1))
+    i32.const 7)
+  (func (export "block_params") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    (block (param i32 i32) (result i32) i32.sub))
+  (func (export "br_values") (result i32 i32)
+    (block (result i32 i32)
+      i32.const 1
+      i32.const 2
+      i32.const 3
+      br 0))
+  (func (export "if_params") (param i32 i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    (if (param i32 i32) (result i32) (local.get 2)
+      (then i32.add)
+      (else i32.sub)))
+  ;; sum of 1..=n with the accumulator and the counter on the stack
+  (func (export "loop_params") (param i32) (result i32)
+    i32.const 0
+    local.get 0
+    (loop (param i32 i32) (result i32)
+      local.tee 0
+      i32.add
+      local.get 0
+      i32.const 1
+      i32.sub
+      local.tee 0
+      local.get 0
+      br_if 0
+      drop)))
+"#,
+        )?;
+        let tests: [(&str, Vec<i32>, Vec<i32>); 8] = [
+            ("swap", vec![1, 2], vec![2, 1]),
+            ("call_swap", vec![1, 2], vec![2, 1, 7]),
+            ("block_params", vec![10, 3], vec![7]),
+            ("br_values", vec![], vec![2, 3]),
+            ("if_params", vec![10, 3, 1], vec![13]),
+            ("if_params", vec![10, 3, 0], vec![7]),
+            ("loop_params", vec![1], vec![1]),
+            ("loop_params", vec![100], vec![5050]),
+        ];
+        for (func_name, args, expected) in tests {
+            //When
+            let args: Vec<Value> = args.into_iter().map(Value::from).collect();
+            let result = runtime.invoke(func_name, &args)?;
+            //Then
+            let expected: Vec<Value> = expected.into_iter().map(Value::from).collect();
+            assert_eq!(result, expected, "func {}: {:?}", func_name, args);
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "tail-call")]
+    fn invoke_tail_calls() -> Result<()> {
+        use crate::binary::module::decode_slice_with_features;
+        //Given
+        let wasm = wat2wasm(
+            br#"
+(module
+  (type $pred (func (param i32) (result i32)))
+  (table funcref (elem $is_even $is_odd))
+  (func $is_even (export "is_even") (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 1))
+      (else (return_call $is_odd (i32.sub (local.get 0) (i32.const 1))))))
+  (func $is_odd (export "is_odd") (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 0))
+      (else
+        (return_call_indirect (type $pred)
+          (i32.sub (local.get 0) (i32.const 1)) (i32.const 0)))))
+  (func (export "count") (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 0))
+      (else (i32.add (i32.const 1) (call 2 (i32.sub (local.get 0) (i32.const 1))))))))
+"#,
+        )?;
+        let features = WasmFeatures::all();
+        let module = decode_slice_with_features(&wasm, &features)?;
+        let mut runtime = Runtime::new_with_features(module, &features)?;
+        //When
+        let even = runtime.invoke("is_even", &[1_000_000.into()])?;
+        let odd = runtime.invoke("is_odd", &[1_000_001.into()])?;
+        let count = runtime.invoke("count", &[1_000_000.into()]);
+        //Then
+        assert_eq!(even, vec![Value::I32(1)]);
+        assert_eq!(odd, vec![Value::I32(1)]);
+        assert_eq!(message(count.unwrap_err()), "call stack exhausted");
+        let disabled = Runtime::new(decode_slice_with_features(&wasm, &features)?);
+        assert_eq!(
+            message(disabled.unwrap_err()),
+            "func[0] $is_even: instruction 7 (return_call 1): feature not enabled: tail-call"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "multi-value", feature = "simd"))]
+    fn invoke_simd() -> Result<()> {
+        //Given
+        let mut runtime = instantiate(
+            br#"
+(module
+  (memory 1)
+  (data (i32.const 16) "\01\02\03\04\05\06\07\08\f9\fa\fb\fc\fd\fe\ff\00")
+  (func (export "add") (param v128 v128) (result v128)
+    (i32x4.add (local.get 0) (local.get 1)))
+  (func (export "sat") (result v128)
+    (i8x16.add_sat_s (v128.const i8x16 127 -128 1 0 0 0 0 0 0 0 0 0 0 0 0 0)
+      (v128.const i8x16 1 -1 1 0 0 0 0 0 0 0 0 0 0 0 0 0)))
+  (func (export "lt") (result v128)
+    (i32x4.lt_s (v128.const i32x4 -1 2 3 4) (v128.const i32x4 0 2 4 -4)))
+  (func (export "lanes") (param f64) (result i32 i64 f64)
+    (i8x16.extract_lane_u 0 (i8x16.splat (i32.const -1)))
+    (i64x2.extract_lane 1 (i64x2.replace_lane 1 (v128.const i64x2 0 0) (i64.const 42)))
+    (f64x2.extract_lane 1 (f64x2.replace_lane 1 (v128.const f64x2 0 0) (local.get 0))))
+  (func (export "shuffle") (result v128)
+    (i8x16.shuffle 16 0 17 1 18 2 19 3 20 4 21 5 22 6 23 7
+      (v128.const i8x16 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15)
+      (v128.const i8x16 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)))
+  (func (export "swizzle") (result v128)
+    (i8x16.swizzle (v128.const i8x16 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25)
+      (v128.const i8x16 15 0 16 255 255 255 255 255 255 255 255 255 255 255 255 255)))
+  (func (export "narrow") (result v128)
+    (i8x16.narrow_i16x8_s (v128.const i16x8 300 -300 1 -1 0 0 0 0)
+      (v128.const i16x8 0 0 0 0 0 0 0 0)))
+  (func (export "dot") (result v128)
+    (i32x4.dot_i16x8_s (v128.const i16x8 1 2 3 4 5 6 7 8) (v128.const i16x8 1 1 2 2 -1 -1 0 0)))
+  (func (export "fmin") (result v128)
+    (f32x4.min (v128.const f32x4 1 -0 nan 3) (v128.const f32x4 2 0 1 -inf)))
+  (func (export "test") (result i32 i32 i32)
+    (v128.any_true (v128.const i64x2 0 1))
+    (i32x4.all_true (v128.const i32x4 1 2 3 0))
+    (i8x16.bitmask (v128.const i8x16 -1 0 -1 0 0 0 0 0 0 0 0 0 0 0 0 -1)))
+  (func (export "shift") (result v128)
+    (i16x8.shr_s (v128.const i16x8 -16 16 0 0 0 0 0 0) (i32.const 18)))
+  (func (export "load") (param i32) (result v128)
+    (v128.load (local.get 0)))
+  (func (export "load_extend") (result v128)
+    (v128.load8x8_s offset=24 (i32.const 0)))
+  (func (export "store_lane") (result v128)
+    (v128.store32_lane 1 (i32.const 0) (v128.const i32x4 1 2 3 4))
+    (v128.load32_zero (i32.const 0))))
+"#,
+        )?;
+        //When
+        let add = runtime.invoke("add", &[Value::V128(1 << 96 | 2), Value::V128(3 << 96 | 4)])?;
+        let sat = runtime.invoke("sat", &[])?;
+        let lt = runtime.invoke("lt", &[])?;
+        let lanes = runtime.invoke("lanes", &[1.5.into()])?;
+        let shuffle = runtime.invoke("shuffle", &[])?;
+        let swizzle = runtime.invoke("swizzle", &[])?;
+        let narrow = runtime.invoke("narrow", &[])?;
+        let dot = runtime.invoke("dot", &[])?;
+        let fmin = runtime.invoke("fmin", &[])?;
+        let test = runtime.invoke("test", &[])?;
+        let shift = runtime.invoke("shift", &[])?;
+        let load = runtime.invoke("load", &[16.into()])?;
+        let load_extend = runtime.invoke("load_extend", &[])?;
+        let store_lane = runtime.invoke("store_lane", &[])?;
+        let out_of_bounds = runtime.invoke("load", &[65530.into()]);
+        //Then
+        assert_eq!(add, vec![Value::V128(4 << 96 | 6)]);
+        assert_eq!(sat, vec![Value::V128(0x02_80_7f)]);
+        assert_eq!(lt, vec![Value::V128(0xffffffff_00000000_ffffffff)]);
+        assert_eq!(
+            lanes,
+            vec![Value::I32(255), Value::I64(42), Value::F64(1.5)]
+        );
+        assert_eq!(
+            shuffle,
+            vec![Value::V128(0x07170616_05150414_03130212_01110010)]
+        );
+        assert_eq!(swizzle, vec![Value::V128(0x0a19)]);
+        assert_eq!(narrow, vec![Value::V128(0xff_01_80_7f)]);
+        assert_eq!(dot, vec![Value::V128(0xfffffff5_0000000e_00000003)]);
+        let fmin = match fmin[..] {
+            [Value::V128(v)] => v,
+            _ => unreachable!(),
+        };
+        assert_eq!(f32::from_bits(fmin as u32), 1.0);
+        assert_eq!((fmin >> 32) as u32, (-0.0f32).to_bits());
+        assert!(f32::from_bits((fmin >> 64) as u32).is_nan());
+        assert_eq!(f32::from_bits((fmin >> 96) as u32), f32::NEG_INFINITY);
+        assert_eq!(test, vec![Value::I32(1), Value::I32(0), Value::I32(0x8005)]);
+        assert_eq!(shift, vec![Value::V128(0x0004_fffc)]);
+        assert_eq!(
+            load,
+            vec![Value::V128(0x00fffefd_fcfbfaf9_08070605_04030201)]
+        );
+        assert_eq!(
+            load_extend,
+            vec![Value::V128(0x0000_ffff_fffe_fffd_fffc_fffb_fffa_fff9)]
+        );
+        assert_eq!(store_lane, vec![Value::V128(2)]);
+        assert_eq!(
+            message(out_of_bounds.unwrap_err()),
+            "out of bounds memory access"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "multi-value", feature = "relaxed-simd"))]
+    fn invoke_relaxed_simd() -> Result<()> {
+        //Given
+        let wat = r#"(module
+  (func (export "relaxed") (result v128 v128 v128 v128 v128 v128 v128)
+    (i8x16.relaxed_swizzle (v128.const i8x16 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15)
+      (v128.const i8x16 0x11 0x80 1 0 0 0 0 0 0 0 0 0 0 0 0 0))
+    (i32x4.relaxed_trunc_f32x4_s (v128.const f32x4 nan -1 3e9 1.5))
+    (f64x2.relaxed_madd (v128.const f64x2 0x1.0000000000001p+0 0)
+      (v128.const f64x2 0x1.0000000000001p+0 0) (v128.const f64x2 -0x1.0000000000002p+0 0))
+    (i32x4.relaxed_laneselect (v128.const i32x4 1 1 1 1) (v128.const i32x4 2 2 2 2)
+      (v128.const i32x4 -1 0 0x80000000 0x7fffffff))
+    (f32x4.relaxed_min (v128.const f32x4 -0 nan 0 0) (v128.const f32x4 0 1 0 0))
+    (i16x8.relaxed_dot_i8x16_i7x16_s (v128.const i8x16 1 2 0 0 0 0 0 0 0 0 0 0 0 0 0 0)
+      (v128.const i8x16 -1 -1 0 0 0 0 0 0 0 0 0 0 0 0 0 0))
+    (i16x8.relaxed_q15mulr_s (v128.const i16x8 -32768 0 0 0 0 0 0 0)
+      (v128.const i16x8 -32768 0 0 0 0 0 0 0))))"#;
+        let run = |deterministic| -> Result<Vec<Value>> {
+            let options = RuntimeOptions {
+                deterministic,
+                ..RuntimeOptions::default()
+            };
+            let module = crate::text::parse(wat)?;
+            Runtime::new_with_options(module, &WasmFeatures::all(), options)?.invoke("relaxed", &[])
+        };
+        //When
+        let deterministic = run(true)?;
+        let native = run(false)?;
+        //Then
+        let expected = [
+            0x010000,
+            0x00000001_7fffffff_ffffffff_00000000,
+            0x3970000000000000,
+            0x00000001_00000002_00000002_00000001,
+            0x7fc00000_80000000,
+            0xfffd,
+            0x7fff,
+        ];
+        assert_eq!(deterministic, expected.map(Value::V128));
+        let expected = [
+            0x010001,
+            0x00000001_80000000_ffffffff_80000000,
+            0,
+            0x00000002_00000001_00000002_00000001,
+            0x3f800000_00000000,
+            0x02fd,
+            0x8000,
+        ];
+        assert_eq!(native, expected.map(Value::V128));
+        assert!(crate::validate::validate(&crate::text::parse(wat)?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "multi-value", feature = "threads"))]
+    fn invoke_atomics() -> Result<()> {
+        use crate::binary::module::decode_slice_with_features;
+        //Given
+        let wasm = wat2wasm(
+            br#"
+(module
+  (memory 1 1 shared)
+  (func (export "rmw") (result i32 i64 i32 i32)
+    (i32.atomic.store (i32.const 0) (i32.const 0x01ff))
+    (i32.atomic.rmw8.add_u (i32.const 0) (i32.const 2))
+    (i64.atomic.rmw.cmpxchg (i32.const 8) (i64.const 0) (i64.const -1))
+    (i32.atomic.rmw16.cmpxchg_u (i32.const 0) (i32.const 0x10101) (i32.const 7))
+    (i32.atomic.load (i32.const 0)))
+  (func (export "unaligned") (result i32)
+    (i32.atomic.load (i32.const 2)))
+  (func (export "wait") (param i32 i64) (result i32)
+    (memory.atomic.wait32 (i32.const 0) (local.get 0) (local.get 1))))
+"#,
+        )?;
+        let features = WasmFeatures::all();
+        let mut runtime =
+            Runtime::new_with_features(decode_slice_with_features(&wasm, &features)?, &features)?;
+        //When
+        let rmw = runtime.invoke("rmw", &[])?;
+        let unaligned = runtime.invoke("unaligned", &[]);
+        let timed_out = runtime.invoke("wait", &[7.into(), Value::I64(1000)])?;
+        let not_equal = runtime.invoke("wait", &[0.into(), Value::I64(-1)])?;
+        //Then
+        assert_eq!(
+            rmw,
+            vec![
+                Value::I32(0xff),
+                Value::I64(0),
+                Value::I32(0x0101),
+                Value::I32(7)
+            ]
+        );
+        assert_eq!(message(unaligned.unwrap_err()), "unaligned atomic");
+        assert_eq!(timed_out, vec![Value::I32(2)]);
+        assert_eq!(not_equal, vec![Value::I32(1)]);
+        let wasm = wat2wasm(
+            br#"(memory 1) (func (export "wait") (result i32)
+                (memory.atomic.wait64 (i32.const 0) (i64.const 0) (i64.const 0)))"#,
+        )?;
+        let mut unshared =
+            Runtime::new_with_features(decode_slice_with_features(&wasm, &features)?, &features)?;
+        assert_eq!(
+            message(unshared.invoke("wait", &[]).unwrap_err()),
+            "expected shared memory"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "multi-value", feature = "threads", feature = "multi-memory"))]
+    fn invoke_multi_memory() -> Result<()> {
+        use crate::binary::module::decode_slice_with_features;
+        //Given
+        let wasm = wat2wasm(
+            br#"
+(module
+  (memory $a 1)
+  (memory $b 1 1 shared)
+  (func (export "load") (param i32) (result i32 i32)
+    (i32.atomic.load $a (local.get 0))
+    (i32.atomic.load $b (local.get 0)))
+  (func (export "store") (param i32 i32)
+    (i32.atomic.store $b (local.get 0) (local.get 1)))
+  (data (memory $b) (i32.const 4) "\2a"))
+"#,
+        )?;
+        let features = WasmFeatures::all();
+        let mut runtime =
+            Runtime::new_with_features(decode_slice_with_features(&wasm, &features)?, &features)?;
+        //When
+        let initialized = runtime.invoke("load", &[4.into()])?;
+        runtime.invoke("store", &[8.into(), 7.into()])?;
+        let stored = runtime.invoke("load", &[8.into()])?;
+        //Then
+        assert_eq!(initialized, vec![Value::I32(0), Value::I32(42)]);
+        assert_eq!(stored, vec![Value::I32(0), Value::I32(7)]);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn invoke_shared_memory_across_threads() -> Result<()> {
+        use crate::binary::module::{decode_slice_with_features, decode_with_features};
+        //Given
+        let wasm = wat2wasm(
+            br#"
+(module
+  (import "env" "memory" (memory 1 1 shared))
+  (func (export "add") (param i32)
+    (loop $l
+      (drop (i32.atomic.rmw.add (i32.const 0) (i32.const 1)))
+      (br_if $l (local.tee 0 (i32.sub (local.get 0) (i32.const 1))))))
+  (func (export "load") (result i32)
+    (i32.atomic.load (i32.const 0)))
+  (func (export "wait") (result i32)
+    (memory.atomic.wait32 (i32.const 8) (i32.const 0) (i64.const -1)))
+  (func (export "notify") (result i32)
+    (memory.atomic.notify (i32.const 8) (i32.const 1))))
+"#,
+        )?;
+        let mut imports = Imports::default();
+        imports.add_memory("env", "memory", SharedMemory::new(1, 1));
+        let instantiate = move || {
+            let features = WasmFeatures::all();
+            let module = decode_with_features(&mut &wasm[..], &features)?;
+            Runtime::new_with_imports(module, &features, RuntimeOptions::default(), &imports)
+        };
+        //When
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let instantiate = instantiate.clone();
+                std::thread::spawn(move || instantiate()?.invoke("add", &[1000.into()]))
+            })
+            .collect();
+        let waiter = {
+            let instantiate = instantiate.clone();
+            std::thread::spawn(move || instantiate()?.invoke("wait", &[]))
+        };
+        let mut runtime = instantiate()?;
+        // the waiter may not be parked yet
+        while runtime.invoke("notify", &[])? != vec![Value::I32(1)] {
+            std::thread::yield_now();
+        }
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+        //Then
+        assert_eq!(waiter.join().unwrap()?, vec![Value::I32(0)]);
+        assert_eq!(runtime.invoke("load", &[])?, vec![Value::I32(4000)]);
+        let wasm = wat2wasm(b"(import \"env\" \"m\" (memory 1 1 shared))")?;
+        let unimported = Runtime::new_with_features(
+            decode_slice_with_features(&wasm, &WasmFeatures::all())?,
+            &WasmFeatures::all(),
+        );
+        assert_eq!(message(unimported.unwrap_err()), "unknown import env.m");
+        Ok(())
+    }
+
+    #[test]
+    fn invoke_imported_unshared_memory() -> Result<()> {
+        //Given
+        let wat = br#"
+(module
+  (import "env" "memory" (memory 1))
+  (func (export "store") (param i32 i32) (i32.store (local.get 0) (local.get 1)))
+  (func (export "load") (param i32) (result i32) (i32.load (local.get 0)))
+  (func (export "grow") (result i32) (memory.grow (i32.const 1))))
+"#;
+        let instantiate = |memory: SharedMemory| {
+            let mut imports = Imports::default();
+            imports.add_memory("env", "memory", memory);
+            let module = decode(&mut &wat2wasm(wat)?[..])?;
+            Runtime::new_with_imports(
+                module,
+                &WasmFeatures::default(),
+                RuntimeOptions::default(),
+                &imports,
+            )
+        };
+        let memory = SharedMemory::unshared(1, None);
+        let mut writer = instantiate(memory.clone())?;
+        let mut reader = instantiate(memory.clone())?;
+        //When
+        writer.invoke("store", &[8.into(), 42.into()])?;
+        let loaded = reader.invoke("load", &[8.into()])?;
+        let grown = reader.invoke("grow", &[])?;
+        //Then
+        assert_eq!(loaded, vec![Value::I32(42)]);
+        assert_eq!(grown, vec![Value::I32(1)]);
+        assert_eq!(memory.size(), 2);
+        assert_eq!(
+            message(instantiate(SharedMemory::new(1, 1)).unwrap_err()),
+            "incompatible import type: expected unshared memory"
+        );
+        assert_eq!(
+            message(instantiate(SharedMemory::unshared(0, None)).unwrap_err()),
+            "incompatible import type: limits of memory don't match"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "exceptions")]
+    fn invoke_deep_recursion() -> Result<()> {
+        //Given
+        // wasmer's wat doesn't know exception handling yet
+        let wat = r#"
+(module
+  (tag $found (param i32))
+  (func $count (export "count") (param i32) (result i32)
+    (local $rest i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 0))
+      (else
+        (local.set $rest (call $count (i32.sub (local.get 0) (i32.const 1))))
+        (i32.add (local.get $rest) (i32.const 1)))))
+  (func $search (param i32)
+    (if (i32.eqz (local.get 0)) (then (throw $found (i32.const 42))))
+    (call $search (i32.sub (local.get 0) (i32.const 1))))
+  (func (export "search") (param i32) (result i32)
+    (local $kept i32)
+    (local.set $kept (i32.const 1))
+    (block $caught (result i32)
+      (try_table (catch $found $caught)
+        (call $search (local.get 0)))
+      (i32.const -1))
+    (i32.add (local.get $kept))))
+"#;
+        let features = WasmFeatures::all();
+        let instantiate = |max_call_depth| {
+            let options = RuntimeOptions {
+                max_call_depth,
+                ..RuntimeOptions::default()
+            };
+            let module = crate::text::parse(wat)?;
+            Runtime::new_with_options(module, &features, options)
+        };
+        let mut runtime = instantiate(super::DEFAULT_MAX_CALL_DEPTH)?;
+        let mut limited = instantiate(100)?;
+        //When
+        let deep = runtime.invoke("count", &[50_000.into()])?;
+        let searched = runtime.invoke("search", &[10_000.into()])?;
+        let exhausted = limited.invoke("count", &[100.into()]);
+        let within = limited.invoke("count", &[99.into()])?;
+        //Then
+        assert_eq!(deep, vec![Value::I32(50_000)]);
+        assert_eq!(searched, vec![Value::I32(43)]);
+        assert_eq!(message(exhausted.unwrap_err()), "call stack exhausted");
+        assert_eq!(within, vec![Value::I32(99)]);
+        assert!(runtime.locals.is_empty());
+        assert!(limited.locals.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn invoke_trap_backtrace() -> Result<()> {
+        //Given
+        let mut runtime = instantiate(
+            br#"
+(module
+  (func $div (param i32 i32) (result i32) (i32.div_s (local.get 0) (local.get 1)))
+  (func $halve (export "halve") (param i32) (result i32) (call $div (local.get 0) (i32.const 0)))
+  (func $loop (export "loop") (call $loop)))
+"#,
+        )?;
+        //When
+        let div_by_zero = runtime.invoke("halve", &[1.into()]).unwrap_err();
+        let exhausted = runtime.invoke("loop", &[]).unwrap_err();
+        //Then
+        assert_eq!(
+            div_by_zero.to_string(),
+            "integer divide by zero\n  0: $div\n  1: $halve"
+        );
+        let trap = exhausted.downcast_ref::<Trap>().unwrap();
+        assert_eq!(trap.message(), "call stack exhausted");
+        assert_eq!(trap.backtrace().len(), 32);
+        assert!(trap.backtrace().iter().all(|func| func == "$loop"));
+        assert!(exhausted
+            .to_string()
+            .ends_with("\n  31: $loop\n  ... 65504 more"));
+        Ok(())
+    }
+
+    #[test]
+    fn instantiate_without_decoding_bodies() -> Result<()> {
+        //Given
+        let mut wasm = wat2wasm(
+            br#"
+(module
+  (func (export "one") (result i32) (i32.const 1))
+  (func (export "two") (result i32) (i32.const 2)))
+"#,
+        )?
+        .to_vec();
+        // replace `i32.const 2` with an undefined instruction
+        let pos = wasm
+            .windows(3)
+            .position(|w| w == [0x41, 0x02, 0x0B])
+            .unwrap();
+        wasm[pos] = 0xFF;
+        let options = RuntimeOptions {
+            lazy_validation: true,
+            ..RuntimeOptions::default()
+        };
+        //When
+        let eager = Runtime::new(decode_slice(&wasm)?).unwrap_err();
+        let mut runtime =
+            Runtime::new_with_options(decode_slice(&wasm)?, &WasmFeatures::default(), options)?;
+        let one = runtime.invoke("one", &[])?;
+        let two = runtime.invoke("two", &[]).unwrap_err();
+        //Then
+        assert_eq!(message(eager), "func[1]: 0xFF is undefined instruction.");
+        assert_eq!(one, vec![Value::I32(1)]);
+        assert!(runtime.module.funcs[0].body.is_decoded());
+        assert!(!runtime.module.funcs[1].body.is_decoded());
+        assert_eq!(message(two), "func[1]: 0xFF is undefined instruction.");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "reference-types")]
+    fn invoke_table_grow() -> Result<()> {
+        //Given
+        let wasm = wat2wasm(
+            br#"
+(module
+  (table $t 0 funcref)
+  (table $small 0 2 funcref)
+  (func (export "grow") (param i32) (result i32)
+    (table.grow $t (ref.null func) (local.get 0)))
+  (func (export "grow_small") (param i32) (result i32)
+    (table.grow $small (ref.null func) (local.get 0)))
+  (func (export "size") (result i32)
+    (table.size $t)))
+"#,
+        )?;
+        let mut runtime = Runtime::new(decode_slice(&wasm)?)?;
+        //When
+        let overflowed = runtime.invoke("grow", &[Value::I32(-1)])?;
+        let too_large = runtime.invoke("grow", &[Value::I32(10_000_001)])?;
+        let grown = runtime.invoke("grow", &[Value::I32(3)])?;
+        let beyond_max = runtime.invoke("grow_small", &[Value::I32(3)])?;
+        let size = runtime.invoke("size", &[])?;
+        //Then
+        assert_eq!(overflowed, vec![Value::I32(-1)]);
+        assert_eq!(too_large, vec![Value::I32(-1)]);
+        assert_eq!(grown, vec![Value::I32(0)]);
+        assert_eq!(beyond_max, vec![Value::I32(-1)]);
+        assert_eq!(size, vec![Value::I32(3)]);
+        Ok(())
+    }
+
+    #[test]
+    fn invoke_traps() -> Result<()> {
+        //Given
+        let mut runtime = instantiate(
+            br#"
+(module
+  (func (export "div_s") (param i32 i32) (result i32)
+    (i32.div_s (local.get 0) (local.get 1)))
+  (func $loop (export "loop") (call $loop))
+  (func (export "unreachable") unreachable))
+"#,
+        )?;
+        //When
+        let div_by_zero = runtime.invoke("div_s", &[1.into(), 0.into()]);
+        let overflow = runtime.invoke("div_s", &[i32::MIN.into(), (-1).into()]);
+        let exhausted = runtime.invoke("loop", &[]);
+        let unreachable = runtime.invoke("unreachable", &[]);
+        let wrong_args = runtime.invoke("div_s", &[1.into()]);
+        //Then
+        assert_eq!(message(div_by_zero.unwrap_err()), "integer divide by zero");
+        assert_eq!(message(overflow.unwrap_err()), "integer overflow");
+        assert_eq!(message(exhausted.unwrap_err()), "call stack exhausted");
+        assert_eq!(message(unreachable.unwrap_err()), "unreachable");
+        assert!(wrong_args.is_err());
+        assert_eq!(
+            runtime.invoke("div_s", &[7.into(), 2.into()])?,
+            vec![Value::I32(3)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "bulk-memory")]
+    fn invoke_memory_instructions() -> Result<()> {
+        //Given
+        let mut runtime = instantiate(
+            br#"
+(module
+  (memory 1 2)
+  (func (export "load8_s") (param i32) (result i32)
+    (i32.load8_s (local.get 0)))
+  (func (export "load16_u") (param i32) (result i64)
+    (i64.load16_u offset=1 (local.get 0)))
+  (func (export "store") (param i32 i64)
+    (i64.store32 (local.get 0) (local.get 1)))
+  (func (export "grow") (param i32) (result i32)
+    (memory.grow (local.get 0)))
+  (func (export "fill") (param i32 i32 i32)
+    (memory.fill (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "copy") (param i32 i32 i32)
+    (memory.copy (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "init") (param i32 i32 i32)
+    (memory.init 0 (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "drop")
+    (data.drop 0))
+  (data "\01\02\03\04"))
+"#,
+        )?;
+        //When
+        runtime.invoke("store", &[0.into(), 0x1_8081_82FFi64.into()])?;
+        let loaded = (
+            runtime.invoke("load8_s", &[0.into()])?,
+            runtime.invoke("load16_u", &[0.into()])?,
+        );
+        let grown = (
+            runtime.invoke("grow", &[1.into()])?,
+            runtime.invoke("grow", &[1.into()])?,
+        );
+        runtime.invoke("fill", &[10.into(), 7.into(), 3.into()])?;
+        runtime.invoke("init", &[20.into(), 1.into(), 3.into()])?;
+        runtime.invoke("copy", &[21.into(), 20.into(), 3.into()])?;
+        let bytes = [10, 12, 13, 20, 21, 22, 23]
+            .iter()
+            .map(|addr| runtime.invoke("load8_s", &[(*addr).into()]))
+            .collect::<Result<Vec<_>>>()?;
+        runtime.invoke("drop", &[])?;
+        let dropped = runtime.invoke("init", &[0.into(), 0.into(), 1.into()]);
+        let out_of_bounds = runtime.invoke("fill", &[131070.into(), 0.into(), 3.into()]);
+        //Then
+        assert_eq!(loaded.0, vec![Value::I32(-1)]);
+        assert_eq!(loaded.1, vec![Value::I64(0x8182)]);
+        assert_eq!(grown, (vec![Value::I32(1)], vec![Value::I32(-1)]));
+        assert_eq!(
+            bytes.concat(),
+            [7, 7, 0, 2, 2, 3, 4].map(Value::I32).to_vec()
+        );
+        assert_eq!(message(dropped.unwrap_err()), "out of bounds memory access");
+        assert_eq!(
+            message(out_of_bounds.unwrap_err()),
+            "out of bounds memory access"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "multi-value", feature = "memory64"))]
+    fn invoke_memory64() -> Result<()> {
+        use crate::binary::module::decode_slice_with_features;
+        //Given
+        let wasm = wat2wasm(
+            br#"
+(module
+  (memory i64 65537)
+  (func (export "load") (param i64) (result i64)
+    (i64.load offset=8 (local.get 0)))
+  (func (export "store") (param i64 i64)
+    (i64.store offset=8 (local.get 0) (local.get 1)))
+  (func (export "grow") (param i64) (result i64 i64)
+    (memory.grow (local.get 0))
+    (memory.size)))
+"#,
+        )?;
+        let features = WasmFeatures::all();
+        let mut runtime =
+            Runtime::new_with_features(decode_slice_with_features(&wasm, &features)?, &features)?;
+        //When
+        runtime.invoke("store", &[0x1_0000_0010i64.into(), 42i64.into()])?;
+        let stored = runtime.invoke("load", &[0x1_0000_0010i64.into()])?;
+        let untouched = runtime.invoke("load", &[0i64.into()])?;
+        let grown = runtime.invoke("grow", &[2i64.into()])?;
+        let out_of_bounds = runtime.invoke("load", &[((65539i64 << 16) - 8).into()]);
+        let overflow = runtime.invoke("load", &[(-1i64).into()]);
+        //Then
+        assert_eq!(stored, vec![Value::I64(42)]);
+        assert_eq!(untouched, vec![Value::I64(0)]);
+        assert_eq!(grown, vec![Value::I64(65537), Value::I64(65539)]);
+        assert_eq!(
+            message(out_of_bounds.unwrap_err()),
+            "out of bounds memory access"
+        );
+        assert_eq!(
+            message(overflow.unwrap_err()),
+            "out of bounds memory access"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "reference-types", feature = "exceptions"))]
+    fn invoke_exceptions() -> Result<()> {
+        use super::Exception;
+        //Given
+        // wasmer's wat doesn't know try_table yet
+        let module = crate::text::parse(
+            r#"
+(module
+  (tag $e (param i32))
+  (tag $other (param i64 i32))
+  (func $throw (param i32)
+    (throw $e (local.get 0)))
+  (func (export "catch") (param i32) (result i32)
+    (block $caught (result i32)
+      (try_table (catch $e $caught)
+        (call $throw (local.get 0)))
+      (i32.const -1)))
+  (func (export "catch_all") (param i32) (result i32)
+    (block $caught
+      (return
+        (try_table (result i32) (catch_all $caught)
+          (i32.add (i32.const 1) (call $rethrow (local.get 0))))))
+    (i32.const 0))
+  (func $rethrow (export "rethrow") (param i32) (result i32)
+    (block $caught (result exnref)
+      (try_table (catch_all_ref $caught)
+        (if (local.get 0) (then (throw $other (i64.const 1) (i32.const 2)))))
+      (return (i32.const 7)))
+    (throw_ref))
+  (func (export "trap") (result i32)
+    (block $caught
+      (try_table (catch_all $caught) (unreachable)))
+    (i32.const 0))
+  (global $kept (mut exnref) (ref.null exn))
+  (func (export "catch_many") (param i32)
+    (loop $l
+      (global.set $kept
+        (block $caught (result exnref)
+          (try_table (catch_all_ref $caught) (throw $e (local.get 0)))
+          (unreachable)))
+      (br_if $l (local.tee 0 (i32.sub (local.get 0) (i32.const 1))))))
+  (func (export "throw_kept")
+    (throw_ref (global.get $kept))))
+"#,
+        )?;
+        let mut runtime = Runtime::new_with_features(module, &WasmFeatures::all())?;
+        //When
+        let caught = runtime.invoke("catch", &[Value::I32(42)])?;
+        let caught_all = runtime.invoke("catch_all", &[Value::I32(1)])?;
+        let not_thrown = runtime.invoke("catch_all", &[Value::I32(0)])?;
+        let rethrown = runtime.invoke("rethrow", &[Value::I32(1)]).unwrap_err();
+        let trap = runtime.invoke("trap", &[]).unwrap_err();
+        runtime.invoke("catch_many", &[Value::I32(3000)])?;
+        let allocated = runtime.exceptions.live;
+        runtime.collect_garbage();
+        let collected = runtime.exceptions.live;
+        let kept = runtime.invoke("throw_kept", &[]).unwrap_err();
+        //Then
+        assert_eq!(caught, vec![Value::I32(42)]);
+        assert_eq!(caught_all, vec![Value::I32(0)]);
+        assert_eq!(not_thrown, vec![Value::I32(8)]);
+        assert_eq!(
+            rethrown.downcast_ref::<Exception>(),
+            Some(&Exception {
+                tag: 1,
+                values: vec![Value::I64(1), Value::I32(2)],
+            })
+        );
+        assert_eq!(rethrown.to_string(), "uncaught exception of tag 1 [1, 2]");
+        assert_eq!(message(trap), "unreachable");
+        assert!(allocated < 3000, "collected while catching: {}", allocated);
+        assert_eq!(collected, 1);
+        assert_eq!(kept.to_string(), "uncaught exception of tag 0 [1]");
+        let imported = crate::text::parse(r#"(import "env" "e" (tag (param i32)))"#)?;
+        assert_eq!(
+            message(Runtime::new_with_features(imported, &WasmFeatures::all()).unwrap_err()),
+            "tag imports are not supported"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "reference-types",
+        feature = "bulk-memory",
+        feature = "tail-call",
+        feature = "function-references"
+    ))]
+    fn invoke_function_references() -> Result<()> {
+        //Given
+        // wasmer's wat doesn't know typed function references yet
+        let module = crate::text::parse(
+            r#"
+(module
+  (type $unop (func (param i32) (result i32)))
+  (type $count (func (param i32 i32) (result i32)))
+  (table $t 2 funcref)
+  (func $inc (type $unop) (i32.add (local.get 0) (i32.const 1)))
+  (func (export "call") (param i32) (result i32)
+    (call_ref $unop (local.get 0) (ref.func $inc)))
+  (func (export "call_null") (result i32)
+    (call_ref $unop (i32.const 0) (ref.null $unop)))
+  (func (export "as_non_null") (param i32) (result i32)
+    (ref.is_null (ref.as_non_null (table.get $t (local.get 0)))))
+  (func (export "on_null") (param i32) (result i32)
+    (local $f (ref null $unop))
+    (if (local.get 0) (then (local.set $f (ref.func $inc))))
+    (block $null
+      (return
+        (call_ref $unop (i32.const 1) (br_on_null $null (local.get $f)))))
+    (i32.const -1))
+  (func (export "on_non_null") (param i32) (result i32)
+    (local $f (ref null $unop))
+    (if (local.get 0) (then (local.set $f (ref.func $inc))))
+    (call_ref $unop (i32.const 2)
+      (block $some (result (ref $unop))
+        (br_on_non_null $some (local.get $f))
+        (return (i32.const -1)))))
+  (func $count (export "count") (type $count)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (local.get 1))
+      (else
+        (return_call_ref $count
+          (i32.sub (local.get 0) (i32.const 1))
+          (i32.add (local.get 1) (i32.const 1))
+          (ref.func $count)))))
+  (elem declare func $inc $count)
+  (elem (i32.const 1) $inc))
+"#,
+        )?;
+        let mut runtime = Runtime::new_with_features(module, &WasmFeatures::all())?;
+        //When
+        let called = runtime.invoke("call", &[Value::I32(41)])?;
+        let null = runtime.invoke("call_null", &[]).unwrap_err();
+        let non_null = runtime.invoke("as_non_null", &[Value::I32(1)])?;
+        let trap = runtime.invoke("as_non_null", &[Value::I32(0)]).unwrap_err();
+        let on_null = runtime.invoke("on_null", &[Value::I32(0)])?;
+        let not_null = runtime.invoke("on_null", &[Value::I32(1)])?;
+        let on_non_null = runtime.invoke("on_non_null", &[Value::I32(1)])?;
+        let non_null_missed = runtime.invoke("on_non_null", &[Value::I32(0)])?;
+        let count = runtime.invoke("count", &[Value::I32(100_000), Value::I32(0)])?;
+        //Then
+        assert_eq!(called, vec![Value::I32(42)]);
+        assert_eq!(message(null), "null function reference");
+        assert_eq!(non_null, vec![Value::I32(0)]);
+        assert_eq!(message(trap), "null reference");
+        assert_eq!(on_null, vec![Value::I32(-1)]);
+        assert_eq!(not_null, vec![Value::I32(2)]);
+        assert_eq!(on_non_null, vec![Value::I32(3)]);
+        assert_eq!(non_null_missed, vec![Value::I32(-1)]);
+        assert_eq!(count, vec![Value::I32(100_000)]);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "extended-const")]
+    fn invoke_extended_const() -> Result<()> {
+        //Given
+        // wasmer's wat doesn't know extended constant expressions yet
+        let module = || {
+            crate::text::parse(
+                r#"
+(module
+  (import "env" "__memory_base" (global $memory_base i32))
+  (import "env" "__table_base" (global $table_base i32))
+  (memory 1)
+  (table 4 funcref)
+  (global $end i32 (i32.add (global.get $memory_base) (i32.const 3)))
+  (global $big i64 (i64.sub (i64.mul (i64.const 4294967296) (i64.const 3)) (i64.const 1)))
+  (func $seven (result i32) (i32.const 7))
+  (elem (i32.add (global.get $table_base) (i32.const 1)) $seven)
+  (data (i32.sub (global.get $end) (i32.const 1)) "\2a")
+  (func (export "load") (param i32) (result i32) (i32.load8_u (local.get 0)))
+  (func (export "call") (param i32) (result i32) (call_indirect (result i32) (local.get 0)))
+  (func (export "end") (result i32) (global.get $end))
+  (func (export "big") (result i64) (global.get $big)))
+"#,
+            )
+        };
+        let features = WasmFeatures::all();
+        let options = RuntimeOptions::default();
+        let mut imports = Imports::default();
+        imports.add_global("env", "__memory_base", Value::I32(16));
+        let missing = Runtime::new_with_imports(module()?, &features, options, &imports);
+        imports.add_global("env", "__table_base", Value::I64(2));
+        let mismatched = Runtime::new_with_imports(module()?, &features, options, &imports);
+        imports.add_global("env", "__table_base", Value::I32(2));
+        let mut runtime = Runtime::new_with_imports(module()?, &features, options, &imports)?;
+        //When
+        let loaded = runtime.invoke("load", &[Value::I32(18)])?;
+        let called = runtime.invoke("call", &[Value::I32(3)])?;
+        let end = runtime.invoke("end", &[])?;
+        let big = runtime.invoke("big", &[])?;
+        //Then
+        assert_eq!(
+            message(missing.unwrap_err()),
+            "unknown import env.__table_base"
+        );
+        assert_eq!(
+            message(mismatched.unwrap_err()),
+            "incompatible import type: expected i32, found i64"
+        );
+        assert_eq!(loaded, vec![Value::I32(42)]);
+        assert_eq!(called, vec![Value::I32(7)]);
+        assert_eq!(end, vec![Value::I32(19)]);
+        assert_eq!(big, vec![Value::I64(3 * (1 << 32) - 1)]);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "multi-value",
+        feature = "reference-types",
+        feature = "bulk-memory",
+        feature = "gc"
+    ))]
+    fn invoke_gc() -> Result<()> {
+        //Given
+        // wasmer's wat doesn't know the gc proposal yet
+        let module = crate::text::parse(
+            r#"
+(module
+  (type $point (sub (struct (field $x (mut i32)) (field $y i32))))
+  (type $point3 (sub final $point (struct (field $x (mut i32)) (field $y i32) (field $z i8))))
+  (type $bytes (array (mut i8)))
+  (global $kept (mut (ref null $point)) (ref.null $point))
+  (data $d "\ff\02\03")
+  (func (export "point") (param i32 i32) (result i32)
+    (local $p (ref $point))
+    (local.set $p (struct.new $point (local.get 0) (local.get 1)))
+    (struct.set $point $x (local.get $p) (i32.mul (struct.get $point $x (local.get $p)) (i32.const 10)))
+    (i32.add (struct.get $point $x (local.get $p)) (struct.get $point $y (local.get $p))))
+  (func (export "null_get") (result i32)
+    (struct.get $point $x (ref.null $point)))
+  (func (export "packed") (param i32) (result i32 i32)
+    (local $p (ref $point3))
+    (local.set $p (struct.new $point3 (i32.const 0) (i32.const 0) (local.get 0)))
+    (struct.get_s $point3 $z (local.get $p))
+    (struct.get_u $point3 $z (local.get $p)))
+  (func (export "array") (param i32) (result i32)
+    (local $a (ref $bytes))
+    (local.set $a (array.new $bytes (i32.const 1) (i32.const 4)))
+    (array.fill $bytes (local.get $a) (i32.const 1) (i32.const 7) (i32.const 2))
+    (array.copy $bytes $bytes (local.get $a) (i32.const 2)
+      (array.new_fixed $bytes 2 (i32.const 5) (i32.const 6)) (i32.const 0) (i32.const 2))
+    (array.get_u $bytes (local.get $a) (local.get 0)))
+  (func (export "data") (result i32 i32 i32)
+    (local $a (ref $bytes))
+    (local.set $a (array.new_data $bytes $d (i32.const 0) (i32.const 3)))
+    (array.get_s $bytes (local.get $a) (i32.const 0))
+    (array.get_u $bytes (local.get $a) (i32.const 0))
+    (array.len (local.get $a)))
+  (func (export "i31") (param i32) (result i32 i32)
+    (i31.get_s (ref.i31 (local.get 0)))
+    (i31.get_u (ref.i31 (local.get 0))))
+  (func (export "test") (param i32) (result i32 i32 i32)
+    (local $r anyref)
+    (local.set $r
+      (if (result anyref) (local.get 0)
+        (then (struct.new $point3 (i32.const 1) (i32.const 2) (i32.const 3)))
+        (else (struct.new $point (i32.const 1) (i32.const 2)))))
+    (ref.test (ref $point) (local.get $r))
+    (ref.test (ref $point3) (local.get $r))
+    (ref.test (ref i31) (local.get $r)))
+  (func (export "cast") (result i32)
+    (struct.get $point $y (ref.cast (ref $point) (ref.i31 (i32.const 0)))))
+  (func (export "on_cast") (param i32) (result i32)
+    (local $r anyref)
+    (local.set $r
+      (if (result anyref) (local.get 0)
+        (then (struct.new $point3 (i32.const 1) (i32.const 2) (i32.const 3)))
+        (else (ref.i31 (i32.const 4)))))
+    (block $i31 (result (ref i31))
+      (br_on_cast $i31 anyref (ref i31)
+        (block $other (result anyref)
+          (return
+            (struct.get_u $point3 $z
+              (br_on_cast_fail $other anyref (ref $point3) (local.get $r))))))
+      (return (i32.const -1)))
+    (i31.get_u))
+  (func (export "alloc") (param i32)
+    (loop $l
+      (global.set $kept (struct.new $point (local.get 0) (i32.const 0)))
+      (br_if $l (local.tee 0 (i32.sub (local.get 0) (i32.const 1))))))
+  (func (export "kept") (result i32)
+    (struct.get $point $x (global.get $kept)))
+  (func (export "new") (param i32) (result (ref $point))
+    (struct.new $point (local.get 0) (i32.const 0)))
+  (func (export "x") (param (ref $point)) (result i32)
+    (struct.get $point $x (local.get 0))))
+"#,
+        )?;
+        let mut runtime = Runtime::new_with_features(module, &WasmFeatures::all())?;
+        //When
+        let point = runtime.invoke("point", &[Value::I32(4), Value::I32(2)])?;
+        let null = runtime.invoke("null_get", &[]).unwrap_err();
+        let packed = runtime.invoke("packed", &[Value::I32(0x1ff)])?;
+        let array = (0..4)
+            .map(|i| Ok(runtime.invoke("array", &[Value::I32(i)])?[0]))
+            .collect::<Result<Vec<_>>>()?;
+        let out_of_bounds = runtime.invoke("array", &[Value::I32(4)]).unwrap_err();
+        let data = runtime.invoke("data", &[])?;
+        let i31 = runtime.invoke("i31", &[Value::I32(-1)])?;
+        let point3 = runtime.invoke("test", &[Value::I32(1)])?;
+        let point_ = runtime.invoke("test", &[Value::I32(0)])?;
+        let cast = runtime.invoke("cast", &[]).unwrap_err();
+        let on_cast = runtime.invoke("on_cast", &[Value::I32(1)])?;
+        let on_cast_fail = runtime.invoke("on_cast", &[Value::I32(0)])?;
+        runtime.invoke("alloc", &[Value::I32(3000)])?;
+        let allocated = runtime.heap.live;
+        runtime.collect_garbage();
+        let collected = runtime.heap.live;
+        let kept = runtime.invoke("kept", &[])?;
+        let held = runtime.invoke("new", &[Value::I32(7)])?[0];
+        runtime.invoke("alloc", &[Value::I32(3000)])?;
+        runtime.collect_garbage();
+        let x = runtime.invoke("x", &[held])?;
+        runtime.release(&held);
+        runtime.collect_garbage();
+        let released = runtime.invoke("x", &[held]).unwrap_err();
+        //Then
+        assert_eq!(point, vec![Value::I32(42)]);
+        assert_eq!(message(null), "null structure reference");
+        assert_eq!(packed, vec![Value::I32(-1), Value::I32(255)]);
+        assert_eq!(
+            array,
+            vec![Value::I32(1), Value::I32(7), Value::I32(5), Value::I32(6)]
+        );
+        assert_eq!(message(out_of_bounds), "out of bounds array access");
+        assert_eq!(data, vec![Value::I32(-1), Value::I32(255), Value::I32(3)]);
+        assert_eq!(i31, vec![Value::I32(-1), Value::I32(0x7fff_ffff)]);
+        assert_eq!(point3, vec![Value::I32(1), Value::I32(1), Value::I32(0)]);
+        assert_eq!(point_, vec![Value::I32(1), Value::I32(0), Value::I32(0)]);
+        assert_eq!(message(cast), "cast failure");
+        assert_eq!(on_cast, vec![Value::I32(3)]);
+        assert_eq!(on_cast_fail, vec![Value::I32(4)]);
+        assert!(
+            allocated < 3000,
+            "collected while allocating: {}",
+            allocated
+        );
+        assert_eq!(collected, 1);
+        assert_eq!(kept, vec![Value::I32(1)]);
+        assert_eq!(x, vec![Value::I32(7)]);
+        assert_eq!(message(released), "reference to a freed object");
+        Ok(())
+    }
 }
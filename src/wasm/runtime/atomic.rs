@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+use super::{memory::effective, Runtime};
+use crate::structure::{
+    instructions::{
+        atomic::{AtomicKind, AtomicOp, RmwOp},
+        MemArg,
+    },
+    types::NumType,
+    values::Value,
+};
+
+fn rmw(op: RmwOp, old: u64, operand: u64) -> u64 {
+    match op {
+        RmwOp::Add => old.wrapping_add(operand),
+        RmwOp::Sub => old.wrapping_sub(operand),
+        RmwOp::And => old & operand,
+        RmwOp::Or => old | operand,
+        RmwOp::Xor => old ^ operand,
+        RmwOp::Xchg => operand,
+    }
+}
+
+/// zero-extend the bytes read by an atomic instruction
+fn value_of(t: NumType, bits: u64) -> Value {
+    match t {
+        NumType::I64 => Value::I64(bits as i64),
+        _ => Value::I32(bits as u32 as i32),
+    }
+}
+
+/// https://webassembly.github.io/threads/core/exec/instructions.html#atomic-memory-instructions
+impl Runtime<'_> {
+    fn pop_bits(&mut self) -> Result<u64> {
+        match self.stack_pop()? {
+            Value::I32(v) => Ok(v as u32 as u64),
+            Value::I64(v) => Ok(v as u64),
+            v => bail!("unexpected value: {:?}", v),
+        }
+    }
+
+    pub(super) fn atomic(&mut self, op: AtomicOp, memarg: &MemArg) -> Result<()> {
+        let kind = op.kind();
+        let width = kind.width() as usize;
+        // operands are wrapped to the accessed bytes
+        let mask = u64::MAX >> (64 - width * 8);
+        let result = match kind {
+            AtomicKind::Notify => {
+                let count = self.pop_i32()? as u32;
+                let ea = effective(self.pop_addr()?, memarg)?;
+                Some(Value::I32(self.memory(memarg).notify(ea, count)? as i32))
+            }
+            AtomicKind::Wait(_) => {
+                // a negative timeout in nanoseconds never expires
+                let timeout = self.pop_bits()? as i64;
+                let timeout = u64::try_from(timeout).ok().map(Duration::from_nanos);
+                let expected = self.pop_bits()?;
+                let ea = effective(self.pop_addr()?, memarg)?;
+                let result = self.memory(memarg).wait(ea, width, expected, timeout)?;
+                Some(Value::I32(result))
+            }
+            AtomicKind::Load(t, _) => {
+                let ea = effective(self.pop_addr()?, memarg)?;
+                let old = self.memory(memarg).atomic(ea, width, |_| None)?;
+                Some(value_of(t, old))
+            }
+            AtomicKind::Store(..) => {
+                let value = self.pop_bits()?;
+                let ea = effective(self.pop_addr()?, memarg)?;
+                self.memory(memarg).atomic(ea, width, |_| Some(value))?;
+                None
+            }
+            AtomicKind::Rmw(t, _, op) => {
+                let operand = self.pop_bits()?;
+                let ea = effective(self.pop_addr()?, memarg)?;
+                let old = self
+                    .memory(memarg)
+                    .atomic(ea, width, |old| Some(rmw(op, old, operand)))?;
+                Some(value_of(t, old))
+            }
+            AtomicKind::Cmpxchg(t, _) => {
+                let replacement = self.pop_bits()?;
+                let expected = self.pop_bits()? & mask;
+                let ea = effective(self.pop_addr()?, memarg)?;
+                let old = self
+                    .memory(memarg)
+                    .atomic(ea, width, |old| (old == expected).then_some(replacement))?;
+                Some(value_of(t, old))
+            }
+        };
+        self.stack.extend(result);
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+#[cfg(feature = "exceptions")]
+use anyhow::bail;
+use anyhow::{Error, Result};
+
+#[cfg(feature = "exceptions")]
+use super::LabelKind;
+use super::{Frame, Runtime};
+#[cfg(feature = "exceptions")]
+use crate::structure::types::{FuncType, ResultType};
+use crate::structure::{module::indices::TagIdx, values::Value};
+
+/// An exception thrown by `throw`, which is the error of `invoke` when no `try_table` catches it
+/// https://webassembly.github.io/exception-handling/core/exec/runtime.html#exception-instances
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exception {
+    pub tag: TagIdx,
+    /// the arguments of the tag
+    pub values: Vec<Value>,
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = self.values.iter().map(Value::to_string).collect();
+        write!(
+            f,
+            "uncaught exception of tag {} [{}]",
+            self.tag,
+            values.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for Exception {}
+
+/// https://webassembly.github.io/exception-handling/core/exec/instructions.html#control-instructions
+#[cfg(feature = "exceptions")]
+impl Runtime<'_> {
+    pub(super) fn throw(&mut self, tag: TagIdx) -> Result<()> {
+        let type_ = self.module.tags[tag as usize].type_;
+        let FuncType(ResultType(params), _) = self.type_(type_);
+        let values = self.stack.split_off(self.stack.len() - params.len());
+        Err(Exception { tag, values }.into())
+    }
+
+    pub(super) fn throw_ref(&mut self) -> Result<()> {
+        let exception = match self.stack_pop()? {
+            Value::ExnRef(Some(addr)) => self.exceptions.get(addr)?.clone(),
+            Value::ExnRef(None) => bail!("null exception reference"),
+            v => bail!("unexpected value: {:?}", v),
+        };
+        Err(exception.into())
+    }
+
+    /// unwind the call stack to the innermost `try_table` with a catch clause matching the exception
+    /// and branch to the label of the clause, while traps and uncaught exceptions pass through
+    pub(super) fn catch(&mut self, frames: &mut Vec<Frame>, err: Error) -> Result<()> {
+        let exception = err.downcast::<Exception>()?;
+        while let Some(frame) = frames.last_mut() {
+            while let Some(label) = frame.labels.pop() {
+                let LabelKind::TryTable(catches) = label.kind else {
+                    continue;
+                };
+                let catch = catches
+                    .iter()
+                    .find(|catch| catch.tag().is_none_or(|tag| tag == exception.tag));
+                let Some(catch) = catch else {
+                    continue;
+                };
+                self.stack.truncate(label.height);
+                if catch.tag().is_some() {
+                    self.stack.extend_from_slice(&exception.values);
+                }
+                if catch.is_ref() {
+                    let addr = self.exceptions.alloc(exception);
+                    self.stack.push(Value::ExnRef(Some(addr)));
+                    // the exception is a root on the stack now
+                    if self.exceptions.is_full() {
+                        self.collect_garbage();
+                    }
+                }
+                // the labels of clauses are outside of the `try_table`
+                self.branch(frame, catch.label());
+                return Ok(());
+            }
+            self.locals.truncate(frame.base);
+            frames.pop();
+        }
+        Err(exception.into())
+    }
+}
+
+/// nothing is caught when exception handling is compiled out
+#[cfg(not(feature = "exceptions"))]
+impl Runtime<'_> {
+    pub(super) fn catch(&mut self, _frames: &mut Vec<Frame>, err: Error) -> Result<()> {
+        Err(err)
+    }
+}
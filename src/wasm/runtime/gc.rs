@@ -0,0 +1,496 @@
+#[cfg(feature = "gc")]
+use anyhow::bail;
+use anyhow::{Context, Result};
+
+use super::Runtime;
+#[cfg(feature = "gc")]
+use crate::structure::{
+    instructions::Instruction,
+    module::indices::FieldIdx,
+    types::{CompositeType, FieldType, NumType, PackedType, StorageType, ValType},
+};
+use crate::structure::{
+    module::indices::TypeIdx,
+    types::HeapType,
+    values::{HeapRef, Value},
+};
+
+/// the number of live objects which triggers the first collection
+const MIN_THRESHOLD: usize = 1024;
+
+/// A structure or an array, whose fields hold packed values masked to their widths
+/// https://webassembly.github.io/gc/core/exec/runtime.html#aggregate-instances
+#[derive(Debug)]
+pub(super) struct Object {
+    pub type_: TypeIdx,
+    pub fields: Vec<Value>,
+}
+
+/// Objects allocated by the instructions of the GC proposal, or exceptions caught by reference,
+/// which are freed by a mark and sweep collection from the roots of the runtime
+#[derive(Debug)]
+pub(super) struct Heap<T> {
+    objects: Vec<Option<T>>,
+    /// addresses of freed objects, reused by later allocations
+    free: Vec<u32>,
+    /// the number of objects which have not been freed
+    pub(super) live: usize,
+    /// the number of live objects at which the next collection runs
+    threshold: usize,
+}
+
+impl<T> Default for Heap<T> {
+    fn default() -> Self {
+        Self {
+            objects: vec![],
+            free: vec![],
+            live: 0,
+            threshold: MIN_THRESHOLD,
+        }
+    }
+}
+
+impl<T> Heap<T> {
+    #[cfg(any(feature = "gc", feature = "exceptions"))]
+    pub(super) fn alloc(&mut self, object: T) -> u32 {
+        self.live += 1;
+        match self.free.pop() {
+            Some(addr) => {
+                self.objects[addr as usize] = Some(object);
+                addr
+            }
+            None => {
+                self.objects.push(Some(object));
+                (self.objects.len() - 1) as u32
+            }
+        }
+    }
+
+    /// trap when the host passes a reference which it released
+    pub(super) fn get(&self, addr: u32) -> Result<&T> {
+        self.objects
+            .get(addr as usize)
+            .and_then(Option::as_ref)
+            .context("reference to a freed object")
+    }
+
+    #[cfg(feature = "gc")]
+    fn get_mut(&mut self, addr: u32) -> Result<&mut T> {
+        self.objects
+            .get_mut(addr as usize)
+            .and_then(Option::as_mut)
+            .context("reference to a freed object")
+    }
+
+    /// whether the next allocation should run a collection first
+    #[cfg(any(feature = "gc", feature = "exceptions"))]
+    pub(super) fn is_full(&self) -> bool {
+        self.live >= self.threshold
+    }
+
+    fn sweep(&mut self, marked: &[bool]) {
+        for (addr, object) in self.objects.iter_mut().enumerate() {
+            if object.is_some() && !marked[addr] {
+                *object = None;
+                self.free.push(addr as u32);
+                self.live -= 1;
+            }
+        }
+        self.threshold = MIN_THRESHOLD.max(self.live * 2);
+    }
+}
+
+/// An address in one of the heaps of the runtime
+#[derive(Debug, Clone, Copy)]
+enum Address {
+    Object(u32),
+    Exception(u32),
+}
+
+/// what the value refers to, where objects may be internalized or externalized
+fn address(value: &Value) -> Option<Address> {
+    match value {
+        Value::AnyRef(Some(HeapRef::Struct(addr) | HeapRef::Array(addr)))
+        | Value::ExternRef(Some(HeapRef::Struct(addr) | HeapRef::Array(addr))) => {
+            Some(Address::Object(*addr))
+        }
+        Value::ExnRef(Some(addr)) => Some(Address::Exception(*addr)),
+        _ => None,
+    }
+}
+
+/// values stored into packed fields keep their low bits
+#[cfg(feature = "gc")]
+fn pack(storage: StorageType, value: Value) -> Value {
+    match (storage, value) {
+        (StorageType::Packed(packed), Value::I32(v)) => Value::I32(v & ((1 << packed.width()) - 1)),
+        _ => value,
+    }
+}
+
+/// `signed` is None for fields which are not packed
+#[cfg(feature = "gc")]
+fn unpack(storage: StorageType, value: Value, signed: Option<bool>) -> Value {
+    match (storage, value, signed) {
+        (StorageType::Packed(packed), Value::I32(v), Some(true)) => {
+            let shift = 32 - packed.width();
+            Value::I32(v << shift >> shift)
+        }
+        _ => value,
+    }
+}
+
+/// the number of bytes which a field takes in data segments
+#[cfg(feature = "gc")]
+fn width(storage: StorageType) -> usize {
+    match storage {
+        StorageType::Packed(PackedType::I8) => 1,
+        StorageType::Packed(PackedType::I16) => 2,
+        StorageType::Val(ValType::Number(NumType::I32 | NumType::F32)) => 4,
+        StorageType::Val(ValType::Number(NumType::I64 | NumType::F64)) => 8,
+        StorageType::Val(ValType::Vec) => 16,
+        StorageType::Val(ValType::Ref(_)) => unreachable!("validated numeric array"),
+    }
+}
+
+/// read a field from its bytes in little endian
+#[cfg(feature = "gc")]
+fn read_field(storage: StorageType, bytes: &[u8]) -> Value {
+    let bits = bytes
+        .iter()
+        .rev()
+        .fold(0u128, |bits, b| bits << 8 | *b as u128);
+    match storage {
+        StorageType::Packed(_) | StorageType::Val(ValType::Number(NumType::I32)) => {
+            Value::I32(bits as i32)
+        }
+        StorageType::Val(ValType::Number(NumType::I64)) => Value::I64(bits as i64),
+        StorageType::Val(ValType::Number(NumType::F32)) => Value::F32(f32::from_bits(bits as u32)),
+        StorageType::Val(ValType::Number(NumType::F64)) => Value::F64(f64::from_bits(bits as u64)),
+        StorageType::Val(ValType::Vec) => Value::V128(bits),
+        StorageType::Val(ValType::Ref(_)) => unreachable!("validated numeric array"),
+    }
+}
+
+/// https://webassembly.github.io/gc/core/exec/instructions.html#reference-instructions
+impl Runtime<'_> {
+    /// Mark the objects and exceptions reachable from the value stack, the locals of the active calls,
+    /// globals, tables, element segments and the references returned to the host,
+    /// and free the others.
+    pub fn collect_garbage(&mut self) {
+        let mut objects = vec![false; self.heap.objects.len()];
+        let mut exceptions = vec![false; self.exceptions.objects.len()];
+        let roots = self
+            .stack
+            .iter()
+            .chain(&self.locals)
+            .chain(&self.globals)
+            .chain(self.tables.iter().flat_map(|table| &table.elems))
+            .chain(self.elems.iter().flatten())
+            .chain(&self.pinned);
+        let mut pending: Vec<Address> = roots.filter_map(address).collect();
+        while let Some(addr) = pending.pop() {
+            let (marked, values) = match addr {
+                Address::Object(addr) => (
+                    objects.get_mut(addr as usize),
+                    self.heap.get(addr).map(|object| &object.fields),
+                ),
+                Address::Exception(addr) => (
+                    exceptions.get_mut(addr as usize),
+                    self.exceptions.get(addr).map(|exception| &exception.values),
+                ),
+            };
+            // the references which the host passes are checked, so the roots are never freed
+            let (Some(marked), Ok(values)) = (marked, values) else {
+                continue;
+            };
+            if std::mem::replace(marked, true) {
+                continue;
+            }
+            pending.extend(values.iter().filter_map(address));
+        }
+        self.heap.sweep(&objects);
+        self.exceptions.sweep(&exceptions);
+    }
+
+    /// keep the references which are returned to the host alive until they are released
+    pub(super) fn pin(&mut self, values: &[Value]) {
+        let refs = values.iter().filter(|value| address(value).is_some());
+        self.pinned.extend(refs);
+    }
+
+    /// let a reference which `invoke` returned be freed once the instance doesn't refer to it,
+    /// after which the host must not pass it again
+    pub fn release(&mut self, value: &Value) {
+        if let Some(pos) = self.pinned.iter().position(|pinned| pinned == value) {
+            self.pinned.swap_remove(pos);
+        }
+    }
+
+    /// the heap type of a non-null reference, where functions have the types of their definitions
+    pub(super) fn heap_type_of(&self, value: &Value) -> Result<Option<HeapType>> {
+        Ok(Some(match value {
+            Value::FuncRef(Some(idx)) => HeapType::Type(self.module.funcs[*idx as usize].type_),
+            Value::AnyRef(Some(HeapRef::I31(_))) => HeapType::I31,
+            Value::AnyRef(Some(HeapRef::Struct(addr) | HeapRef::Array(addr))) => {
+                HeapType::Type(self.heap.get(*addr)?.type_)
+            }
+            Value::AnyRef(Some(HeapRef::Host(_))) => HeapType::Any,
+            Value::ExternRef(Some(r)) => {
+                if let HeapRef::Struct(addr) | HeapRef::Array(addr) = r {
+                    self.heap.get(*addr)?;
+                }
+                HeapType::Extern
+            }
+            Value::ExnRef(Some(addr)) => {
+                self.exceptions.get(*addr)?;
+                HeapType::Exn
+            }
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// https://webassembly.github.io/gc/core/exec/instructions.html#aggregate-instructions
+#[cfg(feature = "gc")]
+impl Runtime<'_> {
+    /// collect before the operands of an allocation are popped, so that they stay roots
+    fn prepare_alloc(&mut self) {
+        if self.heap.is_full() {
+            self.collect_garbage();
+        }
+    }
+
+    fn alloc(&mut self, type_: TypeIdx, fields: Vec<Value>) {
+        let is_struct = matches!(
+            self.module.types[type_ as usize].composite,
+            CompositeType::Struct(_)
+        );
+        let addr = self.heap.alloc(Object { type_, fields });
+        self.stack.push(Value::AnyRef(Some(if is_struct {
+            HeapRef::Struct(addr)
+        } else {
+            HeapRef::Array(addr)
+        })));
+    }
+
+    /// the type of a field, where arrays have the only field
+    fn storage(&self, type_: TypeIdx, field: FieldIdx) -> StorageType {
+        match &self.module.types[type_ as usize].composite {
+            CompositeType::Struct(fields) => fields[field as usize].1,
+            CompositeType::Array(FieldType(_, storage)) => *storage,
+            CompositeType::Func(_) => unreachable!("validated aggregate type"),
+        }
+    }
+
+    fn pop_object(&mut self, kind: &str) -> Result<u32> {
+        match self.stack_pop()? {
+            Value::AnyRef(Some(HeapRef::Struct(addr) | HeapRef::Array(addr))) => Ok(addr),
+            Value::AnyRef(None) => bail!("null {} reference", kind),
+            v => bail!("unexpected value: {:?}", v),
+        }
+    }
+
+    /// pop the index and the array of an access to `n` elements
+    fn pop_range(&mut self, n: usize) -> Result<(u32, usize)> {
+        let i = self.pop_i32()? as u32 as usize;
+        let addr = self.pop_object("array")?;
+        if i + n > self.heap.get(addr)?.fields.len() {
+            bail!("out of bounds array access")
+        }
+        Ok((addr, i))
+    }
+
+    /// the elements which `array.new_data` and `array.init_data` read from the data segment
+    fn data_fields(&mut self, type_: TypeIdx, data: u32, s: usize, n: usize) -> Result<Vec<Value>> {
+        let storage = self.storage(type_, 0);
+        let width = width(storage);
+        let bytes = self.datas[data as usize]
+            .get(s..s + n * width)
+            .context("out of bounds memory access")?;
+        Ok(bytes
+            .chunks(width)
+            .map(|bytes| read_field(storage, bytes))
+            .collect())
+    }
+
+    fn elem_fields(&self, elem: u32, s: usize, n: usize) -> Result<Vec<Value>> {
+        let values = self.elems[elem as usize]
+            .get(s..s + n)
+            .context("out of bounds table access")?;
+        Ok(values.to_vec())
+    }
+
+    pub(super) fn gc(&mut self, inst: &Instruction) -> Result<()> {
+        match inst {
+            Instruction::RefEq => {
+                let (b, a) = (self.stack_pop()?, self.stack_pop()?);
+                self.stack.push((a == b).into());
+            }
+            Instruction::RefTest(ref_type) => {
+                let value = self.stack_pop()?;
+                let is_instance = self.is_instance(&value, ValType::Ref(*ref_type))?;
+                self.stack.push(is_instance.into());
+            }
+            Instruction::RefCast(ref_type) => {
+                let value = self.stack.last().context("empty stack")?;
+                if !self.is_instance(value, ValType::Ref(*ref_type))? {
+                    bail!("cast failure")
+                }
+            }
+            Instruction::StructNew(type_) => {
+                self.prepare_alloc();
+                let CompositeType::Struct(fields) = &self.module.types[*type_ as usize].composite
+                else {
+                    unreachable!("validated structure type")
+                };
+                let storages: Vec<StorageType> = fields.iter().map(|field| field.1).collect();
+                let values = self.stack.split_off(self.stack.len() - storages.len());
+                let fields = storages.into_iter().zip(values).map(|(s, v)| pack(s, v));
+                self.alloc(*type_, fields.collect());
+            }
+            Instruction::StructNewDefault(type_) => {
+                self.prepare_alloc();
+                let CompositeType::Struct(fields) = &self.module.types[*type_ as usize].composite
+                else {
+                    unreachable!("validated structure type")
+                };
+                let fields = fields.iter().map(|field| self.default_of(field.1.unpack()));
+                let fields = fields.collect();
+                self.alloc(*type_, fields);
+            }
+            Instruction::StructGet(type_, field)
+            | Instruction::StructGetS(type_, field)
+            | Instruction::StructGetU(type_, field) => {
+                let addr = self.pop_object("structure")?;
+                let value = self.heap.get(addr)?.fields[*field as usize];
+                let signed = match inst {
+                    Instruction::StructGetS(..) => Some(true),
+                    Instruction::StructGetU(..) => Some(false),
+                    _ => None,
+                };
+                let storage = self.storage(*type_, *field);
+                self.stack.push(unpack(storage, value, signed));
+            }
+            Instruction::StructSet(type_, field) => {
+                let value = pack(self.storage(*type_, *field), self.stack_pop()?);
+                let addr = self.pop_object("structure")?;
+                self.heap.get_mut(addr)?.fields[*field as usize] = value;
+            }
+            Instruction::ArrayNew(type_) => {
+                self.prepare_alloc();
+                let n = self.pop_i32()? as u32 as usize;
+                let value = pack(self.storage(*type_, 0), self.stack_pop()?);
+                self.alloc(*type_, vec![value; n]);
+            }
+            Instruction::ArrayNewDefault(type_) => {
+                self.prepare_alloc();
+                let n = self.pop_i32()? as u32 as usize;
+                let value = self.default_of(self.storage(*type_, 0).unpack());
+                self.alloc(*type_, vec![value; n]);
+            }
+            Instruction::ArrayNewFixed(type_, n) => {
+                self.prepare_alloc();
+                let storage = self.storage(*type_, 0);
+                let values = self.stack.split_off(self.stack.len() - *n as usize);
+                let fields = values.into_iter().map(|v| pack(storage, v)).collect();
+                self.alloc(*type_, fields);
+            }
+            Instruction::ArrayNewData(type_, data) => {
+                self.prepare_alloc();
+                let n = self.pop_i32()? as u32 as usize;
+                let s = self.pop_i32()? as u32 as usize;
+                let fields = self.data_fields(*type_, *data, s, n)?;
+                self.alloc(*type_, fields);
+            }
+            Instruction::ArrayNewElem(type_, elem) => {
+                self.prepare_alloc();
+                let n = self.pop_i32()? as u32 as usize;
+                let s = self.pop_i32()? as u32 as usize;
+                let fields = self.elem_fields(*elem, s, n)?;
+                self.alloc(*type_, fields);
+            }
+            Instruction::ArrayGet(type_)
+            | Instruction::ArrayGetS(type_)
+            | Instruction::ArrayGetU(type_) => {
+                let i = self.pop_i32()? as u32 as usize;
+                let addr = self.pop_object("array")?;
+                let fields = &self.heap.get(addr)?.fields;
+                let value = *fields.get(i).context("out of bounds array access")?;
+                let signed = match inst {
+                    Instruction::ArrayGetS(_) => Some(true),
+                    Instruction::ArrayGetU(_) => Some(false),
+                    _ => None,
+                };
+                self.stack
+                    .push(unpack(self.storage(*type_, 0), value, signed));
+            }
+            Instruction::ArraySet(type_) => {
+                let value = pack(self.storage(*type_, 0), self.stack_pop()?);
+                let i = self.pop_i32()? as u32 as usize;
+                let addr = self.pop_object("array")?;
+                let fields = &mut self.heap.get_mut(addr)?.fields;
+                *fields.get_mut(i).context("out of bounds array access")? = value;
+            }
+            Instruction::ArrayLen => {
+                let addr = self.pop_object("array")?;
+                let len = self.heap.get(addr)?.fields.len() as u32;
+                self.stack.push(len.into());
+            }
+            Instruction::ArrayFill(type_) => {
+                let n = self.pop_i32()? as u32 as usize;
+                let value = pack(self.storage(*type_, 0), self.stack_pop()?);
+                let (addr, i) = self.pop_range(n)?;
+                self.heap.get_mut(addr)?.fields[i..i + n].fill(value);
+            }
+            Instruction::ArrayCopy(_, _) => {
+                let n = self.pop_i32()? as u32 as usize;
+                let (src, s) = self.pop_range(n)?;
+                let (dst, d) = self.pop_range(n)?;
+                let values = self.heap.get(src)?.fields[s..s + n].to_vec();
+                self.heap.get_mut(dst)?.fields[d..d + n].copy_from_slice(&values);
+            }
+            Instruction::ArrayInitData(type_, data) => {
+                let n = self.pop_i32()? as u32 as usize;
+                let s = self.pop_i32()? as u32 as usize;
+                let (addr, d) = self.pop_range(n)?;
+                let values = self.data_fields(*type_, *data, s, n)?;
+                self.heap.get_mut(addr)?.fields[d..d + n].copy_from_slice(&values);
+            }
+            Instruction::ArrayInitElem(_, elem) => {
+                let n = self.pop_i32()? as u32 as usize;
+                let s = self.pop_i32()? as u32 as usize;
+                let (addr, d) = self.pop_range(n)?;
+                let values = self.elem_fields(*elem, s, n)?;
+                self.heap.get_mut(addr)?.fields[d..d + n].copy_from_slice(&values);
+            }
+            Instruction::AnyConvertExtern => match self.stack_pop()? {
+                Value::ExternRef(r) => self.stack.push(Value::AnyRef(r)),
+                v => bail!("unexpected value: {:?}", v),
+            },
+            Instruction::ExternConvertAny => match self.stack_pop()? {
+                Value::AnyRef(r) => self.stack.push(Value::ExternRef(r)),
+                v => bail!("unexpected value: {:?}", v),
+            },
+            Instruction::RefI31 => {
+                let v = self.pop_i32()? as u32;
+                let r = HeapRef::I31(v & 0x7FFF_FFFF);
+                self.stack.push(Value::AnyRef(Some(r)));
+            }
+            Instruction::I31GetS | Instruction::I31GetU => {
+                let v = match self.stack_pop()? {
+                    Value::AnyRef(Some(HeapRef::I31(v))) => v as i32,
+                    Value::AnyRef(None) => bail!("null i31 reference"),
+                    v => bail!("unexpected value: {:?}", v),
+                };
+                let v = match inst {
+                    Instruction::I31GetS => v << 1 >> 1,
+                    _ => v,
+                };
+                self.stack.push(v.into());
+            }
+            _ => bail!("unexpected instruction: {:?}", inst),
+        }
+        Ok(())
+    }
+}
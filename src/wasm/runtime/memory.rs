@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+#[cfg(feature = "threads")]
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "threads")]
+use std::{sync::Condvar, time::Duration};
+
+use anyhow::{bail, Context, Result};
+
+use crate::structure::{
+    instructions::MemArg,
+    types::{IndexType, Limits, MemType, Share},
+    values::Value,
+};
+
+/// https://webassembly.github.io/spec/core/exec/runtime.html#page-size
+pub(super) const PAGE_SIZE: usize = 65536;
+
+/// https://webassembly.github.io/memory64/core/valid/types.html#memory-types
+const MAX_PAGES_64: u64 = 1 << 48;
+
+/// https://webassembly.github.io/spec/core/exec/runtime.html#memory-instances
+#[derive(Debug)]
+pub(super) enum Memory {
+    Unshared(Pages),
+    /// a shared memory or an imported one, which the host holds as well
+    Shared(SharedMemory),
+}
+
+/// The bytes of a memory, which are committed lazily page by page when they are written,
+/// so that a large memory costs only the pages in use
+#[derive(Debug)]
+pub(super) struct Pages {
+    /// pages written with any non-zero byte, the others read as zeros
+    committed: HashMap<u64, Box<[u8]>>,
+    size: u64,
+    max: u64,
+    index: IndexType,
+}
+
+/// A linear memory which instances on different threads can share by importing it.
+/// Every access holds the lock of the bytes, so that all of them are sequentially consistent.
+/// A memory of unshared type can be imported as well, though atomic waits trap on it.
+/// https://webassembly.github.io/threads/core/exec/runtime.html#memory-instances
+#[derive(Debug, Clone)]
+pub struct SharedMemory(Arc<SharedData>);
+
+#[derive(Debug)]
+struct SharedData {
+    share: Share,
+    pages: Mutex<Pages>,
+    /// threads waiting on addresses as futexes do
+    #[cfg(feature = "threads")]
+    waiters: Mutex<HashMap<u64, VecDeque<Arc<Waiter>>>>,
+}
+
+/// A thread parked by `memory.atomic.wait` until it is notified
+#[cfg(feature = "threads")]
+#[derive(Debug, Default)]
+struct Waiter {
+    notified: Mutex<bool>,
+    cond: Condvar,
+}
+
+/// a trap in another thread doesn't leave the bytes inconsistent
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// the address accessed with `memarg`, trapping when it overflows
+/// https://webassembly.github.io/memory64/core/exec/instructions.html#exec-load
+pub(super) fn effective(addr: u64, memarg: &MemArg) -> Result<u64> {
+    addr.checked_add(memarg.offset)
+        .context("out of bounds memory access")
+}
+
+/// an address or a size into a memory with 32 or 64 bit indices
+pub(super) fn address(index: IndexType, n: u64) -> Value {
+    match index {
+        IndexType::I32 => Value::I32(n as u32 as i32),
+        IndexType::I64 => Value::I64(n as i64),
+    }
+}
+
+/// read `bytes` as an unsigned integer in little endian
+#[cfg(feature = "threads")]
+fn to_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+/// the pages covering `len` bytes from `start`, with the offsets in them and the lengths
+fn chunks(start: u64, len: u64) -> impl Iterator<Item = (u64, usize, usize)> {
+    let mut pos = start;
+    let mut remaining = len;
+    std::iter::from_fn(move || {
+        if remaining == 0 {
+            return None;
+        }
+        let offset = (pos % PAGE_SIZE as u64) as usize;
+        let n = remaining.min((PAGE_SIZE - offset) as u64);
+        let chunk = (pos / PAGE_SIZE as u64, offset, n as usize);
+        // the end of a memory of 2^48 pages doesn't fit in u64
+        pos = pos.wrapping_add(n);
+        remaining -= n;
+        Some(chunk)
+    })
+}
+
+impl Pages {
+    fn new(MemType(Limits { min, max, index }, _): &MemType) -> Self {
+        let limit = match index {
+            IndexType::I32 => 1 << 16,
+            IndexType::I64 => MAX_PAGES_64,
+        };
+        Self {
+            committed: HashMap::new(),
+            size: *min,
+            max: max.unwrap_or(limit),
+            index: *index,
+        }
+    }
+
+    /// trap unless `len` bytes from `start` are in the memory
+    fn check(&self, start: u64, len: u64) -> Result<()> {
+        if start as u128 + len as u128 > self.size as u128 * PAGE_SIZE as u128 {
+            bail!("out of bounds memory access")
+        }
+        Ok(())
+    }
+
+    fn read(&self, start: u64, len: usize) -> Result<Vec<u8>> {
+        self.check(start, len as u64)?;
+        let mut buf = Vec::with_capacity(len);
+        for (page, offset, n) in chunks(start, len as u64) {
+            match self.committed.get(&page) {
+                Some(bytes) => buf.extend_from_slice(&bytes[offset..offset + n]),
+                None => buf.resize(buf.len() + n, 0),
+            }
+        }
+        Ok(buf)
+    }
+
+    fn write(&mut self, start: u64, data: &[u8]) -> Result<()> {
+        self.check(start, data.len() as u64)?;
+        let mut data = data;
+        for (page, offset, n) in chunks(start, data.len() as u64) {
+            let (chunk, rest) = data.split_at(n);
+            data = rest;
+            // zeros don't need to be committed
+            if !self.committed.contains_key(&page) && chunk.iter().all(|b| *b == 0) {
+                continue;
+            }
+            let bytes = self
+                .committed
+                .entry(page)
+                .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+            bytes[offset..offset + n].copy_from_slice(chunk);
+        }
+        Ok(())
+    }
+
+    fn fill(&mut self, start: u64, value: u8, len: u64) -> Result<()> {
+        self.check(start, len)?;
+        for (page, offset, n) in chunks(start, len) {
+            if value == 0 && n == PAGE_SIZE {
+                self.committed.remove(&page);
+            } else if value != 0 || self.committed.contains_key(&page) {
+                let bytes = self
+                    .committed
+                    .entry(page)
+                    .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+                bytes[offset..offset + n].fill(value);
+            }
+        }
+        Ok(())
+    }
+
+    /// returns the previous size, or None when the memory can't grow by `delta` pages
+    fn grow(&mut self, delta: u64) -> Option<u64> {
+        let old = self.size;
+        self.size = old.checked_add(delta).filter(|size| *size <= self.max)?;
+        Some(old)
+    }
+}
+
+impl Memory {
+    pub(super) fn new(type_: &MemType) -> Self {
+        let pages = Pages::new(type_);
+        match type_.1 {
+            Share::Unshared => Self::Unshared(pages),
+            Share::Shared => Self::Shared(SharedMemory::from_pages(pages, Share::Shared)),
+        }
+    }
+
+    /// run `f` over the bytes, locking them if they are shared
+    fn with<R>(&mut self, f: impl FnOnce(&mut Pages) -> R) -> R {
+        match self {
+            Self::Unshared(pages) => f(pages),
+            Self::Shared(shared) => f(&mut lock(&shared.0.pages)),
+        }
+    }
+
+    pub(super) fn index(&mut self) -> IndexType {
+        self.with(|pages| pages.index)
+    }
+
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-memory-size
+    pub(super) fn size(&mut self) -> u64 {
+        self.with(|pages| pages.size)
+    }
+
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-memory-grow
+    pub(super) fn grow(&mut self, delta: u64) -> Option<u64> {
+        self.with(|pages| pages.grow(delta))
+    }
+
+    /// trap unless `len` bytes from `start` are in the memory
+    pub(super) fn check(&mut self, start: u64, len: u64) -> Result<()> {
+        self.with(|pages| pages.check(start, len))
+    }
+
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-load
+    pub(super) fn read(&mut self, start: u64, len: usize) -> Result<Vec<u8>> {
+        self.with(|pages| pages.read(start, len))
+    }
+
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-store
+    pub(super) fn write(&mut self, start: u64, data: &[u8]) -> Result<()> {
+        self.with(|pages| pages.write(start, data))
+    }
+
+    /// https://webassembly.github.io/spec/core/exec/instructions.html#exec-memory-fill
+    pub(super) fn fill(&mut self, start: u64, value: u8, len: u64) -> Result<()> {
+        self.with(|pages| pages.fill(start, value, len))
+    }
+}
+
+/// https://webassembly.github.io/threads/core/exec/instructions.html#atomic-memory-instructions
+#[cfg(feature = "threads")]
+impl Memory {
+    /// read `width` bytes and write the bytes of `f` of them if any in a single step,
+    /// returning the bytes read
+    /// https://webassembly.github.io/threads/core/exec/instructions.html#exec-atomic-load
+    pub(super) fn atomic(
+        &mut self,
+        start: u64,
+        width: usize,
+        f: impl FnOnce(u64) -> Option<u64>,
+    ) -> Result<u64> {
+        self.with(|pages| {
+            let old = to_u64(&pages.read(start, width)?);
+            if !start.is_multiple_of(width as u64) {
+                bail!("unaligned atomic")
+            }
+            if let Some(new) = f(old) {
+                pages.write(start, &new.to_le_bytes()[..width])?;
+            }
+            Ok(old)
+        })
+    }
+
+    /// returns 0 when notified, 1 when the bytes differ from `expected` and 2 on the timeout
+    /// https://webassembly.github.io/threads/core/exec/instructions.html#exec-memory-atomic-wait
+    pub(super) fn wait(
+        &mut self,
+        start: u64,
+        width: usize,
+        expected: u64,
+        timeout: Option<Duration>,
+    ) -> Result<i32> {
+        let shared = match self {
+            Self::Shared(shared) if shared.0.share == Share::Shared => shared,
+            _ => bail!("expected shared memory"),
+        };
+        let pages = lock(&shared.0.pages);
+        let actual = to_u64(&pages.read(start, width)?);
+        if !start.is_multiple_of(width as u64) {
+            bail!("unaligned atomic")
+        }
+        if actual != expected {
+            return Ok(1);
+        }
+        let waiter = Arc::new(Waiter::default());
+        lock(&shared.0.waiters)
+            .entry(start)
+            .or_default()
+            .push_back(waiter.clone());
+        // notifiers can find the waiter only after the bytes are released
+        drop(pages);
+        let notified = lock(&waiter.notified);
+        let notified = match timeout {
+            Some(timeout) => {
+                let wait = waiter.cond.wait_timeout_while(notified, timeout, |n| !*n);
+                wait.unwrap_or_else(PoisonError::into_inner).0
+            }
+            None => {
+                let wait = waiter.cond.wait_while(notified, |n| !*n);
+                wait.unwrap_or_else(PoisonError::into_inner)
+            }
+        };
+        if *notified {
+            return Ok(0);
+        }
+        drop(notified);
+        // a notifier may have dequeued the waiter right after the timeout
+        let mut waiters = lock(&shared.0.waiters);
+        let Some(queue) = waiters.get_mut(&start) else {
+            return Ok(0);
+        };
+        let Some(pos) = queue.iter().position(|w| Arc::ptr_eq(w, &waiter)) else {
+            return Ok(0);
+        };
+        queue.remove(pos);
+        if queue.is_empty() {
+            waiters.remove(&start);
+        }
+        Ok(2)
+    }
+
+    /// wake up at most `count` waiters, returning the number of them
+    /// https://webassembly.github.io/threads/core/exec/instructions.html#exec-memory-atomic-notify
+    pub(super) fn notify(&mut self, start: u64, count: u32) -> Result<u32> {
+        self.atomic(start, 4, |_| None)?;
+        let shared = match self {
+            Self::Shared(shared) if shared.0.share == Share::Shared => shared,
+            _ => return Ok(0),
+        };
+        let mut waiters = lock(&shared.0.waiters);
+        let Some(queue) = waiters.get_mut(&start) else {
+            return Ok(0);
+        };
+        let woken = queue.len().min(count as usize);
+        for waiter in queue.drain(..woken) {
+            *lock(&waiter.notified) = true;
+            waiter.cond.notify_one();
+        }
+        if queue.is_empty() {
+            waiters.remove(&start);
+        }
+        Ok(woken as u32)
+    }
+}
+
+impl SharedMemory {
+    /// a memory of `min` pages which can grow up to `max` pages
+    pub fn new(min: u32, max: u32) -> Self {
+        Self::with_type(min, Some(max), Share::Shared)
+    }
+
+    /// a memory of unshared type, which an instance imports to share it with the host
+    pub fn unshared(min: u32, max: Option<u32>) -> Self {
+        Self::with_type(min, max, Share::Unshared)
+    }
+
+    fn with_type(min: u32, max: Option<u32>, share: Share) -> Self {
+        let limits = Limits {
+            min: min.into(),
+            max: max.map(Into::into),
+            index: IndexType::I32,
+        };
+        Self::from_pages(Pages::new(&MemType(limits, share)), share)
+    }
+
+    fn from_pages(pages: Pages, share: Share) -> Self {
+        Self(Arc::new(SharedData {
+            share,
+            pages: Mutex::new(pages),
+            #[cfg(feature = "threads")]
+            waiters: Mutex::default(),
+        }))
+    }
+
+    /// the current size in pages
+    pub fn size(&self) -> u64 {
+        lock(&self.0.pages).size
+    }
+
+    /// whether the memory can be imported as `type_`
+    /// https://webassembly.github.io/threads/core/valid/types.html#match-memtype
+    pub(super) fn matches(
+        &self,
+        MemType(Limits { min, max, index }, share): &MemType,
+    ) -> Result<()> {
+        match (*share, self.0.share) {
+            (Share::Shared, Share::Unshared) => {
+                bail!("incompatible import type: expected shared memory")
+            }
+            (Share::Unshared, Share::Shared) => {
+                bail!("incompatible import type: expected unshared memory")
+            }
+            _ => {}
+        }
+        if *share == Share::Shared && max.is_none() {
+            bail!("incompatible import type: shared memory must have maximum")
+        }
+        let pages = lock(&self.0.pages);
+        if pages.index != *index {
+            bail!("incompatible import type: index types of memory don't match")
+        }
+        // a memory without maximum matches only an import without maximum
+        if pages.size < *min || max.is_some_and(|max| pages.max > max) {
+            bail!("incompatible import type: limits of memory don't match")
+        }
+        Ok(())
+    }
+}
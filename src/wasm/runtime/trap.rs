@@ -0,0 +1,66 @@
+use std::fmt;
+
+use anyhow::Error;
+
+use crate::structure::module::{indices::FuncIdx, Names};
+
+/// functions shown in a backtrace, so that runaway recursion doesn't make the message huge
+const MAX_BACKTRACE: usize = 32;
+
+/// A trap with the functions which were active when it happened,
+/// shown by their names in the name section
+/// https://webassembly.github.io/spec/core/intro/overview.html#trap
+#[derive(Debug)]
+pub struct Trap {
+    error: Error,
+    /// the innermost active functions, from the one which trapped
+    backtrace: Vec<String>,
+    /// the number of outer functions left out of the backtrace
+    omitted: usize,
+}
+
+impl Trap {
+    /// `funcs` are the active functions from the outermost
+    pub(super) fn new(error: Error, funcs: &[FuncIdx], names: &Names) -> Self {
+        let backtrace = funcs
+            .iter()
+            .rev()
+            .take(MAX_BACKTRACE)
+            .map(|idx| names.func(*idx))
+            .collect();
+        Self {
+            error,
+            backtrace,
+            omitted: funcs.len().saturating_sub(MAX_BACKTRACE),
+        }
+    }
+
+    /// what went wrong, without the backtrace
+    pub fn message(&self) -> String {
+        self.error.to_string()
+    }
+
+    /// names of the innermost active functions, from the one which trapped
+    pub fn backtrace(&self) -> &[String] {
+        &self.backtrace
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        for (i, func) in self.backtrace.iter().enumerate() {
+            write!(f, "\n  {}: {}", i, func)?;
+        }
+        if self.omitted > 0 {
+            write!(f, "\n  ... {} more", self.omitted)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Trap {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
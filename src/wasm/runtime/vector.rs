@@ -0,0 +1,723 @@
+use anyhow::{bail, Result};
+use num::Float;
+
+use super::{memory::effective, Runtime};
+use crate::structure::{
+    instructions::{
+        vector::{VectorKind, VectorOp},
+        MemArg,
+    },
+    values::Value,
+};
+
+/// Scalars which a v128 is split into, in little endian
+trait Lane: Copy + Default + PartialOrd {
+    const BYTES: usize;
+    fn read(bytes: &[u8]) -> Self;
+    fn write(self, bytes: &mut Vec<u8>);
+}
+
+macro_rules! impl_lane {
+    ($($t:ty),*) => {
+        $(impl Lane for $t {
+            const BYTES: usize = std::mem::size_of::<$t>();
+
+            fn read(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().unwrap())
+            }
+
+            fn write(self, bytes: &mut Vec<u8>) {
+                bytes.extend(self.to_le_bytes())
+            }
+        })*
+    };
+}
+impl_lane!(i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+
+fn lanes_of<T: Lane>(bytes: &[u8]) -> Vec<T> {
+    bytes.chunks(T::BYTES).map(T::read).collect()
+}
+
+fn lanes<T: Lane>(v: u128) -> Vec<T> {
+    lanes_of(&v.to_le_bytes())
+}
+
+/// build a v128 from lanes filling 16 bytes
+fn from_lanes<T: Lane>(lanes: impl IntoIterator<Item = T>) -> u128 {
+    let mut bytes = Vec::with_capacity(16);
+    for lane in lanes {
+        lane.write(&mut bytes);
+    }
+    u128::from_le_bytes(bytes.try_into().expect("lanes have to fill 16 bytes"))
+}
+
+fn map<T: Lane, U: Lane>(a: u128, f: impl Fn(T) -> U) -> u128 {
+    from_lanes(lanes(a).into_iter().map(f))
+}
+
+fn zip<T: Lane, U: Lane>(a: u128, b: u128, f: impl Fn(T, T) -> U) -> u128 {
+    from_lanes(lanes(a).into_iter().zip(lanes(b)).map(|(a, b)| f(a, b)))
+}
+
+/// lanes of all ones where `f` holds, or of zeros
+fn compare<T: Lane>(a: u128, b: u128, f: impl Fn(T, T) -> bool) -> u128 {
+    let bits = T::BYTES * 8;
+    let ones = u128::MAX >> (128 - bits);
+    let results = lanes(a).into_iter().zip(lanes(b)).map(|(a, b)| f(a, b));
+    results.enumerate().fold(
+        0,
+        |v, (i, result)| if result { v | ones << (i * bits) } else { v },
+    )
+}
+
+/// the lower or the higher half of the lanes
+fn half<T: Lane>(a: u128, high: bool) -> Vec<T> {
+    let mut lanes = lanes(a);
+    let n = lanes.len() / 2;
+    if high {
+        lanes.split_off(n)
+    } else {
+        lanes.truncate(n);
+        lanes
+    }
+}
+
+/// widen the lower or the higher half of the lanes
+fn extend<T: Lane, U: Lane>(a: u128, high: bool, f: impl Fn(T) -> U) -> u128 {
+    from_lanes(half(a, high).into_iter().map(f))
+}
+
+/// sums of adjacent pairs of the lanes
+fn extadd_pairwise<T: Lane, U: Lane>(a: u128, f: impl Fn(T, T) -> U) -> u128 {
+    from_lanes(lanes(a).chunks(2).map(|pair| f(pair[0], pair[1])))
+}
+
+fn extmul<T: Lane, U: Lane>(a: u128, b: u128, high: bool, f: impl Fn(T, T) -> U) -> u128 {
+    from_lanes(
+        half(a, high)
+            .into_iter()
+            .zip(half(b, high))
+            .map(|(a, b)| f(a, b)),
+    )
+}
+
+fn narrow<T: Lane, U: Lane>(a: u128, b: u128, f: impl Fn(T) -> U) -> u128 {
+    from_lanes(lanes(a).into_iter().chain(lanes(b)).map(f))
+}
+
+#[cfg(feature = "relaxed-simd")]
+fn zip3<T: Lane>(a: u128, b: u128, c: u128, f: impl Fn(T, T, T) -> T) -> u128 {
+    let operands = lanes(a).into_iter().zip(lanes(b)).zip(lanes(c));
+    from_lanes(operands.map(|((a, b), c)| f(a, b, c)))
+}
+
+fn all_true<T: Lane>(a: u128) -> bool {
+    lanes::<T>(a).into_iter().all(|lane| lane != T::default())
+}
+
+/// the sign bits of the lanes
+fn bitmask<T: Lane>(a: u128) -> i32 {
+    lanes::<T>(a)
+        .into_iter()
+        .enumerate()
+        .fold(0, |mask, (i, lane)| {
+            mask | ((lane < T::default()) as i32) << i
+        })
+}
+
+/// https://webassembly.github.io/spec/core/exec/numerics.html#op-fmin
+fn fmin<T: Float>(a: T, b: T) -> T {
+    if a.is_nan() || b.is_nan() {
+        T::nan()
+    } else if a == b {
+        if a.is_sign_negative() {
+            a
+        } else {
+            b
+        }
+    } else {
+        a.min(b)
+    }
+}
+
+/// https://webassembly.github.io/spec/core/exec/numerics.html#op-fmax
+fn fmax<T: Float>(a: T, b: T) -> T {
+    if a.is_nan() || b.is_nan() {
+        T::nan()
+    } else if a == b {
+        if a.is_sign_positive() {
+            a
+        } else {
+            b
+        }
+    } else {
+        a.max(b)
+    }
+}
+
+/// https://webassembly.github.io/spec/core/exec/numerics.html#op-fpmin
+fn pmin<T: Float>(a: T, b: T) -> T {
+    if b < a {
+        b
+    } else {
+        a
+    }
+}
+
+/// https://webassembly.github.io/spec/core/exec/numerics.html#op-fpmax
+fn pmax<T: Float>(a: T, b: T) -> T {
+    if a < b {
+        b
+    } else {
+        a
+    }
+}
+
+/// https://webassembly.github.io/spec/core/exec/numerics.html#op-iq15mulrsat-s
+fn q15mulr_sat(a: i16, b: i16) -> i16 {
+    ((a as i32 * b as i32 + 0x4000) >> 15).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+fn unary(op: VectorOp, a: u128) -> Result<u128> {
+    use VectorOp::*;
+    Ok(match op {
+        V128Not => !a,
+        F32x4DemoteF64x2Zero => {
+            from_lanes(lanes(a).into_iter().map(|x: f64| x as f32).chain([0.0; 2]))
+        }
+        F64x2PromoteLowF32x4 => extend(a, false, |x: f32| x as f64),
+        I8x16Abs => map(a, i8::wrapping_abs),
+        I8x16Neg => map(a, i8::wrapping_neg),
+        I8x16Popcnt => map(a, |x: u8| x.count_ones() as u8),
+        F32x4Ceil => map(a, f32::ceil),
+        F32x4Floor => map(a, f32::floor),
+        F32x4Trunc => map(a, f32::trunc),
+        F32x4Nearest => map(a, f32::round_ties_even),
+        F64x2Ceil => map(a, f64::ceil),
+        F64x2Floor => map(a, f64::floor),
+        F64x2Trunc => map(a, f64::trunc),
+        F64x2Nearest => map(a, f64::round_ties_even),
+        I16x8ExtaddPairwiseI8x16S => extadd_pairwise(a, |x: i8, y| x as i16 + y as i16),
+        I16x8ExtaddPairwiseI8x16U => extadd_pairwise(a, |x: u8, y| x as u16 + y as u16),
+        I32x4ExtaddPairwiseI16x8S => extadd_pairwise(a, |x: i16, y| x as i32 + y as i32),
+        I32x4ExtaddPairwiseI16x8U => extadd_pairwise(a, |x: u16, y| x as u32 + y as u32),
+        I16x8Abs => map(a, i16::wrapping_abs),
+        I16x8Neg => map(a, i16::wrapping_neg),
+        I16x8ExtendLowI8x16S => extend(a, false, |x: i8| x as i16),
+        I16x8ExtendHighI8x16S => extend(a, true, |x: i8| x as i16),
+        I16x8ExtendLowI8x16U => extend(a, false, |x: u8| x as u16),
+        I16x8ExtendHighI8x16U => extend(a, true, |x: u8| x as u16),
+        I32x4Abs => map(a, i32::wrapping_abs),
+        I32x4Neg => map(a, i32::wrapping_neg),
+        I32x4ExtendLowI16x8S => extend(a, false, |x: i16| x as i32),
+        I32x4ExtendHighI16x8S => extend(a, true, |x: i16| x as i32),
+        I32x4ExtendLowI16x8U => extend(a, false, |x: u16| x as u32),
+        I32x4ExtendHighI16x8U => extend(a, true, |x: u16| x as u32),
+        I64x2Abs => map(a, i64::wrapping_abs),
+        I64x2Neg => map(a, i64::wrapping_neg),
+        I64x2ExtendLowI32x4S => extend(a, false, |x: i32| x as i64),
+        I64x2ExtendHighI32x4S => extend(a, true, |x: i32| x as i64),
+        I64x2ExtendLowI32x4U => extend(a, false, |x: u32| x as u64),
+        I64x2ExtendHighI32x4U => extend(a, true, |x: u32| x as u64),
+        F32x4Abs => map(a, f32::abs),
+        F32x4Neg => map(a, |x: f32| -x),
+        F32x4Sqrt => map(a, f32::sqrt),
+        F64x2Abs => map(a, f64::abs),
+        F64x2Neg => map(a, |x: f64| -x),
+        F64x2Sqrt => map(a, f64::sqrt),
+        // casts from floats to integers saturate and map NaN to 0 as trunc_sat does
+        I32x4TruncSatF32x4S => map(a, |x: f32| x as i32),
+        I32x4TruncSatF32x4U => map(a, |x: f32| x as u32),
+        F32x4ConvertI32x4S => map(a, |x: i32| x as f32),
+        F32x4ConvertI32x4U => map(a, |x: u32| x as f32),
+        I32x4TruncSatF64x2SZero => {
+            from_lanes(lanes(a).into_iter().map(|x: f64| x as i32).chain([0; 2]))
+        }
+        I32x4TruncSatF64x2UZero => {
+            from_lanes(lanes(a).into_iter().map(|x: f64| x as u32).chain([0; 2]))
+        }
+        F64x2ConvertLowI32x4S => extend(a, false, |x: i32| x as f64),
+        F64x2ConvertLowI32x4U => extend(a, false, |x: u32| x as f64),
+        _ => bail!("{} is not an unary vector instruction", op.name()),
+    })
+}
+
+fn binary(op: VectorOp, a: u128, b: u128) -> Result<u128> {
+    use VectorOp::*;
+    Ok(match op {
+        I8x16Swizzle => {
+            let a: Vec<u8> = lanes(a);
+            map(b, |i: u8| a.get(i as usize).copied().unwrap_or(0))
+        }
+        I8x16Eq => compare(a, b, |x: i8, y| x == y),
+        I8x16Ne => compare(a, b, |x: i8, y| x != y),
+        I8x16LtS => compare(a, b, |x: i8, y| x < y),
+        I8x16LtU => compare(a, b, |x: u8, y| x < y),
+        I8x16GtS => compare(a, b, |x: i8, y| x > y),
+        I8x16GtU => compare(a, b, |x: u8, y| x > y),
+        I8x16LeS => compare(a, b, |x: i8, y| x <= y),
+        I8x16LeU => compare(a, b, |x: u8, y| x <= y),
+        I8x16GeS => compare(a, b, |x: i8, y| x >= y),
+        I8x16GeU => compare(a, b, |x: u8, y| x >= y),
+        I16x8Eq => compare(a, b, |x: i16, y| x == y),
+        I16x8Ne => compare(a, b, |x: i16, y| x != y),
+        I16x8LtS => compare(a, b, |x: i16, y| x < y),
+        I16x8LtU => compare(a, b, |x: u16, y| x < y),
+        I16x8GtS => compare(a, b, |x: i16, y| x > y),
+        I16x8GtU => compare(a, b, |x: u16, y| x > y),
+        I16x8LeS => compare(a, b, |x: i16, y| x <= y),
+        I16x8LeU => compare(a, b, |x: u16, y| x <= y),
+        I16x8GeS => compare(a, b, |x: i16, y| x >= y),
+        I16x8GeU => compare(a, b, |x: u16, y| x >= y),
+        I32x4Eq => compare(a, b, |x: i32, y| x == y),
+        I32x4Ne => compare(a, b, |x: i32, y| x != y),
+        I32x4LtS => compare(a, b, |x: i32, y| x < y),
+        I32x4LtU => compare(a, b, |x: u32, y| x < y),
+        I32x4GtS => compare(a, b, |x: i32, y| x > y),
+        I32x4GtU => compare(a, b, |x: u32, y| x > y),
+        I32x4LeS => compare(a, b, |x: i32, y| x <= y),
+        I32x4LeU => compare(a, b, |x: u32, y| x <= y),
+        I32x4GeS => compare(a, b, |x: i32, y| x >= y),
+        I32x4GeU => compare(a, b, |x: u32, y| x >= y),
+        I64x2Eq => compare(a, b, |x: i64, y| x == y),
+        I64x2Ne => compare(a, b, |x: i64, y| x != y),
+        I64x2LtS => compare(a, b, |x: i64, y| x < y),
+        I64x2GtS => compare(a, b, |x: i64, y| x > y),
+        I64x2LeS => compare(a, b, |x: i64, y| x <= y),
+        I64x2GeS => compare(a, b, |x: i64, y| x >= y),
+        F32x4Eq => compare(a, b, |x: f32, y| x == y),
+        F32x4Ne => compare(a, b, |x: f32, y| x != y),
+        F32x4Lt => compare(a, b, |x: f32, y| x < y),
+        F32x4Gt => compare(a, b, |x: f32, y| x > y),
+        F32x4Le => compare(a, b, |x: f32, y| x <= y),
+        F32x4Ge => compare(a, b, |x: f32, y| x >= y),
+        F64x2Eq => compare(a, b, |x: f64, y| x == y),
+        F64x2Ne => compare(a, b, |x: f64, y| x != y),
+        F64x2Lt => compare(a, b, |x: f64, y| x < y),
+        F64x2Gt => compare(a, b, |x: f64, y| x > y),
+        F64x2Le => compare(a, b, |x: f64, y| x <= y),
+        F64x2Ge => compare(a, b, |x: f64, y| x >= y),
+        V128And => a & b,
+        V128AndNot => a & !b,
+        V128Or => a | b,
+        V128Xor => a ^ b,
+        I8x16NarrowI16x8S => narrow(a, b, |x: i16| x.clamp(i8::MIN as i16, i8::MAX as i16) as i8),
+        I8x16NarrowI16x8U => narrow(a, b, |x: i16| x.clamp(0, u8::MAX as i16) as u8),
+        I16x8NarrowI32x4S => narrow(a, b, |x: i32| {
+            x.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+        }),
+        I16x8NarrowI32x4U => narrow(a, b, |x: i32| x.clamp(0, u16::MAX as i32) as u16),
+        I8x16Add => zip(a, b, i8::wrapping_add),
+        I8x16AddSatS => zip(a, b, i8::saturating_add),
+        I8x16AddSatU => zip(a, b, u8::saturating_add),
+        I8x16Sub => zip(a, b, i8::wrapping_sub),
+        I8x16SubSatS => zip(a, b, i8::saturating_sub),
+        I8x16SubSatU => zip(a, b, u8::saturating_sub),
+        I8x16MinS => zip(a, b, |x: i8, y| x.min(y)),
+        I8x16MinU => zip(a, b, |x: u8, y| x.min(y)),
+        I8x16MaxS => zip(a, b, |x: i8, y| x.max(y)),
+        I8x16MaxU => zip(a, b, |x: u8, y| x.max(y)),
+        I8x16AvgrU => zip(a, b, |x: u8, y| (x as u16 + y as u16).div_ceil(2) as u8),
+        I16x8Q15mulrSatS => zip(a, b, q15mulr_sat),
+        I16x8Add => zip(a, b, i16::wrapping_add),
+        I16x8AddSatS => zip(a, b, i16::saturating_add),
+        I16x8AddSatU => zip(a, b, u16::saturating_add),
+        I16x8Sub => zip(a, b, i16::wrapping_sub),
+        I16x8SubSatS => zip(a, b, i16::saturating_sub),
+        I16x8SubSatU => zip(a, b, u16::saturating_sub),
+        I16x8Mul => zip(a, b, i16::wrapping_mul),
+        I16x8MinS => zip(a, b, |x: i16, y| x.min(y)),
+        I16x8MinU => zip(a, b, |x: u16, y| x.min(y)),
+        I16x8MaxS => zip(a, b, |x: i16, y| x.max(y)),
+        I16x8MaxU => zip(a, b, |x: u16, y| x.max(y)),
+        I16x8AvgrU => zip(a, b, |x: u16, y| (x as u32 + y as u32).div_ceil(2) as u16),
+        I16x8ExtmulLowI8x16S => extmul(a, b, false, |x: i8, y| x as i16 * y as i16),
+        I16x8ExtmulHighI8x16S => extmul(a, b, true, |x: i8, y| x as i16 * y as i16),
+        I16x8ExtmulLowI8x16U => extmul(a, b, false, |x: u8, y| x as u16 * y as u16),
+        I16x8ExtmulHighI8x16U => extmul(a, b, true, |x: u8, y| x as u16 * y as u16),
+        I32x4Add => zip(a, b, i32::wrapping_add),
+        I32x4Sub => zip(a, b, i32::wrapping_sub),
+        I32x4Mul => zip(a, b, i32::wrapping_mul),
+        I32x4MinS => zip(a, b, |x: i32, y| x.min(y)),
+        I32x4MinU => zip(a, b, |x: u32, y| x.min(y)),
+        I32x4MaxS => zip(a, b, |x: i32, y| x.max(y)),
+        I32x4MaxU => zip(a, b, |x: u32, y| x.max(y)),
+        I32x4DotI16x8S => {
+            let products: Vec<i32> = lanes(a)
+                .into_iter()
+                .zip(lanes(b))
+                .map(|(x, y): (i16, i16)| x as i32 * y as i32)
+                .collect();
+            from_lanes(products.chunks(2).map(|pair| pair[0].wrapping_add(pair[1])))
+        }
+        I32x4ExtmulLowI16x8S => extmul(a, b, false, |x: i16, y| x as i32 * y as i32),
+        I32x4ExtmulHighI16x8S => extmul(a, b, true, |x: i16, y| x as i32 * y as i32),
+        I32x4ExtmulLowI16x8U => extmul(a, b, false, |x: u16, y| x as u32 * y as u32),
+        I32x4ExtmulHighI16x8U => extmul(a, b, true, |x: u16, y| x as u32 * y as u32),
+        I64x2Add => zip(a, b, i64::wrapping_add),
+        I64x2Sub => zip(a, b, i64::wrapping_sub),
+        I64x2Mul => zip(a, b, i64::wrapping_mul),
+        I64x2ExtmulLowI32x4S => extmul(a, b, false, |x: i32, y| x as i64 * y as i64),
+        I64x2ExtmulHighI32x4S => extmul(a, b, true, |x: i32, y| x as i64 * y as i64),
+        I64x2ExtmulLowI32x4U => extmul(a, b, false, |x: u32, y| x as u64 * y as u64),
+        I64x2ExtmulHighI32x4U => extmul(a, b, true, |x: u32, y| x as u64 * y as u64),
+        F32x4Add => zip(a, b, |x: f32, y| x + y),
+        F32x4Sub => zip(a, b, |x: f32, y| x - y),
+        F32x4Mul => zip(a, b, |x: f32, y| x * y),
+        F32x4Div => zip(a, b, |x: f32, y| x / y),
+        F32x4Min => zip(a, b, fmin::<f32>),
+        F32x4Max => zip(a, b, fmax::<f32>),
+        F32x4Pmin => zip(a, b, pmin::<f32>),
+        F32x4Pmax => zip(a, b, pmax::<f32>),
+        F64x2Add => zip(a, b, |x: f64, y| x + y),
+        F64x2Sub => zip(a, b, |x: f64, y| x - y),
+        F64x2Mul => zip(a, b, |x: f64, y| x * y),
+        F64x2Div => zip(a, b, |x: f64, y| x / y),
+        F64x2Min => zip(a, b, fmin::<f64>),
+        F64x2Max => zip(a, b, fmax::<f64>),
+        F64x2Pmin => zip(a, b, pmin::<f64>),
+        F64x2Pmax => zip(a, b, pmax::<f64>),
+        _ => bail!("{} is not a binary vector instruction", op.name()),
+    })
+}
+
+fn test(op: VectorOp, a: u128) -> Result<i32> {
+    use VectorOp::*;
+    Ok(match op {
+        V128AnyTrue => (a != 0) as i32,
+        I8x16AllTrue => all_true::<i8>(a) as i32,
+        I16x8AllTrue => all_true::<i16>(a) as i32,
+        I32x4AllTrue => all_true::<i32>(a) as i32,
+        I64x2AllTrue => all_true::<i64>(a) as i32,
+        I8x16Bitmask => bitmask::<i8>(a),
+        I16x8Bitmask => bitmask::<i16>(a),
+        I32x4Bitmask => bitmask::<i32>(a),
+        I64x2Bitmask => bitmask::<i64>(a),
+        _ => bail!("{} is not a test of vectors", op.name()),
+    })
+}
+
+/// shift amounts are taken modulo the lane width as `wrapping_shl` does
+fn shift(op: VectorOp, a: u128, n: u32) -> Result<u128> {
+    use VectorOp::*;
+    Ok(match op {
+        I8x16Shl => map(a, |x: i8| x.wrapping_shl(n)),
+        I8x16ShrS => map(a, |x: i8| x.wrapping_shr(n)),
+        I8x16ShrU => map(a, |x: u8| x.wrapping_shr(n)),
+        I16x8Shl => map(a, |x: i16| x.wrapping_shl(n)),
+        I16x8ShrS => map(a, |x: i16| x.wrapping_shr(n)),
+        I16x8ShrU => map(a, |x: u16| x.wrapping_shr(n)),
+        I32x4Shl => map(a, |x: i32| x.wrapping_shl(n)),
+        I32x4ShrS => map(a, |x: i32| x.wrapping_shr(n)),
+        I32x4ShrU => map(a, |x: u32| x.wrapping_shr(n)),
+        I64x2Shl => map(a, |x: i64| x.wrapping_shl(n)),
+        I64x2ShrS => map(a, |x: i64| x.wrapping_shr(n)),
+        I64x2ShrU => map(a, |x: u64| x.wrapping_shr(n)),
+        _ => bail!("{} is not a shift of vectors", op.name()),
+    })
+}
+
+fn splat(op: VectorOp, value: Value) -> Result<u128> {
+    use VectorOp::*;
+    Ok(match (op, value) {
+        (I8x16Splat, Value::I32(v)) => from_lanes([v as u8; 16]),
+        (I16x8Splat, Value::I32(v)) => from_lanes([v as u16; 8]),
+        (I32x4Splat, Value::I32(v)) => from_lanes([v; 4]),
+        (I64x2Splat, Value::I64(v)) => from_lanes([v; 2]),
+        (F32x4Splat, Value::F32(v)) => from_lanes([v; 4]),
+        (F64x2Splat, Value::F64(v)) => from_lanes([v; 2]),
+        _ => bail!("unexpected value for {}: {:?}", op.name(), value),
+    })
+}
+
+fn extract_lane(op: VectorOp, a: u128, lane: usize) -> Result<Value> {
+    use VectorOp::*;
+    Ok(match op {
+        I8x16ExtractLaneS => Value::I32(lanes::<i8>(a)[lane] as i32),
+        I8x16ExtractLaneU => Value::I32(lanes::<u8>(a)[lane] as i32),
+        I16x8ExtractLaneS => Value::I32(lanes::<i16>(a)[lane] as i32),
+        I16x8ExtractLaneU => Value::I32(lanes::<u16>(a)[lane] as i32),
+        I32x4ExtractLane => Value::I32(lanes::<i32>(a)[lane]),
+        I64x2ExtractLane => Value::I64(lanes::<i64>(a)[lane]),
+        F32x4ExtractLane => Value::F32(lanes::<f32>(a)[lane]),
+        F64x2ExtractLane => Value::F64(lanes::<f64>(a)[lane]),
+        _ => bail!("{} does not extract a lane", op.name()),
+    })
+}
+
+fn replace_lane(op: VectorOp, a: u128, lane: usize, value: Value) -> Result<u128> {
+    fn replace<T: Lane>(a: u128, lane: usize, value: T) -> u128 {
+        let mut lanes = lanes(a);
+        lanes[lane] = value;
+        from_lanes(lanes)
+    }
+    use VectorOp::*;
+    Ok(match (op, value) {
+        (I8x16ReplaceLane, Value::I32(v)) => replace(a, lane, v as u8),
+        (I16x8ReplaceLane, Value::I32(v)) => replace(a, lane, v as u16),
+        (I32x4ReplaceLane, Value::I32(v)) => replace(a, lane, v),
+        (I64x2ReplaceLane, Value::I64(v)) => replace(a, lane, v),
+        (F32x4ReplaceLane, Value::F32(v)) => replace(a, lane, v),
+        (F64x2ReplaceLane, Value::F64(v)) => replace(a, lane, v),
+        _ => bail!("unexpected value for {}: {:?}", op.name(), value),
+    })
+}
+
+/// sums of adjacent products of i8 lanes, where `b` holds signed or unsigned lanes
+#[cfg(feature = "relaxed-simd")]
+fn dot_i8x16(a: u128, b: u128, signed: bool) -> u128 {
+    let b: Vec<i16> = if signed {
+        lanes::<i8>(b).into_iter().map(i16::from).collect()
+    } else {
+        lanes::<u8>(b).into_iter().map(i16::from).collect()
+    };
+    let products: Vec<i16> = lanes::<i8>(a)
+        .into_iter()
+        .zip(b)
+        .map(|(x, y)| x as i16 * y)
+        .collect();
+    from_lanes(products.chunks(2).map(|pair| pair[0].wrapping_add(pair[1])))
+}
+
+#[cfg(feature = "relaxed-simd")]
+fn dot_i8x16_add(a: u128, b: u128, c: u128, signed: bool) -> u128 {
+    let dot = extadd_pairwise(dot_i8x16(a, b, signed), |x: i16, y| x as i32 + y as i32);
+    zip(dot, c, i32::wrapping_add)
+}
+
+/// the deterministic semantics of relaxed instructions, which are the non-relaxed ones
+/// https://webassembly.github.io/spec/core/exec/numerics.html#relaxed-operations
+#[cfg(feature = "relaxed-simd")]
+fn relaxed_deterministic(op: VectorOp, a: u128, b: u128, c: u128) -> Result<u128> {
+    use VectorOp::*;
+    Ok(match op {
+        I8x16RelaxedSwizzle => binary(I8x16Swizzle, a, b)?,
+        I32x4RelaxedTruncF32x4S => unary(I32x4TruncSatF32x4S, a)?,
+        I32x4RelaxedTruncF32x4U => unary(I32x4TruncSatF32x4U, a)?,
+        I32x4RelaxedTruncF64x2SZero => unary(I32x4TruncSatF64x2SZero, a)?,
+        I32x4RelaxedTruncF64x2UZero => unary(I32x4TruncSatF64x2UZero, a)?,
+        F32x4RelaxedMadd => zip3(a, b, c, f32::mul_add),
+        F32x4RelaxedNmadd => zip3(a, b, c, |x: f32, y, z| (-x).mul_add(y, z)),
+        F64x2RelaxedMadd => zip3(a, b, c, f64::mul_add),
+        F64x2RelaxedNmadd => zip3(a, b, c, |x: f64, y, z| (-x).mul_add(y, z)),
+        I8x16RelaxedLaneselect
+        | I16x8RelaxedLaneselect
+        | I32x4RelaxedLaneselect
+        | I64x2RelaxedLaneselect => a & c | b & !c,
+        F32x4RelaxedMin => binary(F32x4Min, a, b)?,
+        F32x4RelaxedMax => binary(F32x4Max, a, b)?,
+        F64x2RelaxedMin => binary(F64x2Min, a, b)?,
+        F64x2RelaxedMax => binary(F64x2Max, a, b)?,
+        I16x8RelaxedQ15mulrS => binary(I16x8Q15mulrSatS, a, b)?,
+        I16x8RelaxedDotI8x16I7x16S => dot_i8x16(a, b, true),
+        I32x4RelaxedDotI8x16I7x16AddS => dot_i8x16_add(a, b, c, true),
+        _ => bail!("{} is not a relaxed instruction", op.name()),
+    })
+}
+
+/// the choices of x86 hardware among the results the spec allows for relaxed instructions,
+/// which skip the fix-ups for NaN, out of range inputs and mask bits
+#[cfg(feature = "relaxed-simd")]
+fn relaxed_native(op: VectorOp, a: u128, b: u128, c: u128) -> Result<u128> {
+    /// `cvttps2dq` and `cvttpd2dq` return the minimum for NaN and out of range inputs
+    fn trunc_s<T: Float>(x: T) -> i32 {
+        x.to_i32().unwrap_or(i32::MIN)
+    }
+    fn trunc_u<T: Float>(x: T) -> u32 {
+        x.to_u32().unwrap_or(u32::MAX)
+    }
+    /// lanes are selected by their top bits as `pblendvb` does
+    fn laneselect<T: Lane + std::ops::Shr<u32, Output = T>>(a: u128, b: u128, c: u128) -> u128 {
+        let mask = map(c, |x: T| x >> (T::BYTES as u32 * 8 - 1));
+        a & mask | b & !mask
+    }
+    use VectorOp::*;
+    Ok(match op {
+        // `pshufb` uses the lower 4 bits of indices below 128
+        I8x16RelaxedSwizzle => {
+            let a = lanes::<u8>(a);
+            map(b, |i: u8| if i < 0x80 { a[i as usize & 0x0F] } else { 0 })
+        }
+        I32x4RelaxedTruncF32x4S => map(a, trunc_s::<f32>),
+        I32x4RelaxedTruncF32x4U => map(a, trunc_u::<f32>),
+        I32x4RelaxedTruncF64x2SZero => {
+            from_lanes(lanes(a).into_iter().map(trunc_s::<f64>).chain([0; 2]))
+        }
+        I32x4RelaxedTruncF64x2UZero => {
+            from_lanes(lanes(a).into_iter().map(trunc_u::<f64>).chain([0; 2]))
+        }
+        F32x4RelaxedMadd => zip3(a, b, c, |x: f32, y, z| x * y + z),
+        F32x4RelaxedNmadd => zip3(a, b, c, |x: f32, y, z| -(x * y) + z),
+        F64x2RelaxedMadd => zip3(a, b, c, |x: f64, y, z| x * y + z),
+        F64x2RelaxedNmadd => zip3(a, b, c, |x: f64, y, z| -(x * y) + z),
+        I8x16RelaxedLaneselect => laneselect::<i8>(a, b, c),
+        I16x8RelaxedLaneselect => laneselect::<i16>(a, b, c),
+        I32x4RelaxedLaneselect => laneselect::<i32>(a, b, c),
+        I64x2RelaxedLaneselect => laneselect::<i64>(a, b, c),
+        // `minps` and `maxps` return the second operand for NaN and zeros of both signs
+        F32x4RelaxedMin => zip(a, b, |x: f32, y| if x < y { x } else { y }),
+        F32x4RelaxedMax => zip(a, b, |x: f32, y| if x > y { x } else { y }),
+        F64x2RelaxedMin => zip(a, b, |x: f64, y| if x < y { x } else { y }),
+        F64x2RelaxedMax => zip(a, b, |x: f64, y| if x > y { x } else { y }),
+        // `pmulhrsw` wraps the only overflowing product
+        I16x8RelaxedQ15mulrS => zip(a, b, |x: i16, y| {
+            ((x as i32 * y as i32 + 0x4000) >> 15) as i16
+        }),
+        // `pmaddubsw` takes the lanes of the second operand as unsigned
+        I16x8RelaxedDotI8x16I7x16S => dot_i8x16(a, b, false),
+        I32x4RelaxedDotI8x16I7x16AddS => dot_i8x16_add(a, b, c, false),
+        _ => bail!("{} is not a relaxed instruction", op.name()),
+    })
+}
+
+/// https://webassembly.github.io/spec/core/exec/instructions.html#exec-vec-load
+fn load(op: VectorOp, bytes: &[u8]) -> Result<u128> {
+    use VectorOp::*;
+    Ok(match op {
+        V128Load => u128::from_le_bytes(bytes.try_into()?),
+        V128Load8x8S => from_lanes(lanes_of::<i8>(bytes).into_iter().map(i16::from)),
+        V128Load8x8U => from_lanes(lanes_of::<u8>(bytes).into_iter().map(u16::from)),
+        V128Load16x4S => from_lanes(lanes_of::<i16>(bytes).into_iter().map(i32::from)),
+        V128Load16x4U => from_lanes(lanes_of::<u16>(bytes).into_iter().map(u32::from)),
+        V128Load32x2S => from_lanes(lanes_of::<i32>(bytes).into_iter().map(i64::from)),
+        V128Load32x2U => from_lanes(lanes_of::<u32>(bytes).into_iter().map(u64::from)),
+        V128Load8Splat | V128Load16Splat | V128Load32Splat | V128Load64Splat => {
+            u128::from_le_bytes(bytes.repeat(16 / bytes.len()).try_into().unwrap())
+        }
+        V128Load32Zero | V128Load64Zero => {
+            let mut zeros = [0; 16];
+            zeros[..bytes.len()].copy_from_slice(bytes);
+            u128::from_le_bytes(zeros)
+        }
+        _ => bail!("{} is not a vector load", op.name()),
+    })
+}
+
+/// https://webassembly.github.io/spec/core/exec/instructions.html#vector-instructions
+impl Runtime<'_> {
+    fn pop_v128(&mut self) -> Result<u128> {
+        match self.stack_pop()? {
+            Value::V128(v) => Ok(v),
+            v => bail!("unexpected value: {:?}", v),
+        }
+    }
+
+    pub(super) fn shuffle(&mut self, lanes: &[u8; 16]) -> Result<()> {
+        let (b, a) = (self.pop_v128()?, self.pop_v128()?);
+        let bytes = [a.to_le_bytes(), b.to_le_bytes()].concat();
+        let shuffled = lanes.map(|lane| bytes[lane as usize]);
+        self.stack.push(Value::V128(u128::from_le_bytes(shuffled)));
+        Ok(())
+    }
+
+    /// relaxed instructions take one to three vectors
+    #[cfg(feature = "relaxed-simd")]
+    fn relaxed(&mut self, op: VectorOp) -> Result<u128> {
+        let arity = match op.kind() {
+            VectorKind::Unary => 1,
+            VectorKind::Binary => 2,
+            VectorKind::Ternary => 3,
+            kind => bail!("unexpected kind of {}: {:?}", op.name(), kind),
+        };
+        let mut operands = [0; 3];
+        for operand in operands[..arity].iter_mut().rev() {
+            *operand = self.pop_v128()?;
+        }
+        let [a, b, c] = operands;
+        if self.options.deterministic {
+            relaxed_deterministic(op, a, b, c)
+        } else {
+            relaxed_native(op, a, b, c)
+        }
+    }
+
+    pub(super) fn vector(&mut self, op: VectorOp) -> Result<()> {
+        let result = match op.kind() {
+            #[cfg(feature = "relaxed-simd")]
+            _ if op.is_relaxed() => Value::V128(self.relaxed(op)?),
+            VectorKind::Splat(_) => {
+                let value = self.stack_pop()?;
+                Value::V128(splat(op, value)?)
+            }
+            VectorKind::Unary => {
+                let a = self.pop_v128()?;
+                Value::V128(unary(op, a)?)
+            }
+            VectorKind::Binary => {
+                let (b, a) = (self.pop_v128()?, self.pop_v128()?);
+                Value::V128(binary(op, a, b)?)
+            }
+            VectorKind::Ternary => {
+                let (c, b, a) = (self.pop_v128()?, self.pop_v128()?, self.pop_v128()?);
+                // v128.bitselect is the only ternary one
+                Value::V128(a & c | b & !c)
+            }
+            VectorKind::Test => {
+                let a = self.pop_v128()?;
+                Value::I32(test(op, a)?)
+            }
+            VectorKind::Shift => {
+                let n = self.pop_i32()? as u32;
+                let a = self.pop_v128()?;
+                Value::V128(shift(op, a, n)?)
+            }
+            _ => bail!("{} takes immediates", op.name()),
+        };
+        self.stack.push(result);
+        Ok(())
+    }
+
+    pub(super) fn vector_lane(&mut self, op: VectorOp, lane: u8) -> Result<()> {
+        let result = match op.kind() {
+            VectorKind::ExtractLane(..) => {
+                let a = self.pop_v128()?;
+                extract_lane(op, a, lane as usize)?
+            }
+            VectorKind::ReplaceLane(..) => {
+                let value = self.stack_pop()?;
+                let a = self.pop_v128()?;
+                Value::V128(replace_lane(op, a, lane as usize, value)?)
+            }
+            _ => bail!("{} does not take a lane", op.name()),
+        };
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// loads and stores of vectors, or of their lanes if `lane` is given
+    pub(super) fn vector_mem(
+        &mut self,
+        op: VectorOp,
+        memarg: &MemArg,
+        lane: Option<u8>,
+    ) -> Result<()> {
+        match (op.kind(), lane) {
+            (VectorKind::Load(width), None) => {
+                let ea = effective(self.pop_addr()?, memarg)?;
+                let v = load(op, &self.memory(memarg).read(ea, width as usize)?)?;
+                self.stack.push(Value::V128(v));
+            }
+            (VectorKind::Store, None) => {
+                let v = self.pop_v128()?;
+                let ea = effective(self.pop_addr()?, memarg)?;
+                self.memory(memarg).write(ea, &v.to_le_bytes())?;
+            }
+            (VectorKind::LoadLane(width), Some(lane)) => {
+                let v = self.pop_v128()?;
+                let ea = effective(self.pop_addr()?, memarg)?;
+                let lane = lane as usize * width as usize..(lane as usize + 1) * width as usize;
+                let mut bytes = v.to_le_bytes();
+                bytes[lane].copy_from_slice(&self.memory(memarg).read(ea, width as usize)?);
+                self.stack.push(Value::V128(u128::from_le_bytes(bytes)));
+            }
+            (VectorKind::StoreLane(width), Some(lane)) => {
+                let v = self.pop_v128()?;
+                let ea = effective(self.pop_addr()?, memarg)?;
+                let lane = lane as usize * width as usize..(lane as usize + 1) * width as usize;
+                self.memory(memarg).write(ea, &v.to_le_bytes()[lane])?;
+            }
+            _ => bail!("invalid immediates of {}", op.name()),
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 // https://webassembly.github.io/spec/core/syntax/index.html
+pub mod builder;
 pub mod instructions;
 pub mod module;
 pub mod types;
+pub mod values;
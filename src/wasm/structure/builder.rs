@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+
+use super::{
+    instructions::Expr,
+    module::{
+        indices::{DataIdx, FuncIdx, GlobalIdx, MemIdx, TableIdx, TagIdx, TypeIdx},
+        Body, Data, DataMode, Export, ExportDesc, Func, Global, Mem, Module, Names, Start, Table,
+        Tag,
+    },
+    types::{FuncType, GlobalType, Limits, MemType, RefType, Share, SubType, TableType, ValType},
+};
+
+/// Builder to construct a Module in Rust code
+///
+/// ```
+/// use chibiwasm::structure::{
+///     builder::ModuleBuilder,
+///     instructions::Instruction,
+///     module::ExportDesc,
+///     types::{FuncType, NumType, ResultType, ValType},
+/// };
+///
+/// let i32 = ValType::Number(NumType::I32);
+/// let mut builder = ModuleBuilder::new();
+/// let add = builder.add_func(
+///     FuncType(ResultType(vec![i32, i32]), ResultType(vec![i32])),
+///     vec![],
+///     vec![
+///         Instruction::LocalGet(0),
+///         Instruction::LocalGet(1),
+///         Instruction::I32Add,
+///     ],
+/// );
+/// builder.add_export("add", ExportDesc::Func(add));
+/// let module = builder.build();
+/// assert_eq!(module.funcs.len(), 1);
+/// ```
+#[derive(Default)]
+pub struct ModuleBuilder {
+    types: Vec<FuncType>,
+    funcs: Vec<Func<'static>>,
+    tables: Vec<Table>,
+    mems: Vec<Mem>,
+    tags: Vec<Tag>,
+    globals: Vec<Global>,
+    datas: Vec<Data<'static>>,
+    start: Option<Start>,
+    exports: Vec<Export<'static>>,
+}
+
+impl ModuleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add a function type unless the same type has been added, and return its index
+    pub fn add_type(&mut self, func_type: FuncType) -> TypeIdx {
+        match self.types.iter().position(|t| t == &func_type) {
+            Some(idx) => idx as TypeIdx,
+            None => push(&mut self.types, func_type),
+        }
+    }
+
+    pub fn add_func(&mut self, func_type: FuncType, locals: Vec<ValType>, body: Expr) -> FuncIdx {
+        let type_ = self.add_type(func_type);
+        push(
+            &mut self.funcs,
+            Func {
+                type_,
+                locals,
+                body: Body::from(body),
+            },
+        )
+    }
+
+    pub fn add_table(&mut self, limits: Limits, ref_type: RefType) -> TableIdx {
+        push(
+            &mut self.tables,
+            Table {
+                type_: TableType(limits, ref_type),
+            },
+        )
+    }
+
+    pub fn add_memory(&mut self, limits: Limits) -> MemIdx {
+        push(
+            &mut self.mems,
+            Mem {
+                type_: MemType(limits, Share::Unshared),
+            },
+        )
+    }
+
+    /// add an exception tag carrying the parameters of `func_type`
+    pub fn add_tag(&mut self, func_type: FuncType) -> TagIdx {
+        let type_ = self.add_type(func_type);
+        push(&mut self.tags, Tag { type_ })
+    }
+
+    pub fn add_global(&mut self, type_: GlobalType, init: Expr) -> GlobalIdx {
+        push(&mut self.globals, Global { type_, init })
+    }
+
+    /// add a data segment copied to `memory` at `offset` on instantiation
+    pub fn add_data(&mut self, memory: MemIdx, offset: Expr, init: Vec<u8>) -> DataIdx {
+        push(
+            &mut self.datas,
+            Data {
+                init: init.into(),
+                mode: DataMode::Active { memory, offset },
+            },
+        )
+    }
+
+    pub fn add_passive_data(&mut self, init: Vec<u8>) -> DataIdx {
+        push(
+            &mut self.datas,
+            Data {
+                init: init.into(),
+                mode: DataMode::Passive,
+            },
+        )
+    }
+
+    pub fn add_export(&mut self, name: impl Into<String>, desc: ExportDesc) {
+        self.exports.push(Export {
+            name: Cow::Owned(name.into()),
+            desc,
+        });
+    }
+
+    pub fn set_start(&mut self, func: FuncIdx) {
+        self.start = Some(Start { func });
+    }
+
+    pub fn build(self) -> Module<'static> {
+        Module {
+            version: 1,
+            rec_groups: vec![1; self.types.len()],
+            types: self.types.into_iter().map(SubType::from).collect(),
+            funcs: self.funcs,
+            tables: self.tables,
+            mems: self.mems,
+            tags: self.tags,
+            globals: self.globals,
+            elems: vec![],
+            datas: self.datas,
+            data_count: None,
+            start: self.start,
+            imports: vec![],
+            exports: self.exports,
+            customs: vec![],
+            names: Names::default(),
+        }
+    }
+}
+
+/// push an item and return its index
+fn push<T>(items: &mut Vec<T>, item: T) -> u32 {
+    items.push(item);
+    (items.len() - 1) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use super::ModuleBuilder;
+    use crate::binary::{encode::encode, module::decode_slice};
+    use crate::structure::{
+        instructions::Instruction::*,
+        module::ExportDesc,
+        types::{FuncType, IndexType, Limits, NumType, ResultType, ValType},
+    };
+
+    #[test]
+    fn build() -> Result<()> {
+        //Given
+        let i32 = ValType::Number(NumType::I32);
+        let binary = || FuncType(ResultType(vec![i32, i32]), ResultType(vec![i32]));
+        let mut builder = ModuleBuilder::new();
+        //When
+        let add = builder.add_func(binary(), vec![], vec![LocalGet(0), LocalGet(1), I32Add]);
+        let sub = builder.add_func(binary(), vec![i32], vec![LocalGet(0), LocalGet(1), I32Sub]);
+        let unit = builder.add_type(FuncType(ResultType(vec![]), ResultType(vec![])));
+        let mem = builder.add_memory(Limits {
+            min: 1,
+            max: None,
+            index: IndexType::I32,
+        });
+        let data = builder.add_data(mem, vec![I32Const(8)], b"abc".to_vec());
+        builder.add_export("add", ExportDesc::Func(add));
+        builder.add_export("sub", ExportDesc::Func(sub));
+        let module = builder.build();
+        //Then
+        assert_eq!((add, sub, unit, mem, data), (0, 1, 1, 0, 0));
+        assert_eq!(module.types.len(), 2);
+        assert_eq!(module.funcs[1].type_, 0);
+        assert_eq!(decode_slice(&encode(&module)?)?, module);
+        Ok(())
+    }
+}
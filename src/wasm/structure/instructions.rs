@@ -1,8 +1,16 @@
 use super::{
     module::indices::*,
-    types::{RefType, ValType},
+    types::{HeapType, RefType, ValType},
 };
 
+pub mod atomic;
+pub mod memory;
+pub mod vector;
+
+use atomic::AtomicOp;
+use memory::MemoryOp;
+use vector::VectorOp;
+
 // https://webassembly.github.io/spec/core/syntax/instructions.html
 pub type Expr = Vec<Instruction>;
 #[derive(PartialEq, Eq, Debug)]
@@ -19,12 +27,58 @@ pub enum Instruction {
     Return,
     Call(FuncIdx),
     CallIndirect(TableIdx, TypeIdx),
+    // [Tail Calls](https://github.com/WebAssembly/tail-call/blob/main/proposals/tail-call/Overview.md)
+    ReturnCall(FuncIdx),
+    ReturnCallIndirect(TableIdx, TypeIdx),
+    // [Exception Handling](https://webassembly.github.io/exception-handling/core/syntax/instructions.html#control-instructions)
+    TryTable(BlockType, Vec<Catch>, Vec<Instruction>),
+    Throw(TagIdx),
+    ThrowRef,
+    // [Typed Function References](https://webassembly.github.io/function-references/core/syntax/instructions.html#control-instructions)
+    CallRef(TypeIdx),
+    ReturnCallRef(TypeIdx),
+    BrOnNull(LabelIdx),
+    BrOnNonNull(LabelIdx),
+    // [Garbage Collection](https://webassembly.github.io/gc/core/syntax/instructions.html#control-instructions)
+    /// branch if the operand of the first type matches the second type
+    BrOnCast(LabelIdx, RefType, RefType),
+    BrOnCastFail(LabelIdx, RefType, RefType),
     Else,
     End,
     //[Reference Instructions](https://webassembly.github.io/spec/core/binary/instructions.html#reference-instructions)
-    RefNull(RefType),
+    RefNull(HeapType),
     RefIsNull,
     RefFunc(FuncIdx),
+    RefAsNonNull,
+    //[Aggregate Instructions](https://webassembly.github.io/gc/core/syntax/instructions.html#aggregate-instructions)
+    RefEq,
+    RefTest(RefType),
+    RefCast(RefType),
+    StructNew(TypeIdx),
+    StructNewDefault(TypeIdx),
+    StructGet(TypeIdx, FieldIdx),
+    StructGetS(TypeIdx, FieldIdx),
+    StructGetU(TypeIdx, FieldIdx),
+    StructSet(TypeIdx, FieldIdx),
+    ArrayNew(TypeIdx),
+    ArrayNewDefault(TypeIdx),
+    ArrayNewFixed(TypeIdx, u32),
+    ArrayNewData(TypeIdx, DataIdx),
+    ArrayNewElem(TypeIdx, ElemIdx),
+    ArrayGet(TypeIdx),
+    ArrayGetS(TypeIdx),
+    ArrayGetU(TypeIdx),
+    ArraySet(TypeIdx),
+    ArrayLen,
+    ArrayFill(TypeIdx),
+    ArrayCopy(TypeIdx, TypeIdx),
+    ArrayInitData(TypeIdx, DataIdx),
+    ArrayInitElem(TypeIdx, ElemIdx),
+    AnyConvertExtern,
+    ExternConvertAny,
+    RefI31,
+    I31GetS,
+    I31GetU,
     //Parametric Instructions
     Drop,
     Select(Option<Vec<ValType>>),
@@ -43,7 +97,14 @@ pub enum Instruction {
     TableGrow(TableIdx),
     TableSize(TableIdx),
     TableFill(TableIdx),
-    //Memory Instructions
+    //[Memory Instructions](https://webassembly.github.io/spec/core/binary/instructions.html#memory-instructions)
+    Memory(MemoryOp, MemArg),
+    MemorySize(MemIdx),
+    MemoryGrow(MemIdx),
+    MemoryInit(DataIdx, MemIdx),
+    DataDrop(DataIdx),
+    MemoryCopy(MemIdx, MemIdx),
+    MemoryFill(MemIdx),
     //Numeric Instructions
     I32Const(i32),
     I64Const(i64),
@@ -80,6 +141,19 @@ pub enum Instruction {
     I32RtoR,
     I32Extend8S,
     I32Extend16S,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    //[Vector Instructions](https://webassembly.github.io/spec/core/binary/instructions.html#vector-instructions)
+    V128Const(u128),
+    I8x16Shuffle([LaneIdx; 16]),
+    Vector(VectorOp),
+    VectorLane(VectorOp, LaneIdx),
+    VectorMem(VectorOp, MemArg),
+    VectorMemLane(VectorOp, MemArg, LaneIdx),
+    //[Atomic Memory Instructions](https://webassembly.github.io/threads/core/binary/instructions.html#atomic-memory-instructions)
+    AtomicFence,
+    Atomic(AtomicOp, MemArg),
     Void,
 }
 
@@ -89,3 +163,66 @@ pub enum BlockType {
     TypeIdx(TypeIdx),
     ValType(super::types::ValType),
 }
+
+/// A handler of `try_table` branching to the label when an exception matches
+/// https://webassembly.github.io/exception-handling/core/syntax/instructions.html#control-instructions
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Catch {
+    /// exceptions of the tag, passing their values
+    Catch(TagIdx, LabelIdx),
+    /// exceptions of the tag, passing their values followed by the exnref
+    CatchRef(TagIdx, LabelIdx),
+    /// any exception, passing nothing
+    CatchAll(LabelIdx),
+    /// any exception, passing the exnref
+    CatchAllRef(LabelIdx),
+}
+
+impl Catch {
+    pub fn label(&self) -> LabelIdx {
+        match self {
+            Self::Catch(_, label)
+            | Self::CatchRef(_, label)
+            | Self::CatchAll(label)
+            | Self::CatchAllRef(label) => *label,
+        }
+    }
+
+    /// None for the clauses catching any exception
+    pub fn tag(&self) -> Option<TagIdx> {
+        match self {
+            Self::Catch(tag, _) | Self::CatchRef(tag, _) => Some(*tag),
+            Self::CatchAll(_) | Self::CatchAllRef(_) => None,
+        }
+    }
+
+    /// whether the exnref is passed to the label
+    pub fn is_ref(&self) -> bool {
+        matches!(self, Self::CatchRef(..) | Self::CatchAllRef(_))
+    }
+}
+
+/// https://webassembly.github.io/spec/core/syntax/instructions.html#memory-instructions
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct MemArg {
+    /// exponent of 2
+    pub align: u32,
+    /// 64 bit only with memory64
+    pub offset: u64,
+    /// the memory accessed, which is not 0 only with multiple memories
+    /// https://webassembly.github.io/multi-memory/core/syntax/instructions.html#memory-instructions
+    pub memory: MemIdx,
+}
+
+impl Instruction {
+    /// the memarg of a memory instruction
+    pub fn memarg(&self) -> Option<&MemArg> {
+        match self {
+            Self::Memory(_, memarg)
+            | Self::VectorMem(_, memarg)
+            | Self::VectorMemLane(_, memarg, _)
+            | Self::Atomic(_, memarg) => Some(memarg),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use num_derive::FromPrimitive;
+
+use super::super::types::NumType;
+
+/// Read-modify-write operations of atomic instructions
+/// https://webassembly.github.io/threads/core/syntax/instructions.html#atomic-memory-instructions
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum RmwOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Xchg,
+}
+
+/// Operands, results and accessed bytes of an atomic instruction, all of which take a memarg
+/// https://webassembly.github.io/threads/core/valid/instructions.html#atomic-memory-instructions
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum AtomicKind {
+    /// [i32 i32] -> [i32]
+    Notify,
+    /// [i32 t i64] -> [i32]
+    Wait(NumType),
+    /// [i32] -> [t] reading the given number of bytes
+    Load(NumType, u32),
+    /// [i32 t] -> [] writing the given number of bytes
+    Store(NumType, u32),
+    /// [i32 t] -> [t] returning the value read before the operation
+    Rmw(NumType, u32, RmwOp),
+    /// [i32 t t] -> [t] returning the value read before the exchange
+    Cmpxchg(NumType, u32),
+}
+
+impl AtomicKind {
+    /// the number of bytes accessed, which is the required alignment
+    pub fn width(self) -> u32 {
+        match self {
+            Self::Notify | Self::Wait(NumType::I32) => 4,
+            Self::Wait(_) => 8,
+            Self::Load(_, width)
+            | Self::Store(_, width)
+            | Self::Rmw(_, width, _)
+            | Self::Cmpxchg(_, width) => width,
+        }
+    }
+}
+
+macro_rules! atomic_ops {
+    ($($op:ident = $code:literal, $name:literal, $kind:expr;)*) => {
+        /// Atomic instructions numbered by their opcodes following the 0xFE prefix,
+        /// except `atomic.fence` which takes no memarg
+        /// https://webassembly.github.io/threads/core/binary/instructions.html#atomic-memory-instructions
+        #[derive(PartialEq, Eq, Debug, Clone, Copy, FromPrimitive)]
+        pub enum AtomicOp {
+            $($op = $code,)*
+        }
+
+        impl AtomicOp {
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$op => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(Self::$op),)*
+                    _ => None,
+                }
+            }
+
+            pub fn kind(self) -> AtomicKind {
+                use AtomicKind::*;
+                use NumType::*;
+                use RmwOp::*;
+                match self {
+                    $(Self::$op => $kind,)*
+                }
+            }
+        }
+    };
+}
+
+atomic_ops! {
+    MemoryAtomicNotify = 0x00, "memory.atomic.notify", Notify;
+    MemoryAtomicWait32 = 0x01, "memory.atomic.wait32", Wait(I32);
+    MemoryAtomicWait64 = 0x02, "memory.atomic.wait64", Wait(I64);
+    I32AtomicLoad = 0x10, "i32.atomic.load", Load(I32, 4);
+    I64AtomicLoad = 0x11, "i64.atomic.load", Load(I64, 8);
+    I32AtomicLoad8U = 0x12, "i32.atomic.load8_u", Load(I32, 1);
+    I32AtomicLoad16U = 0x13, "i32.atomic.load16_u", Load(I32, 2);
+    I64AtomicLoad8U = 0x14, "i64.atomic.load8_u", Load(I64, 1);
+    I64AtomicLoad16U = 0x15, "i64.atomic.load16_u", Load(I64, 2);
+    I64AtomicLoad32U = 0x16, "i64.atomic.load32_u", Load(I64, 4);
+    I32AtomicStore = 0x17, "i32.atomic.store", Store(I32, 4);
+    I64AtomicStore = 0x18, "i64.atomic.store", Store(I64, 8);
+    I32AtomicStore8 = 0x19, "i32.atomic.store8", Store(I32, 1);
+    I32AtomicStore16 = 0x1A, "i32.atomic.store16", Store(I32, 2);
+    I64AtomicStore8 = 0x1B, "i64.atomic.store8", Store(I64, 1);
+    I64AtomicStore16 = 0x1C, "i64.atomic.store16", Store(I64, 2);
+    I64AtomicStore32 = 0x1D, "i64.atomic.store32", Store(I64, 4);
+    I32AtomicRmwAdd = 0x1E, "i32.atomic.rmw.add", Rmw(I32, 4, Add);
+    I64AtomicRmwAdd = 0x1F, "i64.atomic.rmw.add", Rmw(I64, 8, Add);
+    I32AtomicRmw8AddU = 0x20, "i32.atomic.rmw8.add_u", Rmw(I32, 1, Add);
+    I32AtomicRmw16AddU = 0x21, "i32.atomic.rmw16.add_u", Rmw(I32, 2, Add);
+    I64AtomicRmw8AddU = 0x22, "i64.atomic.rmw8.add_u", Rmw(I64, 1, Add);
+    I64AtomicRmw16AddU = 0x23, "i64.atomic.rmw16.add_u", Rmw(I64, 2, Add);
+    I64AtomicRmw32AddU = 0x24, "i64.atomic.rmw32.add_u", Rmw(I64, 4, Add);
+    I32AtomicRmwSub = 0x25, "i32.atomic.rmw.sub", Rmw(I32, 4, Sub);
+    I64AtomicRmwSub = 0x26, "i64.atomic.rmw.sub", Rmw(I64, 8, Sub);
+    I32AtomicRmw8SubU = 0x27, "i32.atomic.rmw8.sub_u", Rmw(I32, 1, Sub);
+    I32AtomicRmw16SubU = 0x28, "i32.atomic.rmw16.sub_u", Rmw(I32, 2, Sub);
+    I64AtomicRmw8SubU = 0x29, "i64.atomic.rmw8.sub_u", Rmw(I64, 1, Sub);
+    I64AtomicRmw16SubU = 0x2A, "i64.atomic.rmw16.sub_u", Rmw(I64, 2, Sub);
+    I64AtomicRmw32SubU = 0x2B, "i64.atomic.rmw32.sub_u", Rmw(I64, 4, Sub);
+    I32AtomicRmwAnd = 0x2C, "i32.atomic.rmw.and", Rmw(I32, 4, And);
+    I64AtomicRmwAnd = 0x2D, "i64.atomic.rmw.and", Rmw(I64, 8, And);
+    I32AtomicRmw8AndU = 0x2E, "i32.atomic.rmw8.and_u", Rmw(I32, 1, And);
+    I32AtomicRmw16AndU = 0x2F, "i32.atomic.rmw16.and_u", Rmw(I32, 2, And);
+    I64AtomicRmw8AndU = 0x30, "i64.atomic.rmw8.and_u", Rmw(I64, 1, And);
+    I64AtomicRmw16AndU = 0x31, "i64.atomic.rmw16.and_u", Rmw(I64, 2, And);
+    I64AtomicRmw32AndU = 0x32, "i64.atomic.rmw32.and_u", Rmw(I64, 4, And);
+    I32AtomicRmwOr = 0x33, "i32.atomic.rmw.or", Rmw(I32, 4, Or);
+    I64AtomicRmwOr = 0x34, "i64.atomic.rmw.or", Rmw(I64, 8, Or);
+    I32AtomicRmw8OrU = 0x35, "i32.atomic.rmw8.or_u", Rmw(I32, 1, Or);
+    I32AtomicRmw16OrU = 0x36, "i32.atomic.rmw16.or_u", Rmw(I32, 2, Or);
+    I64AtomicRmw8OrU = 0x37, "i64.atomic.rmw8.or_u", Rmw(I64, 1, Or);
+    I64AtomicRmw16OrU = 0x38, "i64.atomic.rmw16.or_u", Rmw(I64, 2, Or);
+    I64AtomicRmw32OrU = 0x39, "i64.atomic.rmw32.or_u", Rmw(I64, 4, Or);
+    I32AtomicRmwXor = 0x3A, "i32.atomic.rmw.xor", Rmw(I32, 4, Xor);
+    I64AtomicRmwXor = 0x3B, "i64.atomic.rmw.xor", Rmw(I64, 8, Xor);
+    I32AtomicRmw8XorU = 0x3C, "i32.atomic.rmw8.xor_u", Rmw(I32, 1, Xor);
+    I32AtomicRmw16XorU = 0x3D, "i32.atomic.rmw16.xor_u", Rmw(I32, 2, Xor);
+    I64AtomicRmw8XorU = 0x3E, "i64.atomic.rmw8.xor_u", Rmw(I64, 1, Xor);
+    I64AtomicRmw16XorU = 0x3F, "i64.atomic.rmw16.xor_u", Rmw(I64, 2, Xor);
+    I64AtomicRmw32XorU = 0x40, "i64.atomic.rmw32.xor_u", Rmw(I64, 4, Xor);
+    I32AtomicRmwXchg = 0x41, "i32.atomic.rmw.xchg", Rmw(I32, 4, Xchg);
+    I64AtomicRmwXchg = 0x42, "i64.atomic.rmw.xchg", Rmw(I64, 8, Xchg);
+    I32AtomicRmw8XchgU = 0x43, "i32.atomic.rmw8.xchg_u", Rmw(I32, 1, Xchg);
+    I32AtomicRmw16XchgU = 0x44, "i32.atomic.rmw16.xchg_u", Rmw(I32, 2, Xchg);
+    I64AtomicRmw8XchgU = 0x45, "i64.atomic.rmw8.xchg_u", Rmw(I64, 1, Xchg);
+    I64AtomicRmw16XchgU = 0x46, "i64.atomic.rmw16.xchg_u", Rmw(I64, 2, Xchg);
+    I64AtomicRmw32XchgU = 0x47, "i64.atomic.rmw32.xchg_u", Rmw(I64, 4, Xchg);
+    I32AtomicRmwCmpxchg = 0x48, "i32.atomic.rmw.cmpxchg", Cmpxchg(I32, 4);
+    I64AtomicRmwCmpxchg = 0x49, "i64.atomic.rmw.cmpxchg", Cmpxchg(I64, 8);
+    I32AtomicRmw8CmpxchgU = 0x4A, "i32.atomic.rmw8.cmpxchg_u", Cmpxchg(I32, 1);
+    I32AtomicRmw16CmpxchgU = 0x4B, "i32.atomic.rmw16.cmpxchg_u", Cmpxchg(I32, 2);
+    I64AtomicRmw8CmpxchgU = 0x4C, "i64.atomic.rmw8.cmpxchg_u", Cmpxchg(I64, 1);
+    I64AtomicRmw16CmpxchgU = 0x4D, "i64.atomic.rmw16.cmpxchg_u", Cmpxchg(I64, 2);
+    I64AtomicRmw32CmpxchgU = 0x4E, "i64.atomic.rmw32.cmpxchg_u", Cmpxchg(I64, 4);
+}
@@ -0,0 +1,82 @@
+use num_derive::FromPrimitive;
+
+use super::super::types::NumType;
+
+/// Operands, results and accessed bytes of a load or a store, both of which take a memarg
+/// https://webassembly.github.io/spec/core/valid/instructions.html#memory-instructions
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum MemoryKind {
+    /// [a] -> [t] reading the given number of bytes, sign-extended if signed
+    Load(NumType, u32, bool),
+    /// [a t] -> [] writing the given number of lower bytes
+    Store(NumType, u32),
+}
+
+impl MemoryKind {
+    /// the number of bytes accessed, which is the natural alignment
+    pub fn width(self) -> u32 {
+        match self {
+            Self::Load(_, width, _) | Self::Store(_, width) => width,
+        }
+    }
+}
+
+macro_rules! memory_ops {
+    ($($op:ident = $code:literal, $name:literal, $kind:expr;)*) => {
+        /// Loads and stores of numbers numbered by their opcodes
+        /// https://webassembly.github.io/spec/core/binary/instructions.html#memory-instructions
+        #[derive(PartialEq, Eq, Debug, Clone, Copy, FromPrimitive)]
+        pub enum MemoryOp {
+            $($op = $code,)*
+        }
+
+        impl MemoryOp {
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$op => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(Self::$op),)*
+                    _ => None,
+                }
+            }
+
+            pub fn kind(self) -> MemoryKind {
+                use MemoryKind::*;
+                use NumType::*;
+                match self {
+                    $(Self::$op => $kind,)*
+                }
+            }
+        }
+    };
+}
+
+memory_ops! {
+    I32Load = 0x28, "i32.load", Load(I32, 4, false);
+    I64Load = 0x29, "i64.load", Load(I64, 8, false);
+    F32Load = 0x2A, "f32.load", Load(F32, 4, false);
+    F64Load = 0x2B, "f64.load", Load(F64, 8, false);
+    I32Load8S = 0x2C, "i32.load8_s", Load(I32, 1, true);
+    I32Load8U = 0x2D, "i32.load8_u", Load(I32, 1, false);
+    I32Load16S = 0x2E, "i32.load16_s", Load(I32, 2, true);
+    I32Load16U = 0x2F, "i32.load16_u", Load(I32, 2, false);
+    I64Load8S = 0x30, "i64.load8_s", Load(I64, 1, true);
+    I64Load8U = 0x31, "i64.load8_u", Load(I64, 1, false);
+    I64Load16S = 0x32, "i64.load16_s", Load(I64, 2, true);
+    I64Load16U = 0x33, "i64.load16_u", Load(I64, 2, false);
+    I64Load32S = 0x34, "i64.load32_s", Load(I64, 4, true);
+    I64Load32U = 0x35, "i64.load32_u", Load(I64, 4, false);
+    I32Store = 0x36, "i32.store", Store(I32, 4);
+    I64Store = 0x37, "i64.store", Store(I64, 8);
+    F32Store = 0x38, "f32.store", Store(F32, 4);
+    F64Store = 0x39, "f64.store", Store(F64, 8);
+    I32Store8 = 0x3A, "i32.store8", Store(I32, 1);
+    I32Store16 = 0x3B, "i32.store16", Store(I32, 2);
+    I64Store8 = 0x3C, "i64.store8", Store(I64, 1);
+    I64Store16 = 0x3D, "i64.store16", Store(I64, 2);
+    I64Store32 = 0x3E, "i64.store32", Store(I64, 4);
+}
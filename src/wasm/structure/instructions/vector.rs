@@ -0,0 +1,353 @@
+use num_derive::FromPrimitive;
+
+use super::super::types::NumType;
+
+/// Operands, results and immediates of a vector instruction
+/// https://webassembly.github.io/spec/core/valid/instructions.html#vector-instructions
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum VectorKind {
+    /// [i32] -> [v128] reading the given number of bytes, with a memarg
+    Load(u32),
+    /// [i32 v128] -> [] with a memarg
+    Store,
+    /// [i32 v128] -> [v128] reading a lane of the given number of bytes, with a memarg and a lane
+    LoadLane(u32),
+    /// [i32 v128] -> [] writing a lane of the given number of bytes, with a memarg and a lane
+    StoreLane(u32),
+    /// [v128] -> [t] with a lane below the given number of lanes
+    ExtractLane(u8, NumType),
+    /// [v128 t] -> [v128] with a lane below the given number of lanes
+    ReplaceLane(u8, NumType),
+    /// [t] -> [v128]
+    Splat(NumType),
+    /// [v128] -> [v128]
+    Unary,
+    /// [v128 v128] -> [v128]
+    Binary,
+    /// [v128 v128 v128] -> [v128]
+    Ternary,
+    /// [v128] -> [i32]
+    Test,
+    /// [v128 i32] -> [v128]
+    Shift,
+}
+
+impl VectorKind {
+    /// the number of bytes accessed by a memory instruction, which is the natural alignment
+    pub fn width(self) -> Option<u32> {
+        match self {
+            Self::Load(width) | Self::LoadLane(width) | Self::StoreLane(width) => Some(width),
+            Self::Store => Some(16),
+            _ => None,
+        }
+    }
+
+    /// the number of lanes which a lane immediate indexes
+    pub fn lanes(self) -> Option<u8> {
+        match self {
+            Self::LoadLane(width) | Self::StoreLane(width) => Some(16 / width as u8),
+            Self::ExtractLane(lanes, _) | Self::ReplaceLane(lanes, _) => Some(lanes),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! vector_ops {
+    ($($op:ident = $code:literal, $name:literal, $kind:expr;)*) => {
+        /// Vector instructions numbered by their opcodes following the 0xFD prefix,
+        /// except `v128.const` and `i8x16.shuffle` which have their own immediates
+        /// https://webassembly.github.io/spec/core/binary/instructions.html#vector-instructions
+        #[derive(PartialEq, Eq, Debug, Clone, Copy, FromPrimitive)]
+        pub enum VectorOp {
+            $($op = $code,)*
+        }
+
+        impl VectorOp {
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$op => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(Self::$op),)*
+                    _ => None,
+                }
+            }
+
+            pub fn kind(self) -> VectorKind {
+                use NumType::*;
+                use VectorKind::*;
+                match self {
+                    $(Self::$op => $kind,)*
+                }
+            }
+        }
+    };
+}
+
+vector_ops! {
+    V128Load = 0x00, "v128.load", Load(16);
+    V128Load8x8S = 0x01, "v128.load8x8_s", Load(8);
+    V128Load8x8U = 0x02, "v128.load8x8_u", Load(8);
+    V128Load16x4S = 0x03, "v128.load16x4_s", Load(8);
+    V128Load16x4U = 0x04, "v128.load16x4_u", Load(8);
+    V128Load32x2S = 0x05, "v128.load32x2_s", Load(8);
+    V128Load32x2U = 0x06, "v128.load32x2_u", Load(8);
+    V128Load8Splat = 0x07, "v128.load8_splat", Load(1);
+    V128Load16Splat = 0x08, "v128.load16_splat", Load(2);
+    V128Load32Splat = 0x09, "v128.load32_splat", Load(4);
+    V128Load64Splat = 0x0A, "v128.load64_splat", Load(8);
+    V128Store = 0x0B, "v128.store", Store;
+    I8x16Swizzle = 0x0E, "i8x16.swizzle", Binary;
+    I8x16Splat = 0x0F, "i8x16.splat", Splat(I32);
+    I16x8Splat = 0x10, "i16x8.splat", Splat(I32);
+    I32x4Splat = 0x11, "i32x4.splat", Splat(I32);
+    I64x2Splat = 0x12, "i64x2.splat", Splat(I64);
+    F32x4Splat = 0x13, "f32x4.splat", Splat(F32);
+    F64x2Splat = 0x14, "f64x2.splat", Splat(F64);
+    I8x16ExtractLaneS = 0x15, "i8x16.extract_lane_s", ExtractLane(16, I32);
+    I8x16ExtractLaneU = 0x16, "i8x16.extract_lane_u", ExtractLane(16, I32);
+    I8x16ReplaceLane = 0x17, "i8x16.replace_lane", ReplaceLane(16, I32);
+    I16x8ExtractLaneS = 0x18, "i16x8.extract_lane_s", ExtractLane(8, I32);
+    I16x8ExtractLaneU = 0x19, "i16x8.extract_lane_u", ExtractLane(8, I32);
+    I16x8ReplaceLane = 0x1A, "i16x8.replace_lane", ReplaceLane(8, I32);
+    I32x4ExtractLane = 0x1B, "i32x4.extract_lane", ExtractLane(4, I32);
+    I32x4ReplaceLane = 0x1C, "i32x4.replace_lane", ReplaceLane(4, I32);
+    I64x2ExtractLane = 0x1D, "i64x2.extract_lane", ExtractLane(2, I64);
+    I64x2ReplaceLane = 0x1E, "i64x2.replace_lane", ReplaceLane(2, I64);
+    F32x4ExtractLane = 0x1F, "f32x4.extract_lane", ExtractLane(4, F32);
+    F32x4ReplaceLane = 0x20, "f32x4.replace_lane", ReplaceLane(4, F32);
+    F64x2ExtractLane = 0x21, "f64x2.extract_lane", ExtractLane(2, F64);
+    F64x2ReplaceLane = 0x22, "f64x2.replace_lane", ReplaceLane(2, F64);
+    I8x16Eq = 0x23, "i8x16.eq", Binary;
+    I8x16Ne = 0x24, "i8x16.ne", Binary;
+    I8x16LtS = 0x25, "i8x16.lt_s", Binary;
+    I8x16LtU = 0x26, "i8x16.lt_u", Binary;
+    I8x16GtS = 0x27, "i8x16.gt_s", Binary;
+    I8x16GtU = 0x28, "i8x16.gt_u", Binary;
+    I8x16LeS = 0x29, "i8x16.le_s", Binary;
+    I8x16LeU = 0x2A, "i8x16.le_u", Binary;
+    I8x16GeS = 0x2B, "i8x16.ge_s", Binary;
+    I8x16GeU = 0x2C, "i8x16.ge_u", Binary;
+    I16x8Eq = 0x2D, "i16x8.eq", Binary;
+    I16x8Ne = 0x2E, "i16x8.ne", Binary;
+    I16x8LtS = 0x2F, "i16x8.lt_s", Binary;
+    I16x8LtU = 0x30, "i16x8.lt_u", Binary;
+    I16x8GtS = 0x31, "i16x8.gt_s", Binary;
+    I16x8GtU = 0x32, "i16x8.gt_u", Binary;
+    I16x8LeS = 0x33, "i16x8.le_s", Binary;
+    I16x8LeU = 0x34, "i16x8.le_u", Binary;
+    I16x8GeS = 0x35, "i16x8.ge_s", Binary;
+    I16x8GeU = 0x36, "i16x8.ge_u", Binary;
+    I32x4Eq = 0x37, "i32x4.eq", Binary;
+    I32x4Ne = 0x38, "i32x4.ne", Binary;
+    I32x4LtS = 0x39, "i32x4.lt_s", Binary;
+    I32x4LtU = 0x3A, "i32x4.lt_u", Binary;
+    I32x4GtS = 0x3B, "i32x4.gt_s", Binary;
+    I32x4GtU = 0x3C, "i32x4.gt_u", Binary;
+    I32x4LeS = 0x3D, "i32x4.le_s", Binary;
+    I32x4LeU = 0x3E, "i32x4.le_u", Binary;
+    I32x4GeS = 0x3F, "i32x4.ge_s", Binary;
+    I32x4GeU = 0x40, "i32x4.ge_u", Binary;
+    F32x4Eq = 0x41, "f32x4.eq", Binary;
+    F32x4Ne = 0x42, "f32x4.ne", Binary;
+    F32x4Lt = 0x43, "f32x4.lt", Binary;
+    F32x4Gt = 0x44, "f32x4.gt", Binary;
+    F32x4Le = 0x45, "f32x4.le", Binary;
+    F32x4Ge = 0x46, "f32x4.ge", Binary;
+    F64x2Eq = 0x47, "f64x2.eq", Binary;
+    F64x2Ne = 0x48, "f64x2.ne", Binary;
+    F64x2Lt = 0x49, "f64x2.lt", Binary;
+    F64x2Gt = 0x4A, "f64x2.gt", Binary;
+    F64x2Le = 0x4B, "f64x2.le", Binary;
+    F64x2Ge = 0x4C, "f64x2.ge", Binary;
+    V128Not = 0x4D, "v128.not", Unary;
+    V128And = 0x4E, "v128.and", Binary;
+    V128AndNot = 0x4F, "v128.andnot", Binary;
+    V128Or = 0x50, "v128.or", Binary;
+    V128Xor = 0x51, "v128.xor", Binary;
+    V128Bitselect = 0x52, "v128.bitselect", Ternary;
+    V128AnyTrue = 0x53, "v128.any_true", Test;
+    V128Load8Lane = 0x54, "v128.load8_lane", LoadLane(1);
+    V128Load16Lane = 0x55, "v128.load16_lane", LoadLane(2);
+    V128Load32Lane = 0x56, "v128.load32_lane", LoadLane(4);
+    V128Load64Lane = 0x57, "v128.load64_lane", LoadLane(8);
+    V128Store8Lane = 0x58, "v128.store8_lane", StoreLane(1);
+    V128Store16Lane = 0x59, "v128.store16_lane", StoreLane(2);
+    V128Store32Lane = 0x5A, "v128.store32_lane", StoreLane(4);
+    V128Store64Lane = 0x5B, "v128.store64_lane", StoreLane(8);
+    V128Load32Zero = 0x5C, "v128.load32_zero", Load(4);
+    V128Load64Zero = 0x5D, "v128.load64_zero", Load(8);
+    F32x4DemoteF64x2Zero = 0x5E, "f32x4.demote_f64x2_zero", Unary;
+    F64x2PromoteLowF32x4 = 0x5F, "f64x2.promote_low_f32x4", Unary;
+    I8x16Abs = 0x60, "i8x16.abs", Unary;
+    I8x16Neg = 0x61, "i8x16.neg", Unary;
+    I8x16Popcnt = 0x62, "i8x16.popcnt", Unary;
+    I8x16AllTrue = 0x63, "i8x16.all_true", Test;
+    I8x16Bitmask = 0x64, "i8x16.bitmask", Test;
+    I8x16NarrowI16x8S = 0x65, "i8x16.narrow_i16x8_s", Binary;
+    I8x16NarrowI16x8U = 0x66, "i8x16.narrow_i16x8_u", Binary;
+    F32x4Ceil = 0x67, "f32x4.ceil", Unary;
+    F32x4Floor = 0x68, "f32x4.floor", Unary;
+    F32x4Trunc = 0x69, "f32x4.trunc", Unary;
+    F32x4Nearest = 0x6A, "f32x4.nearest", Unary;
+    I8x16Shl = 0x6B, "i8x16.shl", Shift;
+    I8x16ShrS = 0x6C, "i8x16.shr_s", Shift;
+    I8x16ShrU = 0x6D, "i8x16.shr_u", Shift;
+    I8x16Add = 0x6E, "i8x16.add", Binary;
+    I8x16AddSatS = 0x6F, "i8x16.add_sat_s", Binary;
+    I8x16AddSatU = 0x70, "i8x16.add_sat_u", Binary;
+    I8x16Sub = 0x71, "i8x16.sub", Binary;
+    I8x16SubSatS = 0x72, "i8x16.sub_sat_s", Binary;
+    I8x16SubSatU = 0x73, "i8x16.sub_sat_u", Binary;
+    F64x2Ceil = 0x74, "f64x2.ceil", Unary;
+    F64x2Floor = 0x75, "f64x2.floor", Unary;
+    I8x16MinS = 0x76, "i8x16.min_s", Binary;
+    I8x16MinU = 0x77, "i8x16.min_u", Binary;
+    I8x16MaxS = 0x78, "i8x16.max_s", Binary;
+    I8x16MaxU = 0x79, "i8x16.max_u", Binary;
+    F64x2Trunc = 0x7A, "f64x2.trunc", Unary;
+    I8x16AvgrU = 0x7B, "i8x16.avgr_u", Binary;
+    I16x8ExtaddPairwiseI8x16S = 0x7C, "i16x8.extadd_pairwise_i8x16_s", Unary;
+    I16x8ExtaddPairwiseI8x16U = 0x7D, "i16x8.extadd_pairwise_i8x16_u", Unary;
+    I32x4ExtaddPairwiseI16x8S = 0x7E, "i32x4.extadd_pairwise_i16x8_s", Unary;
+    I32x4ExtaddPairwiseI16x8U = 0x7F, "i32x4.extadd_pairwise_i16x8_u", Unary;
+    I16x8Abs = 0x80, "i16x8.abs", Unary;
+    I16x8Neg = 0x81, "i16x8.neg", Unary;
+    I16x8Q15mulrSatS = 0x82, "i16x8.q15mulr_sat_s", Binary;
+    I16x8AllTrue = 0x83, "i16x8.all_true", Test;
+    I16x8Bitmask = 0x84, "i16x8.bitmask", Test;
+    I16x8NarrowI32x4S = 0x85, "i16x8.narrow_i32x4_s", Binary;
+    I16x8NarrowI32x4U = 0x86, "i16x8.narrow_i32x4_u", Binary;
+    I16x8ExtendLowI8x16S = 0x87, "i16x8.extend_low_i8x16_s", Unary;
+    I16x8ExtendHighI8x16S = 0x88, "i16x8.extend_high_i8x16_s", Unary;
+    I16x8ExtendLowI8x16U = 0x89, "i16x8.extend_low_i8x16_u", Unary;
+    I16x8ExtendHighI8x16U = 0x8A, "i16x8.extend_high_i8x16_u", Unary;
+    I16x8Shl = 0x8B, "i16x8.shl", Shift;
+    I16x8ShrS = 0x8C, "i16x8.shr_s", Shift;
+    I16x8ShrU = 0x8D, "i16x8.shr_u", Shift;
+    I16x8Add = 0x8E, "i16x8.add", Binary;
+    I16x8AddSatS = 0x8F, "i16x8.add_sat_s", Binary;
+    I16x8AddSatU = 0x90, "i16x8.add_sat_u", Binary;
+    I16x8Sub = 0x91, "i16x8.sub", Binary;
+    I16x8SubSatS = 0x92, "i16x8.sub_sat_s", Binary;
+    I16x8SubSatU = 0x93, "i16x8.sub_sat_u", Binary;
+    F64x2Nearest = 0x94, "f64x2.nearest", Unary;
+    I16x8Mul = 0x95, "i16x8.mul", Binary;
+    I16x8MinS = 0x96, "i16x8.min_s", Binary;
+    I16x8MinU = 0x97, "i16x8.min_u", Binary;
+    I16x8MaxS = 0x98, "i16x8.max_s", Binary;
+    I16x8MaxU = 0x99, "i16x8.max_u", Binary;
+    I16x8AvgrU = 0x9B, "i16x8.avgr_u", Binary;
+    I16x8ExtmulLowI8x16S = 0x9C, "i16x8.extmul_low_i8x16_s", Binary;
+    I16x8ExtmulHighI8x16S = 0x9D, "i16x8.extmul_high_i8x16_s", Binary;
+    I16x8ExtmulLowI8x16U = 0x9E, "i16x8.extmul_low_i8x16_u", Binary;
+    I16x8ExtmulHighI8x16U = 0x9F, "i16x8.extmul_high_i8x16_u", Binary;
+    I32x4Abs = 0xA0, "i32x4.abs", Unary;
+    I32x4Neg = 0xA1, "i32x4.neg", Unary;
+    I32x4AllTrue = 0xA3, "i32x4.all_true", Test;
+    I32x4Bitmask = 0xA4, "i32x4.bitmask", Test;
+    I32x4ExtendLowI16x8S = 0xA7, "i32x4.extend_low_i16x8_s", Unary;
+    I32x4ExtendHighI16x8S = 0xA8, "i32x4.extend_high_i16x8_s", Unary;
+    I32x4ExtendLowI16x8U = 0xA9, "i32x4.extend_low_i16x8_u", Unary;
+    I32x4ExtendHighI16x8U = 0xAA, "i32x4.extend_high_i16x8_u", Unary;
+    I32x4Shl = 0xAB, "i32x4.shl", Shift;
+    I32x4ShrS = 0xAC, "i32x4.shr_s", Shift;
+    I32x4ShrU = 0xAD, "i32x4.shr_u", Shift;
+    I32x4Add = 0xAE, "i32x4.add", Binary;
+    I32x4Sub = 0xB1, "i32x4.sub", Binary;
+    I32x4Mul = 0xB5, "i32x4.mul", Binary;
+    I32x4MinS = 0xB6, "i32x4.min_s", Binary;
+    I32x4MinU = 0xB7, "i32x4.min_u", Binary;
+    I32x4MaxS = 0xB8, "i32x4.max_s", Binary;
+    I32x4MaxU = 0xB9, "i32x4.max_u", Binary;
+    I32x4DotI16x8S = 0xBA, "i32x4.dot_i16x8_s", Binary;
+    I32x4ExtmulLowI16x8S = 0xBC, "i32x4.extmul_low_i16x8_s", Binary;
+    I32x4ExtmulHighI16x8S = 0xBD, "i32x4.extmul_high_i16x8_s", Binary;
+    I32x4ExtmulLowI16x8U = 0xBE, "i32x4.extmul_low_i16x8_u", Binary;
+    I32x4ExtmulHighI16x8U = 0xBF, "i32x4.extmul_high_i16x8_u", Binary;
+    I64x2Abs = 0xC0, "i64x2.abs", Unary;
+    I64x2Neg = 0xC1, "i64x2.neg", Unary;
+    I64x2AllTrue = 0xC3, "i64x2.all_true", Test;
+    I64x2Bitmask = 0xC4, "i64x2.bitmask", Test;
+    I64x2ExtendLowI32x4S = 0xC7, "i64x2.extend_low_i32x4_s", Unary;
+    I64x2ExtendHighI32x4S = 0xC8, "i64x2.extend_high_i32x4_s", Unary;
+    I64x2ExtendLowI32x4U = 0xC9, "i64x2.extend_low_i32x4_u", Unary;
+    I64x2ExtendHighI32x4U = 0xCA, "i64x2.extend_high_i32x4_u", Unary;
+    I64x2Shl = 0xCB, "i64x2.shl", Shift;
+    I64x2ShrS = 0xCC, "i64x2.shr_s", Shift;
+    I64x2ShrU = 0xCD, "i64x2.shr_u", Shift;
+    I64x2Add = 0xCE, "i64x2.add", Binary;
+    I64x2Sub = 0xD1, "i64x2.sub", Binary;
+    I64x2Mul = 0xD5, "i64x2.mul", Binary;
+    I64x2Eq = 0xD6, "i64x2.eq", Binary;
+    I64x2Ne = 0xD7, "i64x2.ne", Binary;
+    I64x2LtS = 0xD8, "i64x2.lt_s", Binary;
+    I64x2GtS = 0xD9, "i64x2.gt_s", Binary;
+    I64x2LeS = 0xDA, "i64x2.le_s", Binary;
+    I64x2GeS = 0xDB, "i64x2.ge_s", Binary;
+    I64x2ExtmulLowI32x4S = 0xDC, "i64x2.extmul_low_i32x4_s", Binary;
+    I64x2ExtmulHighI32x4S = 0xDD, "i64x2.extmul_high_i32x4_s", Binary;
+    I64x2ExtmulLowI32x4U = 0xDE, "i64x2.extmul_low_i32x4_u", Binary;
+    I64x2ExtmulHighI32x4U = 0xDF, "i64x2.extmul_high_i32x4_u", Binary;
+    F32x4Abs = 0xE0, "f32x4.abs", Unary;
+    F32x4Neg = 0xE1, "f32x4.neg", Unary;
+    F32x4Sqrt = 0xE3, "f32x4.sqrt", Unary;
+    F32x4Add = 0xE4, "f32x4.add", Binary;
+    F32x4Sub = 0xE5, "f32x4.sub", Binary;
+    F32x4Mul = 0xE6, "f32x4.mul", Binary;
+    F32x4Div = 0xE7, "f32x4.div", Binary;
+    F32x4Min = 0xE8, "f32x4.min", Binary;
+    F32x4Max = 0xE9, "f32x4.max", Binary;
+    F32x4Pmin = 0xEA, "f32x4.pmin", Binary;
+    F32x4Pmax = 0xEB, "f32x4.pmax", Binary;
+    F64x2Abs = 0xEC, "f64x2.abs", Unary;
+    F64x2Neg = 0xED, "f64x2.neg", Unary;
+    F64x2Sqrt = 0xEF, "f64x2.sqrt", Unary;
+    F64x2Add = 0xF0, "f64x2.add", Binary;
+    F64x2Sub = 0xF1, "f64x2.sub", Binary;
+    F64x2Mul = 0xF2, "f64x2.mul", Binary;
+    F64x2Div = 0xF3, "f64x2.div", Binary;
+    F64x2Min = 0xF4, "f64x2.min", Binary;
+    F64x2Max = 0xF5, "f64x2.max", Binary;
+    F64x2Pmin = 0xF6, "f64x2.pmin", Binary;
+    F64x2Pmax = 0xF7, "f64x2.pmax", Binary;
+    I32x4TruncSatF32x4S = 0xF8, "i32x4.trunc_sat_f32x4_s", Unary;
+    I32x4TruncSatF32x4U = 0xF9, "i32x4.trunc_sat_f32x4_u", Unary;
+    F32x4ConvertI32x4S = 0xFA, "f32x4.convert_i32x4_s", Unary;
+    F32x4ConvertI32x4U = 0xFB, "f32x4.convert_i32x4_u", Unary;
+    I32x4TruncSatF64x2SZero = 0xFC, "i32x4.trunc_sat_f64x2_s_zero", Unary;
+    I32x4TruncSatF64x2UZero = 0xFD, "i32x4.trunc_sat_f64x2_u_zero", Unary;
+    F64x2ConvertLowI32x4S = 0xFE, "f64x2.convert_low_i32x4_s", Unary;
+    F64x2ConvertLowI32x4U = 0xFF, "f64x2.convert_low_i32x4_u", Unary;
+    I8x16RelaxedSwizzle = 0x100, "i8x16.relaxed_swizzle", Binary;
+    I32x4RelaxedTruncF32x4S = 0x101, "i32x4.relaxed_trunc_f32x4_s", Unary;
+    I32x4RelaxedTruncF32x4U = 0x102, "i32x4.relaxed_trunc_f32x4_u", Unary;
+    I32x4RelaxedTruncF64x2SZero = 0x103, "i32x4.relaxed_trunc_f64x2_s_zero", Unary;
+    I32x4RelaxedTruncF64x2UZero = 0x104, "i32x4.relaxed_trunc_f64x2_u_zero", Unary;
+    F32x4RelaxedMadd = 0x105, "f32x4.relaxed_madd", Ternary;
+    F32x4RelaxedNmadd = 0x106, "f32x4.relaxed_nmadd", Ternary;
+    F64x2RelaxedMadd = 0x107, "f64x2.relaxed_madd", Ternary;
+    F64x2RelaxedNmadd = 0x108, "f64x2.relaxed_nmadd", Ternary;
+    I8x16RelaxedLaneselect = 0x109, "i8x16.relaxed_laneselect", Ternary;
+    I16x8RelaxedLaneselect = 0x10A, "i16x8.relaxed_laneselect", Ternary;
+    I32x4RelaxedLaneselect = 0x10B, "i32x4.relaxed_laneselect", Ternary;
+    I64x2RelaxedLaneselect = 0x10C, "i64x2.relaxed_laneselect", Ternary;
+    F32x4RelaxedMin = 0x10D, "f32x4.relaxed_min", Binary;
+    F32x4RelaxedMax = 0x10E, "f32x4.relaxed_max", Binary;
+    F64x2RelaxedMin = 0x10F, "f64x2.relaxed_min", Binary;
+    F64x2RelaxedMax = 0x110, "f64x2.relaxed_max", Binary;
+    I16x8RelaxedQ15mulrS = 0x111, "i16x8.relaxed_q15mulr_s", Binary;
+    I16x8RelaxedDotI8x16I7x16S = 0x112, "i16x8.relaxed_dot_i8x16_i7x16_s", Binary;
+    I32x4RelaxedDotI8x16I7x16AddS = 0x113, "i32x4.relaxed_dot_i8x16_i7x16_add_s", Ternary;
+}
+
+impl VectorOp {
+    /// instructions of the relaxed SIMD proposal, whose results may depend on the host
+    /// https://github.com/WebAssembly/relaxed-simd
+    pub fn is_relaxed(self) -> bool {
+        self as u32 >= Self::I8x16RelaxedSwizzle as u32
+    }
+}
@@ -1,15 +1,247 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+
+use crate::features::WasmFeatures;
+
 use super::{
     instructions::Expr,
-    types::{FuncType, ValType},
+    types::{
+        CompositeType, FieldType, FuncType, GlobalType, HeapType, MemType, RefType, ResultType,
+        StorageType, SubType, TableType, ValType,
+    },
 };
 
+/// A module, whose function bodies, data segments, names of imports and exports,
+/// and custom sections may borrow from the binary it was decoded from
 /// https://webassembly.github.io/spec/core/syntax/modules.html#syntax-module
 #[derive(PartialEq, Eq, Debug)]
-pub struct Module {
+pub struct Module<'a> {
     pub version: u32,
-    pub types: Vec<FuncType>,
-    pub funcs: Vec<Func>,
-    pub exports: Vec<Export>,
+    pub types: Vec<SubType>,
+    /// the numbers of types in the recursive type groups, which split `types` in order
+    /// https://webassembly.github.io/gc/core/syntax/types.html#recursive-types
+    pub rec_groups: Vec<u32>,
+    pub funcs: Vec<Func<'a>>,
+    pub tables: Vec<Table>,
+    pub mems: Vec<Mem>,
+    pub tags: Vec<Tag>,
+    pub globals: Vec<Global>,
+    pub elems: Vec<Elem>,
+    pub datas: Vec<Data<'a>>,
+    /// the number of data segments declared ahead of the code, if the module declares it
+    /// https://webassembly.github.io/spec/core/binary/modules.html#data-count-section
+    pub data_count: Option<u32>,
+    pub start: Option<Start>,
+    pub imports: Vec<Import<'a>>,
+    pub exports: Vec<Export<'a>>,
+    pub customs: Vec<Custom<'a>>,
+    pub names: Names,
+}
+
+impl<'a> Module<'a> {
+    /// decode every function body not decoded yet and collect the errors by function index
+    pub fn decode_bodies(&self) -> Vec<(indices::FuncIdx, anyhow::Error)> {
+        self.decode_bodies_in(&self.funcs, self.num_of_imported_funcs())
+    }
+
+    /// the same as `decode_bodies` but spreads the bodies over `num_of_threads` threads,
+    /// which defaults to the available parallelism of the machine
+    pub fn decode_bodies_parallel(
+        &self,
+        num_of_threads: Option<usize>,
+    ) -> Vec<(indices::FuncIdx, anyhow::Error)> {
+        let num_of_threads = num_of_threads.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        });
+        let chunk_size = self.funcs.len().div_ceil(num_of_threads.max(1)).max(1);
+        let offset = self.num_of_imported_funcs();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .funcs
+                .chunks(chunk_size)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    scope.spawn(move || self.decode_bodies_in(chunk, offset + i * chunk_size))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("decoder thread panicked"))
+                .collect()
+        })
+    }
+
+    fn decode_bodies_in(
+        &self,
+        funcs: &[Func<'a>],
+        offset: usize,
+    ) -> Vec<(indices::FuncIdx, anyhow::Error)> {
+        funcs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, func)| func.body.expr().err().map(|e| ((offset + i) as u32, e)))
+            .collect()
+    }
+
+    /// imported functions precede the functions defined in the module in the index space
+    pub fn num_of_imported_funcs(&self) -> usize {
+        self.imports
+            .iter()
+            .filter(|import| matches!(import.desc, ImportDesc::Func(_)))
+            .count()
+    }
+
+    /// the type if it is a function type
+    pub fn func_type(&self, idx: indices::TypeIdx) -> Option<&FuncType> {
+        self.types.get(idx as usize).and_then(SubType::func)
+    }
+
+    /// the indices of the types in the recursive type group which the type belongs to
+    pub fn rec_group(&self, idx: indices::TypeIdx) -> Range<indices::TypeIdx> {
+        let mut start = 0;
+        for &len in &self.rec_groups {
+            if idx < start + len {
+                return start..start + len;
+            }
+            start += len;
+        }
+        idx..idx + 1
+    }
+
+    /// Types are equivalent when their recursive type groups are defined the same,
+    /// where references inside the groups are compared by their positions.
+    /// https://webassembly.github.io/gc/core/valid/conventions.html#rolling-and-unrolling
+    pub fn equivalent(&self, a: indices::TypeIdx, b: indices::TypeIdx) -> bool {
+        if a == b {
+            return true;
+        }
+        let (ga, gb) = (self.rec_group(a), self.rec_group(b));
+        if ga.len() != gb.len() || a - ga.start != b - gb.start {
+            return false;
+        }
+        let same =
+            |x: indices::TypeIdx, y: indices::TypeIdx| match (ga.contains(&x), gb.contains(&y)) {
+                (true, true) => x - ga.start == y - gb.start,
+                // types outside of the groups precede them
+                (false, false) => x < ga.start && y < gb.start && self.equivalent(x, y),
+                _ => false,
+            };
+        ga.clone().zip(gb.clone()).all(|(x, y)| {
+            match (self.types.get(x as usize), self.types.get(y as usize)) {
+                (Some(x), Some(y)) => same_sub_type(x, y, &same),
+                _ => false,
+            }
+        })
+    }
+
+    /// whether `sub` is `sup` or declares it as a supertype transitively
+    /// https://webassembly.github.io/gc/core/valid/matching.html#heap-types
+    pub fn is_subtype(&self, sub: indices::TypeIdx, sup: indices::TypeIdx) -> bool {
+        let mut idx = sub;
+        loop {
+            if self.equivalent(idx, sup) {
+                return true;
+            }
+            match self.types.get(idx as usize).and_then(|t| t.supers.first()) {
+                // supertypes precede their subtypes, which ends the walk
+                Some(&next) if next < idx => idx = next,
+                _ => return false,
+            }
+        }
+    }
+
+    /// the abstract heap type which a defined type belongs to
+    pub fn abstract_heap(&self, heap: HeapType) -> HeapType {
+        let HeapType::Type(idx) = heap else {
+            return heap;
+        };
+        match self.types.get(idx as usize).map(|t| &t.composite) {
+            Some(CompositeType::Struct(_)) => HeapType::Struct,
+            Some(CompositeType::Array(_)) => HeapType::Array,
+            _ => HeapType::Func,
+        }
+    }
+
+    /// the top of the hierarchy which the heap type belongs to
+    pub fn top_heap(&self, heap: HeapType) -> HeapType {
+        match self.abstract_heap(heap) {
+            HeapType::Func | HeapType::NoFunc => HeapType::Func,
+            HeapType::Extern | HeapType::NoExtern => HeapType::Extern,
+            HeapType::Exn | HeapType::NoExn => HeapType::Exn,
+            _ => HeapType::Any,
+        }
+    }
+
+    /// https://webassembly.github.io/gc/core/valid/matching.html#value-types
+    pub fn matches(&self, sub: ValType, sup: ValType) -> bool {
+        match (sub, sup) {
+            (ValType::Ref(sub), ValType::Ref(sup)) => self.matches_ref(sub, sup),
+            _ => sub == sup,
+        }
+    }
+
+    /// https://webassembly.github.io/gc/core/valid/matching.html#reference-types
+    pub fn matches_ref(&self, sub: RefType, sup: RefType) -> bool {
+        (sup.nullable || !sub.nullable) && self.matches_heap(sub.heap, sup.heap)
+    }
+
+    /// https://webassembly.github.io/gc/core/valid/matching.html#heap-types
+    pub fn matches_heap(&self, sub: HeapType, sup: HeapType) -> bool {
+        use HeapType::*;
+        match (sub, sup) {
+            _ if sub == sup => true,
+            (Type(a), Type(b)) => self.is_subtype(a, b),
+            (Type(_), _) => self.matches_heap(self.abstract_heap(sub), sup),
+            (None, _) => self.top_heap(sup) == Any,
+            (NoFunc, _) => self.top_heap(sup) == Func,
+            (NoExtern, _) => self.top_heap(sup) == Extern,
+            (NoExn, _) => self.top_heap(sup) == Exn,
+            (I31 | Struct | Array, Eq | Any) | (Eq, Any) => true,
+            _ => false,
+        }
+    }
+}
+
+/// compare definitions, where `same` tells whether type indices refer to the same types
+fn same_sub_type(a: &SubType, b: &SubType, same: &impl Fn(u32, u32) -> bool) -> bool {
+    let val = |x: &ValType, y: &ValType| match (x, y) {
+        (ValType::Ref(x), ValType::Ref(y)) => {
+            x.nullable == y.nullable
+                && match (x.heap, y.heap) {
+                    (HeapType::Type(x), HeapType::Type(y)) => same(x, y),
+                    (x, y) => x == y,
+                }
+        }
+        _ => x == y,
+    };
+    let vals = |x: &[ValType], y: &[ValType]| {
+        x.len() == y.len() && x.iter().zip(y).all(|(x, y)| val(x, y))
+    };
+    let field = |FieldType(mx, x): &FieldType, FieldType(my, y): &FieldType| {
+        mx == my
+            && match (x, y) {
+                (StorageType::Val(x), StorageType::Val(y)) => val(x, y),
+                _ => x == y,
+            }
+    };
+    a.is_final == b.is_final
+        && a.supers.len() == b.supers.len()
+        && a.supers.iter().zip(&b.supers).all(|(x, y)| same(*x, *y))
+        && match (&a.composite, &b.composite) {
+            (
+                CompositeType::Func(FuncType(ResultType(px), ResultType(rx))),
+                CompositeType::Func(FuncType(ResultType(py), ResultType(ry))),
+            ) => vals(px, py) && vals(rx, ry),
+            (CompositeType::Struct(x), CompositeType::Struct(y)) => {
+                x.len() == y.len() && x.iter().zip(y).all(|(x, y)| field(x, y))
+            }
+            (CompositeType::Array(x), CompositeType::Array(y)) => field(x, y),
+            _ => false,
+        }
 }
 
 /// https://webassembly.github.io/spec/core/syntax/modules.html#indices
@@ -22,22 +254,195 @@ pub mod indices {
     pub type LocalIdx = u32;
     pub type ElemIdx = u32;
     pub type DataIdx = u32;
+    pub type TagIdx = u32;
+    pub type FieldIdx = u32;
     pub type LabelIdx = u32;
+    pub type LaneIdx = u8;
 }
 
 /// https://webassembly.github.io/spec/core/syntax/modules.html#functions
 #[derive(PartialEq, Eq, Debug)]
-pub struct Func {
+pub struct Func<'a> {
     pub type_: indices::TypeIdx,
     pub locals: Vec<ValType>,
-    pub body: Expr,
+    pub body: Body<'a>,
 }
 
-pub type Name = String;
+/// Instructions of a function body which are decoded on first use and cached
+pub struct Body<'a> {
+    bytes: Cow<'a, [u8]>,
+    range: Option<Range<usize>>,
+    features: WasmFeatures,
+    /// instructions decoded successfully, while failures are decoded again on each use
+    /// so that their errors are returned as they are
+    expr: OnceLock<Expr>,
+}
+
+impl<'a> Body<'a> {
+    /// `range` is where `bytes` are placed in the module, if known.
+    /// Instructions of proposals disabled in `features` are rejected on decoding.
+    pub fn lazy(
+        bytes: impl Into<Cow<'a, [u8]>>,
+        range: Option<Range<usize>>,
+        features: WasmFeatures,
+    ) -> Self {
+        Self {
+            bytes: bytes.into(),
+            range,
+            features,
+            expr: OnceLock::new(),
+        }
+    }
+
+    /// decode the instructions unless they have been decoded already
+    pub fn expr(&self) -> Result<&Expr> {
+        if let Some(expr) = self.expr.get() {
+            return Ok(expr);
+        }
+        let expr = crate::binary::instructions::decode_instructions(&self.bytes)?;
+        self.features.check_expr(&expr)?;
+        Ok(self.expr.get_or_init(|| expr))
+    }
 
+    pub fn is_decoded(&self) -> bool {
+        self.expr.get().is_some()
+    }
+
+    /// undecoded instructions, empty if the body was built from instructions
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn range(&self) -> Option<Range<usize>> {
+        self.range.clone()
+    }
+}
+
+impl From<Expr> for Body<'_> {
+    fn from(expr: Expr) -> Self {
+        Self {
+            bytes: Cow::Borrowed(&[]),
+            range: None,
+            features: WasmFeatures::all(),
+            expr: OnceLock::from(expr),
+        }
+    }
+}
+
+/// Bodies are compared by their instructions wherever they are placed,
+/// where equal bytes are equal without decoding them,
+/// and bodies which cannot be decoded are equal only to the same bytes.
+impl PartialEq for Body<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        if !self.bytes.is_empty() && self.bytes == other.bytes {
+            return true;
+        }
+        match (self.expr(), other.expr()) {
+            (Ok(lhs), Ok(rhs)) => lhs == rhs,
+            _ => false,
+        }
+    }
+}
+impl Eq for Body<'_> {}
+
+impl std::fmt::Debug for Body<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.expr.get() {
+            Some(expr) => expr.fmt(f),
+            _ => write!(f, "Body({} bytes undecoded)", self.bytes.len()),
+        }
+    }
+}
+
+/// https://webassembly.github.io/spec/core/syntax/modules.html#tables
 #[derive(PartialEq, Eq, Debug)]
-pub struct Export {
-    pub name: Name,
+pub struct Table {
+    pub type_: TableType,
+}
+
+/// https://webassembly.github.io/spec/core/syntax/modules.html#memories
+#[derive(PartialEq, Eq, Debug)]
+pub struct Mem {
+    pub type_: MemType,
+}
+
+/// An exception tag whose type gives the values the exceptions carry
+/// https://webassembly.github.io/exception-handling/core/syntax/modules.html#tags
+#[derive(PartialEq, Eq, Debug)]
+pub struct Tag {
+    pub type_: indices::TypeIdx,
+}
+
+/// https://webassembly.github.io/spec/core/syntax/modules.html#globals
+#[derive(PartialEq, Eq, Debug)]
+pub struct Global {
+    pub type_: GlobalType,
+    pub init: Expr,
+}
+
+/// https://webassembly.github.io/spec/core/syntax/modules.html#element-segments
+#[derive(PartialEq, Eq, Debug)]
+pub struct Elem {
+    pub type_: RefType,
+    pub init: Vec<Expr>,
+    pub mode: ElemMode,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ElemMode {
+    Passive,
+    Active {
+        table: indices::TableIdx,
+        offset: Expr,
+    },
+    Declarative,
+}
+
+/// https://webassembly.github.io/spec/core/syntax/modules.html#data-segments
+#[derive(PartialEq, Eq, Debug)]
+pub struct Data<'a> {
+    pub init: Cow<'a, [u8]>,
+    pub mode: DataMode,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum DataMode {
+    Passive,
+    Active {
+        memory: indices::MemIdx,
+        offset: Expr,
+    },
+}
+
+/// https://webassembly.github.io/spec/core/syntax/modules.html#start-function
+#[derive(PartialEq, Eq, Debug)]
+pub struct Start {
+    pub func: indices::FuncIdx,
+}
+
+/// https://webassembly.github.io/spec/core/syntax/values.html#names
+pub type Name<'a> = Cow<'a, str>;
+
+/// https://webassembly.github.io/spec/core/syntax/modules.html#imports
+#[derive(PartialEq, Eq, Debug)]
+pub struct Import<'a> {
+    pub module: Name<'a>,
+    pub name: Name<'a>,
+    pub desc: ImportDesc,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ImportDesc {
+    Func(indices::TypeIdx),
+    Table(TableType),
+    Mem(MemType),
+    Global(GlobalType),
+    Tag(indices::TypeIdx),
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct Export<'a> {
+    pub name: Name<'a>,
     pub desc: ExportDesc,
 }
 
@@ -47,4 +452,96 @@ pub enum ExportDesc {
     Table(indices::TableIdx),
     Mem(indices::MemIdx),
     Global(indices::GlobalIdx),
+    Tag(indices::TagIdx),
+}
+
+/// https://webassembly.github.io/spec/core/binary/modules.html#custom-section
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Custom<'a> {
+    pub name: Name<'a>,
+    pub data: Cow<'a, [u8]>,
+    /// id of the last non-custom section preceding this one, None if it precedes all of them
+    pub after: Option<u8>,
+}
+
+/// names decoded from the name section, which are kept apart from the section itself
+pub type NameMap = BTreeMap<u32, String>;
+pub type IndirectNameMap = BTreeMap<u32, NameMap>;
+
+/// Debug names given by the name section including the extended name section
+/// https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+/// https://github.com/WebAssembly/extended-name-section
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct Names {
+    pub module: Option<String>,
+    pub funcs: NameMap,
+    /// local names per function
+    pub locals: IndirectNameMap,
+    /// label names per function, indexed in order of appearance of the labels
+    pub labels: IndirectNameMap,
+    pub types: NameMap,
+    /// field names per struct type
+    pub fields: IndirectNameMap,
+    pub tables: NameMap,
+    pub mems: NameMap,
+    pub globals: NameMap,
+    pub elems: NameMap,
+    pub datas: NameMap,
+    pub tags: NameMap,
+}
+
+impl Names {
+    /// `$name` if the function is named, otherwise `func[idx]`
+    pub fn func(&self, idx: indices::FuncIdx) -> String {
+        label(&self.funcs, "func", idx)
+    }
+
+    pub fn local(&self, func: indices::FuncIdx, idx: indices::LocalIdx) -> String {
+        match self.locals.get(&func) {
+            Some(locals) => label(locals, "local", idx),
+            None => format!("local[{}]", idx),
+        }
+    }
+
+    pub fn type_(&self, idx: indices::TypeIdx) -> String {
+        label(&self.types, "type", idx)
+    }
+
+    pub fn field(&self, type_: indices::TypeIdx, idx: indices::FieldIdx) -> String {
+        match self.fields.get(&type_) {
+            Some(fields) => label(fields, "field", idx),
+            None => format!("field[{}]", idx),
+        }
+    }
+
+    pub fn table(&self, idx: indices::TableIdx) -> String {
+        label(&self.tables, "table", idx)
+    }
+
+    pub fn mem(&self, idx: indices::MemIdx) -> String {
+        label(&self.mems, "memory", idx)
+    }
+
+    pub fn global(&self, idx: indices::GlobalIdx) -> String {
+        label(&self.globals, "global", idx)
+    }
+
+    pub fn elem(&self, idx: indices::ElemIdx) -> String {
+        label(&self.elems, "elem", idx)
+    }
+
+    pub fn data(&self, idx: indices::DataIdx) -> String {
+        label(&self.datas, "data", idx)
+    }
+
+    pub fn tag(&self, idx: indices::TagIdx) -> String {
+        label(&self.tags, "tag", idx)
+    }
+}
+
+fn label(map: &NameMap, kind: &str, idx: u32) -> String {
+    match map.get(&idx) {
+        Some(name) => format!("${}", name),
+        None => format!("{}[{}]", kind, idx),
+    }
 }
@@ -1,5 +1,5 @@
 /// https://webassembly.github.io/spec/core/syntax/types.html#number-types
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum NumType {
     I32,
     I64,
@@ -7,15 +7,58 @@ pub enum NumType {
     F64,
 }
 
-/// https://webassembly.github.io/spec/core/syntax/types.html#reference-types
-#[derive(PartialEq, Eq, Debug)]
-pub enum RefType {
-    FuncRef,
-    ExternRef,
+/// https://webassembly.github.io/function-references/core/syntax/types.html#reference-types
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct RefType {
+    pub nullable: bool,
+    pub heap: HeapType,
+}
+
+impl RefType {
+    pub const FUNCREF: RefType = RefType::null(HeapType::Func);
+    pub const EXTERNREF: RefType = RefType::null(HeapType::Extern);
+    /// https://webassembly.github.io/exception-handling/core/syntax/types.html#reference-types
+    pub const EXNREF: RefType = RefType::null(HeapType::Exn);
+
+    /// `(ref null ht)`
+    pub const fn null(heap: HeapType) -> Self {
+        Self {
+            nullable: true,
+            heap,
+        }
+    }
+
+    /// `(ref ht)`
+    pub const fn non_null(heap: HeapType) -> Self {
+        Self {
+            nullable: false,
+            heap,
+        }
+    }
+}
+
+/// https://webassembly.github.io/gc/core/syntax/types.html#heap-types
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum HeapType {
+    Func,
+    Extern,
+    Exn,
+    Any,
+    Eq,
+    I31,
+    Struct,
+    Array,
+    /// the bottom types of the hierarchies, which only null references have
+    None,
+    NoFunc,
+    NoExtern,
+    NoExn,
+    /// a type defined in the module
+    Type(super::module::indices::TypeIdx),
 }
 
 /// https://webassembly.github.io/spec/core/syntax/types.html#value-types
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum ValType {
     Number(NumType),
     Ref(RefType),
@@ -23,6 +66,20 @@ pub enum ValType {
     Vec,
 }
 
+impl ValType {
+    /// whether locals of the type can start with a default value, which non-nullable references lack
+    /// https://webassembly.github.io/function-references/core/valid/types.html#defaultable-types
+    pub fn is_defaultable(&self) -> bool {
+        !matches!(
+            self,
+            ValType::Ref(RefType {
+                nullable: false,
+                ..
+            })
+        )
+    }
+}
+
 /// https://webassembly.github.io/spec/core/syntax/types.html#result-types
 #[derive(PartialEq, Eq, Debug)]
 pub struct ResultType(pub Vec<ValType>);
@@ -31,8 +88,205 @@ pub struct ResultType(pub Vec<ValType>);
 #[derive(PartialEq, Eq, Debug)]
 pub struct FuncType(pub ResultType, pub ResultType);
 
-// https://webassembly.github.io/spec/core/syntax/types.html#limits
-// https://webassembly.github.io/spec/core/syntax/types.html#memory-types
-// https://webassembly.github.io/spec/core/syntax/types.html#table-types
-// https://webassembly.github.io/spec/core/syntax/types.html#global-types
+/// https://webassembly.github.io/gc/core/syntax/types.html#aggregate-types
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum PackedType {
+    I8,
+    I16,
+}
+
+impl PackedType {
+    pub fn width(self) -> u32 {
+        match self {
+            PackedType::I8 => 8,
+            PackedType::I16 => 16,
+        }
+    }
+}
+
+/// https://webassembly.github.io/gc/core/syntax/types.html#aggregate-types
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum StorageType {
+    Val(ValType),
+    Packed(PackedType),
+}
+
+impl StorageType {
+    /// the type of the values read from fields, which is i32 for packed types
+    pub fn unpack(self) -> ValType {
+        match self {
+            StorageType::Val(val_type) => val_type,
+            StorageType::Packed(_) => ValType::Number(NumType::I32),
+        }
+    }
+}
+
+/// https://webassembly.github.io/gc/core/syntax/types.html#aggregate-types
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct FieldType(pub Mut, pub StorageType);
+
+/// https://webassembly.github.io/gc/core/syntax/types.html#composite-types
+#[derive(PartialEq, Eq, Debug)]
+pub enum CompositeType {
+    Func(FuncType),
+    Struct(Vec<FieldType>),
+    Array(FieldType),
+}
+
+/// A type definition with its declared supertypes, which is final unless it may be extended
+/// https://webassembly.github.io/gc/core/syntax/types.html#recursive-types
+#[derive(PartialEq, Eq, Debug)]
+pub struct SubType {
+    pub is_final: bool,
+    pub supers: Vec<super::module::indices::TypeIdx>,
+    pub composite: CompositeType,
+}
+
+impl SubType {
+    pub fn func(&self) -> Option<&FuncType> {
+        match &self.composite {
+            CompositeType::Func(func_type) => Some(func_type),
+            _ => None,
+        }
+    }
+}
+
+/// a function type abbreviated without `sub`, which is final without supertypes
+impl From<FuncType> for SubType {
+    fn from(func_type: FuncType) -> Self {
+        Self {
+            is_final: true,
+            supers: vec![],
+            composite: CompositeType::Func(func_type),
+        }
+    }
+}
+
+/// https://webassembly.github.io/spec/core/syntax/types.html#limits
+#[derive(PartialEq, Eq, Debug)]
+pub struct Limits {
+    pub min: u64,
+    pub max: Option<u64>,
+    pub index: IndexType,
+}
+
+/// the type of addresses into a memory or a table, which is i64 only with memory64
+/// https://webassembly.github.io/memory64/core/syntax/types.html#limits
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum IndexType {
+    I32,
+    I64,
+}
+
+impl From<IndexType> for ValType {
+    fn from(index: IndexType) -> Self {
+        match index {
+            IndexType::I32 => ValType::Number(NumType::I32),
+            IndexType::I64 => ValType::Number(NumType::I64),
+        }
+    }
+}
+
+/// https://webassembly.github.io/spec/core/syntax/types.html#memory-types
+#[derive(PartialEq, Eq, Debug)]
+pub struct MemType(pub Limits, pub Share);
+
+/// whether a memory can be accessed by multiple threads
+/// https://webassembly.github.io/threads/core/syntax/types.html#memory-types
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Share {
+    Unshared,
+    Shared,
+}
+
+/// https://webassembly.github.io/spec/core/syntax/types.html#table-types
+#[derive(PartialEq, Eq, Debug)]
+pub struct TableType(pub Limits, pub RefType);
+
+/// https://webassembly.github.io/spec/core/syntax/types.html#global-types
+#[derive(PartialEq, Eq, Debug)]
+pub struct GlobalType(pub Mut, pub ValType);
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Mut {
+    Const,
+    Var,
+}
+
 // https://webassembly.github.io/spec/core/syntax/types.html#external-types
+
+/// https://webassembly.github.io/spec/core/text/types.html#value-types
+impl std::fmt::Display for ValType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValType::Number(NumType::I32) => f.write_str("i32"),
+            ValType::Number(NumType::I64) => f.write_str("i64"),
+            ValType::Number(NumType::F32) => f.write_str("f32"),
+            ValType::Number(NumType::F64) => f.write_str("f64"),
+            ValType::Vec => f.write_str("v128"),
+            ValType::Ref(r) => r.fmt(f),
+        }
+    }
+}
+
+/// https://webassembly.github.io/function-references/core/text/types.html#reference-types
+impl std::fmt::Display for RefType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.nullable, self.heap) {
+            (true, HeapType::Func) => f.write_str("funcref"),
+            (true, HeapType::Extern) => f.write_str("externref"),
+            (true, HeapType::Exn) => f.write_str("exnref"),
+            (true, HeapType::Any) => f.write_str("anyref"),
+            (true, HeapType::Eq) => f.write_str("eqref"),
+            (true, HeapType::I31) => f.write_str("i31ref"),
+            (true, HeapType::Struct) => f.write_str("structref"),
+            (true, HeapType::Array) => f.write_str("arrayref"),
+            (true, HeapType::None) => f.write_str("nullref"),
+            (true, HeapType::NoFunc) => f.write_str("nullfuncref"),
+            (true, HeapType::NoExtern) => f.write_str("nullexternref"),
+            (true, HeapType::NoExn) => f.write_str("nullexnref"),
+            (true, heap) => write!(f, "(ref null {})", heap),
+            (false, heap) => write!(f, "(ref {})", heap),
+        }
+    }
+}
+
+impl std::fmt::Display for HeapType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeapType::Func => f.write_str("func"),
+            HeapType::Extern => f.write_str("extern"),
+            HeapType::Exn => f.write_str("exn"),
+            HeapType::Any => f.write_str("any"),
+            HeapType::Eq => f.write_str("eq"),
+            HeapType::I31 => f.write_str("i31"),
+            HeapType::Struct => f.write_str("struct"),
+            HeapType::Array => f.write_str("array"),
+            HeapType::None => f.write_str("none"),
+            HeapType::NoFunc => f.write_str("nofunc"),
+            HeapType::NoExtern => f.write_str("noextern"),
+            HeapType::NoExn => f.write_str("noexn"),
+            HeapType::Type(idx) => write!(f, "{}", idx),
+        }
+    }
+}
+
+/// https://webassembly.github.io/gc/core/text/types.html#aggregate-types
+impl std::fmt::Display for StorageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageType::Val(val_type) => val_type.fmt(f),
+            StorageType::Packed(PackedType::I8) => f.write_str("i8"),
+            StorageType::Packed(PackedType::I16) => f.write_str("i16"),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldType(Mut::Const, storage) => storage.fmt(f),
+            FieldType(Mut::Var, storage) => write!(f, "(mut {})", storage),
+        }
+    }
+}
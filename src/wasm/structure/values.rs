@@ -1,6 +1,167 @@
+use std::fmt;
+
+use super::{
+    module::indices::FuncIdx,
+    types::{HeapType, NumType, RefType, ValType},
+};
+
+/// https://webassembly.github.io/spec/core/exec/runtime.html#values
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Value {
     I32(i32),
     I64(i64),
     F32(f32),
     F64(f64),
+    /// lanes in little endian
+    V128(u128),
+    /// a reference to a function, None for null
+    FuncRef(Option<FuncIdx>),
+    /// a reference in the hierarchy of `any`, None for null
+    AnyRef(Option<HeapRef>),
+    /// a reference to a host object or an externalized `any` reference, None for null
+    ExternRef(Option<HeapRef>),
+    /// a reference to a caught exception, None for null
+    ExnRef(Option<u32>),
+}
+
+/// The referent of `anyref` and `externref`, which keeps its identity when converted between them
+/// https://webassembly.github.io/gc/core/exec/runtime.html#values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapRef {
+    /// an unboxed scalar of 31 bits
+    I31(u32),
+    /// the address of a structure in the heap of the runtime
+    Struct(u32),
+    /// the address of an array in the heap of the runtime
+    Array(u32),
+    /// a host object
+    Host(u32),
+}
+
+impl Value {
+    /// https://webassembly.github.io/spec/core/exec/runtime.html#default-val
+    pub fn default_of(val_type: ValType) -> Self {
+        match val_type {
+            ValType::Number(NumType::I32) => Value::I32(0),
+            ValType::Number(NumType::I64) => Value::I64(0),
+            ValType::Number(NumType::F32) => Value::F32(0.0),
+            ValType::Number(NumType::F64) => Value::F64(0.0),
+            ValType::Ref(ref_type) => Value::null(ref_type.heap),
+            ValType::Vec => Value::V128(0),
+        }
+    }
+
+    /// the null of the hierarchy which the heap type belongs to,
+    /// where defined types are taken as function types
+    pub fn null(heap: HeapType) -> Self {
+        match heap {
+            HeapType::Func | HeapType::NoFunc | HeapType::Type(_) => Value::FuncRef(None),
+            HeapType::Extern | HeapType::NoExtern => Value::ExternRef(None),
+            HeapType::Exn | HeapType::NoExn => Value::ExnRef(None),
+            HeapType::Any
+            | HeapType::Eq
+            | HeapType::I31
+            | HeapType::Struct
+            | HeapType::Array
+            | HeapType::None => Value::AnyRef(None),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(
+            self,
+            Value::FuncRef(None)
+                | Value::AnyRef(None)
+                | Value::ExternRef(None)
+                | Value::ExnRef(None)
+        )
+    }
+
+    pub fn type_(&self) -> ValType {
+        match self {
+            Value::I32(_) => ValType::Number(NumType::I32),
+            Value::I64(_) => ValType::Number(NumType::I64),
+            Value::F32(_) => ValType::Number(NumType::F32),
+            Value::F64(_) => ValType::Number(NumType::F64),
+            Value::V128(_) => ValType::Vec,
+            Value::FuncRef(_) => ValType::Ref(RefType::FUNCREF),
+            Value::AnyRef(_) => ValType::Ref(RefType::null(HeapType::Any)),
+            Value::ExternRef(_) => ValType::Ref(RefType::EXTERNREF),
+            Value::ExnRef(_) => ValType::Ref(RefType::EXNREF),
+        }
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::I32(v)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(v: u32) -> Self {
+        Value::I32(v as i32)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::I32(v as i32)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::I64(v)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(v: f32) -> Self {
+        Value::F32(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::F64(v)
+    }
+}
+
+impl From<u128> for Value {
+    fn from(v: u128) -> Self {
+        Value::V128(v)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::I32(v) => write!(f, "{}", v),
+            Value::I64(v) => write!(f, "{}", v),
+            Value::F32(v) => write!(f, "{}", v),
+            Value::F64(v) => write!(f, "{}", v),
+            Value::V128(v) => write!(f, "{:#034x}", v),
+            Value::FuncRef(Some(idx)) => write!(f, "ref.func {}", idx),
+            Value::AnyRef(Some(r)) => r.fmt(f),
+            Value::ExternRef(Some(HeapRef::Host(idx))) => write!(f, "ref.extern {}", idx),
+            Value::ExternRef(Some(r)) => write!(f, "ref.extern ({})", r),
+            Value::ExnRef(Some(idx)) => write!(f, "ref.exn {}", idx),
+            Value::FuncRef(None) => f.write_str("ref.null func"),
+            Value::AnyRef(None) => f.write_str("ref.null any"),
+            Value::ExternRef(None) => f.write_str("ref.null extern"),
+            Value::ExnRef(None) => f.write_str("ref.null exn"),
+        }
+    }
+}
+
+impl fmt::Display for HeapRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeapRef::I31(v) => write!(f, "ref.i31 {}", v),
+            HeapRef::Struct(addr) => write!(f, "ref.struct {}", addr),
+            HeapRef::Array(addr) => write!(f, "ref.array {}", addr),
+            HeapRef::Host(idx) => write!(f, "ref.host {}", idx),
+        }
+    }
 }
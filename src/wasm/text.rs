@@ -0,0 +1,387 @@
+mod instructions;
+mod lexer;
+mod module;
+mod printer;
+mod sexpr;
+mod types;
+
+use anyhow::*;
+
+use crate::structure::module::Module;
+
+pub use printer::{print, print_expr, Style};
+
+/// parse a module in the text format
+/// https://webassembly.github.io/spec/core/text/index.html
+///
+/// Either `(module $id? field*)` or the fields without the enclosing `module` are accepted.
+pub fn parse(wat: &str) -> Result<Module<'static>> {
+    let sexprs = sexpr::parse(lexer::tokenize(wat)?)?;
+    match sexprs.as_slice() {
+        [list @ sexpr::Sexpr::List(_, _)] if list.head() == Some("module") => {
+            let mut items = list.items()?;
+            items.keyword()?;
+            let id = items.id();
+            module::parse_module(id, items.rest())
+        }
+        fields => module::parse_module(None, fields),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use crate::binary::module::decode_slice_with_features;
+    use crate::features::WasmFeatures;
+
+    /// parse `wat` and compare it with the module decoded from the binary built by wasmer
+    fn assert_same_as_binary(wat: &str) -> Result<()> {
+        assert_same_as_binary_with_features(wat, &WasmFeatures::default())
+    }
+
+    fn assert_same_as_binary_with_features(wat: &str, features: &WasmFeatures) -> Result<()> {
+        let parsed = super::parse(wat)?;
+        let wasm = wasmer::wat2wasm(wat.as_bytes())?;
+        let mut decoded = decode_slice_with_features(&wasm, features)?;
+        assert_eq!(parsed.names.module, decoded.names.module);
+        assert_eq!(parsed.names.funcs, decoded.names.funcs);
+        assert_eq!(parsed.names.locals, decoded.names.locals);
+        decoded.customs = vec![];
+        // the text format has no DataCount section
+        decoded.data_count = None;
+        decoded.names = parsed.names.clone();
+        // compare the text first to show a readable diff
+        assert_eq!(
+            super::print(&parsed, super::Style::Flat),
+            super::print(&decoded, super::Style::Flat)
+        );
+        assert_eq!(parsed, decoded);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_func() -> Result<()> {
+        assert_same_as_binary(
+            r#"(module $m
+                (func $add (export "add") (param $lhs i32) (param $rhs i32) (result i32)
+                    local.get $lhs
+                    local.get $rhs
+                    i32.add)
+                (func (export "fib") (param i32) (result i32) (local $x i32) (local i64 i32)
+                    (if (result i32) (i32.lt_s (local.get 0) (i32.const 2))
+                        (then (i32.const 1))
+                        (else
+                            (i32.add
+                                (call 1 (i32.sub (local.get 0) (i32.const 1)))
+                                (call 1 (i32.sub (local.get 0) (i32.const 2)))))))
+            )"#,
+        )
+    }
+
+    #[test]
+    #[cfg(all(feature = "multi-value", feature = "reference-types"))]
+    fn parse_control() -> Result<()> {
+        assert_same_as_binary(
+            r#"(module
+                (type $t (func (param i32) (result i32)))
+                (func $f (type $t)
+                    block $outer (result i32)
+                        loop $inner
+                            local.get 0
+                            br_if $inner
+                            local.get 0
+                            br_table 0 $outer $inner 1
+                        end $inner
+                        i32.const 0
+                    end
+                    (block (param i32) (result i32 i32)
+                        (br 0 (i32.const 1) (i32.const 2)))
+                    drop
+                    (select (result i32) (i32.const 3))
+                    if (result i32) i32.const 4 else i32.const 5 end
+                    i32.add
+                    return)
+                (func (result i32) i32.const -1 i32.const 0xffff_ffff i32.xor)
+            )"#,
+        )
+    }
+
+    #[test]
+    #[cfg(all(feature = "reference-types", feature = "bulk-memory"))]
+    fn parse_imports_and_segments() -> Result<()> {
+        assert_same_as_binary(
+            r#"(module
+                (import "env" "f" (func $imported (param i32)))
+                (global $g (import "env" "g") i32)
+                (memory $mem (export "mem") 1 2)
+                (table $tbl 2 funcref)
+                (table $inline funcref (elem $imported $f))
+                (global $counter (mut i32) (global.get $g))
+                (func $f
+                    (call_indirect $tbl (type 0) (i32.const 1) (i32.const 0))
+                    (table.init $tbl $passive (i32.const 0) (i32.const 0) (i32.const 1))
+                    (elem.drop $passive)
+                    (drop (ref.func $f))
+                    (global.set $counter (i32.const 1)))
+                (elem (i32.const 0) $f)
+                (elem $passive funcref (ref.func $f) (item ref.null func))
+                (elem declare func $imported)
+                (data (offset (i32.const 8)) "hello" "\00")
+                (data $passive "\de\ad")
+                (export "f" (func $f))
+                (start $f)
+            )"#,
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "simd")]
+    fn parse_simd() -> Result<()> {
+        assert_same_as_binary(
+            r#"(module
+                (memory 1)
+                (func (param v128) (result v128)
+                    (v128.const i8x16 -1 0 1 2 3 4 5 6 7 8 9 10 11 12 13 0xff)
+                    (v128.const i16x8 -1 0 1 2 3 4 5 0xffff)
+                    i8x16.shuffle 0 1 2 3 4 5 6 7 16 17 18 19 20 21 22 31
+                    (v128.const f32x4 1.5 -0x1p-3 inf nan:0x200000)
+                    (f64x2.extract_lane 1 (v128.const f64x2 -0.0 -nan))
+                    f64x2.replace_lane 0
+                    (i32x4.dot_i16x8_s (local.get 0))
+                    (v128.bitselect (local.get 0) (v128.const i64x2 1 -1))
+                    (i8x16.shl (i32.const 3))
+                    (v128.store offset=16 align=8 (i32.const 0) (local.get 0))
+                    (v128.load32_lane offset=4 2 (i32.const 8))
+                    (v128.store64_lane align=1 1 (i32.const 0) (v128.load64_splat (i32.const 0)))
+                    (v128.any_true (i16x8.extmul_high_i8x16_u (local.get 0) (local.get 0)))
+                    drop)
+            )"#,
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn parse_atomics() -> Result<()> {
+        assert_same_as_binary_with_features(
+            r#"(module
+                (memory (export "mem") 1 2 shared)
+                (func (param i32) (result i32)
+                    (drop (memory.atomic.notify offset=4 (local.get 0) (i32.const 1)))
+                    (drop (memory.atomic.wait64 (local.get 0) (i64.const 0) (i64.const -1)))
+                    atomic.fence
+                    (i64.atomic.store32 (local.get 0) (i64.atomic.load8_u (local.get 0)))
+                    (drop (i64.atomic.rmw16.xchg_u (local.get 0) (i64.const 1)))
+                    (i32.atomic.rmw.cmpxchg offset=8 (local.get 0) (i32.const 0)
+                        (i32.atomic.rmw8.add_u (local.get 0) (i32.const 2))))
+            )"#,
+            &WasmFeatures::all(),
+        )
+    }
+
+    #[test]
+    #[cfg(all(feature = "simd", feature = "threads", feature = "multi-memory"))]
+    fn parse_multi_memory() -> Result<()> {
+        assert_same_as_binary_with_features(
+            r#"(module
+                (import "env" "mem" (memory $imported 1))
+                (memory $a 1)
+                (memory $b (export "b") 1 2)
+                (func (param i32) (result v128)
+                    (v128.store $b offset=16 (local.get 0) (v128.load 1 (local.get 0)))
+                    (drop (i32.atomic.load $b align=4 (local.get 0)))
+                    (v128.load8_lane 3 (local.get 0)
+                        (v128.load8_lane 2 3 (local.get 0)
+                            (v128.load8_lane $b offset=1 3 (local.get 0) (v128.load $imported (local.get 0))))))
+                (data (memory $b) (i32.const 0) "ab")
+            )"#,
+            &WasmFeatures::all(),
+        )
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "bulk-memory",
+        feature = "threads",
+        feature = "multi-memory",
+        feature = "memory64"
+    ))]
+    fn parse_memory_instructions() -> Result<()> {
+        assert_same_as_binary_with_features(
+            r#"(module
+                (memory $m 1 2)
+                (memory $wide i64 1)
+                (func (param i32 i64)
+                    (i32.store8 offset=3 (local.get 0) (i32.load16_s align=1 (local.get 0)))
+                    (f64.store $wide offset=4294967296 (local.get 1) (f64.load $wide (local.get 1)))
+                    (drop (memory.grow (memory.size)))
+                    (drop (memory.grow $wide (memory.size $wide)))
+                    (memory.fill (local.get 0) (i32.const 0) (i32.const 1))
+                    (memory.copy $wide $m (local.get 1) (local.get 0) (i32.const 1))
+                    (memory.init $d (local.get 0) (i32.const 0) (i32.const 1))
+                    (memory.init $wide 0 (local.get 1) (i32.const 0) (i32.const 1))
+                    (data.drop $d))
+                (data $d "a")
+                (data (memory $wide) (i64.const 0) "b")
+            )"#,
+            &WasmFeatures::all(),
+        )?;
+        assert_same_as_binary_with_features(
+            r#"(module
+                (import "env" "m" (memory i64 1 2 shared))
+                (memory $m i64 (data "abc"))
+            )"#,
+            &WasmFeatures::all(),
+        )
+    }
+
+    #[test]
+    fn parse_inline_memory_data() -> Result<()> {
+        assert_same_as_binary(r#"(memory (data "abc"))"#)
+    }
+
+    #[test]
+    #[cfg(feature = "exceptions")]
+    fn parse_exceptions() -> Result<()> {
+        assert_same_as_binary_with_features(
+            r#"(module
+                (import "env" "e" (tag $imported (param i32)))
+                (tag $empty)
+                (tag $pair (export "pair") (param i64 f32))
+                (func (param i64 f32)
+                    (throw $pair (local.get 0) (local.get 1))
+                    (throw $imported (i32.const 1))
+                    (throw 1))
+                (export "imported" (tag $imported))
+            )"#,
+            &WasmFeatures::all(),
+        )
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "multi-value",
+        feature = "reference-types",
+        feature = "exceptions"
+    ))]
+    fn parse_try_table() -> Result<()> {
+        //Given
+        let wat = r#"(module
+            (tag $e (param i32))
+            (func (result i32 exnref)
+                (block $outer (result i32 exnref)
+                    (block $inner (result i32)
+                        (try_table $try (catch $e $inner) (catch_ref $e $outer)
+                            (throw $e (i32.const 1)))
+                        (unreachable))
+                    (ref.null exn)))
+            (func
+                block $b (result exnref)
+                    try_table $t (result i32) (catch_all_ref $b) (catch_all 1)
+                        i32.const 1
+                    end
+                    drop
+                    ref.null exn
+                end
+                drop))"#;
+        //When
+        let parsed = super::parse(wat)?;
+        let flat = super::parse(&super::print(&parsed, super::Style::Flat))?;
+        let folded = super::parse(&super::print(&parsed, super::Style::Folded))?;
+        //Then
+        crate::validate::validate_with_features(&parsed, &WasmFeatures::all())?;
+        let encoded = crate::binary::encode::encode(&parsed)?;
+        let decoded = decode_slice_with_features(&encoded, &WasmFeatures::all())?;
+        assert_eq!(parsed.funcs, decoded.funcs);
+        assert_eq!(parsed, flat);
+        assert_eq!(parsed, folded);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "reference-types",
+        feature = "bulk-memory",
+        feature = "tail-call",
+        feature = "function-references"
+    ))]
+    fn parse_typed_function_references() -> Result<()> {
+        //Given
+        let wat = r#"(module
+            (type $unary (func (param i32) (result i32)))
+            (type $apply (func (param (ref $unary) i32) (result i32)))
+            (func $inc (type $unary) (i32.add (local.get 0) (i32.const 1)))
+            (func (type $apply)
+                (call_ref $unary (local.get 1) (local.get 0)))
+            (func (param (ref null $unary)) (result i32)
+                (local $f (ref $unary))
+                (block $null
+                    (local.set $f (br_on_null $null (local.get 0)))
+                    (return_call_ref $unary (i32.const 1) (local.get $f)))
+                (block $some (result (ref $unary))
+                    (br_on_non_null $some (ref.null $unary))
+                    (ref.as_non_null (ref.func $inc)))
+                (drop)
+                (i32.const 0))
+            (elem declare func $inc))"#;
+        //When
+        let parsed = super::parse(wat)?;
+        let flat = super::parse(&super::print(&parsed, super::Style::Flat))?;
+        let folded = super::parse(&super::print(&parsed, super::Style::Folded))?;
+        //Then
+        crate::validate::validate_with_features(&parsed, &WasmFeatures::all())?;
+        let encoded = crate::binary::encode::encode(&parsed)?;
+        let decoded = decode_slice_with_features(&encoded, &WasmFeatures::all())?;
+        assert_eq!(parsed.types, decoded.types);
+        assert_eq!(parsed.funcs, decoded.funcs);
+        assert_eq!(parsed, flat);
+        assert_eq!(parsed, folded);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "reference-types", feature = "bulk-memory", feature = "gc"))]
+    fn parse_gc() -> Result<()> {
+        //Given
+        let wat = r#"(module
+            (rec
+                (type $node (sub (struct (field $value i32) (field $next (ref null $node)))))
+                (type $leaf (sub final $node (struct (field $value i32) (field $next (ref null $node)) (field i8)))))
+            (type $bytes (array (mut i8)))
+            (type $pair (struct (field f32 (mut i64))))
+            (data $d "\01\02")
+            (func (param (ref null $node)) (result i32)
+                (struct.set $pair 1 (struct.new_default $pair) (i64.const 3))
+                (drop (struct.get_s $leaf 2 (ref.cast (ref $leaf) (local.get 0))))
+                (drop (ref.test (ref null $leaf) (local.get 0)))
+                (drop (block $b (result (ref $leaf))
+                    (drop (br_on_cast $b (ref null $node) (ref $leaf) (local.get 0)))
+                    (unreachable)))
+                (array.fill $bytes (array.new_data $bytes $d (i32.const 0) (i32.const 2))
+                    (i32.const 0) (i32.const 7) (i32.const 1))
+                (drop (array.len (array.new_fixed $bytes 2 (i32.const 1) (i32.const 2))))
+                (drop (ref.eq (ref.i31 (i32.const 5)) (ref.null eq)))
+                (drop (extern.convert_any (any.convert_extern (ref.null noextern))))
+                (i31.get_u (ref.i31 (struct.get $node $value (local.get 0))))))"#;
+        //When
+        let parsed = super::parse(wat)?;
+        let flat = super::parse(&super::print(&parsed, super::Style::Flat))?;
+        let folded = super::parse(&super::print(&parsed, super::Style::Folded))?;
+        //Then
+        crate::validate::validate_with_features(&parsed, &WasmFeatures::all())?;
+        let encoded = crate::binary::encode::encode(&parsed)?;
+        let decoded = decode_slice_with_features(&encoded, &WasmFeatures::all())?;
+        assert_eq!(parsed.types, decoded.types);
+        assert_eq!(parsed.rec_groups, decoded.rec_groups);
+        assert_eq!(parsed.funcs, decoded.funcs);
+        assert_eq!(parsed, flat);
+        assert_eq!(parsed, folded);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_error_has_position() {
+        let err = super::parse("(module\n  (func (local.get $x)))").unwrap_err();
+        assert_eq!(err.to_string(), "2:20: unknown local $x");
+    }
+}
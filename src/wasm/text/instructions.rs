@@ -0,0 +1,594 @@
+use anyhow::*;
+
+use super::{
+    module::{ModuleContext, Space},
+    sexpr::{Items, Sexpr},
+    types::{heap_type, ref_type, val_type},
+};
+use crate::structure::{
+    instructions::{
+        atomic::AtomicOp,
+        memory::MemoryOp,
+        vector::VectorOp,
+        Catch, Expr,
+        Instruction::{self, *},
+        MemArg,
+    },
+    module::NameMap,
+};
+
+/// Parser of instructions in a function or a constant expression
+/// https://webassembly.github.io/spec/core/text/instructions.html
+pub struct FuncContext<'c> {
+    ctx: &'c mut ModuleContext,
+    locals: Space,
+    labels: Vec<Option<String>>,
+    num_of_labels: u32,
+    label_names: NameMap,
+}
+
+impl<'c> FuncContext<'c> {
+    pub fn new(ctx: &'c mut ModuleContext, locals: Space) -> Self {
+        Self {
+            ctx,
+            locals,
+            labels: vec![],
+            num_of_labels: 0,
+            label_names: NameMap::new(),
+        }
+    }
+
+    /// parse all the remaining items as instructions
+    pub fn instrs(&mut self, items: &mut Items) -> Result<Expr> {
+        let mut out = Expr::new();
+        self.seq(items, &mut out, &[])?;
+        items.end()?;
+        Ok(out)
+    }
+
+    /// parse a single folded instruction such as `(i32.const 0)`
+    pub fn folded_instr(&mut self, list: &Sexpr) -> Result<Expr> {
+        let mut out = Expr::new();
+        self.folded(list, &mut out)?;
+        Ok(out)
+    }
+
+    /// parse instructions until one of `terminators` or the end of the items
+    fn seq(&mut self, items: &mut Items, out: &mut Expr, terminators: &[&str]) -> Result<()> {
+        while let Some(item) = items.peek() {
+            match item {
+                Sexpr::List(_, _) => {
+                    items.next();
+                    self.folded(item, out)?;
+                }
+                _ => match item.keyword() {
+                    Some(kw) if terminators.contains(&kw) => break,
+                    Some(_) => self.plain(items, out)?,
+                    None => return items.end(),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn plain(&mut self, items: &mut Items, out: &mut Expr) -> Result<()> {
+        let pos = items.pos();
+        let kw = items.keyword()?;
+        match kw {
+            "block" | "loop" => {
+                let label = self.push_label(items);
+                let block_type = self.ctx.block_type(items)?;
+                let mut body = Expr::new();
+                self.seq(items, &mut body, &["end"])?;
+                self.end_label(items, "end", &label)?;
+                out.push(if kw == "block" {
+                    Block(block_type, body)
+                } else {
+                    Loop(block_type, body)
+                });
+            }
+            "if" => {
+                let label = self.push_label(items);
+                let block_type = self.ctx.block_type(items)?;
+                let mut then = Expr::new();
+                self.seq(items, &mut then, &["else", "end"])?;
+                let else_ = if items.peek_keyword() == Some("else") {
+                    self.check_label(items, "else", &label)?;
+                    let mut else_ = Expr::new();
+                    self.seq(items, &mut else_, &["end"])?;
+                    Some(else_)
+                } else {
+                    None
+                };
+                self.end_label(items, "end", &label)?;
+                out.push(If(block_type, then, else_));
+            }
+            "try_table" => {
+                let label = items.id().map(str::to_string);
+                self.name_label(&label);
+                let block_type = self.ctx.block_type(items)?;
+                let catches = self.catches(items)?;
+                self.labels.push(label.clone());
+                let mut body = Expr::new();
+                self.seq(items, &mut body, &["end"])?;
+                self.end_label(items, "end", &label)?;
+                out.push(TryTable(block_type, catches, body));
+            }
+            "end" | "else" => bail!("{}: unexpected {}", pos, kw),
+            _ => out.push(self.op(kw, items)?),
+        }
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/text/instructions.html#folded-instructions
+    fn folded(&mut self, list: &Sexpr, out: &mut Expr) -> Result<()> {
+        let mut items = list.items()?;
+        let kw = items.keyword()?;
+        match kw {
+            "block" | "loop" => {
+                self.push_label(&mut items);
+                let block_type = self.ctx.block_type(&mut items)?;
+                let body = self.instrs(&mut items);
+                self.labels.pop();
+                out.push(if kw == "block" {
+                    Block(block_type, body?)
+                } else {
+                    Loop(block_type, body?)
+                });
+            }
+            "if" => {
+                let label = items.id().map(str::to_string);
+                self.name_label(&label);
+                let block_type = self.ctx.block_type(&mut items)?;
+                while let Some(condition) =
+                    items.next_if(|i| i.is_list() && i.head() != Some("then"))
+                {
+                    self.folded(condition, out)?;
+                }
+                self.labels.push(label);
+                let then = match items.list("then") {
+                    Some(mut then) => self.instrs(&mut then),
+                    None => Err(items.error("expected (then ...)")),
+                };
+                let else_ = items
+                    .list("else")
+                    .map(|mut else_| self.instrs(&mut else_))
+                    .transpose();
+                self.labels.pop();
+                items.end()?;
+                out.push(If(block_type, then?, else_?));
+            }
+            "try_table" => {
+                let label = items.id().map(str::to_string);
+                self.name_label(&label);
+                let block_type = self.ctx.block_type(&mut items)?;
+                let catches = self.catches(&mut items)?;
+                self.labels.push(label);
+                let body = self.instrs(&mut items);
+                self.labels.pop();
+                out.push(TryTable(block_type, catches, body?));
+            }
+            _ => {
+                let inst = self.op(kw, &mut items)?;
+                while let Some(operand) = items.next_if(Sexpr::is_list) {
+                    self.folded(operand, out)?;
+                }
+                items.end()?;
+                out.push(inst);
+            }
+        }
+        Ok(())
+    }
+
+    /// the names of the labels indexed in order of appearance
+    pub fn label_names(&self) -> &NameMap {
+        &self.label_names
+    }
+
+    fn push_label(&mut self, items: &mut Items) -> Option<String> {
+        let label = items.id().map(str::to_string);
+        self.name_label(&label);
+        self.labels.push(label.clone());
+        label
+    }
+
+    fn name_label(&mut self, label: &Option<String>) {
+        if let Some(label) = label {
+            self.label_names.insert(self.num_of_labels, label.clone());
+        }
+        self.num_of_labels += 1;
+    }
+
+    /// consume `end` followed by the optional label, which has to match the block
+    fn end_label(&mut self, items: &mut Items, kw: &str, label: &Option<String>) -> Result<()> {
+        self.check_label(items, kw, label)?;
+        self.labels.pop();
+        Ok(())
+    }
+
+    fn check_label(&mut self, items: &mut Items, kw: &str, label: &Option<String>) -> Result<()> {
+        if !items.take_keyword(kw) {
+            return Err(items.error(format!("expected {}", kw)));
+        }
+        let pos = items.pos();
+        match items.id() {
+            Some(id) if label.as_deref() != Some(id) => {
+                bail!("{}: ${} does not match the label of the block", pos, id)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// catch clauses of `try_table`, whose labels are resolved outside of it
+    /// https://webassembly.github.io/exception-handling/core/text/instructions.html#control-instructions
+    fn catches(&mut self, items: &mut Items) -> Result<Vec<Catch>> {
+        let mut catches = vec![];
+        while let Some(item) = items.next_if(|item| {
+            matches!(
+                item.head(),
+                Some("catch" | "catch_ref" | "catch_all" | "catch_all_ref")
+            )
+        }) {
+            let mut clause = item.items()?;
+            catches.push(match clause.keyword()? {
+                "catch" => {
+                    let tag = self.ctx.tags.index(&mut clause, "tag")?;
+                    Catch::Catch(tag, self.label(&mut clause)?)
+                }
+                "catch_ref" => {
+                    let tag = self.ctx.tags.index(&mut clause, "tag")?;
+                    Catch::CatchRef(tag, self.label(&mut clause)?)
+                }
+                "catch_all" => Catch::CatchAll(self.label(&mut clause)?),
+                _ => Catch::CatchAllRef(self.label(&mut clause)?),
+            });
+            clause.end()?;
+        }
+        Ok(catches)
+    }
+
+    /// https://webassembly.github.io/spec/core/text/instructions.html#labels
+    fn label(&mut self, items: &mut Items) -> Result<u32> {
+        let pos = items.pos();
+        match items.id() {
+            Some(id) => self
+                .labels
+                .iter()
+                .rev()
+                .position(|label| label.as_deref() == Some(id))
+                .map(|depth| depth as u32)
+                .with_context(|| format!("{}: unknown label ${}", pos, id)),
+            None => items.u32(),
+        }
+    }
+
+    /// an instruction other than structured ones, with its immediates
+    fn op(&mut self, kw: &str, items: &mut Items) -> Result<Instruction> {
+        let pos = items.pos();
+        Ok(match kw {
+            //Control Instructions
+            "unreachable" => Unreachable,
+            "nop" => Nop,
+            "br" => Br(self.label(items)?),
+            "br_if" => BrIf(self.label(items)?),
+            "br_table" => {
+                let mut labels = vec![self.label(items)?];
+                while items.peek().is_some_and(Sexpr::is_index) {
+                    labels.push(self.label(items)?);
+                }
+                let default = labels.pop().unwrap();
+                BrTable(labels, default)
+            }
+            "return" => Return,
+            "call" => Call(self.ctx.funcs.index(items, "func")?),
+            "call_indirect" => {
+                let table = self.table(items)?;
+                let (type_idx, _) = self.ctx.type_use(items)?;
+                CallIndirect(table, type_idx)
+            }
+            "throw" => Throw(self.ctx.tags.index(items, "tag")?),
+            "throw_ref" => ThrowRef,
+            "call_ref" => CallRef(self.type_(items)?),
+            "return_call_ref" => ReturnCallRef(self.type_(items)?),
+            "br_on_null" => BrOnNull(self.label(items)?),
+            "br_on_non_null" => BrOnNonNull(self.label(items)?),
+            "br_on_cast" | "br_on_cast_fail" => {
+                let label = self.label(items)?;
+                let from = ref_type(items, &self.ctx.type_ids)?;
+                let to = ref_type(items, &self.ctx.type_ids)?;
+                match kw {
+                    "br_on_cast" => BrOnCast(label, from, to),
+                    _ => BrOnCastFail(label, from, to),
+                }
+            }
+            "return_call" => ReturnCall(self.ctx.funcs.index(items, "func")?),
+            "return_call_indirect" => {
+                let table = self.table(items)?;
+                let (type_idx, _) = self.ctx.type_use(items)?;
+                ReturnCallIndirect(table, type_idx)
+            }
+            //Reference Instructions
+            "ref.null" => RefNull(heap_type(items, &self.ctx.type_ids)?),
+            "ref.is_null" => RefIsNull,
+            "ref.func" => RefFunc(self.ctx.funcs.index(items, "func")?),
+            "ref.as_non_null" => RefAsNonNull,
+            "ref.eq" => RefEq,
+            "ref.test" => RefTest(ref_type(items, &self.ctx.type_ids)?),
+            "ref.cast" => RefCast(ref_type(items, &self.ctx.type_ids)?),
+            "ref.i31" => RefI31,
+            "i31.get_s" => I31GetS,
+            "i31.get_u" => I31GetU,
+            "any.convert_extern" => AnyConvertExtern,
+            "extern.convert_any" => ExternConvertAny,
+            //Aggregate Instructions
+            "struct.new" => StructNew(self.type_(items)?),
+            "struct.new_default" => StructNewDefault(self.type_(items)?),
+            "struct.get" | "struct.get_s" | "struct.get_u" | "struct.set" => {
+                let type_ = self.type_(items)?;
+                let fields = self.ctx.fields.get(&type_);
+                let pos = items.pos();
+                let field = fields
+                    .with_context(|| format!("{}: type {} is not a structure type", pos, type_))?
+                    .index(items, "field")?;
+                match kw {
+                    "struct.get" => StructGet(type_, field),
+                    "struct.get_s" => StructGetS(type_, field),
+                    "struct.get_u" => StructGetU(type_, field),
+                    _ => StructSet(type_, field),
+                }
+            }
+            "array.new" => ArrayNew(self.type_(items)?),
+            "array.new_default" => ArrayNewDefault(self.type_(items)?),
+            "array.new_fixed" => ArrayNewFixed(self.type_(items)?, items.u32()?),
+            "array.new_data" => {
+                ArrayNewData(self.type_(items)?, self.ctx.datas.index(items, "data")?)
+            }
+            "array.new_elem" => {
+                ArrayNewElem(self.type_(items)?, self.ctx.elems.index(items, "elem")?)
+            }
+            "array.get" => ArrayGet(self.type_(items)?),
+            "array.get_s" => ArrayGetS(self.type_(items)?),
+            "array.get_u" => ArrayGetU(self.type_(items)?),
+            "array.set" => ArraySet(self.type_(items)?),
+            "array.len" => ArrayLen,
+            "array.fill" => ArrayFill(self.type_(items)?),
+            "array.copy" => ArrayCopy(self.type_(items)?, self.type_(items)?),
+            "array.init_data" => {
+                ArrayInitData(self.type_(items)?, self.ctx.datas.index(items, "data")?)
+            }
+            "array.init_elem" => {
+                ArrayInitElem(self.type_(items)?, self.ctx.elems.index(items, "elem")?)
+            }
+            //Parametric Instructions
+            "drop" => Drop,
+            "select" => {
+                let mut types = None;
+                while let Some(mut result) = items.list("result") {
+                    let types = types.get_or_insert_with(Vec::new);
+                    while !result.is_empty() {
+                        types.push(val_type(&mut result, &self.ctx.type_ids)?);
+                    }
+                }
+                Select(types)
+            }
+            //Variable Instructions
+            "local.get" => LocalGet(self.locals.index(items, "local")?),
+            "local.set" => LocalSet(self.locals.index(items, "local")?),
+            "local.tee" => LocalTee(self.locals.index(items, "local")?),
+            "global.get" => GlobalGet(self.ctx.globals.index(items, "global")?),
+            "global.set" => GlobalSet(self.ctx.globals.index(items, "global")?),
+            //Table Instructions
+            "table.get" => TableGet(self.table(items)?),
+            "table.set" => TableSet(self.table(items)?),
+            "table.size" => TableSize(self.table(items)?),
+            "table.grow" => TableGrow(self.table(items)?),
+            "table.fill" => TableFill(self.table(items)?),
+            "table.copy" => {
+                let dst = self.table(items)?;
+                TableCopy(dst, self.table(items)?)
+            }
+            "table.init" => {
+                let first = items.expect("an element segment")?;
+                match items.next_if(Sexpr::is_index) {
+                    Some(elem) => TableInit(
+                        self.ctx.elems.resolve(elem, "elem")?,
+                        self.ctx.tables.resolve(first, "table")?,
+                    ),
+                    None => TableInit(self.ctx.elems.resolve(first, "elem")?, 0),
+                }
+            }
+            "elem.drop" => TableDrop(self.ctx.elems.index(items, "elem")?),
+            //Memory Instructions
+            "memory.size" => MemorySize(self.memory(items)?),
+            "memory.grow" => MemoryGrow(self.memory(items)?),
+            "memory.fill" => MemoryFill(self.memory(items)?),
+            "memory.copy" => {
+                let dst = self.memory(items)?;
+                MemoryCopy(dst, self.memory(items)?)
+            }
+            "memory.init" => {
+                let first = items.expect("a data segment")?;
+                match items.next_if(Sexpr::is_index) {
+                    Some(data) => MemoryInit(
+                        self.ctx.datas.resolve(data, "data")?,
+                        self.ctx.mems.resolve(first, "memory")?,
+                    ),
+                    None => MemoryInit(self.ctx.datas.resolve(first, "data")?, 0),
+                }
+            }
+            "data.drop" => DataDrop(self.ctx.datas.index(items, "data")?),
+            //Numeric Instructions
+            "i32.const" => I32Const(items.i32()?),
+            "i64.const" => I64Const(items.i64()?),
+            //Vector Instructions
+            "v128.const" => V128Const(v128_const(items)?),
+            "i8x16.shuffle" => {
+                let mut lanes = [0; 16];
+                for lane in &mut lanes {
+                    *lane = lane_idx(items)?;
+                }
+                I8x16Shuffle(lanes)
+            }
+            //Atomic Memory Instructions
+            "atomic.fence" => AtomicFence,
+            _ => match (
+                MemoryOp::from_name(kw),
+                VectorOp::from_name(kw),
+                AtomicOp::from_name(kw),
+            ) {
+                (Some(op), _, _) => Memory(op, self.memarg(items, op.kind().width(), false)?),
+                (_, Some(op), _) => self.vector(op, items)?,
+                (_, _, Some(op)) => Atomic(op, self.memarg(items, op.kind().width(), false)?),
+                _ => numeric(kw).with_context(|| format!("{}: unknown instruction {}", pos, kw))?,
+            },
+        })
+    }
+
+    fn type_(&mut self, items: &mut Items) -> Result<u32> {
+        self.ctx.type_ids.index(items, "type")
+    }
+
+    fn table(&mut self, items: &mut Items) -> Result<u32> {
+        match items.next_if(Sexpr::is_index) {
+            Some(item) => self.ctx.tables.resolve(item, "table"),
+            None => Ok(0),
+        }
+    }
+
+    /// the optional memory index of memory instructions other than loads and stores
+    /// https://webassembly.github.io/multi-memory/core/text/instructions.html#memory-instructions
+    fn memory(&mut self, items: &mut Items) -> Result<u32> {
+        match items.next_if(Sexpr::is_index) {
+            Some(item) => self.ctx.mems.resolve(item, "memory"),
+            None => Ok(0),
+        }
+    }
+
+    /// https://webassembly.github.io/spec/core/text/instructions.html#vector-instructions
+    fn vector(&mut self, op: VectorOp, items: &mut Items) -> Result<Instruction> {
+        let kind = op.kind();
+        let lanes = kind.lanes();
+        let memarg = kind
+            .width()
+            .map(|width| self.memarg(items, width, lanes.is_some()))
+            .transpose()?;
+        let lane = lanes.map(|_| lane_idx(items)).transpose()?;
+        Ok(match (memarg, lane) {
+            (Some(memarg), Some(lane)) => VectorMemLane(op, memarg, lane),
+            (Some(memarg), None) => VectorMem(op, memarg),
+            (None, Some(lane)) => VectorLane(op, lane),
+            (None, None) => Vector(op),
+        })
+    }
+
+    /// `memidx? offset=N? align=N?`, which a lane index follows if `lane` holds
+    /// https://webassembly.github.io/multi-memory/core/text/instructions.html#memory-instructions
+    fn memarg(&mut self, items: &mut Items, width: u32, lane: bool) -> Result<MemArg> {
+        let indices = items
+            .rest()
+            .iter()
+            .take_while(|item| {
+                item.is_index()
+                    || item
+                        .keyword()
+                        .is_some_and(|kw| kw.starts_with("offset=") || kw.starts_with("align="))
+            })
+            .filter(|item| item.is_index())
+            .count();
+        let memory = if indices > lane as usize {
+            self.ctx.mems.index(items, "memory")?
+        } else {
+            0
+        };
+        let offset = items.u64_field("offset")?.unwrap_or(0);
+        let pos = items.pos();
+        let align = items.u64_field("align")?.unwrap_or(width as u64);
+        if !align.is_power_of_two() {
+            bail!("{}: alignment must be a power of two", pos)
+        }
+        Ok(MemArg {
+            align: align.trailing_zeros(),
+            offset,
+            memory,
+        })
+    }
+}
+
+fn lane_idx(items: &mut Items) -> Result<u8> {
+    let pos = items.pos();
+    let lane = items.u32()?;
+    u8::try_from(lane).with_context(|| format!("{}: invalid lane index {}", pos, lane))
+}
+
+/// lanes of the shape such as `i32x4 0 1 2 3`, in little endian
+fn v128_const(items: &mut Items) -> Result<u128> {
+    let pos = items.pos();
+    let shape = items.keyword()?;
+    let (lanes, bits) = match shape {
+        "i8x16" => (16, 8),
+        "i16x8" => (8, 16),
+        "i32x4" | "f32x4" => (4, 32),
+        "i64x2" | "f64x2" => (2, 64),
+        _ => bail!("{}: unknown vector shape {}", pos, shape),
+    };
+    let mut v = 0u128;
+    for i in 0..lanes {
+        let pos = items.pos();
+        let lane = match shape {
+            "i8x16" | "i16x8" => {
+                let n = items.i32()?;
+                if !(-(1 << (bits - 1))..1 << bits).contains(&n) {
+                    bail!("{}: invalid {} lane {}", pos, shape, n)
+                }
+                n as u128 & ((1 << bits) - 1)
+            }
+            "i32x4" => items.i32()? as u32 as u128,
+            "i64x2" => items.i64()? as u64 as u128,
+            "f32x4" => items.f32()?.to_bits() as u128,
+            _ => items.f64()?.to_bits() as u128,
+        };
+        v |= lane << (i * bits);
+    }
+    Ok(v)
+}
+
+/// numeric instructions without immediates
+fn numeric(kw: &str) -> Option<Instruction> {
+    Some(match kw {
+        "i32.eqz" => I32Eqz,
+        "i32.eq" => I32Eq,
+        "i32.ne" => I32Ne,
+        "i32.lt_s" => I32LtS,
+        "i32.lt_u" => I32LtU,
+        "i32.gt_s" => I32GtS,
+        "i32.gt_u" => I32GtU,
+        "i32.le_s" => I32LeS,
+        "i32.le_u" => I32LeU,
+        "i32.ge_s" => I32GeS,
+        "i32.ge_u" => I32GeU,
+        "i32.clz" => I32Clz,
+        "i32.ctz" => I32Ctz,
+        "i32.popcnt" => I32Popcnt,
+        "i32.add" => I32Add,
+        "i32.sub" => I32Sub,
+        "i32.mul" => I32Mul,
+        "i32.div_s" => I32DivS,
+        "i32.div_u" => I32DivU,
+        "i32.rem_s" => I32RemS,
+        "i32.rem_u" => I32RemU,
+        "i32.and" => I32And,
+        "i32.or" => I32Or,
+        "i32.xor" => I32Xor,
+        "i32.shl" => I32ShL,
+        "i32.shr_s" => I32ShrS,
+        "i32.shr_u" => I32ShrU,
+        "i32.rotl" => I32RtoL,
+        "i32.rotr" => I32RtoR,
+        "i32.extend8_s" => I32Extend8S,
+        "i32.extend16_s" => I32Extend16S,
+        "i64.add" => I64Add,
+        "i64.sub" => I64Sub,
+        "i64.mul" => I64Mul,
+        _ => return None,
+    })
+}
@@ -0,0 +1,241 @@
+use anyhow::*;
+use std::fmt;
+
+/// https://webassembly.github.io/spec/core/text/lexical.html#tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    LParen,
+    RParen,
+    Keyword(String),
+    /// unsigned or signed integers and floats are kept as written
+    Num(String),
+    /// identifiers without the leading `$`
+    Id(String),
+    String(Vec<u8>),
+    Reserved(String),
+}
+
+/// line and column of a token, both start with 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+pub fn tokenize(text: &str) -> Result<Vec<(Token, Pos)>> {
+    let mut lexer = Lexer {
+        chars: text.chars().collect(),
+        idx: 0,
+        pos: Pos { line: 1, col: 1 },
+    };
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.next_token()? {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    idx: usize,
+    pos: Pos,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.idx).copied()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.chars.get(self.idx + 1).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.idx += 1;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.col = 1;
+        } else {
+            self.pos.col += 1;
+        }
+        Some(c)
+    }
+
+    fn next_token(&mut self) -> Result<Option<(Token, Pos)>> {
+        self.skip_whitespace()?;
+        let pos = self.pos;
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let token = match c {
+            '(' => {
+                self.bump();
+                Token::LParen
+            }
+            ')' => {
+                self.bump();
+                Token::RParen
+            }
+            '"' => Token::String(self.string()?),
+            c if is_idchar(c) => {
+                let word = self.word();
+                match word.chars().next() {
+                    Some('$') if word.len() > 1 => Token::Id(word[1..].to_string()),
+                    Some('a'..='z') => Token::Keyword(word),
+                    Some('0'..='9' | '+' | '-') => Token::Num(word),
+                    _ => Token::Reserved(word),
+                }
+            }
+            c => bail!("{}: unexpected character {:?}", pos, c),
+        };
+        Ok(Some((token, pos)))
+    }
+
+    /// skip white spaces, line comments and nested block comments
+    fn skip_whitespace(&mut self) -> Result<()> {
+        loop {
+            match (self.peek(), self.peek_next()) {
+                (Some(' ' | '\t' | '\n' | '\r'), _) => {
+                    self.bump();
+                }
+                (Some(';'), Some(';')) => while !matches!(self.bump(), Some('\n') | None) {},
+                (Some('('), Some(';')) => {
+                    let pos = self.pos;
+                    let mut depth = 0;
+                    loop {
+                        match (self.bump(), self.peek()) {
+                            (Some('('), Some(';')) => {
+                                self.bump();
+                                depth += 1;
+                            }
+                            (Some(';'), Some(')')) => {
+                                self.bump();
+                                depth -= 1;
+                                if depth == 0 {
+                                    break;
+                                }
+                            }
+                            (None, _) => bail!("{}: unterminated block comment", pos),
+                            _ => (),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek().filter(|c| is_idchar(*c)) {
+            word.push(c);
+            self.bump();
+        }
+        word
+    }
+
+    /// https://webassembly.github.io/spec/core/text/values.html#strings
+    fn string(&mut self) -> Result<Vec<u8>> {
+        let pos = self.pos;
+        self.bump();
+        let mut bytes = Vec::new();
+        loop {
+            let c = self
+                .bump()
+                .with_context(|| format!("{}: unterminated string", pos))?;
+            match c {
+                '"' => return Ok(bytes),
+                '\\' => {
+                    let c = self
+                        .bump()
+                        .with_context(|| format!("{}: unterminated string", pos))?;
+                    match c {
+                        't' => bytes.push(b'\t'),
+                        'n' => bytes.push(b'\n'),
+                        'r' => bytes.push(b'\r'),
+                        '"' => bytes.push(b'"'),
+                        '\'' => bytes.push(b'\''),
+                        '\\' => bytes.push(b'\\'),
+                        'u' => {
+                            if self.bump() != Some('{') {
+                                bail!("{}: invalid unicode escape", self.pos)
+                            }
+                            let mut hex = String::new();
+                            while let Some(c) = self.bump().filter(|c| *c != '}') {
+                                hex.push(c);
+                            }
+                            let c = u32::from_str_radix(&hex.replace('_', ""), 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .with_context(|| format!("{}: invalid unicode escape", pos))?;
+                            bytes.extend(c.to_string().as_bytes());
+                        }
+                        h => {
+                            let l = self.bump().unwrap_or(' ');
+                            let byte = u8::from_str_radix(&format!("{}{}", h, l), 16)
+                                .with_context(|| format!("{}: invalid escape \\{}{}", pos, h, l))?;
+                            bytes.push(byte);
+                        }
+                    }
+                }
+                c => bytes.extend(c.to_string().as_bytes()),
+            }
+        }
+    }
+}
+
+/// https://webassembly.github.io/spec/core/text/values.html#text-idchar
+pub fn is_idchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, Pos, Token};
+
+    #[test]
+    fn tokenize_module() {
+        //Given
+        let text = r#"(module ;; comment
+  (; block (; nested ;) comment ;)
+  (func $add (param i32) "a\n\41\u{3042}" -1 0x10))"#;
+        //When
+        let tokens: Vec<Token> = tokenize(text).unwrap().into_iter().map(|t| t.0).collect();
+        //Then
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LParen,
+                Token::Keyword("module".to_string()),
+                Token::LParen,
+                Token::Keyword("func".to_string()),
+                Token::Id("add".to_string()),
+                Token::LParen,
+                Token::Keyword("param".to_string()),
+                Token::Keyword("i32".to_string()),
+                Token::RParen,
+                Token::String("a\nA\u{3042}".as_bytes().to_vec()),
+                Token::Num("-1".to_string()),
+                Token::Num("0x10".to_string()),
+                Token::RParen,
+                Token::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn token_position() {
+        let tokens = tokenize("(module\n  (func))").unwrap();
+        assert_eq!(tokens[2].1, Pos { line: 2, col: 3 });
+        assert!(tokenize("(module (; unterminated").is_err());
+        assert!(tokenize("\"unterminated").is_err());
+    }
+}
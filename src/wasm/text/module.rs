@@ -0,0 +1,804 @@
+use std::collections::HashMap;
+
+use anyhow::*;
+
+use super::{
+    instructions::FuncContext,
+    lexer::{Pos, Token},
+    sexpr::{Items, Sexpr},
+    types::{
+        global_type, index_type, is_ref_type, mem_type, params, ref_type, results, sub_type,
+        table_type, val_type,
+    },
+};
+use crate::structure::{
+    instructions::{BlockType, Expr, Instruction},
+    module::{
+        indices::TypeIdx, Body, Data, DataMode, Elem, ElemMode, Export, ExportDesc, Func, Global,
+        Import, ImportDesc, Mem, Module, NameMap, Names, Start, Table, Tag,
+    },
+    types::{
+        CompositeType, FuncType, IndexType, Limits, MemType, RefType, ResultType, Share, SubType,
+        TableType,
+    },
+};
+
+/// https://webassembly.github.io/spec/core/exec/runtime.html#page-size
+const PAGE_SIZE: usize = 65536;
+
+/// An index space with the identifiers bound to its indices
+/// https://webassembly.github.io/spec/core/text/modules.html#indices
+#[derive(Default)]
+pub struct Space {
+    ids: HashMap<String, u32>,
+    count: u32,
+}
+
+impl Space {
+    /// allocate the next index and bind `id` to it
+    pub fn define(&mut self, id: Option<&str>, pos: Pos) -> Result<u32> {
+        let idx = self.count;
+        if let Some(id) = id {
+            if self.ids.insert(id.to_string(), idx).is_some() {
+                bail!("{}: ${} is defined twice", pos, id)
+            }
+        }
+        self.count += 1;
+        Ok(idx)
+    }
+
+    /// an index given as a number or an identifier
+    pub fn resolve(&self, item: &Sexpr, kind: &str) -> Result<u32> {
+        let idx = match item {
+            Sexpr::Atom(Token::Id(id), pos) => *self
+                .ids
+                .get(id)
+                .with_context(|| format!("{}: unknown {} ${}", pos, kind, id))?,
+            Sexpr::Atom(Token::Num(_), pos) => {
+                Items::new(std::slice::from_ref(item), *pos).u32()?
+            }
+            _ => bail!("{}: expected a {} index", item.pos(), kind),
+        };
+        if idx >= self.count {
+            bail!("{}: unknown {} {}", item.pos(), kind, idx)
+        }
+        Ok(idx)
+    }
+
+    /// consume the next item as an index
+    pub fn index(&self, items: &mut Items, kind: &str) -> Result<u32> {
+        let item = items.expect(&format!("a {} index", kind))?;
+        self.resolve(item, kind)
+    }
+
+    pub fn names(&self) -> NameMap {
+        self.ids
+            .iter()
+            .map(|(id, idx)| (*idx, id.clone()))
+            .collect()
+    }
+}
+
+/// Index spaces of a module and the types including function types defined implicitly
+#[derive(Default)]
+pub struct ModuleContext {
+    types: Vec<SubType>,
+    /// the sizes of the recursive type groups
+    rec_groups: Vec<u32>,
+    pub type_ids: Space,
+    /// the fields of the structure types
+    pub fields: HashMap<TypeIdx, Space>,
+    pub funcs: Space,
+    pub tables: Space,
+    pub mems: Space,
+    pub tags: Space,
+    pub globals: Space,
+    pub elems: Space,
+    pub datas: Space,
+}
+
+impl ModuleContext {
+    /// implicit function types are the same as the ones defined alone without `sub`
+    fn find_or_add_type(&mut self, func_type: FuncType) -> TypeIdx {
+        let sub_type = SubType::from(func_type);
+        let mut start = 0;
+        for &len in &self.rec_groups {
+            if len == 1 && self.types[start as usize] == sub_type {
+                return start;
+            }
+            start += len;
+        }
+        self.add_types(vec![sub_type])
+    }
+
+    /// add a recursive type group, returning the index of its first type
+    fn add_types(&mut self, types: Vec<SubType>) -> TypeIdx {
+        let idx = self.types.len() as TypeIdx;
+        self.rec_groups.push(types.len() as u32);
+        self.types.extend(types);
+        self.type_ids.count = self.type_ids.count.max(self.types.len() as u32);
+        idx
+    }
+
+    /// the function type of `idx`
+    fn func_type(&self, idx: TypeIdx, pos: Pos) -> Result<&FuncType> {
+        match self.types[idx as usize].func() {
+            Some(func_type) => Ok(func_type),
+            None => bail!("{}: type {} is not a function type", pos, idx),
+        }
+    }
+
+    /// a type definition of the index, binding the ids of its fields
+    /// https://webassembly.github.io/gc/core/text/modules.html#types
+    fn type_def(&mut self, items: &mut Items, idx: TypeIdx) -> Result<SubType> {
+        let mut fields = Space::default();
+        let sub_type = sub_type(items, &self.type_ids, &mut fields)?;
+        if matches!(sub_type.composite, CompositeType::Struct(_)) {
+            self.fields.insert(idx, fields);
+        }
+        Ok(sub_type)
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#type-uses
+    /// returns the type index with the ids of the parameters
+    pub fn type_use(&mut self, items: &mut Items) -> Result<(TypeIdx, Vec<Option<String>>)> {
+        let pos = items.pos();
+        let explicit = match items.list("type") {
+            Some(mut type_) => {
+                let idx = self.type_ids.index(&mut type_, "type")?;
+                type_.end()?;
+                Some(idx)
+            }
+            None => None,
+        };
+        let has_inline = matches!(items.peek_head(), Some("param" | "result"));
+        let (params, ids) = params(items, &self.type_ids)?;
+        let inline = FuncType(
+            ResultType(params),
+            ResultType(results(items, &self.type_ids)?),
+        );
+        match explicit {
+            Some(idx) => {
+                let func_type = self.func_type(idx, pos)?;
+                let FuncType(ResultType(params), _) = func_type;
+                if has_inline && &inline != func_type {
+                    bail!("{}: inline function type does not match type {}", pos, idx)
+                }
+                let ids = if has_inline {
+                    ids
+                } else {
+                    vec![None; params.len()]
+                };
+                Ok((idx, ids))
+            }
+            None => Ok((self.find_or_add_type(inline), ids)),
+        }
+    }
+
+    /// https://webassembly.github.io/spec/core/text/instructions.html#control-instructions
+    pub fn block_type(&mut self, items: &mut Items) -> Result<BlockType> {
+        if items.peek_head() == Some("type") {
+            return Ok(BlockType::TypeIdx(self.type_use(items)?.0));
+        }
+        let pos = items.pos();
+        let (params, ids) = params(items, &self.type_ids)?;
+        if ids.iter().any(Option::is_some) {
+            bail!("{}: parameters of a block cannot be named", pos)
+        }
+        let mut results = results(items, &self.type_ids)?;
+        Ok(match (params.is_empty(), results.len()) {
+            (true, 0) => BlockType::Empty,
+            (true, 1) => BlockType::ValType(results.remove(0)),
+            _ => BlockType::TypeIdx(
+                self.find_or_add_type(FuncType(ResultType(params), ResultType(results))),
+            ),
+        })
+    }
+}
+
+/// https://webassembly.github.io/spec/core/text/modules.html#modules
+pub fn parse_module(id: Option<&str>, fields: &[Sexpr]) -> Result<Module<'static>> {
+    let mut ctx = ModuleContext::default();
+    // types may refer to types defined after them
+    for field in fields {
+        let mut items = field.items()?;
+        let pos = items.pos();
+        if items.take_keyword("type") {
+            ctx.type_ids.define(items.id(), pos)?;
+        } else if items.take_keyword("rec") {
+            while let Some(mut type_) = items.list("type") {
+                ctx.type_ids.define(type_.id(), pos)?;
+            }
+        }
+    }
+    for field in fields {
+        declare(&mut ctx, field)?;
+    }
+    let mut parser = ModuleParser {
+        module: Module {
+            version: 1,
+            types: vec![],
+            rec_groups: vec![],
+            funcs: vec![],
+            tables: vec![],
+            mems: vec![],
+            tags: vec![],
+            globals: vec![],
+            elems: vec![],
+            datas: vec![],
+            data_count: None,
+            start: None,
+            imports: vec![],
+            exports: vec![],
+            customs: vec![],
+            names: Names {
+                module: id.map(str::to_string),
+                ..Names::default()
+            },
+        },
+        ctx,
+        counts: Counts::default(),
+    };
+    for field in fields {
+        parser.field(field)?;
+    }
+    let ModuleParser {
+        mut module, ctx, ..
+    } = parser;
+    module.types = ctx.types;
+    module.rec_groups = ctx.rec_groups;
+    module.names.fields = ctx
+        .fields
+        .iter()
+        .map(|(idx, fields)| (*idx, fields.names()))
+        .filter(|(_, names)| !names.is_empty())
+        .collect();
+    module.names.funcs = ctx.funcs.names();
+    module.names.types = ctx.type_ids.names();
+    module.names.tables = ctx.tables.names();
+    module.names.mems = ctx.mems.names();
+    module.names.tags = ctx.tags.names();
+    module.names.globals = ctx.globals.names();
+    module.names.elems = ctx.elems.names();
+    module.names.datas = ctx.datas.names();
+    Ok(module)
+}
+
+/// the first pass binding identifiers to indices, in which explicit types are defined as well
+fn declare(ctx: &mut ModuleContext, field: &Sexpr) -> Result<()> {
+    let mut items = field.items()?;
+    let pos = items.pos();
+    let head = items.keyword()?;
+    let id = items.id();
+    let rest = items.rest();
+    let has = |head| rest.iter().any(|item| item.head() == Some(head));
+    let space = match head {
+        "type" => {
+            let sub_type = ctx.type_def(&mut items, ctx.types.len() as TypeIdx)?;
+            ctx.add_types(vec![sub_type]);
+            return items.end();
+        }
+        "rec" => {
+            let mut types = vec![];
+            while let Some(mut type_) = items.list("type") {
+                type_.id();
+                let idx = (ctx.types.len() + types.len()) as TypeIdx;
+                types.push(ctx.type_def(&mut type_, idx)?);
+                type_.end()?;
+            }
+            ctx.add_types(types);
+            return items.end();
+        }
+        "func" => &mut ctx.funcs,
+        "table" => {
+            if has("elem") {
+                ctx.elems.define(None, pos)?;
+            }
+            &mut ctx.tables
+        }
+        "memory" => {
+            if has("data") {
+                ctx.datas.define(None, pos)?;
+            }
+            &mut ctx.mems
+        }
+        "tag" => &mut ctx.tags,
+        "global" => &mut ctx.globals,
+        "elem" => &mut ctx.elems,
+        "data" => &mut ctx.datas,
+        "import" => {
+            items.name()?;
+            items.name()?;
+            let mut desc = items.expect("an import description")?.items()?;
+            let space = match desc.keyword()? {
+                "func" => &mut ctx.funcs,
+                "table" => &mut ctx.tables,
+                "memory" => &mut ctx.mems,
+                "tag" => &mut ctx.tags,
+                "global" => &mut ctx.globals,
+                kw => bail!("{}: unknown import kind {}", pos, kw),
+            };
+            space.define(desc.id(), pos)?;
+            return Ok(());
+        }
+        "export" | "start" => return Ok(()),
+        _ => bail!("{}: unknown module field {}", pos, head),
+    };
+    space.define(id, pos)?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct Counts {
+    funcs: u32,
+    tables: u32,
+    mems: u32,
+    tags: u32,
+    globals: u32,
+}
+
+/// the second pass building each field
+struct ModuleParser {
+    module: Module<'static>,
+    ctx: ModuleContext,
+    counts: Counts,
+}
+
+impl ModuleParser {
+    fn field(&mut self, field: &Sexpr) -> Result<()> {
+        let mut items = field.items()?;
+        let head = items.keyword()?;
+        if head != "start" {
+            // ids have been bound in the first pass
+            items.id();
+        }
+        match head {
+            "type" | "rec" => Ok(()),
+            "func" => self.func(&mut items),
+            "table" => self.table(&mut items),
+            "memory" => self.memory(&mut items),
+            "tag" => self.tag(&mut items),
+            "global" => self.global(&mut items),
+            "import" => self.import(&mut items),
+            "export" => self.export(&mut items),
+            "start" => self.start(&mut items),
+            "elem" => self.elem(&mut items),
+            "data" => self.data(&mut items),
+            _ => unreachable!("checked in the first pass"),
+        }
+    }
+
+    /// inline exports such as `(func (export "name") ...)`
+    fn inline_exports(
+        &mut self,
+        items: &mut Items,
+        desc: impl Fn(u32) -> ExportDesc,
+        idx: u32,
+    ) -> Result<()> {
+        while let Some(mut export) = items.list("export") {
+            let name = export.name()?;
+            export.end()?;
+            self.module.exports.push(Export {
+                name: name.into(),
+                desc: desc(idx),
+            });
+        }
+        Ok(())
+    }
+
+    /// an inline import such as `(func (import "module" "name") ...)`
+    fn inline_import(items: &mut Items) -> Result<Option<(String, String)>> {
+        match items.list("import") {
+            Some(mut import) => {
+                let names = (import.name()?, import.name()?);
+                import.end()?;
+                Ok(Some(names))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn import_or_define(
+        &mut self,
+        pos: Pos,
+        import: Option<(String, String)>,
+        desc: ImportDesc,
+    ) -> Result<bool> {
+        match import {
+            Some((module, name)) => {
+                if !self.module.funcs.is_empty()
+                    || !self.module.tables.is_empty()
+                    || !self.module.mems.is_empty()
+                    || !self.module.tags.is_empty()
+                    || !self.module.globals.is_empty()
+                {
+                    bail!("{}: imports must occur before all definitions", pos)
+                }
+                self.module.imports.push(Import {
+                    module: module.into(),
+                    name: name.into(),
+                    desc,
+                });
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#functions
+    fn func(&mut self, items: &mut Items) -> Result<()> {
+        let pos = items.pos();
+        let idx = self.counts.funcs;
+        self.counts.funcs += 1;
+        self.inline_exports(items, ExportDesc::Func, idx)?;
+        let import = Self::inline_import(items)?;
+        let (type_, param_ids) = self.ctx.type_use(items)?;
+        if self.import_or_define(pos, import, ImportDesc::Func(type_))? {
+            return items.end();
+        }
+        let mut locals = Space::default();
+        for id in &param_ids {
+            locals.define(id.as_deref(), pos)?;
+        }
+        let mut local_types = vec![];
+        while let Some(mut local) = items.list("local") {
+            let pos = local.pos();
+            if let Some(id) = local.id() {
+                local_types.push(val_type(&mut local, &self.ctx.type_ids)?);
+                locals.define(Some(id), pos)?;
+                local.end()?;
+            } else {
+                while !local.is_empty() {
+                    local_types.push(val_type(&mut local, &self.ctx.type_ids)?);
+                    locals.define(None, pos)?;
+                }
+            }
+        }
+        let local_names = locals.names();
+        let mut func = FuncContext::new(&mut self.ctx, locals);
+        let body = func.instrs(items)?;
+        let label_names = func.label_names().clone();
+        if !local_names.is_empty() {
+            self.module.names.locals.insert(idx, local_names);
+        }
+        if !label_names.is_empty() {
+            self.module.names.labels.insert(idx, label_names);
+        }
+        self.module.funcs.push(Func {
+            type_,
+            locals: local_types,
+            body: Body::from(body),
+        });
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#tables
+    fn table(&mut self, items: &mut Items) -> Result<()> {
+        let pos = items.pos();
+        let idx = self.counts.tables;
+        self.counts.tables += 1;
+        self.inline_exports(items, ExportDesc::Table, idx)?;
+        let import = Self::inline_import(items)?;
+        if is_ref_type(items) {
+            let type_ = ref_type(items, &self.ctx.type_ids)?;
+            let mut elem = items
+                .list("elem")
+                .ok_or(items.error("expected (elem ...)"))?;
+            let init = self.elem_list(&mut elem, type_, true)?;
+            items.end()?;
+            let n = init.len() as u32;
+            self.module.tables.push(Table {
+                type_: TableType(
+                    Limits {
+                        min: n as u64,
+                        max: Some(n as u64),
+                        index: IndexType::I32,
+                    },
+                    type_,
+                ),
+            });
+            self.module.elems.push(Elem {
+                type_,
+                init,
+                mode: ElemMode::Active {
+                    table: idx,
+                    offset: vec![Instruction::I32Const(0)],
+                },
+            });
+            return Ok(());
+        }
+        let type_ = table_type(items, &self.ctx.type_ids)?;
+        items.end()?;
+        if let Some(import) = import {
+            return self
+                .import_or_define(pos, Some(import), ImportDesc::Table(type_))
+                .map(|_| ());
+        }
+        self.module.tables.push(Table { type_ });
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#memories
+    fn memory(&mut self, items: &mut Items) -> Result<()> {
+        let pos = items.pos();
+        let idx = self.counts.mems;
+        self.counts.mems += 1;
+        self.inline_exports(items, ExportDesc::Mem, idx)?;
+        let import = Self::inline_import(items)?;
+        let inline_data = items
+            .rest()
+            .iter()
+            .find(|item| !matches!(item.keyword(), Some("i32" | "i64")))
+            .is_some_and(|item| item.head() == Some("data"));
+        if inline_data {
+            let index = index_type(items);
+            let mut data = items.list("data").unwrap();
+            let init = data.strings();
+            data.end()?;
+            items.end()?;
+            let pages = init.len().div_ceil(PAGE_SIZE) as u64;
+            self.module.mems.push(Mem {
+                type_: MemType(
+                    Limits {
+                        min: pages,
+                        max: Some(pages),
+                        index,
+                    },
+                    Share::Unshared,
+                ),
+            });
+            let offset = match index {
+                IndexType::I32 => Instruction::I32Const(0),
+                IndexType::I64 => Instruction::I64Const(0),
+            };
+            self.module.datas.push(Data {
+                init: init.into(),
+                mode: DataMode::Active {
+                    memory: idx,
+                    offset: vec![offset],
+                },
+            });
+            return Ok(());
+        }
+        let type_ = mem_type(items)?;
+        items.end()?;
+        if let Some(import) = import {
+            return self
+                .import_or_define(pos, Some(import), ImportDesc::Mem(type_))
+                .map(|_| ());
+        }
+        self.module.mems.push(Mem { type_ });
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/exception-handling/core/text/modules.html#tags
+    fn tag(&mut self, items: &mut Items) -> Result<()> {
+        let pos = items.pos();
+        let idx = self.counts.tags;
+        self.counts.tags += 1;
+        self.inline_exports(items, ExportDesc::Tag, idx)?;
+        let import = Self::inline_import(items)?;
+        let (type_, _) = self.ctx.type_use(items)?;
+        items.end()?;
+        if self.import_or_define(pos, import, ImportDesc::Tag(type_))? {
+            return Ok(());
+        }
+        self.module.tags.push(Tag { type_ });
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#globals
+    fn global(&mut self, items: &mut Items) -> Result<()> {
+        let pos = items.pos();
+        let idx = self.counts.globals;
+        self.counts.globals += 1;
+        self.inline_exports(items, ExportDesc::Global, idx)?;
+        let import = Self::inline_import(items)?;
+        let type_ = global_type(items, &self.ctx.type_ids)?;
+        if let Some(import) = import {
+            items.end()?;
+            return self
+                .import_or_define(pos, Some(import), ImportDesc::Global(type_))
+                .map(|_| ());
+        }
+        let init = self.const_expr(items)?;
+        self.module.globals.push(Global { type_, init });
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#imports
+    fn import(&mut self, items: &mut Items) -> Result<()> {
+        let pos = items.pos();
+        let names = (items.name()?, items.name()?);
+        let mut desc = items.expect("an import description")?.items()?;
+        items.end()?;
+        let kind = desc.keyword()?;
+        desc.id();
+        let import_desc = match kind {
+            "func" => {
+                self.counts.funcs += 1;
+                ImportDesc::Func(self.ctx.type_use(&mut desc)?.0)
+            }
+            "table" => {
+                self.counts.tables += 1;
+                ImportDesc::Table(table_type(&mut desc, &self.ctx.type_ids)?)
+            }
+            "memory" => {
+                self.counts.mems += 1;
+                ImportDesc::Mem(mem_type(&mut desc)?)
+            }
+            "global" => {
+                self.counts.globals += 1;
+                ImportDesc::Global(global_type(&mut desc, &self.ctx.type_ids)?)
+            }
+            "tag" => {
+                self.counts.tags += 1;
+                ImportDesc::Tag(self.ctx.type_use(&mut desc)?.0)
+            }
+            _ => unreachable!("checked in the first pass"),
+        };
+        desc.end()?;
+        self.import_or_define(pos, Some(names), import_desc)?;
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#exports
+    fn export(&mut self, items: &mut Items) -> Result<()> {
+        let pos = items.pos();
+        let name = items.name()?;
+        let mut desc = items.expect("an export description")?.items()?;
+        items.end()?;
+        let export_desc = match desc.keyword()? {
+            "func" => ExportDesc::Func(self.ctx.funcs.index(&mut desc, "func")?),
+            "table" => ExportDesc::Table(self.ctx.tables.index(&mut desc, "table")?),
+            "memory" => ExportDesc::Mem(self.ctx.mems.index(&mut desc, "memory")?),
+            "global" => ExportDesc::Global(self.ctx.globals.index(&mut desc, "global")?),
+            "tag" => ExportDesc::Tag(self.ctx.tags.index(&mut desc, "tag")?),
+            kw => bail!("{}: unknown export kind {}", pos, kw),
+        };
+        desc.end()?;
+        self.module.exports.push(Export {
+            name: name.into(),
+            desc: export_desc,
+        });
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#start-function
+    fn start(&mut self, items: &mut Items) -> Result<()> {
+        let pos = items.pos();
+        if self.module.start.is_some() {
+            bail!("{}: multiple start functions", pos)
+        }
+        let func = self.ctx.funcs.index(items, "func")?;
+        items.end()?;
+        self.module.start = Some(Start { func });
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#element-segments
+    fn elem(&mut self, items: &mut Items) -> Result<()> {
+        if items.take_keyword("declare") {
+            let (type_, init) = self.elem_type_and_list(items, false)?;
+            self.module.elems.push(Elem {
+                type_,
+                init,
+                mode: ElemMode::Declarative,
+            });
+            return Ok(());
+        }
+        let table = match items.list("table") {
+            Some(mut table) => {
+                let idx = self.ctx.tables.index(&mut table, "table")?;
+                table.end()?;
+                Some(idx)
+            }
+            None => None,
+        };
+        let mode = match (table, items.peek().is_some_and(Sexpr::is_list)) {
+            (None, false) => ElemMode::Passive,
+            (table, _) => ElemMode::Active {
+                table: table.unwrap_or(0),
+                offset: self.offset(items)?,
+            },
+        };
+        // function indices without `func` are allowed only for the abbreviated active segment
+        let legacy = table.is_none() && matches!(mode, ElemMode::Active { .. });
+        let (type_, init) = self.elem_type_and_list(items, legacy)?;
+        self.module.elems.push(Elem { type_, init, mode });
+        Ok(())
+    }
+
+    fn elem_type_and_list(
+        &mut self,
+        items: &mut Items,
+        legacy: bool,
+    ) -> Result<(RefType, Vec<Expr>)> {
+        if items.take_keyword("func") {
+            return Ok((
+                RefType::FUNCREF,
+                self.elem_list(items, RefType::FUNCREF, true)?,
+            ));
+        }
+        if legacy && !is_ref_type(items) {
+            return Ok((
+                RefType::FUNCREF,
+                self.elem_list(items, RefType::FUNCREF, true)?,
+            ));
+        }
+        let type_ = ref_type(items, &self.ctx.type_ids)?;
+        Ok((type_, self.elem_list(items, type_, false)?))
+    }
+
+    /// function indices if `func_indices`, otherwise `(item ...)` or folded instructions
+    fn elem_list(
+        &mut self,
+        items: &mut Items,
+        type_: RefType,
+        func_indices: bool,
+    ) -> Result<Vec<Expr>> {
+        let mut init = vec![];
+        if func_indices && items.peek().is_some_and(Sexpr::is_index) {
+            while !items.is_empty() {
+                init.push(vec![Instruction::RefFunc(
+                    self.ctx.funcs.index(items, "func")?,
+                )]);
+            }
+            return Ok(init);
+        }
+        let pos = items.pos();
+        if func_indices && type_ != RefType::FUNCREF {
+            bail!("{}: function indices require funcref", pos)
+        }
+        while let Some(item) = items.next() {
+            init.push(match item.head() {
+                Some("item") => {
+                    let mut expr = item.items()?;
+                    expr.keyword()?;
+                    self.const_expr(&mut expr)?
+                }
+                _ => FuncContext::new(&mut self.ctx, Space::default()).folded_instr(item)?,
+            });
+        }
+        Ok(init)
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#data-segments
+    fn data(&mut self, items: &mut Items) -> Result<()> {
+        let memory = match items.list("memory") {
+            Some(mut memory) => {
+                let idx = self.ctx.mems.index(&mut memory, "memory")?;
+                memory.end()?;
+                Some(idx)
+            }
+            None => None,
+        };
+        let mode = match (memory, items.peek().is_some_and(Sexpr::is_list)) {
+            (None, false) => DataMode::Passive,
+            (memory, _) => DataMode::Active {
+                memory: memory.unwrap_or(0),
+                offset: self.offset(items)?,
+            },
+        };
+        let init = items.strings();
+        items.end()?;
+        self.module.datas.push(Data {
+            init: init.into(),
+            mode,
+        });
+        Ok(())
+    }
+
+    /// `(offset instr*)` or a single folded instruction
+    fn offset(&mut self, items: &mut Items) -> Result<Expr> {
+        if let Some(mut offset) = items.list("offset") {
+            return self.const_expr(&mut offset);
+        }
+        let item = items.expect("an offset")?;
+        FuncContext::new(&mut self.ctx, Space::default()).folded_instr(item)
+    }
+
+    fn const_expr(&mut self, items: &mut Items) -> Result<Expr> {
+        FuncContext::new(&mut self.ctx, Space::default()).instrs(items)
+    }
+}
@@ -0,0 +1,1282 @@
+use std::fmt::{self, Write};
+
+use super::lexer::is_idchar;
+use crate::structure::{
+    instructions::{
+        atomic::AtomicKind, memory::MemoryKind, vector::VectorKind, BlockType, Catch, Expr,
+        Instruction, MemArg,
+    },
+    module::{
+        indices::{FuncIdx, LabelIdx, TypeIdx},
+        DataMode, Elem, ElemMode, ExportDesc, ImportDesc, Module, NameMap, Names,
+    },
+    types::{
+        CompositeType, FieldType, FuncType, GlobalType, HeapType, IndexType, Limits, MemType, Mut,
+        RefType, ResultType, Share, StorageType, SubType, ValType,
+    },
+};
+
+/// How instructions are written
+/// https://webassembly.github.io/spec/core/text/instructions.html#folded-instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Style {
+    /// one instruction per line with `end` closing blocks
+    #[default]
+    Flat,
+    /// instructions in parentheses around the ones producing their operands,
+    /// such as `(i32.add (local.get 0) (i32.const 1))`, and `if` with `(then ...)` and `(else ...)`.
+    /// Instructions after a branch are not nested, since the stack is polymorphic there.
+    Folded,
+}
+
+/// render a module in the text format, using the names of the name section as identifiers
+pub fn print(module: &Module, style: Style) -> String {
+    let mut printer = Printer::new(style, Some(module));
+    printer.module(module);
+    printer.out.push('\n');
+    printer.out
+}
+
+/// render instructions with indentation, without any identifiers
+pub fn print_expr(expr: &Expr, style: Style) -> String {
+    let mut printer = Printer::new(style, None);
+    printer.instrs(expr);
+    printer.out.trim_start().to_string()
+}
+
+/// The flat form on a single line such as `block (result i32) i32.const 1 end`
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new(Style::Flat, None);
+        printer.inline = true;
+        printer.instr(self, &[]);
+        f.write_str(printer.out.trim_start())
+    }
+}
+
+/// Names which can be written as identifiers, indexed like `Names`
+#[derive(Default)]
+struct Ids {
+    module: Option<String>,
+    funcs: NameMap,
+    locals: std::collections::BTreeMap<FuncIdx, NameMap>,
+    labels: std::collections::BTreeMap<FuncIdx, NameMap>,
+    types: NameMap,
+    fields: std::collections::BTreeMap<TypeIdx, NameMap>,
+    tables: NameMap,
+    mems: NameMap,
+    tags: NameMap,
+    globals: NameMap,
+    elems: NameMap,
+    datas: NameMap,
+}
+
+impl From<&Names> for Ids {
+    fn from(names: &Names) -> Self {
+        Self {
+            module: names.module.clone().filter(|name| is_id(name)),
+            funcs: unique_ids(&names.funcs),
+            locals: names
+                .locals
+                .iter()
+                .map(|(i, m)| (*i, unique_ids(m)))
+                .collect(),
+            // labels may shadow each other
+            labels: names
+                .labels
+                .iter()
+                .map(|(i, m)| {
+                    (
+                        *i,
+                        m.iter()
+                            .filter(|(_, n)| is_id(n))
+                            .map(|(j, n)| (*j, n.clone()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+            types: unique_ids(&names.types),
+            fields: names
+                .fields
+                .iter()
+                .map(|(i, m)| (*i, unique_ids(m)))
+                .collect(),
+            tables: unique_ids(&names.tables),
+            mems: unique_ids(&names.mems),
+            tags: unique_ids(&names.tags),
+            globals: unique_ids(&names.globals),
+            elems: unique_ids(&names.elems),
+            datas: unique_ids(&names.datas),
+        }
+    }
+}
+
+/// https://webassembly.github.io/spec/core/text/values.html#text-id
+fn is_id(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_idchar)
+}
+
+/// names which are valid identifiers, dropping the later ones of duplicated names
+fn unique_ids(names: &NameMap) -> NameMap {
+    let mut seen = std::collections::HashSet::new();
+    names
+        .iter()
+        .filter(|(_, name)| is_id(name) && seen.insert(name.as_str()))
+        .map(|(idx, name)| (*idx, name.clone()))
+        .collect()
+}
+
+/// `$id` if the index has an identifier, otherwise the index itself
+fn index(ids: &NameMap, idx: u32) -> String {
+    match ids.get(&idx) {
+        Some(id) => format!("${}", id),
+        None => idx.to_string(),
+    }
+}
+
+/// ` $id` to follow the keyword of a definition, if the index has an identifier
+fn binder(ids: &NameMap, idx: u32) -> String {
+    match ids.get(&idx) {
+        Some(id) => format!(" ${}", id),
+        None => String::new(),
+    }
+}
+
+/// An instruction with the ones before it which produce its operands,
+/// written as `(instr operands...)` in the folded form
+/// https://webassembly.github.io/spec/core/text/instructions.html#folded-instructions
+struct Node<'e> {
+    instr: &'e Instruction,
+    operands: Vec<Node<'e>>,
+    /// the number of results, which is none if the printer can't tell it
+    results: Option<usize>,
+}
+
+struct Printer<'m> {
+    out: String,
+    style: Style,
+    indent: usize,
+    /// write lines separated by spaces instead of newlines
+    inline: bool,
+    ids: Ids,
+    /// the module whose types tell the arities of instructions to fold
+    module: Option<&'m Module<'m>>,
+    /// the types of the functions including the imported ones
+    func_types: Vec<TypeIdx>,
+    locals: NameMap,
+    labels: NameMap,
+    num_of_labels: u32,
+    /// the numbers of values which branches pass to the enclosing labels, from the outermost one
+    label_arities: Vec<Option<usize>>,
+}
+
+impl<'m> Printer<'m> {
+    fn new(style: Style, module: Option<&'m Module<'m>>) -> Self {
+        let imported = module.iter().flat_map(|module| {
+            module
+                .imports
+                .iter()
+                .filter_map(|import| match import.desc {
+                    ImportDesc::Func(type_) => Some(type_),
+                    _ => None,
+                })
+        });
+        let defined = module
+            .iter()
+            .flat_map(|module| module.funcs.iter().map(|f| f.type_));
+        Self {
+            out: String::new(),
+            style,
+            indent: 0,
+            inline: false,
+            ids: module.map_or_else(Ids::default, |module| Ids::from(&module.names)),
+            module,
+            func_types: imported.chain(defined).collect(),
+            locals: NameMap::new(),
+            labels: NameMap::new(),
+            num_of_labels: 0,
+            label_arities: vec![],
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        if self.inline {
+            self.out.push(' ');
+        } else {
+            self.out.push('\n');
+            self.out.push_str(&"  ".repeat(self.indent));
+        }
+        self.out.push_str(text);
+    }
+
+    fn close(&mut self) {
+        self.out.push(')');
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#modules
+    fn module(&mut self, module: &Module) {
+        self.out.push_str("(module");
+        if let Some(id) = &self.ids.module {
+            write!(self.out, " ${}", id).unwrap();
+        }
+        self.indent += 1;
+        let mut start = 0;
+        for &len in &module.rec_groups {
+            let group = start..(start + len).min(module.types.len() as u32);
+            start += len;
+            if len != 1 {
+                self.line("(rec");
+                self.indent += 1;
+            }
+            for idx in group {
+                let type_ = format!(
+                    "(type{} {})",
+                    binder(&self.ids.types, idx),
+                    self.sub_type(idx, &module.types[idx as usize])
+                );
+                self.line(&type_);
+            }
+            if len != 1 {
+                self.indent -= 1;
+                self.close();
+            }
+        }
+        let mut counts = [0u32; 5];
+        for import in &module.imports {
+            let desc = match &import.desc {
+                ImportDesc::Func(type_) => {
+                    let idx = next(&mut counts[0]);
+                    let func_type = module.func_type(*type_);
+                    format!(
+                        "func{} (type {}){}",
+                        binder(&self.ids.funcs, idx),
+                        index(&self.ids.types, *type_),
+                        func_type.map_or(String::new(), |t| signature(t, &NameMap::new()))
+                    )
+                }
+                ImportDesc::Table(type_) => {
+                    let idx = next(&mut counts[1]);
+                    format!(
+                        "table{} {} {}",
+                        binder(&self.ids.tables, idx),
+                        limits(&type_.0),
+                        type_.1
+                    )
+                }
+                ImportDesc::Mem(type_) => {
+                    let idx = next(&mut counts[2]);
+                    format!("memory{} {}", binder(&self.ids.mems, idx), mem_type(type_))
+                }
+                ImportDesc::Global(type_) => {
+                    let idx = next(&mut counts[3]);
+                    format!(
+                        "global{} {}",
+                        binder(&self.ids.globals, idx),
+                        global_type(type_)
+                    )
+                }
+                ImportDesc::Tag(type_) => {
+                    let idx = next(&mut counts[4]);
+                    self.tag(module, idx, *type_)
+                }
+            };
+            let import = format!(
+                "(import {} {} ({}))",
+                string(import.module.as_bytes()),
+                string(import.name.as_bytes()),
+                desc
+            );
+            self.line(&import);
+        }
+        for func in &module.funcs {
+            let idx = next(&mut counts[0]);
+            self.locals = self.ids.locals.remove(&idx).unwrap_or_default();
+            self.labels = self.ids.labels.remove(&idx).unwrap_or_default();
+            self.num_of_labels = 0;
+            let func_type = module.func_type(func.type_);
+            self.label_arities = vec![func_type.map(|FuncType(_, ResultType(r))| r.len())];
+            let head = format!(
+                "(func{} (type {}){}",
+                binder(&self.ids.funcs, idx),
+                index(&self.ids.types, func.type_),
+                func_type.map_or(String::new(), |t| signature(t, &self.locals))
+            );
+            self.line(&head);
+            self.indent += 1;
+            let num_of_params = func_type.map_or(0, |FuncType(ResultType(params), _)| params.len());
+            for (i, local) in func.locals.iter().enumerate() {
+                let local = match self.locals.get(&((num_of_params + i) as u32)) {
+                    Some(id) => format!("(local ${} {})", id, local),
+                    None => format!("(local {})", local),
+                };
+                self.line(&local);
+            }
+            match func.body.expr() {
+                Ok(expr) => self.instrs(expr),
+                Err(e) => self.line(&format!("(; {} ;)", e)),
+            }
+            self.indent -= 1;
+            self.close();
+        }
+        self.locals = NameMap::new();
+        for table in &module.tables {
+            let idx = next(&mut counts[1]);
+            let table = format!(
+                "(table{} {} {})",
+                binder(&self.ids.tables, idx),
+                limits(&table.type_.0),
+                table.type_.1
+            );
+            self.line(&table);
+        }
+        for mem in &module.mems {
+            let idx = next(&mut counts[2]);
+            let mem = format!(
+                "(memory{} {})",
+                binder(&self.ids.mems, idx),
+                mem_type(&mem.type_)
+            );
+            self.line(&mem);
+        }
+        for tag in &module.tags {
+            let idx = next(&mut counts[4]);
+            let tag = format!("({})", self.tag(module, idx, tag.type_));
+            self.line(&tag);
+        }
+        for global in &module.globals {
+            let idx = next(&mut counts[3]);
+            let head = format!(
+                "(global{} {}",
+                binder(&self.ids.globals, idx),
+                global_type(&global.type_)
+            );
+            self.line(&head);
+            self.const_expr(&global.init);
+            self.close();
+        }
+        for export in &module.exports {
+            let desc = match export.desc {
+                ExportDesc::Func(idx) => format!("func {}", index(&self.ids.funcs, idx)),
+                ExportDesc::Table(idx) => format!("table {}", index(&self.ids.tables, idx)),
+                ExportDesc::Mem(idx) => format!("memory {}", index(&self.ids.mems, idx)),
+                ExportDesc::Global(idx) => format!("global {}", index(&self.ids.globals, idx)),
+                ExportDesc::Tag(idx) => format!("tag {}", index(&self.ids.tags, idx)),
+            };
+            let export = format!("(export {} ({}))", string(export.name.as_bytes()), desc);
+            self.line(&export);
+        }
+        if let Some(start) = &module.start {
+            let start = format!("(start {})", index(&self.ids.funcs, start.func));
+            self.line(&start);
+        }
+        for (idx, elem) in module.elems.iter().enumerate() {
+            self.elem(idx as u32, elem);
+        }
+        for (idx, data) in module.datas.iter().enumerate() {
+            let head = format!("(data{}", binder(&self.ids.datas, idx as u32));
+            self.line(&head);
+            if let DataMode::Active { memory, offset } = &data.mode {
+                write!(
+                    self.out,
+                    " (memory {}) (offset",
+                    index(&self.ids.mems, *memory)
+                )
+                .unwrap();
+                self.const_expr(offset);
+                self.close();
+            }
+            write!(self.out, " {})", string(&data.init)).unwrap();
+        }
+        self.indent -= 1;
+        self.close();
+    }
+
+    /// https://webassembly.github.io/spec/core/text/modules.html#element-segments
+    fn elem(&mut self, idx: u32, elem: &Elem) {
+        let head = format!("(elem{}", binder(&self.ids.elems, idx));
+        self.line(&head);
+        match &elem.mode {
+            ElemMode::Passive => {}
+            ElemMode::Declarative => self.out.push_str(" declare"),
+            ElemMode::Active { table, offset } => {
+                write!(
+                    self.out,
+                    " (table {}) (offset",
+                    index(&self.ids.tables, *table)
+                )
+                .unwrap();
+                self.const_expr(offset);
+                self.close();
+            }
+        }
+        let funcs: Option<Vec<u32>> = elem
+            .init
+            .iter()
+            .map(|expr| match expr.as_slice() {
+                [Instruction::RefFunc(idx)] => Some(*idx),
+                _ => None,
+            })
+            .collect();
+        match funcs.filter(|_| elem.type_ == RefType::FUNCREF) {
+            Some(funcs) => {
+                self.out.push_str(" func");
+                for func in funcs {
+                    write!(self.out, " {}", index(&self.ids.funcs, func)).unwrap();
+                }
+            }
+            None => {
+                write!(self.out, " {}", elem.type_).unwrap();
+                for expr in &elem.init {
+                    self.out.push_str(" (item");
+                    self.const_expr(expr);
+                    self.close();
+                }
+            }
+        }
+        self.close();
+    }
+
+    /// instructions on the current line
+    fn const_expr(&mut self, expr: &Expr) {
+        let inline = std::mem::replace(&mut self.inline, true);
+        self.instrs(expr);
+        self.inline = inline;
+    }
+
+    /// `tag $id (type N) (param ...)` shared by tag definitions and imports
+    fn tag(&self, module: &Module, idx: u32, type_: TypeIdx) -> String {
+        let func_type = module.func_type(type_);
+        format!(
+            "tag{} (type {}){}",
+            binder(&self.ids.tags, idx),
+            index(&self.ids.types, type_),
+            func_type.map_or(String::new(), |t| signature(t, &NameMap::new()))
+        )
+    }
+
+    /// https://webassembly.github.io/gc/core/text/types.html#recursive-types
+    fn sub_type(&self, idx: TypeIdx, sub_type: &SubType) -> String {
+        let composite = match &sub_type.composite {
+            CompositeType::Func(func_type) => {
+                format!("(func{})", signature(func_type, &NameMap::new()))
+            }
+            CompositeType::Struct(fields) => {
+                let ids = self.ids.fields.get(&idx);
+                let fields: String = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| {
+                        let id = ids.map_or(String::new(), |ids| binder(ids, i as u32));
+                        format!(" (field{} {})", id, self.field_type(field))
+                    })
+                    .collect();
+                format!("(struct{})", fields)
+            }
+            CompositeType::Array(field) => format!("(array {})", self.field_type(field)),
+        };
+        if sub_type.is_final && sub_type.supers.is_empty() {
+            return composite;
+        }
+        let supers: String = sub_type
+            .supers
+            .iter()
+            .map(|sup| format!(" {}", index(&self.ids.types, *sup)))
+            .collect();
+        let final_ = if sub_type.is_final { " final" } else { "" };
+        format!("(sub{}{} {})", final_, supers, composite)
+    }
+
+    fn field_type(&self, FieldType(mutability, storage): &FieldType) -> String {
+        let storage = match storage {
+            StorageType::Val(val_type) => self.val_type(*val_type),
+            StorageType::Packed(_) => storage.to_string(),
+        };
+        match mutability {
+            Mut::Const => storage,
+            Mut::Var => format!("(mut {})", storage),
+        }
+    }
+
+    /// value types referring to the types by their ids
+    fn val_type(&self, val_type: ValType) -> String {
+        match val_type {
+            ValType::Ref(ref_type) => self.ref_type(ref_type),
+            _ => val_type.to_string(),
+        }
+    }
+
+    fn ref_type(&self, ref_type: RefType) -> String {
+        match ref_type {
+            RefType {
+                nullable,
+                heap: HeapType::Type(idx),
+            } => {
+                let null = if nullable { "null " } else { "" };
+                format!("(ref {}{})", null, index(&self.ids.types, idx))
+            }
+            _ => ref_type.to_string(),
+        }
+    }
+
+    fn field(&self, type_: TypeIdx, field: u32) -> String {
+        match self.ids.fields.get(&type_) {
+            Some(ids) => index(ids, field),
+            None => field.to_string(),
+        }
+    }
+
+    fn instrs(&mut self, expr: &Expr) {
+        match self.style {
+            Style::Flat => {
+                for instr in expr {
+                    self.instr(instr, &[]);
+                }
+            }
+            Style::Folded => {
+                for node in self.fold(expr) {
+                    self.node(&node);
+                }
+            }
+        }
+    }
+
+    /// nest the instructions producing the operands of each instruction into it,
+    /// until the stack becomes polymorphic
+    fn fold<'e>(&self, expr: &'e Expr) -> Vec<Node<'e>> {
+        let mut nodes = Vec::<Node>::new();
+        let mut polymorphic = false;
+        for instr in expr {
+            let arity = self.arity(instr).filter(|_| !polymorphic);
+            let mut node = Node {
+                instr,
+                operands: vec![],
+                results: arity.map(|(_, results)| results),
+            };
+            if let Some((params, _)) = arity {
+                // the nodes on the top which produce exactly the operands
+                let (mut start, mut count) = (nodes.len(), 0);
+                while let Some(n) = start.checked_sub(1).and_then(|i| nodes[i].results) {
+                    if n == 0 || count + n > params {
+                        break;
+                    }
+                    count += n;
+                    start -= 1;
+                }
+                if count == params {
+                    node.operands = nodes.split_off(start);
+                }
+            }
+            polymorphic |= is_polymorphic(instr);
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    fn node(&mut self, node: &Node) {
+        self.instr(node.instr, &node.operands);
+    }
+
+    /// `(instr operands...)` on a single line, if the operands have no operands or blocks
+    fn inline_node(&self, instr: &Instruction, operands: &[Node]) -> Option<String> {
+        let is_leaf = |node: &Node| node.operands.is_empty() && !is_structured(node.instr);
+        if is_structured(instr) || !operands.iter().all(is_leaf) {
+            return None;
+        }
+        let mut text = format!("({}", self.plain(instr));
+        for operand in operands {
+            write!(text, " ({})", self.plain(operand.instr)).unwrap();
+        }
+        text.push(')');
+        Some(text)
+    }
+
+    /// the numbers of the operands which can be folded into an instruction and of its results,
+    /// or none if they depend on what the printer doesn't know
+    /// or the instruction makes the stack polymorphic
+    fn arity(&self, instr: &Instruction) -> Option<(usize, usize)> {
+        use Instruction::*;
+        Some(match instr {
+            // the parameters of blocks can't be folded, while the condition of `if` can be
+            Block(block_type, _) | Loop(block_type, _) | TryTable(block_type, ..) => {
+                (0, self.block_arity(block_type)?.1)
+            }
+            If(block_type, ..) => (1, self.block_arity(block_type)?.1),
+            Nop | DataDrop(_) | TableDrop(_) | AtomicFence => (0, 0),
+            BrIf(l) => {
+                let n = self.label_arity(*l)?;
+                (n + 1, n)
+            }
+            Call(f) => self.signature(*self.func_types.get(*f as usize)?)?,
+            CallIndirect(_, t) | CallRef(t) => {
+                let (params, results) = self.signature(*t)?;
+                (params + 1, results)
+            }
+            I32Const(_) | I64Const(_) | V128Const(_) | LocalGet(_) | GlobalGet(_) | RefNull(_)
+            | RefFunc(_) | TableSize(_) | MemorySize(_) | StructNewDefault(_) => (0, 1),
+            LocalTee(_) | TableGet(_) | MemoryGrow(_) | RefIsNull | RefAsNonNull | RefTest(_)
+            | RefCast(_) | StructGet(..) | StructGetS(..) | StructGetU(..) | ArrayNewDefault(_)
+            | ArrayLen | AnyConvertExtern | ExternConvertAny | RefI31 | I31GetS | I31GetU
+            | I32Eqz | I32Clz | I32Ctz | I32Popcnt | I32Extend8S | I32Extend16S => (1, 1),
+            Drop | LocalSet(_) | GlobalSet(_) => (1, 0),
+            TableGrow(_) | RefEq | ArrayNew(_) | ArrayNewData(..) | ArrayNewElem(..)
+            | ArrayGet(_) | ArrayGetS(_) | ArrayGetU(_) | I8x16Shuffle(_) => (2, 1),
+            TableSet(_) | StructSet(..) => (2, 0),
+            Select(_) => (3, 1),
+            TableFill(_) | TableCopy(..) | TableInit(..) | MemoryFill(_) | MemoryCopy(..)
+            | MemoryInit(..) | ArraySet(_) => (3, 0),
+            ArrayFill(_) | ArrayInitData(..) | ArrayInitElem(..) => (4, 0),
+            ArrayCopy(..) => (5, 0),
+            ArrayNewFixed(_, n) => (*n as usize, 1),
+            StructNew(t) => match &self.module?.types.get(*t as usize)?.composite {
+                CompositeType::Struct(fields) => (fields.len(), 1),
+                _ => return None,
+            },
+            Memory(op, _) => match op.kind() {
+                MemoryKind::Load(..) => (1, 1),
+                MemoryKind::Store(..) => (2, 0),
+            },
+            Vector(op) | VectorLane(op, _) | VectorMem(op, _) | VectorMemLane(op, ..) => {
+                match op.kind() {
+                    VectorKind::Store | VectorKind::StoreLane(_) => (2, 0),
+                    VectorKind::Load(_)
+                    | VectorKind::ExtractLane(..)
+                    | VectorKind::Splat(_)
+                    | VectorKind::Unary
+                    | VectorKind::Test => (1, 1),
+                    VectorKind::LoadLane(_)
+                    | VectorKind::ReplaceLane(..)
+                    | VectorKind::Binary
+                    | VectorKind::Shift => (2, 1),
+                    VectorKind::Ternary => (3, 1),
+                }
+            }
+            Atomic(op, _) => match op.kind() {
+                AtomicKind::Load(..) => (1, 1),
+                AtomicKind::Store(..) => (2, 0),
+                AtomicKind::Notify | AtomicKind::Rmw(..) => (2, 1),
+                AtomicKind::Wait(_) | AtomicKind::Cmpxchg(..) => (3, 1),
+            },
+            _ if numeric(instr).is_some() => (2, 1),
+            _ => return None,
+        })
+    }
+
+    /// the numbers of the parameters and the results of a function type
+    fn signature(&self, type_: TypeIdx) -> Option<(usize, usize)> {
+        let FuncType(ResultType(params), ResultType(results)) = self.module?.func_type(type_)?;
+        Some((params.len(), results.len()))
+    }
+
+    fn block_arity(&self, block_type: &BlockType) -> Option<(usize, usize)> {
+        match block_type {
+            BlockType::Empty => Some((0, 0)),
+            BlockType::ValType(_) => Some((0, 1)),
+            BlockType::TypeIdx(idx) => self.signature(*idx),
+        }
+    }
+
+    fn label_arity(&self, label: LabelIdx) -> Option<usize> {
+        let depth = self.label_arities.len().checked_sub(label as usize + 1)?;
+        self.label_arities[depth]
+    }
+
+    /// https://webassembly.github.io/spec/core/text/instructions.html
+    fn instr(&mut self, instr: &Instruction, operands: &[Node]) {
+        let mut catches = &[][..];
+        let (kw, block_type, body, else_) = match instr {
+            Instruction::Block(block_type, body) => ("block", block_type, body, None),
+            Instruction::Loop(block_type, body) => ("loop", block_type, body, None),
+            Instruction::If(block_type, then, else_) => ("if", block_type, then, else_.as_ref()),
+            Instruction::TryTable(block_type, catches_, body) => {
+                catches = catches_;
+                ("try_table", block_type, body, None)
+            }
+            _ => {
+                match self.style {
+                    Style::Flat => self.line(&self.plain(instr)),
+                    Style::Folded => match self.inline_node(instr, operands) {
+                        Some(text) => self.line(&text),
+                        None => {
+                            self.line(&format!("({}", self.plain(instr)));
+                            self.nodes(operands);
+                            self.close();
+                        }
+                    },
+                }
+                return;
+            }
+        };
+        let label = self
+            .labels
+            .get(&self.num_of_labels)
+            .map(|id| format!(" ${}", id))
+            .unwrap_or_default();
+        self.num_of_labels += 1;
+        let mut head = format!("{}{}{}", kw, label, self.block_type(block_type));
+        for catch in catches {
+            let tag = catch
+                .tag()
+                .map(|tag| format!(" {}", index(&self.ids.tags, tag)))
+                .unwrap_or_default();
+            let kw = match catch {
+                Catch::Catch(..) => "catch",
+                Catch::CatchRef(..) => "catch_ref",
+                Catch::CatchAll(_) => "catch_all",
+                Catch::CatchAllRef(_) => "catch_all_ref",
+            };
+            write!(head, " ({}{} {})", kw, tag, catch.label()).unwrap();
+        }
+        // branches in a loop continue it, taking its parameters
+        let arity = self.block_arity(block_type);
+        let label_arity = match instr {
+            Instruction::Loop(..) => arity.map(|(params, _)| params),
+            _ => arity.map(|(_, results)| results),
+        };
+        self.label_arities.push(label_arity);
+        match self.style {
+            Style::Flat => {
+                self.line(&head);
+                self.nested(body);
+                if let Some(else_) = else_ {
+                    self.line("else");
+                    self.nested(else_);
+                }
+                self.line("end");
+            }
+            Style::Folded if kw == "if" => {
+                self.line(&format!("({}", head));
+                self.indent += 1;
+                for operand in operands {
+                    self.node(operand);
+                }
+                self.line("(then");
+                self.nested(body);
+                self.close();
+                if let Some(else_) = else_ {
+                    self.line("(else");
+                    self.nested(else_);
+                    self.close();
+                }
+                self.indent -= 1;
+                self.close();
+            }
+            Style::Folded => {
+                self.line(&format!("({}", head));
+                self.nested(body);
+                self.close();
+            }
+        }
+        self.label_arities.pop();
+    }
+
+    /// folded operands on their own lines
+    fn nodes(&mut self, nodes: &[Node]) {
+        self.indent += 1;
+        for node in nodes {
+            self.node(node);
+        }
+        self.indent -= 1;
+    }
+
+    fn nested(&mut self, expr: &Expr) {
+        self.indent += 1;
+        self.instrs(expr);
+        self.indent -= 1;
+    }
+
+    fn block_type(&self, block_type: &BlockType) -> String {
+        match block_type {
+            BlockType::Empty => String::new(),
+            BlockType::ValType(val) => format!(" (result {})", val),
+            BlockType::TypeIdx(idx) => format!(" (type {})", index(&self.ids.types, *idx)),
+        }
+    }
+
+    /// an instruction other than structured ones with its immediates
+    fn plain(&self, instr: &Instruction) -> String {
+        use Instruction::*;
+        let ids = &self.ids;
+        match instr {
+            Unreachable => "unreachable".into(),
+            Nop => "nop".into(),
+            Br(l) => format!("br {}", l),
+            BrIf(l) => format!("br_if {}", l),
+            BrTable(labels, default) => {
+                let labels: Vec<String> =
+                    labels.iter().chain([default]).map(u32::to_string).collect();
+                format!("br_table {}", labels.join(" "))
+            }
+            Return => "return".into(),
+            Call(f) => format!("call {}", index(&ids.funcs, *f)),
+            CallIndirect(t, ty) => format!(
+                "call_indirect {} (type {})",
+                index(&ids.tables, *t),
+                index(&ids.types, *ty)
+            ),
+            ReturnCall(f) => format!("return_call {}", index(&ids.funcs, *f)),
+            ReturnCallIndirect(t, ty) => format!(
+                "return_call_indirect {} (type {})",
+                index(&ids.tables, *t),
+                index(&ids.types, *ty)
+            ),
+            RefNull(HeapType::Type(t)) => format!("ref.null {}", index(&ids.types, *t)),
+            RefNull(heap) => format!("ref.null {}", heap),
+            RefAsNonNull => "ref.as_non_null".into(),
+            CallRef(t) => format!("call_ref {}", index(&ids.types, *t)),
+            ReturnCallRef(t) => format!("return_call_ref {}", index(&ids.types, *t)),
+            BrOnNull(l) => format!("br_on_null {}", l),
+            BrOnNonNull(l) => format!("br_on_non_null {}", l),
+            BrOnCast(l, from, to) => format!(
+                "br_on_cast {} {} {}",
+                l,
+                self.ref_type(*from),
+                self.ref_type(*to)
+            ),
+            BrOnCastFail(l, from, to) => format!(
+                "br_on_cast_fail {} {} {}",
+                l,
+                self.ref_type(*from),
+                self.ref_type(*to)
+            ),
+            RefEq => "ref.eq".into(),
+            RefTest(r) => format!("ref.test {}", self.ref_type(*r)),
+            RefCast(r) => format!("ref.cast {}", self.ref_type(*r)),
+            StructNew(t) => format!("struct.new {}", index(&ids.types, *t)),
+            StructNewDefault(t) => format!("struct.new_default {}", index(&ids.types, *t)),
+            StructGet(t, f) => format!(
+                "struct.get {} {}",
+                index(&ids.types, *t),
+                self.field(*t, *f)
+            ),
+            StructGetS(t, f) => format!(
+                "struct.get_s {} {}",
+                index(&ids.types, *t),
+                self.field(*t, *f)
+            ),
+            StructGetU(t, f) => format!(
+                "struct.get_u {} {}",
+                index(&ids.types, *t),
+                self.field(*t, *f)
+            ),
+            StructSet(t, f) => format!(
+                "struct.set {} {}",
+                index(&ids.types, *t),
+                self.field(*t, *f)
+            ),
+            ArrayNew(t) => format!("array.new {}", index(&ids.types, *t)),
+            ArrayNewDefault(t) => format!("array.new_default {}", index(&ids.types, *t)),
+            ArrayNewFixed(t, n) => format!("array.new_fixed {} {}", index(&ids.types, *t), n),
+            ArrayNewData(t, d) => format!(
+                "array.new_data {} {}",
+                index(&ids.types, *t),
+                index(&ids.datas, *d)
+            ),
+            ArrayNewElem(t, e) => format!(
+                "array.new_elem {} {}",
+                index(&ids.types, *t),
+                index(&ids.elems, *e)
+            ),
+            ArrayGet(t) => format!("array.get {}", index(&ids.types, *t)),
+            ArrayGetS(t) => format!("array.get_s {}", index(&ids.types, *t)),
+            ArrayGetU(t) => format!("array.get_u {}", index(&ids.types, *t)),
+            ArraySet(t) => format!("array.set {}", index(&ids.types, *t)),
+            ArrayLen => "array.len".into(),
+            ArrayFill(t) => format!("array.fill {}", index(&ids.types, *t)),
+            ArrayCopy(d, s) => format!(
+                "array.copy {} {}",
+                index(&ids.types, *d),
+                index(&ids.types, *s)
+            ),
+            ArrayInitData(t, d) => format!(
+                "array.init_data {} {}",
+                index(&ids.types, *t),
+                index(&ids.datas, *d)
+            ),
+            ArrayInitElem(t, e) => format!(
+                "array.init_elem {} {}",
+                index(&ids.types, *t),
+                index(&ids.elems, *e)
+            ),
+            AnyConvertExtern => "any.convert_extern".into(),
+            ExternConvertAny => "extern.convert_any".into(),
+            RefI31 => "ref.i31".into(),
+            I31GetS => "i31.get_s".into(),
+            I31GetU => "i31.get_u".into(),
+            Throw(x) => format!("throw {}", index(&ids.tags, *x)),
+            ThrowRef => "throw_ref".into(),
+            RefIsNull => "ref.is_null".into(),
+            RefFunc(f) => format!("ref.func {}", index(&ids.funcs, *f)),
+            Drop => "drop".into(),
+            Select(None) => "select".into(),
+            Select(Some(types)) => {
+                let types: Vec<String> = types.iter().map(ValType::to_string).collect();
+                format!("select (result {})", types.join(" "))
+            }
+            LocalGet(l) => format!("local.get {}", index(&self.locals, *l)),
+            LocalSet(l) => format!("local.set {}", index(&self.locals, *l)),
+            LocalTee(l) => format!("local.tee {}", index(&self.locals, *l)),
+            GlobalGet(g) => format!("global.get {}", index(&ids.globals, *g)),
+            GlobalSet(g) => format!("global.set {}", index(&ids.globals, *g)),
+            TableGet(t) => format!("table.get {}", index(&ids.tables, *t)),
+            TableSet(t) => format!("table.set {}", index(&ids.tables, *t)),
+            TableInit(e, t) => format!(
+                "table.init {} {}",
+                index(&ids.tables, *t),
+                index(&ids.elems, *e)
+            ),
+            TableDrop(e) => format!("elem.drop {}", index(&ids.elems, *e)),
+            TableCopy(d, s) => format!(
+                "table.copy {} {}",
+                index(&ids.tables, *d),
+                index(&ids.tables, *s)
+            ),
+            TableGrow(t) => format!("table.grow {}", index(&ids.tables, *t)),
+            TableSize(t) => format!("table.size {}", index(&ids.tables, *t)),
+            TableFill(t) => format!("table.fill {}", index(&ids.tables, *t)),
+            Memory(op, m) => format!(
+                "{}{}",
+                op.name(),
+                memarg(&ids.mems, m, Some(op.kind().width()))
+            ),
+            MemorySize(m) => format!("memory.size{}", memory(&ids.mems, *m)),
+            MemoryGrow(m) => format!("memory.grow{}", memory(&ids.mems, *m)),
+            MemoryFill(m) => format!("memory.fill{}", memory(&ids.mems, *m)),
+            MemoryCopy(0, 0) => "memory.copy".into(),
+            MemoryCopy(d, s) => format!(
+                "memory.copy {} {}",
+                index(&ids.mems, *d),
+                index(&ids.mems, *s)
+            ),
+            MemoryInit(d, m) => format!(
+                "memory.init{} {}",
+                memory(&ids.mems, *m),
+                index(&ids.datas, *d)
+            ),
+            DataDrop(d) => format!("data.drop {}", index(&ids.datas, *d)),
+            I32Const(n) => format!("i32.const {}", n),
+            I64Const(n) => format!("i64.const {}", n),
+            V128Const(v) => {
+                let lanes: Vec<String> = (0..4)
+                    .map(|i| format!("{:#010x}", (v >> (i * 32)) as u32))
+                    .collect();
+                format!("v128.const i32x4 {}", lanes.join(" "))
+            }
+            I8x16Shuffle(lanes) => {
+                let lanes: Vec<String> = lanes.iter().map(u8::to_string).collect();
+                format!("i8x16.shuffle {}", lanes.join(" "))
+            }
+            Vector(op) => op.name().into(),
+            VectorLane(op, lane) => format!("{} {}", op.name(), lane),
+            VectorMem(op, m) => format!("{}{}", op.name(), memarg(&ids.mems, m, op.kind().width())),
+            VectorMemLane(op, m, lane) => {
+                format!(
+                    "{}{} {}",
+                    op.name(),
+                    memarg(&ids.mems, m, op.kind().width()),
+                    lane
+                )
+            }
+            AtomicFence => "atomic.fence".into(),
+            Atomic(op, m) => format!(
+                "{}{}",
+                op.name(),
+                memarg(&ids.mems, m, Some(op.kind().width()))
+            ),
+            Else => "else".into(),
+            End => "end".into(),
+            Void => "(; void ;)".into(),
+            _ => numeric(instr).unwrap_or("(; unknown ;)").into(),
+        }
+    }
+}
+
+/// ` memidx` omitted for the first memory, as the MVP has no memory index
+fn memory(mems: &NameMap, idx: u32) -> String {
+    match idx {
+        0 => String::new(),
+        _ => format!(" {}", index(mems, idx)),
+    }
+}
+
+/// ` memidx offset=N align=N` omitting the defaults, where the natural alignment is `width`
+fn memarg(mems: &NameMap, memarg: &MemArg, width: Option<u32>) -> String {
+    let mut text = memory(mems, memarg.memory);
+    if memarg.offset != 0 {
+        write!(text, " offset={}", memarg.offset).unwrap();
+    }
+    match 1u64.checked_shl(memarg.align) {
+        Some(align) if Some(align) == width.map(u64::from) => {}
+        Some(align) => write!(text, " align={}", align).unwrap(),
+        None => write!(text, " align=2**{}", memarg.align).unwrap(),
+    }
+    text
+}
+
+fn is_structured(instr: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(instr, Block(..) | Loop(..) | If(..) | TryTable(..))
+}
+
+/// instructions after which any values can be on the stack, as the rest of the block is unreachable
+/// https://webassembly.github.io/spec/core/valid/instructions.html#polymorphism
+fn is_polymorphic(instr: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instr,
+        Unreachable
+            | Br(_)
+            | BrTable(..)
+            | Return
+            | ReturnCall(_)
+            | ReturnCallIndirect(..)
+            | ReturnCallRef(_)
+            | Throw(_)
+            | ThrowRef
+    )
+}
+
+fn next(count: &mut u32) -> u32 {
+    *count += 1;
+    *count - 1
+}
+
+/// numeric instructions without immediates
+fn numeric(instr: &Instruction) -> Option<&'static str> {
+    use Instruction::*;
+    Some(match instr {
+        I32Eqz => "i32.eqz",
+        I32Eq => "i32.eq",
+        I32Ne => "i32.ne",
+        I32LtS => "i32.lt_s",
+        I32LtU => "i32.lt_u",
+        I32GtS => "i32.gt_s",
+        I32GtU => "i32.gt_u",
+        I32LeS => "i32.le_s",
+        I32LeU => "i32.le_u",
+        I32GeS => "i32.ge_s",
+        I32GeU => "i32.ge_u",
+        I32Clz => "i32.clz",
+        I32Ctz => "i32.ctz",
+        I32Popcnt => "i32.popcnt",
+        I32Add => "i32.add",
+        I32Sub => "i32.sub",
+        I32Mul => "i32.mul",
+        I32DivS => "i32.div_s",
+        I32DivU => "i32.div_u",
+        I32RemS => "i32.rem_s",
+        I32RemU => "i32.rem_u",
+        I32And => "i32.and",
+        I32Or => "i32.or",
+        I32Xor => "i32.xor",
+        I32ShL => "i32.shl",
+        I32ShrS => "i32.shr_s",
+        I32ShrU => "i32.shr_u",
+        I32RtoL => "i32.rotl",
+        I32RtoR => "i32.rotr",
+        I64Add => "i64.add",
+        I64Sub => "i64.sub",
+        I64Mul => "i64.mul",
+        I32Extend8S => "i32.extend8_s",
+        I32Extend16S => "i32.extend16_s",
+        _ => return None,
+    })
+}
+
+/// ` (param ...) (result ...)` with the ids of the parameters
+fn signature(
+    FuncType(ResultType(params), ResultType(results)): &FuncType,
+    ids: &NameMap,
+) -> String {
+    let mut text = String::new();
+    for (idx, param) in params.iter().enumerate() {
+        match ids.get(&(idx as u32)) {
+            Some(id) => write!(text, " (param ${} {})", id, param),
+            None => write!(text, " (param {})", param),
+        }
+        .unwrap();
+    }
+    if !results.is_empty() {
+        let results: Vec<String> = results.iter().map(ValType::to_string).collect();
+        write!(text, " (result {})", results.join(" ")).unwrap();
+    }
+    text
+}
+
+fn limits(limits: &Limits) -> String {
+    let index = match limits.index {
+        IndexType::I32 => "",
+        IndexType::I64 => "i64 ",
+    };
+    match limits.max {
+        Some(max) => format!("{}{} {}", index, limits.min, max),
+        None => format!("{}{}", index, limits.min),
+    }
+}
+
+fn mem_type(MemType(limits_, share): &MemType) -> String {
+    match share {
+        Share::Unshared => limits(limits_),
+        Share::Shared => format!("{} shared", limits(limits_)),
+    }
+}
+
+fn global_type(GlobalType(mutability, val): &GlobalType) -> String {
+    match mutability {
+        Mut::Const => val.to_string(),
+        Mut::Var => format!("(mut {})", val),
+    }
+}
+
+/// https://webassembly.github.io/spec/core/text/values.html#strings
+fn string(bytes: &[u8]) -> String {
+    let mut text = String::from("\"");
+    for b in bytes {
+        match b {
+            b'"' | b'\\' => write!(text, "\\{}", *b as char),
+            0x20..=0x7E => write!(text, "{}", *b as char),
+            _ => write!(text, "\\{:02x}", b),
+        }
+        .unwrap();
+    }
+    text.push('"');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "reference-types", feature = "bulk-memory"))]
+    use {
+        super::print,
+        crate::{binary::module::decode_slice, text::parse},
+        anyhow::*,
+    };
+
+    use super::{print_expr, Style};
+    use crate::structure::instructions::{BlockType, Instruction::*};
+
+    #[cfg(all(feature = "reference-types", feature = "bulk-memory"))]
+    const WAT: &str = r#"(module $m
+        (type $binary (func (param i32 i32) (result i32)))
+        (import "env" "log" (func $log (param i32)))
+        (import "env" "g" (global $g i32))
+        (func $add (export "add") (type $binary) (param $lhs i32) (param $rhs i32) (result i32)
+            (local $tmp i32) (local i64)
+            block $exit (result i32)
+                local.get $lhs
+                local.get $rhs
+                i32.add
+                local.tee $tmp
+                i32.eqz
+                if $zero
+                    i32.const 0
+                    call $log
+                else
+                    loop
+                        br 2
+                    end
+                end
+                local.get $tmp
+                br_table 0 0
+            end)
+        (func $rotate (result i32)
+            i32.const 1 i32.const 2 i32.rotl i32.const 3 i32.rotr i32.popcnt i32.extend8_s
+            i32.const 4 i32.shr_u i32.const 5 i32.rem_u i32.const 6 i32.ge_u
+            (select (result i32) (i32.const -7) (global.get $g)))
+        (table $t 2 10 funcref)
+        (memory $mem 1)
+        (global $count (mut i32) (i32.const 0))
+        (start $rotate)
+        (elem (i32.const 0) $add $rotate)
+        (elem $refs funcref (ref.func $add) (ref.null func))
+        (elem declare func $log)
+        (data (i32.const 16) "\00a\"\\\ff")
+        (data $bytes "xyz"))"#;
+
+    #[test]
+    #[cfg(all(feature = "reference-types", feature = "bulk-memory"))]
+    fn print_is_reparsable() -> Result<()> {
+        for style in [Style::Flat, Style::Folded] {
+            //Given
+            let module = parse(WAT)?;
+            //When
+            let wat = print(&module, style);
+            //Then
+            assert_eq!(parse(&wat)?, module, "{}", wat);
+            let wasm = wasmer::wat2wasm(wat.as_bytes())?;
+            let decoded = decode_slice(&wasm)?;
+            assert_eq!(decoded.funcs, module.funcs, "{}", wat);
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "reference-types", feature = "bulk-memory"))]
+    fn print_names_from_name_section() -> Result<()> {
+        //Given
+        let wasm = wasmer::wat2wasm(WAT.as_bytes())?;
+        let module = decode_slice(&wasm)?;
+        //When
+        let wat = print(&module, Style::Flat);
+        //Then
+        assert!(wat.starts_with(
+            "(module $m\n  (type $binary (func (param i32) (param i32) (result i32)))"
+        ));
+        assert!(wat.contains("\n  (func $add (type $binary) (param $lhs i32) (param $rhs i32) (result i32)\n    (local $tmp i32)\n    (local i64)\n"));
+        assert!(wat.contains("\n        call $log\n"));
+        assert!(wat.contains("\n  (export \"add\" (func $add))"));
+        assert!(
+            wat.contains("\n  (data (memory $mem) (offset i32.const 16) \"\\00a\\\"\\\\\\ff\")")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn print_expr_indents_blocks() {
+        //Given
+        let expr = vec![If(
+            BlockType::Empty,
+            vec![Block(BlockType::Empty, vec![Br(1)])],
+            Some(vec![Nop]),
+        )];
+        //When
+        let flat = print_expr(&expr, Style::Flat);
+        let folded = print_expr(&expr, Style::Folded);
+        //Then
+        assert_eq!(flat, "if\n  block\n    br 1\n  end\nelse\n  nop\nend");
+        assert_eq!(
+            folded,
+            "(if\n  (then\n    (block\n      (br 1)))\n  (else\n    (nop)))"
+        );
+        assert_eq!(expr[0].to_string(), "if block br 1 end else nop end");
+    }
+
+    #[test]
+    fn print_folds_operands() -> anyhow::Result<()> {
+        //Given
+        let expr = vec![
+            LocalGet(0),
+            LocalGet(1),
+            I32Add,
+            LocalTee(2),
+            I32Eqz,
+            If(BlockType::Empty, vec![I32Const(1), Drop], None),
+            Unreachable,
+            I32Const(2),
+            Drop,
+        ];
+        let module = crate::text::parse(
+            "(module (func $f (param i32) (result i32) (call $f (i32.const 1))))",
+        )?;
+        //When
+        let folded = print_expr(&expr, Style::Folded);
+        let wat = super::print(&module, Style::Folded);
+        //Then
+        assert_eq!(
+            folded,
+            "(if\n  (i32.eqz\n    (local.tee 2\n      (i32.add (local.get 0) (local.get 1))))\n  (then\n    (drop (i32.const 1))))\n(unreachable)\n(i32.const 2)\n(drop)"
+        );
+        // the types of the module tell the arities of calls
+        assert!(wat.contains("\n    (call $f (i32.const 1)))"), "{}", wat);
+        Ok(())
+    }
+}
@@ -0,0 +1,430 @@
+use anyhow::*;
+
+use super::lexer::{Pos, Token};
+
+/// Tokens grouped by parentheses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sexpr {
+    Atom(Token, Pos),
+    List(Vec<Sexpr>, Pos),
+}
+
+impl Sexpr {
+    pub fn pos(&self) -> Pos {
+        match self {
+            Self::Atom(_, pos) | Self::List(_, pos) => *pos,
+        }
+    }
+
+    pub fn keyword(&self) -> Option<&str> {
+        match self {
+            Self::Atom(Token::Keyword(kw), _) => Some(kw),
+            _ => None,
+        }
+    }
+
+    /// the keyword which a list begins with
+    pub fn head(&self) -> Option<&str> {
+        match self {
+            Self::List(items, _) => items.first().and_then(Sexpr::keyword),
+            _ => None,
+        }
+    }
+
+    pub fn is_list(&self) -> bool {
+        matches!(self, Self::List(_, _))
+    }
+
+    pub fn is_index(&self) -> bool {
+        matches!(self, Self::Atom(Token::Num(_) | Token::Id(_), _))
+    }
+
+    /// items of a list
+    pub fn items(&self) -> Result<Items<'_>> {
+        match self {
+            Self::List(items, pos) => Ok(Items {
+                items,
+                idx: 0,
+                pos: *pos,
+            }),
+            Self::Atom(_, pos) => bail!("{}: expected a list", pos),
+        }
+    }
+}
+
+/// parse tokens into S-expressions, dropping annotations such as `(@custom ...)`
+pub fn parse(tokens: Vec<(Token, Pos)>) -> Result<Vec<Sexpr>> {
+    let mut stack: Vec<(Vec<Sexpr>, Pos)> = vec![(vec![], Pos { line: 1, col: 1 })];
+    for (token, pos) in tokens {
+        match token {
+            Token::LParen => stack.push((vec![], pos)),
+            Token::RParen => {
+                let (items, pos) = stack.pop().unwrap();
+                let parent = &mut stack
+                    .last_mut()
+                    .with_context(|| format!("{}: unexpected )", pos))?
+                    .0;
+                let is_annotation = matches!(
+                    items.first(),
+                    Some(Sexpr::Atom(Token::Reserved(r), _)) if r.starts_with('@')
+                );
+                if !is_annotation {
+                    parent.push(Sexpr::List(items, pos));
+                }
+                if stack.is_empty() {
+                    bail!("{}: unexpected )", pos)
+                }
+            }
+            token => stack.last_mut().unwrap().0.push(Sexpr::Atom(token, pos)),
+        }
+    }
+    match stack.pop() {
+        Some((items, _)) if stack.is_empty() => Ok(items),
+        Some((_, pos)) => bail!("{}: ( is not closed", pos),
+        None => unreachable!(),
+    }
+}
+
+/// Cursor over the items of a list
+pub struct Items<'a> {
+    items: &'a [Sexpr],
+    idx: usize,
+    pos: Pos,
+}
+
+impl<'a> Items<'a> {
+    pub fn new(items: &'a [Sexpr], pos: Pos) -> Self {
+        Self { items, idx: 0, pos }
+    }
+
+    pub fn peek(&self) -> Option<&'a Sexpr> {
+        self.items.get(self.idx)
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&'a Sexpr> {
+        let item = self.items.get(self.idx);
+        self.idx += 1;
+        item
+    }
+
+    /// consume the next item if it satisfies `pred`
+    pub fn next_if(&mut self, pred: impl FnOnce(&Sexpr) -> bool) -> Option<&'a Sexpr> {
+        let item = self.peek().filter(|item| pred(item))?;
+        self.idx += 1;
+        Some(item)
+    }
+
+    /// the items not consumed yet
+    pub fn rest(&self) -> &'a [Sexpr] {
+        &self.items[self.idx.min(self.items.len())..]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.idx >= self.items.len()
+    }
+
+    /// the position of the next item, or of the list itself if no item remains
+    pub fn pos(&self) -> Pos {
+        self.peek().map_or(self.pos, Sexpr::pos)
+    }
+
+    pub fn error(&self, msg: impl std::fmt::Display) -> anyhow::Error {
+        anyhow!("{}: {}", self.pos(), msg)
+    }
+
+    pub fn end(&self) -> Result<()> {
+        match self.peek() {
+            Some(item) => bail!("{}: unexpected {}", item.pos(), describe(item)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn expect(&mut self, what: &str) -> Result<&'a Sexpr> {
+        let err = self.error(format!("expected {}", what));
+        self.next().ok_or(err)
+    }
+
+    pub fn keyword(&mut self) -> Result<&'a str> {
+        let err = self.error("expected a keyword");
+        self.next().and_then(Sexpr::keyword).ok_or(err)
+    }
+
+    pub fn peek_keyword(&self) -> Option<&'a str> {
+        self.peek().and_then(Sexpr::keyword)
+    }
+
+    /// consume the keyword if it comes next
+    pub fn take_keyword(&mut self, kw: &str) -> bool {
+        if self.peek_keyword() == Some(kw) {
+            self.idx += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn id(&mut self) -> Option<&'a str> {
+        match self.peek() {
+            Some(Sexpr::Atom(Token::Id(id), _)) => {
+                self.idx += 1;
+                Some(id)
+            }
+            _ => None,
+        }
+    }
+
+    /// consume a list beginning with `head` and return the items following the head
+    pub fn list(&mut self, head: &str) -> Option<Items<'a>> {
+        match self.peek() {
+            Some(Sexpr::List(items, pos)) if self.peek_head() == Some(head) => {
+                self.idx += 1;
+                Some(Items {
+                    items: &items[1..],
+                    idx: 0,
+                    pos: *pos,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn peek_head(&self) -> Option<&'a str> {
+        self.peek().and_then(Sexpr::head)
+    }
+
+    pub fn string(&mut self) -> Result<&'a [u8]> {
+        match self.peek() {
+            Some(Sexpr::Atom(Token::String(bytes), _)) => {
+                self.idx += 1;
+                Ok(bytes)
+            }
+            _ => Err(self.error("expected a string")),
+        }
+    }
+
+    /// a string which has to be valid UTF-8
+    pub fn name(&mut self) -> Result<String> {
+        let pos = self.pos();
+        String::from_utf8(self.string()?.to_vec())
+            .with_context(|| format!("{}: malformed UTF-8", pos))
+    }
+
+    /// consume all strings which come next and concatenate them
+    pub fn strings(&mut self) -> Vec<u8> {
+        let mut bytes = vec![];
+        while let Result::Ok(s) = self.string() {
+            bytes.extend(s);
+        }
+        bytes
+    }
+
+    fn num(&mut self) -> Result<(&'a str, Pos)> {
+        match self.peek() {
+            Some(Sexpr::Atom(Token::Num(n), pos)) => {
+                self.idx += 1;
+                Ok((n, *pos))
+            }
+            _ => Err(self.error("expected a number")),
+        }
+    }
+
+    pub fn peek_num(&self) -> bool {
+        matches!(self.peek(), Some(Sexpr::Atom(Token::Num(_), _)))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let (n, pos) = self.num()?;
+        parse_int(n)
+            .filter(|m| (0..=u32::MAX as i128).contains(m) && !n.starts_with(['+', '-']))
+            .map(|n| n as u32)
+            .with_context(|| format!("{}: invalid u32 {}", pos, n))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        let (n, pos) = self.num()?;
+        parse_int(n)
+            .filter(|m| (0..=u64::MAX as i128).contains(m) && !n.starts_with(['+', '-']))
+            .map(|n| n as u64)
+            .with_context(|| format!("{}: invalid u64 {}", pos, n))
+    }
+
+    /// signed or unsigned 32 bit integer, which is wrapped into i32
+    pub fn i32(&mut self) -> Result<i32> {
+        let (n, pos) = self.num()?;
+        parse_int(n)
+            .filter(|n| (i32::MIN as i128..=u32::MAX as i128).contains(n))
+            .map(|n| n as i32)
+            .with_context(|| format!("{}: invalid i32 {}", pos, n))
+    }
+
+    /// a keyword such as `offset=16` giving an u64 value to `key`, if it comes next
+    pub fn u64_field(&mut self, key: &str) -> Result<Option<u64>> {
+        let pos = self.pos();
+        let value = match self.peek_keyword().and_then(|kw| kw.strip_prefix(key)) {
+            Some(value) => value.strip_prefix('=').unwrap_or(value),
+            None => return Ok(None),
+        };
+        self.idx += 1;
+        parse_int(value)
+            .filter(|n| (0..=u64::MAX as i128).contains(n) && !value.starts_with(['+', '-']))
+            .map(|n| Some(n as u64))
+            .with_context(|| format!("{}: invalid {}={}", pos, key, value))
+    }
+
+    /// a number or a keyword such as `inf` and `nan:0x1`
+    fn float(&mut self) -> Result<(&'a str, Pos)> {
+        match self.peek() {
+            Some(Sexpr::Atom(Token::Num(n) | Token::Keyword(n), pos)) => {
+                self.idx += 1;
+                Ok((n, *pos))
+            }
+            _ => Err(self.error("expected a number")),
+        }
+    }
+
+    pub fn f32(&mut self) -> Result<f32> {
+        let (n, pos) = self.float()?;
+        let value = match parse_nan(n, 23) {
+            Some(payload) => payload.map(|p| f32::from_bits(0x7F80_0000 | p as u32)),
+            None => parse_float(n).map(|v| v as f32),
+        };
+        value
+            .map(|v| if n.starts_with('-') { -v } else { v })
+            .with_context(|| format!("{}: invalid f32 {}", pos, n))
+    }
+
+    pub fn f64(&mut self) -> Result<f64> {
+        let (n, pos) = self.float()?;
+        let value = match parse_nan(n, 52) {
+            Some(payload) => payload.map(|p| f64::from_bits(0x7FF0_0000_0000_0000 | p as u64)),
+            None => parse_float(n),
+        };
+        value
+            .map(|v| if n.starts_with('-') { -v } else { v })
+            .with_context(|| format!("{}: invalid f64 {}", pos, n))
+    }
+
+    /// signed or unsigned 64 bit integer, which is wrapped into i64
+    pub fn i64(&mut self) -> Result<i64> {
+        let (n, pos) = self.num()?;
+        parse_int(n)
+            .filter(|n| (i64::MIN as i128..=u64::MAX as i128).contains(n))
+            .map(|n| n as i64)
+            .with_context(|| format!("{}: invalid i64 {}", pos, n))
+    }
+}
+
+/// https://webassembly.github.io/spec/core/text/values.html#integers
+fn parse_int(s: &str) -> Option<i128> {
+    let (negative, digits) = match s.as_bytes().first()? {
+        b'-' => (true, &s[1..]),
+        b'+' => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return None;
+    }
+    let digits = digits.replace('_', "");
+    let n = match digits.strip_prefix("0x") {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i128>().ok()?,
+    };
+    Some(if negative { -n } else { n })
+}
+
+/// the payload of `nan` or `nan:0x...` which has to fit in the significand,
+/// or None if `s` is not a nan
+fn parse_nan(s: &str, significand_bits: u32) -> Option<Option<i128>> {
+    let s = s.trim_start_matches(['+', '-']);
+    if s == "nan" {
+        return Some(Some(1 << (significand_bits - 1)));
+    }
+    let payload = s.strip_prefix("nan:")?;
+    Some(
+        parse_int(payload)
+            .filter(|p| payload.starts_with("0x") && (1..1 << significand_bits).contains(p)),
+    )
+}
+
+/// https://webassembly.github.io/spec/core/text/values.html#floating-point
+/// The sign is left to the caller so that it applies to zeros and nans as well.
+fn parse_float(s: &str) -> Option<f64> {
+    let s = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if s == "inf" {
+        return Some(f64::INFINITY);
+    }
+    if !s.starts_with(|c: char| c.is_ascii_digit()) || s.contains("__") || s.contains("_.") {
+        return None;
+    }
+    let s = s.replace('_', "");
+    let Some(hex) = s.strip_prefix("0x") else {
+        return s.parse().ok();
+    };
+    let (mantissa, exp) = match hex.split_once(['p', 'P']) {
+        Some((mantissa, exp)) => (mantissa, exp.parse::<i32>().ok()?),
+        None => (hex, 0),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    // keep 60 bits of the significand, which is more than f64 has
+    let (mut m, mut e) = (0u64, exp);
+    for (i, c) in int.chars().chain(frac.chars()).enumerate() {
+        let digit = c.to_digit(16)? as u64;
+        let is_frac = i >= int.len();
+        if m >> 56 == 0 {
+            m = m * 16 + digit;
+            e -= if is_frac { 4 } else { 0 };
+        } else if !is_frac {
+            e += 4;
+        }
+    }
+    // scale in two steps not to overflow on subnormals
+    let e = e.clamp(-1200, 1200);
+    Some(m as f64 * 2f64.powi(e / 2) * 2f64.powi(e - e / 2))
+}
+
+fn describe(item: &Sexpr) -> String {
+    match item {
+        Sexpr::Atom(Token::Keyword(kw), _) => kw.clone(),
+        Sexpr::Atom(Token::Num(n), _) => n.clone(),
+        Sexpr::Atom(Token::Id(id), _) => format!("${}", id),
+        Sexpr::Atom(Token::String(_), _) => "string".to_string(),
+        Sexpr::Atom(Token::Reserved(r), _) => r.clone(),
+        Sexpr::Atom(_, _) => "parenthesis".to_string(),
+        Sexpr::List(_, _) => match item.head() {
+            Some(head) => format!("({} ...)", head),
+            None => "list".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use super::{parse, Sexpr};
+    use crate::text::lexer::tokenize;
+
+    #[test]
+    fn parse_sexpr() -> Result<()> {
+        //Given
+        let sexprs = parse(tokenize(
+            r#"(module (@custom "a" "b") (func $f 1 -0x10 4_294_967_295))"#,
+        )?)?;
+        //When
+        let mut module = sexprs[0].items()?;
+        //Then
+        assert_eq!(module.keyword()?, "module");
+        let mut func = module.list("func").unwrap();
+        module.end()?;
+        assert_eq!(func.id(), Some("f"));
+        assert_eq!(func.u32()?, 1);
+        assert_eq!(func.i32()?, -16);
+        assert_eq!(func.i32()?, -1);
+        func.end()?;
+        assert!(matches!(sexprs[0], Sexpr::List(_, _)));
+        assert!(parse(tokenize("(module")?).is_err());
+        assert!(parse(tokenize("(module))")?).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use anyhow::*;
+
+use super::{
+    module::Space,
+    sexpr::{Items, Sexpr},
+};
+use crate::structure::types::{
+    CompositeType, FieldType, GlobalType, HeapType, IndexType, Limits, MemType, Mut, NumType,
+    PackedType, RefType, ResultType, Share, StorageType, SubType, TableType, ValType,
+};
+
+/// https://webassembly.github.io/spec/core/text/types.html#value-types
+/// `types` resolves the type indices of `(ref $t)`
+pub fn val_type(items: &mut Items, types: &Space) -> Result<ValType> {
+    if let Some(mut ref_) = items.list("ref") {
+        let nullable = ref_.take_keyword("null");
+        let heap = heap_type(&mut ref_, types)?;
+        ref_.end()?;
+        return Ok(ValType::Ref(RefType { nullable, heap }));
+    }
+    let pos = items.pos();
+    let err = items.error("expected a value type");
+    Ok(match items.keyword().map_err(|_| err)? {
+        "i32" => ValType::Number(NumType::I32),
+        "i64" => ValType::Number(NumType::I64),
+        "f32" => ValType::Number(NumType::F32),
+        "f64" => ValType::Number(NumType::F64),
+        "v128" => ValType::Vec,
+        "funcref" => ValType::Ref(RefType::FUNCREF),
+        "externref" => ValType::Ref(RefType::EXTERNREF),
+        "exnref" => ValType::Ref(RefType::EXNREF),
+        "anyref" => ValType::Ref(RefType::null(HeapType::Any)),
+        "eqref" => ValType::Ref(RefType::null(HeapType::Eq)),
+        "i31ref" => ValType::Ref(RefType::null(HeapType::I31)),
+        "structref" => ValType::Ref(RefType::null(HeapType::Struct)),
+        "arrayref" => ValType::Ref(RefType::null(HeapType::Array)),
+        "nullref" => ValType::Ref(RefType::null(HeapType::None)),
+        "nullfuncref" => ValType::Ref(RefType::null(HeapType::NoFunc)),
+        "nullexternref" => ValType::Ref(RefType::null(HeapType::NoExtern)),
+        "nullexnref" => ValType::Ref(RefType::null(HeapType::NoExn)),
+        kw => bail!("{}: unknown value type {}", pos, kw),
+    })
+}
+
+/// whether a reference type comes next
+pub fn is_ref_type(items: &Items) -> bool {
+    matches!(
+        items.peek_keyword(),
+        Some(
+            "funcref"
+                | "externref"
+                | "exnref"
+                | "anyref"
+                | "eqref"
+                | "i31ref"
+                | "structref"
+                | "arrayref"
+                | "nullref"
+                | "nullfuncref"
+                | "nullexternref"
+                | "nullexnref"
+        )
+    ) || items.peek_head() == Some("ref")
+}
+
+/// https://webassembly.github.io/spec/core/text/types.html#reference-types
+pub fn ref_type(items: &mut Items, types: &Space) -> Result<RefType> {
+    let pos = items.pos();
+    match val_type(items, types)? {
+        ValType::Ref(r) => Ok(r),
+        v => bail!("{}: {:?} is not a reference type", pos, v),
+    }
+}
+
+/// https://webassembly.github.io/gc/core/text/types.html#heap-types
+pub fn heap_type(items: &mut Items, types: &Space) -> Result<HeapType> {
+    if items.peek_keyword().is_none() {
+        return Ok(HeapType::Type(types.index(items, "type")?));
+    }
+    let pos = items.pos();
+    match items.keyword()? {
+        "func" => Ok(HeapType::Func),
+        "extern" => Ok(HeapType::Extern),
+        "exn" => Ok(HeapType::Exn),
+        "any" => Ok(HeapType::Any),
+        "eq" => Ok(HeapType::Eq),
+        "i31" => Ok(HeapType::I31),
+        "struct" => Ok(HeapType::Struct),
+        "array" => Ok(HeapType::Array),
+        "none" => Ok(HeapType::None),
+        "nofunc" => Ok(HeapType::NoFunc),
+        "noextern" => Ok(HeapType::NoExtern),
+        "noexn" => Ok(HeapType::NoExn),
+        kw => bail!("{}: unknown heap type {}", pos, kw),
+    }
+}
+
+/// value types of `(param ...)` lists which come next, with the ids of the parameters
+pub fn params(items: &mut Items, types: &Space) -> Result<(Vec<ValType>, Vec<Option<String>>)> {
+    let (mut vals, mut ids) = (vec![], vec![]);
+    while let Some(mut param) = items.list("param") {
+        if let Some(id) = param.id() {
+            vals.push(val_type(&mut param, types)?);
+            ids.push(Some(id.to_string()));
+        } else {
+            while !param.is_empty() {
+                vals.push(val_type(&mut param, types)?);
+                ids.push(None);
+            }
+        }
+        param.end()?;
+    }
+    Ok((vals, ids))
+}
+
+/// value types of `(result ...)` lists which come next
+pub fn results(items: &mut Items, types: &Space) -> Result<Vec<ValType>> {
+    let mut vals = vec![];
+    while let Some(mut result) = items.list("result") {
+        while !result.is_empty() {
+            vals.push(val_type(&mut result, types)?);
+        }
+    }
+    Ok(vals)
+}
+
+/// https://webassembly.github.io/spec/core/text/types.html#function-types
+pub fn func_type(items: &mut Items, types: &Space) -> Result<crate::structure::types::FuncType> {
+    let (params, _) = params(items, types)?;
+    let results = results(items, types)?;
+    Ok(crate::structure::types::FuncType(
+        ResultType(params),
+        ResultType(results),
+    ))
+}
+
+/// https://webassembly.github.io/gc/core/text/types.html#aggregate-types
+fn storage_type(items: &mut Items, types: &Space) -> Result<StorageType> {
+    if items.take_keyword("i8") {
+        Ok(StorageType::Packed(PackedType::I8))
+    } else if items.take_keyword("i16") {
+        Ok(StorageType::Packed(PackedType::I16))
+    } else {
+        Ok(StorageType::Val(val_type(items, types)?))
+    }
+}
+
+pub fn field_type(items: &mut Items, types: &Space) -> Result<FieldType> {
+    if let Some(mut mutable) = items.list("mut") {
+        let storage = storage_type(&mut mutable, types)?;
+        mutable.end()?;
+        Ok(FieldType(Mut::Var, storage))
+    } else {
+        Ok(FieldType(Mut::Const, storage_type(items, types)?))
+    }
+}
+
+/// `fields` binds the ids of the fields of a structure type
+/// https://webassembly.github.io/gc/core/text/types.html#composite-types
+fn composite_type(items: &mut Items, types: &Space, fields: &mut Space) -> Result<CompositeType> {
+    if let Some(mut func) = items.list("func") {
+        let func_type = func_type(&mut func, types)?;
+        func.end()?;
+        return Ok(CompositeType::Func(func_type));
+    }
+    if let Some(mut struct_) = items.list("struct") {
+        let mut field_types = vec![];
+        while let Some(mut field) = struct_.list("field") {
+            let pos = field.pos();
+            if let Some(id) = field.id() {
+                field_types.push(field_type(&mut field, types)?);
+                fields.define(Some(id), pos)?;
+                field.end()?;
+            } else {
+                while !field.is_empty() {
+                    field_types.push(field_type(&mut field, types)?);
+                    fields.define(None, pos)?;
+                }
+            }
+        }
+        struct_.end()?;
+        return Ok(CompositeType::Struct(field_types));
+    }
+    if let Some(mut array) = items.list("array") {
+        let field = field_type(&mut array, types)?;
+        array.end()?;
+        return Ok(CompositeType::Array(field));
+    }
+    Err(items.error("expected (func ...), (struct ...) or (array ...)"))
+}
+
+/// a composite type without `sub` is final without supertypes
+/// https://webassembly.github.io/gc/core/text/types.html#recursive-types
+pub fn sub_type(items: &mut Items, types: &Space, fields: &mut Space) -> Result<SubType> {
+    let Some(mut sub) = items.list("sub") else {
+        return Ok(SubType {
+            is_final: true,
+            supers: vec![],
+            composite: composite_type(items, types, fields)?,
+        });
+    };
+    let is_final = sub.take_keyword("final");
+    let mut supers = vec![];
+    while sub.peek().is_some_and(Sexpr::is_index) {
+        supers.push(types.index(&mut sub, "type")?);
+    }
+    let composite = composite_type(&mut sub, types, fields)?;
+    sub.end()?;
+    Ok(SubType {
+        is_final,
+        supers,
+        composite,
+    })
+}
+
+/// `i64` tells 64 bit indices, and `i32` or nothing tells 32 bit ones
+/// https://webassembly.github.io/memory64/core/text/types.html#limits
+pub fn index_type(items: &mut Items) -> IndexType {
+    if items.take_keyword("i64") {
+        IndexType::I64
+    } else {
+        items.take_keyword("i32");
+        IndexType::I32
+    }
+}
+
+/// https://webassembly.github.io/spec/core/text/types.html#limits
+pub fn limits(items: &mut Items) -> Result<Limits> {
+    let index = index_type(items);
+    let bound = |items: &mut Items| match index {
+        IndexType::I32 => items.u32().map(u64::from),
+        IndexType::I64 => items.u64(),
+    };
+    let min = bound(items)?;
+    let max = if items.peek_num() {
+        Some(bound(items)?)
+    } else {
+        None
+    };
+    Ok(Limits { min, max, index })
+}
+
+/// https://webassembly.github.io/threads/core/text/types.html#memory-types
+pub fn mem_type(items: &mut Items) -> Result<MemType> {
+    let limits = limits(items)?;
+    let share = if items.take_keyword("shared") {
+        Share::Shared
+    } else {
+        Share::Unshared
+    };
+    Ok(MemType(limits, share))
+}
+
+pub fn table_type(items: &mut Items, types: &Space) -> Result<TableType> {
+    let limits = limits(items)?;
+    Ok(TableType(limits, ref_type(items, types)?))
+}
+
+/// https://webassembly.github.io/spec/core/text/types.html#global-types
+pub fn global_type(items: &mut Items, types: &Space) -> Result<GlobalType> {
+    if let Some(mut mutable) = items.list("mut") {
+        let val_type = val_type(&mut mutable, types)?;
+        mutable.end()?;
+        Ok(GlobalType(Mut::Var, val_type))
+    } else {
+        Ok(GlobalType(Mut::Const, val_type(items, types)?))
+    }
+}
@@ -0,0 +1,1014 @@
+mod func;
+
+use std::collections::HashSet;
+
+use anyhow::*;
+
+use crate::features::WasmFeatures;
+use crate::structure::{
+    instructions::{Expr, Instruction},
+    module::{
+        indices::{FuncIdx, TypeIdx},
+        DataMode, ElemMode, ExportDesc, ImportDesc, Module, Names,
+    },
+    types::{
+        CompositeType, FieldType, FuncType, GlobalType, HeapType, IndexType, Limits, MemType, Mut,
+        NumType, RefType, ResultType, Share, StorageType, SubType, TableType, ValType,
+    },
+};
+
+/// https://webassembly.github.io/spec/core/valid/types.html#memory-types
+const MAX_PAGES: u64 = 1 << 16;
+/// https://webassembly.github.io/memory64/core/valid/types.html#memory-types
+const MAX_PAGES_64: u64 = 1 << 48;
+
+/// Types of the definitions in the module, in their index spaces
+/// https://webassembly.github.io/spec/core/valid/conventions.html#contexts
+pub struct ValidationContext<'a> {
+    /// the module which defines the types and relates them by subtyping
+    pub module: &'a Module<'a>,
+    /// the type indices of the functions
+    pub funcs: Vec<TypeIdx>,
+    pub tables: Vec<&'a TableType>,
+    pub mems: Vec<&'a MemType>,
+    /// types of the values which exceptions of the tags carry
+    pub tags: Vec<&'a FuncType>,
+    pub globals: Vec<&'a GlobalType>,
+    pub num_of_imported_globals: usize,
+    pub elems: Vec<RefType>,
+    pub num_of_datas: usize,
+    /// functions which may be referenced by `ref.func` in function bodies
+    pub refs: HashSet<FuncIdx>,
+    pub names: &'a Names,
+    pub features: WasmFeatures,
+}
+
+/// check that the module is valid, following the validation algorithm of the spec
+/// https://webassembly.github.io/spec/core/valid/modules.html#valid-module
+/// https://webassembly.github.io/spec/core/appendix/algorithm.html
+pub fn validate(module: &Module) -> Result<()> {
+    validate_with_features(module, &WasmFeatures::default())
+}
+
+/// the same as `validate` but rejects proposals not enabled in `features`
+pub fn validate_with_features(module: &Module, features: &WasmFeatures) -> Result<()> {
+    let ctx = validate_declarations(module, features)?;
+    for i in 0..module.funcs.len() {
+        validate_body(&ctx, i)?;
+    }
+    Ok(())
+}
+
+/// the same as `validate_with_features` but leaves out the function bodies,
+/// which the runtime validates with `validate_func_body` when they are called first
+/// if `RuntimeOptions::lazy_validation` is set, so that bodies are decoded only when they are needed
+pub(crate) fn validate_declarations<'a>(
+    module: &'a Module<'a>,
+    features: &WasmFeatures,
+) -> Result<ValidationContext<'a>> {
+    features.check_module(module)?;
+    let ctx = ValidationContext::new(module, *features)?;
+    for idx in 0..module.types.len() as TypeIdx {
+        validate_sub_type(&ctx, idx)
+            .with_context(|| module.names.type_(idx))
+            .map_err(flatten)?;
+    }
+    for (idx, import) in module.imports.iter().enumerate() {
+        match &import.desc {
+            ImportDesc::Func(_) => Ok(()),
+            ImportDesc::Table(table_type) => validate_table_type(&ctx, table_type),
+            ImportDesc::Mem(mem_type) => validate_mem_type(mem_type),
+            ImportDesc::Global(GlobalType(_, val_type)) => ctx.val_type(*val_type),
+            ImportDesc::Tag(type_) => ctx.func_type(*type_).and_then(validate_tag_type),
+        }
+        .with_context(|| format!("import[{}] {}.{}", idx, import.module, import.name))
+        .map_err(flatten)?;
+    }
+    let offset = ctx.tables.len() - module.tables.len();
+    for (i, table) in module.tables.iter().enumerate() {
+        validate_table_type(&ctx, &table.type_)
+            .with_context(|| module.names.table((offset + i) as u32))
+            .map_err(flatten)?;
+    }
+    let offset = ctx.mems.len() - module.mems.len();
+    for (i, mem) in module.mems.iter().enumerate() {
+        validate_mem_type(&mem.type_)
+            .with_context(|| module.names.mem((offset + i) as u32))
+            .map_err(flatten)?;
+    }
+    let offset = ctx.tags.len() - module.tags.len();
+    for (i, tag_type) in ctx.tags[offset..].iter().enumerate() {
+        validate_tag_type(tag_type)
+            .with_context(|| module.names.tag((offset + i) as u32))
+            .map_err(flatten)?;
+    }
+    for (i, global) in module.globals.iter().enumerate() {
+        let GlobalType(_, val_type) = global.type_;
+        ctx.val_type(val_type)
+            .and_then(|_| {
+                let num_of_globals = ctx.num_of_imported_globals + i;
+                validate_const_expr(&ctx, &global.init, val_type, num_of_globals)
+            })
+            .with_context(|| {
+                module
+                    .names
+                    .global((ctx.num_of_imported_globals + i) as u32)
+            })
+            .map_err(flatten)?;
+    }
+    for (idx, elem) in module.elems.iter().enumerate() {
+        validate_elem(&ctx, elem)
+            .with_context(|| module.names.elem(idx as u32))
+            .map_err(flatten)?;
+    }
+    for (idx, data) in module.datas.iter().enumerate() {
+        if let DataMode::Active { memory, offset } = &data.mode {
+            ensure_index(*memory, ctx.mems.len(), "memory")
+                .and_then(|_| {
+                    let MemType(limits, _) = ctx.mems[*memory as usize];
+                    validate_const_expr(&ctx, offset, limits.index.into(), ctx.globals.len())
+                })
+                .with_context(|| module.names.data(idx as u32))
+                .map_err(flatten)?;
+        }
+    }
+    if let Some(start) = &module.start {
+        let FuncType(ResultType(params), ResultType(results)) = ctx.func(start.func)?;
+        if !params.is_empty() || !results.is_empty() {
+            bail!(
+                "start function {} must have type [] -> []",
+                module.names.func(start.func)
+            )
+        }
+    }
+    let mut export_names = HashSet::new();
+    for export in &module.exports {
+        if !export_names.insert(&export.name) {
+            bail!("duplicate export name {:?}", export.name)
+        }
+        match export.desc {
+            ExportDesc::Func(idx) => ensure_index(idx, ctx.funcs.len(), "function"),
+            ExportDesc::Table(idx) => ensure_index(idx, ctx.tables.len(), "table"),
+            ExportDesc::Mem(idx) => ensure_index(idx, ctx.mems.len(), "memory"),
+            ExportDesc::Global(idx) => ensure_index(idx, ctx.globals.len(), "global"),
+            ExportDesc::Tag(idx) => ensure_index(idx, ctx.tags.len(), "tag"),
+        }
+        .with_context(|| format!("export {:?}", export.name))
+        .map_err(flatten)?;
+    }
+    Ok(ctx)
+}
+
+/// check the body of the function at `i` among the ones defined in a module
+/// which `validate_declarations` accepted
+pub(crate) fn validate_func_body(module: &Module, features: &WasmFeatures, i: usize) -> Result<()> {
+    validate_body(&ValidationContext::new(module, *features)?, i)
+}
+
+fn validate_body(ctx: &ValidationContext, i: usize) -> Result<()> {
+    let module = ctx.module;
+    let func = &module.funcs[i];
+    let idx = (module.num_of_imported_funcs() + i) as FuncIdx;
+    func.body
+        .expr()
+        .and_then(|expr| func::validate_func(ctx, func.type_, &func.locals, expr))
+        .with_context(|| match module.names.funcs.get(&idx) {
+            Some(name) => format!("func[{}] ${}", idx, name),
+            None => format!("func[{}]", idx),
+        })
+        .map_err(flatten)
+}
+
+impl<'a> ValidationContext<'a> {
+    fn new(module: &'a Module<'a>, features: WasmFeatures) -> Result<Self> {
+        let mut ctx = Self {
+            module,
+            funcs: vec![],
+            tables: vec![],
+            mems: vec![],
+            tags: vec![],
+            globals: vec![],
+            num_of_imported_globals: 0,
+            elems: module.elems.iter().map(|elem| elem.type_).collect(),
+            num_of_datas: module.datas.len(),
+            refs: HashSet::new(),
+            names: &module.names,
+            features,
+        };
+        for import in &module.imports {
+            match &import.desc {
+                ImportDesc::Func(type_) => {
+                    ctx.func_type(*type_)?;
+                    ctx.funcs.push(*type_);
+                }
+                ImportDesc::Table(table_type) => ctx.tables.push(table_type),
+                ImportDesc::Mem(mem_type) => ctx.mems.push(mem_type),
+                ImportDesc::Global(global_type) => ctx.globals.push(global_type),
+                ImportDesc::Tag(type_) => ctx.tags.push(ctx.func_type(*type_)?),
+            }
+        }
+        ctx.num_of_imported_globals = ctx.globals.len();
+        for func in &module.funcs {
+            ctx.func_type(func.type_)?;
+            ctx.funcs.push(func.type_);
+        }
+        ctx.tables
+            .extend(module.tables.iter().map(|table| &table.type_));
+        ctx.mems.extend(module.mems.iter().map(|mem| &mem.type_));
+        for tag in &module.tags {
+            ctx.tags.push(ctx.func_type(tag.type_)?);
+        }
+        ctx.globals
+            .extend(module.globals.iter().map(|global| &global.type_));
+        // https://webassembly.github.io/spec/core/valid/modules.html#valid-module
+        let inits = module
+            .globals
+            .iter()
+            .map(|global| &global.init)
+            .chain(module.elems.iter().flat_map(|elem| &elem.init));
+        for init in inits {
+            ctx.refs.extend(init.iter().filter_map(|instr| match instr {
+                Instruction::RefFunc(idx) => Some(*idx),
+                _ => None,
+            }));
+        }
+        ctx.refs.extend(
+            module
+                .exports
+                .iter()
+                .filter_map(|export| match export.desc {
+                    ExportDesc::Func(idx) => Some(idx),
+                    _ => None,
+                }),
+        );
+        Ok(ctx)
+    }
+
+    pub fn sub_type(&self, idx: TypeIdx) -> Result<&'a SubType> {
+        ensure_index(idx, self.module.types.len(), "type")?;
+        Ok(&self.module.types[idx as usize])
+    }
+
+    pub fn func_type(&self, idx: TypeIdx) -> Result<&'a FuncType> {
+        match &self.sub_type(idx)?.composite {
+            CompositeType::Func(func_type) => Ok(func_type),
+            _ => bail!("type {} is not a function type", idx),
+        }
+    }
+
+    pub fn struct_type(&self, idx: TypeIdx) -> Result<&'a [FieldType]> {
+        match &self.sub_type(idx)?.composite {
+            CompositeType::Struct(fields) => Ok(fields),
+            _ => bail!("type {} is not a structure type", idx),
+        }
+    }
+
+    pub fn array_type(&self, idx: TypeIdx) -> Result<FieldType> {
+        match &self.sub_type(idx)?.composite {
+            CompositeType::Array(field) => Ok(*field),
+            _ => bail!("type {} is not an array type", idx),
+        }
+    }
+
+    /// the type of the function
+    pub fn func(&self, idx: FuncIdx) -> Result<&'a FuncType> {
+        ensure_index(idx, self.funcs.len(), "function")?;
+        self.func_type(self.funcs[idx as usize])
+    }
+
+    /// check that the type indices in the value type are defined
+    /// https://webassembly.github.io/function-references/core/valid/types.html#heap-types
+    pub fn val_type(&self, val_type: ValType) -> Result<()> {
+        match val_type {
+            ValType::Ref(RefType {
+                heap: HeapType::Type(idx),
+                ..
+            }) => ensure_index(idx, self.module.types.len(), "type"),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn matches(&self, sub: ValType, sup: ValType) -> bool {
+        self.module.matches(sub, sup)
+    }
+
+    pub fn matches_ref(&self, sub: RefType, sup: RefType) -> bool {
+        self.module.matches_ref(sub, sup)
+    }
+
+    /// https://webassembly.github.io/gc/core/valid/matching.html#field-types
+    pub fn matches_field(
+        &self,
+        FieldType(m1, s1): FieldType,
+        FieldType(m2, s2): FieldType,
+    ) -> bool {
+        let storage = |sub: StorageType, sup: StorageType| match (sub, sup) {
+            (StorageType::Val(sub), StorageType::Val(sup)) => self.matches(sub, sup),
+            _ => sub == sup,
+        };
+        // mutable fields are both read and written, so their types have to be equivalent
+        m1 == m2 && storage(s1, s2) && (m1 == Mut::Const || storage(s2, s1))
+    }
+
+    /// https://webassembly.github.io/gc/core/valid/matching.html#composite-types
+    fn matches_composite(&self, sub: &CompositeType, sup: &CompositeType) -> bool {
+        match (sub, sup) {
+            (
+                CompositeType::Func(FuncType(ResultType(p1), ResultType(r1))),
+                CompositeType::Func(FuncType(ResultType(p2), ResultType(r2))),
+            ) => self.matches_all(p2, p1) && self.matches_all(r1, r2),
+            (CompositeType::Struct(f1), CompositeType::Struct(f2)) => {
+                f1.len() >= f2.len() && f1.iter().zip(f2).all(|(a, b)| self.matches_field(*a, *b))
+            }
+            (CompositeType::Array(a), CompositeType::Array(b)) => self.matches_field(*a, *b),
+            _ => false,
+        }
+    }
+
+    /// https://webassembly.github.io/function-references/core/valid/matching.html#result-types
+    pub fn matches_all(&self, subs: &[ValType], sups: &[ValType]) -> bool {
+        subs.len() == sups.len()
+            && subs
+                .iter()
+                .zip(sups)
+                .all(|(sub, sup)| self.matches(*sub, *sup))
+    }
+}
+
+fn ensure_index(idx: u32, len: usize, kind: &str) -> Result<()> {
+    if idx as usize >= len {
+        bail!("unknown {} {}", kind, idx)
+    }
+    Ok(())
+}
+
+/// join the contexts into a single line such as `func[1]: instruction 3 (i32.add): ...`
+fn flatten(e: Error) -> Error {
+    let messages: Vec<String> = e.chain().map(ToString::to_string).collect();
+    anyhow!(messages.join(": "))
+}
+
+/// Types refer to the types before them and in their recursive type group,
+/// and declare at most one supertype which they match.
+/// https://webassembly.github.io/gc/core/valid/types.html#recursive-types
+fn validate_sub_type(ctx: &ValidationContext, idx: TypeIdx) -> Result<()> {
+    let sub_type = ctx.sub_type(idx)?;
+    let end = ctx.module.rec_group(idx).end as usize;
+    let val_type = |val_type: &ValType| match val_type {
+        ValType::Ref(RefType {
+            heap: HeapType::Type(i),
+            ..
+        }) => ensure_index(*i, end, "type"),
+        _ => Ok(()),
+    };
+    let field = |FieldType(_, storage): &FieldType| match storage {
+        StorageType::Val(v) => val_type(v),
+        StorageType::Packed(_) => Ok(()),
+    };
+    match &sub_type.composite {
+        CompositeType::Func(FuncType(ResultType(params), ResultType(results))) => {
+            params.iter().chain(results).try_for_each(val_type)?
+        }
+        CompositeType::Struct(fields) => fields.iter().try_for_each(field)?,
+        CompositeType::Array(f) => field(f)?,
+    }
+    match sub_type.supers.as_slice() {
+        [] => {}
+        [sup] => {
+            if *sup >= idx {
+                bail!("supertype {} must be defined before the type", sup)
+            }
+            let sup_type = ctx.sub_type(*sup)?;
+            if sup_type.is_final {
+                bail!("supertype {} is final", sup)
+            }
+            if !ctx.matches_composite(&sub_type.composite, &sup_type.composite) {
+                bail!("type mismatch: type does not match its supertype {}", sup)
+            }
+        }
+        _ => bail!("type must not have more than one supertype"),
+    }
+    Ok(())
+}
+
+/// https://webassembly.github.io/spec/core/valid/types.html#limits
+fn validate_limits(limits: &Limits, range: u64) -> Result<()> {
+    if limits.min > range || limits.max.is_some_and(|max| max > range) {
+        bail!("limits must be at most {}", range)
+    }
+    if limits.max.is_some_and(|max| limits.min > max) {
+        bail!("size minimum must not be greater than maximum")
+    }
+    Ok(())
+}
+
+/// tables are filled with null, since initializer expressions of tables are not supported
+fn validate_table_type(
+    ctx: &ValidationContext,
+    TableType(limits, ref_type): &TableType,
+) -> Result<()> {
+    if limits.index == IndexType::I64 {
+        bail!("tables with 64 bit indices are not supported")
+    }
+    ctx.val_type(ValType::Ref(*ref_type))?;
+    if !ref_type.nullable {
+        bail!("table of {} must have an initializer", ref_type)
+    }
+    validate_limits(limits, u32::MAX as u64)
+}
+
+/// https://webassembly.github.io/threads/core/valid/types.html#memory-types
+fn validate_mem_type(MemType(limits, share): &MemType) -> Result<()> {
+    if *share == Share::Shared && limits.max.is_none() {
+        bail!("shared memory must have maximum")
+    }
+    match limits.index {
+        IndexType::I32 => validate_limits(limits, MAX_PAGES),
+        IndexType::I64 => validate_limits(limits, MAX_PAGES_64),
+    }
+}
+
+/// exceptions carry the parameters, and nothing returns to the throw
+/// https://webassembly.github.io/exception-handling/core/valid/types.html#tag-types
+fn validate_tag_type(FuncType(_, ResultType(results)): &FuncType) -> Result<()> {
+    if !results.is_empty() {
+        bail!("tag type must not have results")
+    }
+    Ok(())
+}
+
+/// https://webassembly.github.io/spec/core/valid/modules.html#element-segments
+fn validate_elem(ctx: &ValidationContext, elem: &crate::structure::module::Elem) -> Result<()> {
+    ctx.val_type(ValType::Ref(elem.type_))?;
+    for init in &elem.init {
+        match init.as_slice() {
+            // function indices, which do not depend on reference types
+            [Instruction::RefFunc(idx)] if elem.type_ == RefType::FUNCREF => {
+                ensure_index(*idx, ctx.funcs.len(), "function")?
+            }
+            _ => validate_const_expr(ctx, init, ValType::Ref(elem.type_), ctx.globals.len())?,
+        }
+    }
+    if let ElemMode::Active { table, offset } = &elem.mode {
+        ensure_index(*table, ctx.tables.len(), "table")?;
+        let TableType(_, ref_type) = ctx.tables[*table as usize];
+        if !ctx.matches_ref(elem.type_, *ref_type) {
+            bail!(
+                "type mismatch: element of {} for table of {}",
+                elem.type_,
+                ref_type
+            )
+        }
+        let i32 = ValType::Number(NumType::I32);
+        validate_const_expr(ctx, offset, i32, ctx.globals.len())?;
+    }
+    Ok(())
+}
+
+/// https://webassembly.github.io/spec/core/valid/instructions.html#constant-expressions
+/// `global.get` may refer to the first `num_of_globals` globals only,
+/// which are the imported ones and the ones defined before in initializers of globals
+fn validate_const_expr(
+    ctx: &ValidationContext,
+    expr: &Expr,
+    expected: ValType,
+    num_of_globals: usize,
+) -> Result<()> {
+    for instr in expr {
+        match instr {
+            Instruction::I32Const(_)
+            | Instruction::I64Const(_)
+            | Instruction::I32Add
+            | Instruction::I32Sub
+            | Instruction::I32Mul
+            | Instruction::I64Add
+            | Instruction::I64Sub
+            | Instruction::I64Mul
+            | Instruction::RefNull(_)
+            | Instruction::RefFunc(_)
+            | Instruction::StructNew(_)
+            | Instruction::StructNewDefault(_)
+            | Instruction::ArrayNew(_)
+            | Instruction::ArrayNewDefault(_)
+            | Instruction::ArrayNewFixed(..)
+            | Instruction::RefI31
+            | Instruction::AnyConvertExtern
+            | Instruction::ExternConvertAny => {}
+            Instruction::GlobalGet(idx) => {
+                if *idx as usize >= num_of_globals {
+                    bail!("constant expression can only refer to imported globals and globals defined before")
+                }
+                if ctx.globals[*idx as usize].0 != Mut::Const {
+                    bail!("constant expression cannot refer to a mutable global")
+                }
+            }
+            instr => bail!("{} is not a constant instruction", instr),
+        }
+    }
+    func::validate_expr(ctx, expr, &[expected])
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::*;
+
+    use super::validate;
+    use crate::text::parse;
+
+    #[cfg(feature = "reference-types")]
+    fn validate_wat(wat: &str) -> Result<()> {
+        validate(&parse(wat)?)
+    }
+
+    #[test]
+    #[cfg(feature = "reference-types")]
+    fn validate_valid_module() -> Result<()> {
+        validate_wat(
+            r#"(module
+                (import "env" "g" (global $g i32))
+                (table $t 1 funcref)
+                (memory 1)
+                (global $count (mut i32) (global.get $g))
+                (func $fib (export "fib") (param $n i32) (result i32)
+                    (if (result i32) (i32.lt_s (local.get $n) (i32.const 2))
+                        (then (local.get $n))
+                        (else
+                            (i32.add
+                                (call $fib (i32.sub (local.get $n) (i32.const 1)))
+                                (call $fib (i32.sub (local.get $n) (i32.const 2)))))))
+                (func $loop (param i32) (result i32)
+                    (block $exit (result i32)
+                        (loop $continue
+                            (drop (br_if $exit (local.get 0) (i32.eqz (local.get 0))))
+                            (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                            (br $continue))
+                        (i32.const 0))
+                    (drop)
+                    (global.set $count (i32.const 0))
+                    (call_indirect (type 0) (i32.const 1) (i32.const 0))
+                    (drop (ref.func $fib))
+                    (select (i32.const 1) (i32.const 2) (i32.const 0))
+                    (br_table 0 0)
+                    unreachable)
+                (elem (i32.const 0) $fib)
+                (data (i32.const 0) "abc")
+                (start 2)
+                (func))"#,
+        )
+    }
+
+    #[test]
+    #[cfg(all(feature = "reference-types", feature = "simd"))]
+    fn validate_invalid_funcs() {
+        use crate::binary::module::decode_slice;
+        let cases = [
+            (
+                "(func (result i32) (i32.add (i32.const 1)))",
+                "func[0]: instruction 1 (i32.add): type mismatch: the operand stack is empty",
+            ),
+            (
+                "(func $f (param i32) local.get 1 drop)",
+                "func[0] $f: instruction 0 (local.get 1): unknown local 1",
+            ),
+            (
+                "(func block br 2 end)",
+                "func[0]: instruction 1 (br 2): unknown label 2",
+            ),
+            (
+                "(func (result i32) i64.const 0)",
+                "func[0]: end of the function: type mismatch: expected i32, found i64",
+            ),
+            (
+                "(func block (result i32) i32.const 0 i32.const 1 end drop)",
+                "func[0]: instruction 0 (block): type mismatch: 1 values are left on the operand stack",
+            ),
+            (
+                "(global i32 (i32.const 0)) (func i32.const 1 global.set 0)",
+                "func[0]: instruction 1 (global.set 0): global global[0] is immutable",
+            ),
+            (
+                "(func ref.func 0 drop)",
+                "func[0]: instruction 0 (ref.func 0): undeclared function reference func[0]",
+            ),
+            (
+                "(memory 1) (func (drop (v128.load align=32 (i32.const 0))))",
+                "func[0]: instruction 1 (v128.load align=32): alignment must not be larger than natural",
+            ),
+            (
+                "(func (drop (i32x4.extract_lane 4 (v128.const i64x2 0 0))))",
+                "func[0]: instruction 1 (i32x4.extract_lane 4): invalid lane index 4",
+            ),
+        ];
+        for (wat, expected) in cases {
+            // decoded from the binary since the parser rejects undefined indices
+            let wasm = wasmer::wat2wasm(wat.as_bytes()).unwrap();
+            let module = decode_slice(&wasm).unwrap();
+            assert_eq!(
+                validate(&module).unwrap_err().to_string(),
+                expected,
+                "{}",
+                wat
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "tail-call")]
+    fn validate_tail_calls() -> Result<()> {
+        use super::validate_with_features;
+        use crate::features::WasmFeatures;
+        //Given
+        let valid = parse(
+            "(type $t (func (result i32))) (table 1 funcref)
+             (func $f (result i32) (return_call $f))
+             (func (result i32) i64.const 0 (return_call_indirect (type $t) (i32.const 0)))",
+        )?;
+        let invalid =
+            parse("(func $f (result i32) (return_call $g)) (func $g (result i64) i64.const 0)")?;
+        let features = WasmFeatures::all();
+        //When
+        let err = validate_with_features(&invalid, &features).unwrap_err();
+        //Then
+        assert!(validate_with_features(&valid, &features).is_ok());
+        assert_eq!(
+            err.to_string(),
+            "func[0] $f: instruction 0 (return_call 1): type mismatch: tail call results [i64] differ from function results [i32]"
+        );
+        assert_eq!(
+            validate(&valid).unwrap_err().to_string(),
+            "func[0] $f: instruction 0 (return_call 0): feature not enabled: tail-call"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn validate_atomics() -> Result<()> {
+        use super::validate_with_features;
+        use crate::features::WasmFeatures;
+        //Given
+        let valid = parse(
+            "(memory 1 1 shared)
+             (func (result i32) (i32.atomic.rmw16.sub_u (i32.const 0) (i32.const 1)))",
+        )?;
+        let misaligned =
+            parse("(memory 1 1 shared) (func (drop (i32.atomic.load align=2 (i32.const 0))))")?;
+        let unbounded = parse("(memory 1 shared)")?;
+        let features = WasmFeatures::all();
+        //When
+        let misaligned = validate_with_features(&misaligned, &features).unwrap_err();
+        let unbounded = validate_with_features(&unbounded, &features).unwrap_err();
+        //Then
+        assert!(validate_with_features(&valid, &features).is_ok());
+        assert_eq!(
+            misaligned.to_string(),
+            "func[0]: instruction 1 (i32.atomic.load align=2): alignment must be equal to natural"
+        );
+        assert_eq!(
+            unbounded.to_string(),
+            "memory[0]: shared memory must have maximum"
+        );
+        assert_eq!(
+            validate(&valid).unwrap_err().to_string(),
+            "feature not enabled: threads"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "simd", feature = "multi-memory"))]
+    fn validate_multi_memory() -> Result<()> {
+        use super::validate_with_features;
+        use crate::{binary::module::decode_slice_with_features, features::WasmFeatures};
+        //Given
+        let valid = parse(
+            "(memory 1) (memory $m 1)
+             (func (v128.store $m (i32.const 0) (v128.load 0 (i32.const 0))))",
+        )?;
+        let wasm = wasmer::wat2wasm(b"(memory 1) (func (drop (v128.load 1 (i32.const 0))))")?;
+        let features = WasmFeatures::all();
+        let invalid = decode_slice_with_features(&wasm, &features)?;
+        //When
+        let err = validate_with_features(&invalid, &features).unwrap_err();
+        //Then
+        assert!(validate_with_features(&valid, &features).is_ok());
+        assert_eq!(
+            err.to_string(),
+            "func[0]: instruction 1 (v128.load 1): unknown memory 1"
+        );
+        assert_eq!(
+            validate(&valid).unwrap_err().to_string(),
+            "feature not enabled: multi-memory"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn validate_const_expr_referring_to_defined_globals() -> Result<()> {
+        //Given
+        let module = parse(
+            r#"(global $a i32 (i32.const 1))
+               (global $b i32 (global.get $a))
+               (table 2 funcref) (func)
+               (elem (global.get $b) func 0)
+               (memory 1) (data (global.get $b) "x")"#,
+        )?;
+        //When
+        let result = validate(&module);
+        //Then
+        assert!(result.is_ok(), "{:?}", result);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "reference-types")]
+    fn validate_invalid_module_fields() {
+        let cases = [
+            (
+                "(memory 2 1)",
+                "memory[0]: size minimum must not be greater than maximum",
+            ),
+            ("(memory 65537)", "memory[0]: limits must be at most 65536"),
+            (
+                "(global $g (mut i32) (i32.const 0)) (global i32 (global.get $g))",
+                "global[1]: constant expression cannot refer to a mutable global",
+            ),
+            (
+                "(global i32 (global.get 1)) (global i32 (i32.const 0))",
+                "global[0]: constant expression can only refer to imported globals and globals defined before",
+            ),
+            (
+                "(global i32 (i32.div_s (i32.const 4) (i32.const 2)))",
+                "global[0]: i32.div_s is not a constant instruction",
+            ),
+            (
+                "(func (param i32)) (start 0)",
+                "start function func[0] must have type [] -> []",
+            ),
+            (
+                r#"(func) (export "f" (func 0)) (export "f" (func 0))"#,
+                r#"duplicate export name "f""#,
+            ),
+            (
+                "(table 1 externref) (func) (elem (i32.const 0) func 0)",
+                "elem[0]: type mismatch: element of funcref for table of externref",
+            ),
+        ];
+        for (wat, expected) in cases {
+            assert_eq!(
+                validate_wat(wat).unwrap_err().to_string(),
+                expected,
+                "{}",
+                wat
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "bulk-memory",
+        feature = "multi-memory",
+        feature = "memory64"
+    ))]
+    fn validate_memory64() -> Result<()> {
+        use super::validate_with_features;
+        use crate::features::WasmFeatures;
+        //Given
+        let features = WasmFeatures::all();
+        let valid = parse(
+            r#"(memory i64 1) (memory 1)
+               (func (param i64) (result i64)
+                  (i32.store (local.get 0) (i32.load 1 offset=4294967295 (i32.const 0)))
+                  (memory.copy 0 1 (local.get 0) (i32.const 0) (i32.const 1))
+                  (memory.grow (memory.size)))
+               (data (i64.const 0) "a")"#,
+        )?;
+        let cases = [
+            (
+                "(memory i64 1) (func (drop (i32.load (i32.const 0))))",
+                "func[0]: instruction 1 (i32.load): type mismatch: expected i64, found i32",
+            ),
+            (
+                "(memory 1) (func (drop (i32.load offset=4294967296 (i32.const 0))))",
+                "func[0]: instruction 1 (i32.load offset=4294967296): offset out of range",
+            ),
+            (
+                "(memory i64 281474976710657)",
+                "memory[0]: limits must be at most 281474976710656",
+            ),
+            (
+                r#"(memory i64 1) (data (i32.const 0) "a")"#,
+                "data[0]: end of the function: type mismatch: expected i64, found i32",
+            ),
+        ];
+        //When
+        let errors = cases
+            .iter()
+            .map(|(wat, _)| Ok(validate_with_features(&parse(wat)?, &features).unwrap_err()))
+            .collect::<Result<Vec<_>>>()?;
+        //Then
+        for ((wat, expected), err) in cases.iter().zip(errors) {
+            assert_eq!(err.to_string(), *expected, "{}", wat);
+        }
+        assert!(validate_with_features(&valid, &features).is_ok());
+        assert_eq!(
+            validate(&parse("(memory i64 1)")?).unwrap_err().to_string(),
+            "feature not enabled: memory64"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "multi-value",
+        feature = "reference-types",
+        feature = "exceptions"
+    ))]
+    fn validate_exceptions() -> Result<()> {
+        use super::validate_with_features;
+        use crate::features::WasmFeatures;
+        //Given
+        let features = WasmFeatures::all();
+        let valid = parse(
+            r#"(tag $e (param i32))
+               (func
+                  (block $b (result i32 exnref)
+                     (try_table (catch_ref $e $b) (catch_all 1)
+                        (throw $e (i32.const 1)))
+                     (unreachable))
+                  (throw_ref))"#,
+        )?;
+        let cases = [
+            (
+                "(tag (result i32))",
+                "tag[0]: tag type must not have results",
+            ),
+            (
+                "(tag $e (param i32)) (func (block $b (try_table (catch $e $b))))",
+                "func[0]: instruction 1 (try_table): type mismatch: catch clause passes [i32] to a label of []",
+            ),
+            (
+                "(tag $e (param i64)) (func (throw $e (i32.const 0)))",
+                "func[0]: instruction 1 (throw 0): type mismatch: expected i64, found i32",
+            ),
+            (
+                "(func (throw_ref (ref.null extern)))",
+                "func[0]: instruction 1 (throw_ref): type mismatch: expected exnref, found externref",
+            ),
+        ];
+        //When
+        let errors = cases
+            .iter()
+            .map(|(wat, _)| Ok(validate_with_features(&parse(wat)?, &features).unwrap_err()))
+            .collect::<Result<Vec<_>>>()?;
+        //Then
+        for ((wat, expected), err) in cases.iter().zip(errors) {
+            assert_eq!(err.to_string(), *expected, "{}", wat);
+        }
+        assert!(validate_with_features(&valid, &features).is_ok());
+        assert_eq!(
+            validate(&parse("(tag)")?).unwrap_err().to_string(),
+            "feature not enabled: exceptions"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "reference-types",
+        feature = "bulk-memory",
+        feature = "function-references"
+    ))]
+    fn validate_function_references() -> Result<()> {
+        use super::validate_with_features;
+        use crate::features::WasmFeatures;
+        //Given
+        let features = WasmFeatures::all();
+        let valid = parse(
+            r#"(type $t (func (result i32)))
+               (table 1 (ref null $t))
+               (func $f (type $t) (i32.const 1))
+               (func (param (ref null $t)) (result i32)
+                  (local $l (ref $t))
+                  (local.set $l (ref.as_non_null (local.get 0)))
+                  (table.set (i32.const 0) (local.get $l))
+                  (drop (call_indirect (type $t) (i32.const 0)))
+                  (block $b (result (ref $t))
+                     (br_on_non_null $b (local.get 0))
+                     (ref.func $f))
+                  (call_ref $t))
+               (elem declare func $f)"#,
+        )?;
+        let cases = [
+            (
+                "(type $t (func)) (func (local (ref $t)) (drop (local.get 0)))",
+                "func[0]: instruction 0 (local.get 0): uninitialized local 0",
+            ),
+            (
+                "(type $t (func)) (func (param (ref $t)) (local (ref $t))
+                    (block (local.set 1 (local.get 0)))
+                    (drop (local.get 1)))",
+                "func[0]: instruction 3 (local.get 1): uninitialized local 1",
+            ),
+            (
+                "(type $t (func)) (type $u (func (param i32))) (func (param (ref $u)) (call_ref $t (local.get 0)))",
+                "func[0]: instruction 1 (call_ref 0): type mismatch: expected (ref null 0), found (ref 1)",
+            ),
+            (
+                "(func (param funcref) (result (ref func)) (local.get 0))",
+                "func[0]: end of the function: type mismatch: expected (ref func), found funcref",
+            ),
+            (
+                "(func (block $b (br_on_non_null $b (ref.null func))))",
+                "func[0]: instruction 2 (br_on_non_null 0): type mismatch: br_on_non_null requires a label taking a reference",
+            ),
+            (
+                "(type $t (func)) (table 1 (ref $t))",
+                "table[0]: table of (ref 0) must have an initializer",
+            ),
+        ];
+        //When
+        let errors = cases
+            .iter()
+            .map(|(wat, _)| Ok(validate_with_features(&parse(wat)?, &features).unwrap_err()))
+            .collect::<Result<Vec<_>>>()?;
+        //Then
+        for ((wat, expected), err) in cases.iter().zip(errors) {
+            assert_eq!(err.to_string(), *expected, "{}", wat);
+        }
+        assert!(validate_with_features(&valid, &features).is_ok());
+        assert_eq!(
+            validate(&parse("(func (param (ref func)))")?)
+                .unwrap_err()
+                .to_string(),
+            "feature not enabled: function-references"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "reference-types", feature = "gc"))]
+    fn validate_gc() -> Result<()> {
+        use super::validate_with_features;
+        use crate::features::WasmFeatures;
+        //Given
+        let features = WasmFeatures::all();
+        let valid = parse(
+            r#"(rec
+                 (type $list (sub (struct (field i32) (field (ref null $list)))))
+                 (type $cons (sub final $list (struct (field i32) (field (ref null $list)) (field (mut i8))))))
+               (type $bytes (array (mut i8)))
+               (global $g (ref $bytes) (array.new_default $bytes (i32.const 4)))
+               (func (param (ref $cons)) (result i32)
+                  (struct.set $cons 2 (local.get 0) (i32.const 300))
+                  (drop (ref.cast (ref $list) (local.get 0)))
+                  (array.copy $bytes $bytes (global.get $g) (i32.const 0) (global.get $g) (i32.const 1) (i32.const 2))
+                  (i32.add
+                     (struct.get $list 0 (local.get 0))
+                     (array.get_u $bytes (global.get $g) (i32.const 0))))"#,
+        )?;
+        let cases = [
+            (
+                "(type $p (struct (field i32))) (func (param (ref $p)) (struct.set $p 0 (local.get 0) (i32.const 1)))",
+                "func[0]: instruction 2 (struct.set 0 0): field of i32 is immutable",
+            ),
+            (
+                "(type $a (array i8)) (func (param (ref $a)) (result i32) (array.get $a (local.get 0) (i32.const 0)))",
+                "func[0]: instruction 2 (array.get 0): array.get 0 cannot read a field of i8",
+            ),
+            (
+                "(type $a (sub final (struct))) (type $b (sub $a (struct)))",
+                "$b: supertype 0 is final",
+            ),
+            (
+                "(type $a (sub (struct (field i32)))) (type $b (sub $a (struct (field i64))))",
+                "$b: type mismatch: type does not match its supertype 0",
+            ),
+            (
+                "(type $a (struct (field (ref $b)))) (type $b (struct))",
+                "$a: unknown type 1",
+            ),
+            (
+                "(type $a (struct (field (ref $a)))) (func (drop (struct.new_default $a)))",
+                "func[0]: instruction 0 (struct.new_default 0): field of (ref 0) has no default value",
+            ),
+        ];
+        //When
+        let errors = cases
+            .iter()
+            .map(|(wat, _)| Ok(validate_with_features(&parse(wat)?, &features).unwrap_err()))
+            .collect::<Result<Vec<_>>>()?;
+        //Then
+        for ((wat, expected), err) in cases.iter().zip(errors) {
+            assert_eq!(err.to_string(), *expected, "{}", wat);
+        }
+        assert!(validate_with_features(&valid, &features).is_ok());
+        assert_eq!(
+            validate(&parse("(type (struct))")?)
+                .unwrap_err()
+                .to_string(),
+            "feature not enabled: gc"
+        );
+        Ok(())
+    }
+}
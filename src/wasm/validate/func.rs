@@ -0,0 +1,970 @@
+use anyhow::*;
+
+use super::{ensure_index, ValidationContext};
+use crate::structure::{
+    instructions::{
+        atomic::{AtomicKind, AtomicOp},
+        memory::{MemoryKind, MemoryOp},
+        vector::{VectorKind, VectorOp},
+        BlockType, Catch, Expr, Instruction, MemArg,
+    },
+    module::indices::{FieldIdx, TypeIdx},
+    types::{
+        FieldType, FuncType, HeapType, IndexType, MemType, Mut, NumType, RefType, ResultType,
+        StorageType, TableType, ValType,
+    },
+};
+
+const I32: ValType = ValType::Number(NumType::I32);
+const I64: ValType = ValType::Number(NumType::I64);
+const V128: ValType = ValType::Vec;
+
+/// https://webassembly.github.io/spec/core/valid/modules.html#functions
+pub fn validate_func(
+    ctx: &ValidationContext,
+    type_: TypeIdx,
+    locals: &[ValType],
+    expr: &Expr,
+) -> Result<()> {
+    let FuncType(ResultType(params), ResultType(results)) = ctx.func_type(type_)?;
+    for local in locals {
+        ctx.val_type(*local)?;
+    }
+    let mut validator =
+        Validator::new(ctx, params.iter().chain(locals).copied().collect(), results);
+    // parameters are given by the caller, while locals without defaults have to be set first
+    for (init, local) in validator.inits[params.len()..].iter_mut().zip(locals) {
+        *init = local.is_defaultable();
+    }
+    validator.validate(expr)
+}
+
+/// validate instructions producing `results` without any local
+pub fn validate_expr(ctx: &ValidationContext, expr: &Expr, results: &[ValType]) -> Result<()> {
+    Validator::new(ctx, vec![], results).validate(expr)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Func,
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+/// https://webassembly.github.io/spec/core/appendix/algorithm.html#data-structures
+struct Frame {
+    kind: FrameKind,
+    start_types: Vec<ValType>,
+    end_types: Vec<ValType>,
+    height: usize,
+    /// the height of the stack of locals initialized inside, which are reset at the end
+    init_height: usize,
+    unreachable: bool,
+}
+
+impl Frame {
+    fn label_types(&self) -> &[ValType] {
+        match self.kind {
+            FrameKind::Loop => &self.start_types,
+            _ => &self.end_types,
+        }
+    }
+}
+
+/// The operand stack and the control stack of the validation algorithm,
+/// where `None` on the operand stack stands for the unknown type
+struct Validator<'a> {
+    ctx: &'a ValidationContext<'a>,
+    locals: Vec<ValType>,
+    /// whether each local has been initialized
+    /// https://webassembly.github.io/function-references/core/appendix/algorithm.html#data-structures
+    inits: Vec<bool>,
+    init_stack: Vec<u32>,
+    returns: &'a [ValType],
+    vals: Vec<Option<ValType>>,
+    ctrls: Vec<Frame>,
+    /// the number of instructions visited, nested ones included
+    num_of_instrs: usize,
+}
+
+impl<'a> Validator<'a> {
+    fn new(ctx: &'a ValidationContext<'a>, locals: Vec<ValType>, returns: &'a [ValType]) -> Self {
+        Self {
+            ctx,
+            inits: vec![true; locals.len()],
+            init_stack: vec![],
+            locals,
+            returns,
+            vals: vec![],
+            ctrls: vec![],
+            num_of_instrs: 0,
+        }
+    }
+
+    fn validate(mut self, expr: &Expr) -> Result<()> {
+        self.push_ctrl(FrameKind::Func, vec![], self.returns.to_vec());
+        self.expr(expr)?;
+        self.pop_ctrl().context("end of the function")?;
+        Ok(())
+    }
+
+    fn push_val(&mut self, val: impl Into<Option<ValType>>) {
+        self.vals.push(val.into());
+    }
+
+    fn pop_val(&mut self) -> Result<Option<ValType>> {
+        let frame = self.ctrls.last().unwrap();
+        if self.vals.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            bail!("type mismatch: the operand stack is empty")
+        }
+        Ok(self.vals.pop().unwrap())
+    }
+
+    fn pop_expect(&mut self, expected: ValType) -> Result<Option<ValType>> {
+        let actual = self.pop_val()?;
+        match actual {
+            Some(actual) if !self.ctx.matches(actual, expected) => {
+                bail!("type mismatch: expected {}, found {}", expected, actual)
+            }
+            _ => Ok(actual.or(Some(expected))),
+        }
+    }
+
+    fn push_vals(&mut self, vals: &[ValType]) {
+        self.vals.extend(vals.iter().copied().map(Some));
+    }
+
+    fn pop_vals(&mut self, vals: &[ValType]) -> Result<Vec<Option<ValType>>> {
+        let mut popped = vec![];
+        for val in vals.iter().rev() {
+            popped.insert(0, self.pop_expect(*val)?);
+        }
+        Ok(popped)
+    }
+
+    fn push_ctrl(&mut self, kind: FrameKind, start_types: Vec<ValType>, end_types: Vec<ValType>) {
+        let height = self.vals.len();
+        self.push_vals(&start_types);
+        self.ctrls.push(Frame {
+            kind,
+            start_types,
+            end_types,
+            height,
+            init_height: self.init_stack.len(),
+            unreachable: false,
+        });
+    }
+
+    fn pop_ctrl(&mut self) -> Result<Frame> {
+        let end_types = self.ctrls.last().unwrap().end_types.clone();
+        self.pop_vals(&end_types)?;
+        let frame = self.ctrls.last().unwrap();
+        if self.vals.len() != frame.height {
+            bail!(
+                "type mismatch: {} values are left on the operand stack",
+                self.vals.len() - frame.height
+            )
+        }
+        let frame = self.ctrls.pop().unwrap();
+        for idx in self.init_stack.drain(frame.init_height..) {
+            self.inits[idx as usize] = false;
+        }
+        Ok(frame)
+    }
+
+    fn unreachable(&mut self) {
+        let frame = self.ctrls.last_mut().unwrap();
+        self.vals.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn label(&self, depth: u32) -> Result<&Frame> {
+        ensure_index(depth, self.ctrls.len(), "label")?;
+        Ok(&self.ctrls[self.ctrls.len() - 1 - depth as usize])
+    }
+
+    /// pop a reference, which is None for the unknown type
+    fn pop_ref(&mut self) -> Result<Option<RefType>> {
+        match self.pop_val()? {
+            Some(ValType::Ref(ref_type)) => Ok(Some(ref_type)),
+            Some(val) => bail!("type mismatch: expected a reference, found {}", val),
+            None => Ok(None),
+        }
+    }
+
+    fn set_local(&mut self, idx: u32) {
+        if !self.inits[idx as usize] {
+            self.inits[idx as usize] = true;
+            self.init_stack.push(idx);
+        }
+    }
+
+    /// https://github.com/WebAssembly/tail-call/blob/main/proposals/tail-call/Overview.md#validation
+    fn tail_call(&mut self, params: &[ValType], results: &[ValType]) -> Result<()> {
+        if !self.ctx.matches_all(results, self.returns) {
+            bail!(
+                "type mismatch: tail call results [{}] differ from function results [{}]",
+                type_list(results),
+                type_list(self.returns)
+            )
+        }
+        self.pop_vals(params)?;
+        self.unreachable();
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<()> {
+        for instr in expr {
+            let pos = self.num_of_instrs;
+            self.num_of_instrs += 1;
+            let at = |e: Error| anyhow!("instruction {} ({}): {}", pos, describe(instr), e);
+            self.ctx.features.check_instruction(instr).map_err(at)?;
+            match instr {
+                Instruction::Block(block_type, body) | Instruction::Loop(block_type, body) => {
+                    let kind = match instr {
+                        Instruction::Block(..) => FrameKind::Block,
+                        _ => FrameKind::Loop,
+                    };
+                    let (params, results) = self.block_type(block_type).map_err(at)?;
+                    self.pop_vals(&params).map_err(at)?;
+                    self.push_ctrl(kind, params, results);
+                    self.expr(body)?;
+                    self.end().map_err(at)?;
+                }
+                Instruction::TryTable(block_type, catches, body) => {
+                    let (params, results) = self.block_type(block_type).map_err(at)?;
+                    for catch in catches {
+                        self.catch(catch).map_err(at)?;
+                    }
+                    self.pop_vals(&params).map_err(at)?;
+                    self.push_ctrl(FrameKind::Block, params, results);
+                    self.expr(body)?;
+                    self.end().map_err(at)?;
+                }
+                Instruction::If(block_type, then, else_) => {
+                    let (params, results) = self.block_type(block_type).map_err(at)?;
+                    self.pop_expect(I32).map_err(at)?;
+                    self.pop_vals(&params).map_err(at)?;
+                    self.push_ctrl(FrameKind::If, params, results);
+                    self.expr(then)?;
+                    if let Some(else_) = else_ {
+                        let frame = self.pop_ctrl().map_err(at)?;
+                        self.push_ctrl(FrameKind::Else, frame.start_types, frame.end_types);
+                        self.expr(else_)?;
+                    }
+                    self.end().map_err(at)?;
+                }
+                _ => self.instr(instr).map_err(at)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        let frame = self.pop_ctrl()?;
+        if frame.kind == FrameKind::If && frame.start_types != frame.end_types {
+            bail!("type mismatch: if without else must leave the same types as its parameters")
+        }
+        self.push_vals(&frame.end_types);
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/valid/instructions.html#valid-blocktype
+    fn block_type(&self, block_type: &BlockType) -> Result<(Vec<ValType>, Vec<ValType>)> {
+        Ok(match block_type {
+            BlockType::Empty => (vec![], vec![]),
+            BlockType::ValType(val) => {
+                self.ctx.val_type(*val)?;
+                (vec![], vec![*val])
+            }
+            BlockType::TypeIdx(idx) => {
+                let FuncType(ResultType(params), ResultType(results)) = self.ctx.func_type(*idx)?;
+                (params.clone(), results.clone())
+            }
+        })
+    }
+
+    /// the types of the values which exceptions of the tag carry
+    fn tag(&self, idx: u32) -> Result<&'a [ValType]> {
+        ensure_index(idx, self.ctx.tags.len(), "tag")?;
+        let FuncType(ResultType(params), _) = self.ctx.tags[idx as usize];
+        Ok(params)
+    }
+
+    /// the label of a catch clause is outside of its try_table
+    /// https://webassembly.github.io/exception-handling/core/valid/instructions.html#valid-catch
+    fn catch(&self, catch: &Catch) -> Result<()> {
+        let mut types = match catch.tag() {
+            Some(tag) => self.tag(tag)?.to_vec(),
+            None => vec![],
+        };
+        if catch.is_ref() {
+            types.push(ValType::Ref(RefType::EXNREF));
+        }
+        let label_types = self.label(catch.label())?.label_types();
+        if !self.ctx.matches_all(&types, label_types) {
+            bail!(
+                "type mismatch: catch clause passes [{}] to a label of [{}]",
+                type_list(&types),
+                type_list(label_types)
+            )
+        }
+        Ok(())
+    }
+
+    fn local(&self, idx: u32) -> Result<ValType> {
+        ensure_index(idx, self.locals.len(), "local")?;
+        Ok(self.locals[idx as usize])
+    }
+
+    fn table(&self, idx: u32) -> Result<RefType> {
+        ensure_index(idx, self.ctx.tables.len(), "table")?;
+        let TableType(_, ref_type) = self.ctx.tables[idx as usize];
+        Ok(*ref_type)
+    }
+
+    fn elem(&self, idx: u32) -> Result<RefType> {
+        ensure_index(idx, self.ctx.elems.len(), "element segment")?;
+        Ok(self.ctx.elems[idx as usize])
+    }
+
+    fn field(&self, type_: TypeIdx, idx: FieldIdx) -> Result<FieldType> {
+        let fields = self.ctx.struct_type(type_)?;
+        ensure_index(idx, fields.len(), "field")?;
+        Ok(fields[idx as usize])
+    }
+
+    /// the element segment has to provide the references which the array holds
+    fn elem_of(&self, idx: u32, FieldType(_, storage): FieldType) -> Result<()> {
+        let elem_type = self.elem(idx)?;
+        if !self.ctx.matches(ValType::Ref(elem_type), storage.unpack()) {
+            bail!(
+                "type mismatch: element of {} for array of {}",
+                elem_type,
+                storage
+            )
+        }
+        Ok(())
+    }
+
+    /// the type of addresses into the memory
+    /// https://webassembly.github.io/memory64/core/valid/instructions.html#memory-instructions
+    fn memory(&self, idx: u32) -> Result<IndexType> {
+        ensure_index(idx, self.ctx.mems.len(), "memory")?;
+        let MemType(limits, _) = self.ctx.mems[idx as usize];
+        Ok(limits.index)
+    }
+
+    /// returns the type of the address which the instruction takes
+    /// https://webassembly.github.io/spec/core/valid/instructions.html#memory-instructions
+    fn memarg(&self, memarg: &MemArg, width: u32) -> Result<ValType> {
+        let index = self.memory(memarg.memory)?;
+        if 1u64
+            .checked_shl(memarg.align)
+            .is_none_or(|align| align > width as u64)
+        {
+            bail!("alignment must not be larger than natural")
+        }
+        if index == IndexType::I32 && memarg.offset > u32::MAX as u64 {
+            bail!("offset out of range")
+        }
+        Ok(index.into())
+    }
+
+    /// https://webassembly.github.io/spec/core/valid/instructions.html#memory-instructions
+    fn load_store(&mut self, op: MemoryOp, memarg: &MemArg) -> Result<()> {
+        let kind = op.kind();
+        let addr = self.memarg(memarg, kind.width())?;
+        match kind {
+            MemoryKind::Load(t, _, _) => {
+                self.pop_expect(addr)?;
+                self.push_val(ValType::Number(t));
+            }
+            MemoryKind::Store(t, _) => {
+                self.pop_vals(&[addr, ValType::Number(t)])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/valid/instructions.html#vector-instructions
+    fn vector(&mut self, op: VectorOp, memarg: Option<&MemArg>, lane: Option<u8>) -> Result<()> {
+        use VectorKind::*;
+        let kind = op.kind();
+        let addr = match (memarg, kind.width()) {
+            (Some(memarg), Some(width)) => self.memarg(memarg, width)?,
+            (None, None) => I32,
+            _ => bail!("invalid immediates of {}", op.name()),
+        };
+        match (lane, kind.lanes()) {
+            (Some(lane), Some(lanes)) if lane >= lanes => bail!("invalid lane index {}", lane),
+            (Some(_), Some(_)) | (None, None) => {}
+            _ => bail!("invalid immediates of {}", op.name()),
+        }
+        let (params, results) = match kind {
+            Load(_) => (vec![addr], vec![V128]),
+            Store | StoreLane(_) => (vec![addr, V128], vec![]),
+            LoadLane(_) => (vec![addr, V128], vec![V128]),
+            ExtractLane(_, t) => (vec![V128], vec![ValType::Number(t)]),
+            ReplaceLane(_, t) => (vec![V128, ValType::Number(t)], vec![V128]),
+            Splat(t) => (vec![ValType::Number(t)], vec![V128]),
+            Unary => (vec![V128], vec![V128]),
+            Binary => (vec![V128, V128], vec![V128]),
+            Ternary => (vec![V128, V128, V128], vec![V128]),
+            Test => (vec![V128], vec![I32]),
+            Shift => (vec![V128, I32], vec![V128]),
+        };
+        self.pop_vals(&params)?;
+        self.push_vals(&results);
+        Ok(())
+    }
+
+    /// atomic accesses must be aligned exactly to their widths
+    /// https://webassembly.github.io/threads/core/valid/instructions.html#atomic-memory-instructions
+    fn atomic(&mut self, op: AtomicOp, memarg: &MemArg) -> Result<()> {
+        use AtomicKind::*;
+        let kind = op.kind();
+        let addr = self.memarg(memarg, kind.width())?;
+        if 1u64.checked_shl(memarg.align) != Some(kind.width() as u64) {
+            bail!("alignment must be equal to natural")
+        }
+        let (params, results) = match kind {
+            Notify => (vec![addr, I32], vec![I32]),
+            Wait(t) => (vec![addr, ValType::Number(t), I64], vec![I32]),
+            Load(t, _) => (vec![addr], vec![ValType::Number(t)]),
+            Store(t, _) => (vec![addr, ValType::Number(t)], vec![]),
+            Rmw(t, _, _) => (vec![addr, ValType::Number(t)], vec![ValType::Number(t)]),
+            Cmpxchg(t, _) => (
+                vec![addr, ValType::Number(t), ValType::Number(t)],
+                vec![ValType::Number(t)],
+            ),
+        };
+        self.pop_vals(&params)?;
+        self.push_vals(&results);
+        Ok(())
+    }
+
+    /// https://webassembly.github.io/spec/core/valid/instructions.html
+    fn instr(&mut self, instr: &Instruction) -> Result<()> {
+        use Instruction::*;
+        match instr {
+            Unreachable => self.unreachable(),
+            Nop => {}
+            Br(depth) => {
+                let label_types = self.label(*depth)?.label_types().to_vec();
+                self.pop_vals(&label_types)?;
+                self.unreachable();
+            }
+            BrIf(depth) => {
+                self.pop_expect(I32)?;
+                let label_types = self.label(*depth)?.label_types().to_vec();
+                self.pop_vals(&label_types)?;
+                self.push_vals(&label_types);
+            }
+            BrTable(depths, default) => {
+                self.pop_expect(I32)?;
+                let default_types = self.label(*default)?.label_types().to_vec();
+                for depth in depths {
+                    let label_types = self.label(*depth)?.label_types().to_vec();
+                    if label_types.len() != default_types.len() {
+                        bail!("type mismatch: labels of br_table have different arities")
+                    }
+                    let vals = self.pop_vals(&label_types)?;
+                    self.vals.extend(vals);
+                }
+                self.pop_vals(&default_types)?;
+                self.unreachable();
+            }
+            Return => {
+                self.pop_vals(self.returns)?;
+                self.unreachable();
+            }
+            Call(idx) => {
+                let FuncType(ResultType(params), ResultType(results)) = self.ctx.func(*idx)?;
+                self.pop_vals(params)?;
+                self.push_vals(results);
+            }
+            CallIndirect(table, type_) => {
+                if !self.ctx.matches_ref(self.table(*table)?, RefType::FUNCREF) {
+                    bail!("type mismatch: call_indirect requires a table of funcref")
+                }
+                let FuncType(ResultType(params), ResultType(results)) =
+                    self.ctx.func_type(*type_)?;
+                self.pop_expect(I32)?;
+                self.pop_vals(params)?;
+                self.push_vals(results);
+            }
+            ReturnCall(idx) => {
+                let FuncType(ResultType(params), ResultType(results)) = self.ctx.func(*idx)?;
+                self.tail_call(params, results)?;
+            }
+            ReturnCallIndirect(table, type_) => {
+                if !self.ctx.matches_ref(self.table(*table)?, RefType::FUNCREF) {
+                    bail!("type mismatch: return_call_indirect requires a table of funcref")
+                }
+                let FuncType(ResultType(params), ResultType(results)) =
+                    self.ctx.func_type(*type_)?;
+                self.pop_expect(I32)?;
+                self.tail_call(params, results)?;
+            }
+            Throw(idx) => {
+                let params = self.tag(*idx)?;
+                self.pop_vals(params)?;
+                self.unreachable();
+            }
+            ThrowRef => {
+                self.pop_expect(ValType::Ref(RefType::EXNREF))?;
+                self.unreachable();
+            }
+            // https://webassembly.github.io/function-references/core/valid/instructions.html#control-instructions
+            CallRef(type_) => {
+                let FuncType(ResultType(params), ResultType(results)) =
+                    self.ctx.func_type(*type_)?;
+                self.pop_expect(ValType::Ref(RefType::null(HeapType::Type(*type_))))?;
+                self.pop_vals(params)?;
+                self.push_vals(results);
+            }
+            ReturnCallRef(type_) => {
+                let FuncType(ResultType(params), ResultType(results)) =
+                    self.ctx.func_type(*type_)?;
+                self.pop_expect(ValType::Ref(RefType::null(HeapType::Type(*type_))))?;
+                self.tail_call(params, results)?;
+            }
+            BrOnNull(depth) => {
+                let ref_type = self.pop_ref()?;
+                let label_types = self.label(*depth)?.label_types().to_vec();
+                self.pop_vals(&label_types)?;
+                self.push_vals(&label_types);
+                self.push_val(ref_type.map(|r| ValType::Ref(RefType::non_null(r.heap))));
+            }
+            BrOnNonNull(depth) => {
+                let mut label_types = self.label(*depth)?.label_types().to_vec();
+                let Some(ValType::Ref(ref_type)) = label_types.pop() else {
+                    bail!("type mismatch: br_on_non_null requires a label taking a reference")
+                };
+                self.pop_expect(ValType::Ref(RefType::null(ref_type.heap)))?;
+                self.pop_vals(&label_types)?;
+                self.push_vals(&label_types);
+            }
+            // https://webassembly.github.io/gc/core/valid/instructions.html#control-instructions
+            BrOnCast(depth, from, to) | BrOnCastFail(depth, from, to) => {
+                self.ctx.val_type(ValType::Ref(*from))?;
+                self.ctx.val_type(ValType::Ref(*to))?;
+                if !self.ctx.matches_ref(*to, *from) {
+                    bail!("type mismatch: {} is not a subtype of {}", to, from)
+                }
+                // the type of the references which fail the cast
+                let diff = RefType {
+                    nullable: from.nullable && !to.nullable,
+                    heap: from.heap,
+                };
+                let (taken, left) = match instr {
+                    BrOnCast(..) => (*to, diff),
+                    _ => (diff, *to),
+                };
+                let mut label_types = self.label(*depth)?.label_types().to_vec();
+                let Some(ValType::Ref(label_type)) = label_types.pop() else {
+                    bail!(
+                        "type mismatch: {} requires a label taking a reference",
+                        instr
+                    )
+                };
+                if !self.ctx.matches_ref(taken, label_type) {
+                    bail!(
+                        "type mismatch: {} passes {} to a label of {}",
+                        instr,
+                        taken,
+                        label_type
+                    )
+                }
+                self.pop_expect(ValType::Ref(*from))?;
+                self.pop_vals(&label_types)?;
+                self.push_vals(&label_types);
+                self.push_val(ValType::Ref(left));
+            }
+            RefNull(heap) => {
+                let ref_type = ValType::Ref(RefType::null(*heap));
+                self.ctx.val_type(ref_type)?;
+                self.push_val(ref_type);
+            }
+            RefIsNull => {
+                self.pop_ref()?;
+                self.push_val(I32);
+            }
+            RefAsNonNull => {
+                let ref_type = self.pop_ref()?;
+                self.push_val(ref_type.map(|r| ValType::Ref(RefType::non_null(r.heap))));
+            }
+            // https://webassembly.github.io/gc/core/valid/instructions.html#reference-instructions
+            RefEq => {
+                let eqref = ValType::Ref(RefType::null(HeapType::Eq));
+                self.pop_vals(&[eqref, eqref])?;
+                self.push_val(I32);
+            }
+            RefTest(ref_type) | RefCast(ref_type) => {
+                self.ctx.val_type(ValType::Ref(*ref_type))?;
+                let top = self.ctx.module.top_heap(ref_type.heap);
+                self.pop_expect(ValType::Ref(RefType::null(top)))?;
+                self.push_val(match instr {
+                    RefTest(_) => I32,
+                    _ => ValType::Ref(*ref_type),
+                });
+            }
+            StructNew(type_) => {
+                let fields = self.ctx.struct_type(*type_)?;
+                let types: Vec<ValType> = fields.iter().map(|f| f.1.unpack()).collect();
+                self.pop_vals(&types)?;
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::Type(*type_))));
+            }
+            StructNewDefault(type_) => {
+                for field in self.ctx.struct_type(*type_)? {
+                    defaultable(*field)?;
+                }
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::Type(*type_))));
+            }
+            StructGet(type_, idx) | StructGetS(type_, idx) | StructGetU(type_, idx) => {
+                let field = self.field(*type_, *idx)?;
+                unpacked(instr, field, matches!(instr, StructGet(..)))?;
+                self.pop_expect(ValType::Ref(RefType::null(HeapType::Type(*type_))))?;
+                self.push_val(field.1.unpack());
+            }
+            StructSet(type_, idx) => {
+                let field = mutable(self.field(*type_, *idx)?)?;
+                self.pop_expect(field.1.unpack())?;
+                self.pop_expect(ValType::Ref(RefType::null(HeapType::Type(*type_))))?;
+            }
+            ArrayNew(type_) => {
+                let field = self.ctx.array_type(*type_)?;
+                self.pop_vals(&[field.1.unpack(), I32])?;
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::Type(*type_))));
+            }
+            ArrayNewDefault(type_) => {
+                defaultable(self.ctx.array_type(*type_)?)?;
+                self.pop_expect(I32)?;
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::Type(*type_))));
+            }
+            ArrayNewFixed(type_, n) => {
+                let field = self.ctx.array_type(*type_)?;
+                self.pop_vals(&vec![field.1.unpack(); *n as usize])?;
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::Type(*type_))));
+            }
+            ArrayNewData(type_, data) => {
+                numeric(self.ctx.array_type(*type_)?)?;
+                ensure_index(*data, self.ctx.num_of_datas, "data segment")?;
+                self.pop_vals(&[I32, I32])?;
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::Type(*type_))));
+            }
+            ArrayNewElem(type_, elem) => {
+                let field = self.ctx.array_type(*type_)?;
+                self.elem_of(*elem, field)?;
+                self.pop_vals(&[I32, I32])?;
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::Type(*type_))));
+            }
+            ArrayGet(type_) | ArrayGetS(type_) | ArrayGetU(type_) => {
+                let field = self.ctx.array_type(*type_)?;
+                unpacked(instr, field, matches!(instr, ArrayGet(_)))?;
+                let array = ValType::Ref(RefType::null(HeapType::Type(*type_)));
+                self.pop_vals(&[array, I32])?;
+                self.push_val(field.1.unpack());
+            }
+            ArraySet(type_) => {
+                let field = mutable(self.ctx.array_type(*type_)?)?;
+                let array = ValType::Ref(RefType::null(HeapType::Type(*type_)));
+                self.pop_vals(&[array, I32, field.1.unpack()])?;
+            }
+            ArrayLen => {
+                self.pop_expect(ValType::Ref(RefType::null(HeapType::Array)))?;
+                self.push_val(I32);
+            }
+            ArrayFill(type_) => {
+                let field = mutable(self.ctx.array_type(*type_)?)?;
+                let array = ValType::Ref(RefType::null(HeapType::Type(*type_)));
+                self.pop_vals(&[array, I32, field.1.unpack(), I32])?;
+            }
+            ArrayCopy(dst, src) => {
+                let dst_field = mutable(self.ctx.array_type(*dst)?)?;
+                let src_field = self.ctx.array_type(*src)?;
+                let storage_matches = match (src_field.1, dst_field.1) {
+                    (StorageType::Val(s), StorageType::Val(d)) => self.ctx.matches(s, d),
+                    (s, d) => s == d,
+                };
+                if !storage_matches {
+                    bail!(
+                        "type mismatch: copy from an array of {} to {}",
+                        src_field.1,
+                        dst_field.1
+                    )
+                }
+                let dst = ValType::Ref(RefType::null(HeapType::Type(*dst)));
+                let src = ValType::Ref(RefType::null(HeapType::Type(*src)));
+                self.pop_vals(&[dst, I32, src, I32, I32])?;
+            }
+            ArrayInitData(type_, data) => {
+                numeric(mutable(self.ctx.array_type(*type_)?)?)?;
+                ensure_index(*data, self.ctx.num_of_datas, "data segment")?;
+                let array = ValType::Ref(RefType::null(HeapType::Type(*type_)));
+                self.pop_vals(&[array, I32, I32, I32])?;
+            }
+            ArrayInitElem(type_, elem) => {
+                let field = mutable(self.ctx.array_type(*type_)?)?;
+                self.elem_of(*elem, field)?;
+                let array = ValType::Ref(RefType::null(HeapType::Type(*type_)));
+                self.pop_vals(&[array, I32, I32, I32])?;
+            }
+            // conversions keep the nullability of the references
+            AnyConvertExtern | ExternConvertAny => {
+                let (from, to) = match instr {
+                    AnyConvertExtern => (HeapType::Extern, HeapType::Any),
+                    _ => (HeapType::Any, HeapType::Extern),
+                };
+                let actual = self.pop_expect(ValType::Ref(RefType::null(from)))?;
+                let nullable = !matches!(actual, Some(ValType::Ref(r)) if !r.nullable);
+                self.push_val(ValType::Ref(RefType { nullable, heap: to }));
+            }
+            RefI31 => {
+                self.pop_expect(I32)?;
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::I31)));
+            }
+            I31GetS | I31GetU => {
+                self.pop_expect(ValType::Ref(RefType::null(HeapType::I31)))?;
+                self.push_val(I32);
+            }
+            RefFunc(idx) => {
+                ensure_index(*idx, self.ctx.funcs.len(), "function")?;
+                if !self.ctx.refs.contains(idx) {
+                    bail!(
+                        "undeclared function reference {}",
+                        self.ctx.names.func(*idx)
+                    )
+                }
+                let type_ = self.ctx.funcs[*idx as usize];
+                self.push_val(ValType::Ref(RefType::non_null(HeapType::Type(type_))));
+            }
+            Drop => {
+                self.pop_val()?;
+            }
+            Select(None) => {
+                self.pop_expect(I32)?;
+                let (t1, t2) = (self.pop_val()?, self.pop_val()?);
+                if let Some(t) = t1.or(t2).filter(|t| matches!(t, ValType::Ref(_))) {
+                    bail!("type mismatch: select without types cannot take {}", t)
+                }
+                if let (Some(t1), Some(t2)) = (t1, t2) {
+                    if t1 != t2 {
+                        bail!("type mismatch: select takes {} and {}", t2, t1)
+                    }
+                }
+                self.push_val(t1.or(t2));
+            }
+            Select(Some(types)) => {
+                let [t] = types.as_slice() else {
+                    bail!("invalid result arity of select: {}", types.len())
+                };
+                self.ctx.val_type(*t)?;
+                self.pop_expect(I32)?;
+                self.pop_expect(*t)?;
+                self.pop_expect(*t)?;
+                self.push_val(*t);
+            }
+            LocalGet(idx) => {
+                let local = self.local(*idx)?;
+                if !self.inits[*idx as usize] {
+                    bail!("uninitialized local {}", idx)
+                }
+                self.push_val(local);
+            }
+            LocalSet(idx) => {
+                let local = self.local(*idx)?;
+                self.pop_expect(local)?;
+                self.set_local(*idx);
+            }
+            LocalTee(idx) => {
+                let local = self.local(*idx)?;
+                self.pop_expect(local)?;
+                self.set_local(*idx);
+                self.push_val(local);
+            }
+            GlobalGet(idx) => {
+                ensure_index(*idx, self.ctx.globals.len(), "global")?;
+                self.push_val(self.ctx.globals[*idx as usize].1);
+            }
+            GlobalSet(idx) => {
+                ensure_index(*idx, self.ctx.globals.len(), "global")?;
+                let global = self.ctx.globals[*idx as usize];
+                if global.0 != Mut::Var {
+                    bail!("global {} is immutable", self.ctx.names.global(*idx))
+                }
+                self.pop_expect(global.1)?;
+            }
+            TableGet(idx) => {
+                let ref_type = self.table(*idx)?;
+                self.pop_expect(I32)?;
+                self.push_val(ValType::Ref(ref_type));
+            }
+            TableSet(idx) => {
+                let ref_type = self.table(*idx)?;
+                self.pop_expect(ValType::Ref(ref_type))?;
+                self.pop_expect(I32)?;
+            }
+            TableSize(idx) => {
+                self.table(*idx)?;
+                self.push_val(I32);
+            }
+            TableGrow(idx) => {
+                let ref_type = self.table(*idx)?;
+                self.pop_expect(I32)?;
+                self.pop_expect(ValType::Ref(ref_type))?;
+                self.push_val(I32);
+            }
+            TableFill(idx) => {
+                let ref_type = self.table(*idx)?;
+                self.pop_expect(I32)?;
+                self.pop_expect(ValType::Ref(ref_type))?;
+                self.pop_expect(I32)?;
+            }
+            TableCopy(dst, src) => {
+                let (dst_type, src_type) = (self.table(*dst)?, self.table(*src)?);
+                if !self.ctx.matches_ref(src_type, dst_type) {
+                    bail!(
+                        "type mismatch: copy from a table of {} to {}",
+                        src_type,
+                        dst_type
+                    )
+                }
+                self.pop_vals(&[I32, I32, I32])?;
+            }
+            TableInit(elem, table) => {
+                let (elem_type, table_type) = (self.elem(*elem)?, self.table(*table)?);
+                if !self.ctx.matches_ref(elem_type, table_type) {
+                    bail!(
+                        "type mismatch: element of {} for table of {}",
+                        elem_type,
+                        table_type
+                    )
+                }
+                self.pop_vals(&[I32, I32, I32])?;
+            }
+            TableDrop(elem) => {
+                self.elem(*elem)?;
+            }
+            Memory(op, memarg) => self.load_store(*op, memarg)?,
+            MemorySize(idx) => {
+                let addr: ValType = self.memory(*idx)?.into();
+                self.push_val(addr);
+            }
+            MemoryGrow(idx) => {
+                let addr: ValType = self.memory(*idx)?.into();
+                self.pop_expect(addr)?;
+                self.push_val(addr);
+            }
+            MemoryFill(idx) => {
+                let addr: ValType = self.memory(*idx)?.into();
+                self.pop_vals(&[addr, I32, addr])?;
+            }
+            MemoryCopy(dst, src) => {
+                let (dst, src) = (self.memory(*dst)?, self.memory(*src)?);
+                // the length has to fit both memories
+                let len = if dst == IndexType::I64 && src == IndexType::I64 {
+                    I64
+                } else {
+                    I32
+                };
+                self.pop_vals(&[dst.into(), src.into(), len])?;
+            }
+            MemoryInit(data, idx) => {
+                let addr: ValType = self.memory(*idx)?.into();
+                ensure_index(*data, self.ctx.num_of_datas, "data segment")?;
+                self.pop_vals(&[addr, I32, I32])?;
+            }
+            DataDrop(data) => ensure_index(*data, self.ctx.num_of_datas, "data segment")?,
+            I32Const(_) => self.push_val(I32),
+            I64Const(_) => self.push_val(I64),
+            I32Eqz | I32Clz | I32Ctz | I32Popcnt | I32Extend8S | I32Extend16S => {
+                self.pop_expect(I32)?;
+                self.push_val(I32);
+            }
+            I32Eq | I32Ne | I32LtS | I32LtU | I32GtS | I32GtU | I32LeS | I32LeU | I32GeS
+            | I32GeU | I32Add | I32Sub | I32Mul | I32DivS | I32DivU | I32RemS | I32RemU
+            | I32And | I32Or | I32Xor | I32ShL | I32ShrS | I32ShrU | I32RtoL | I32RtoR => {
+                self.pop_vals(&[I32, I32])?;
+                self.push_val(I32);
+            }
+            I64Add | I64Sub | I64Mul => {
+                self.pop_vals(&[I64, I64])?;
+                self.push_val(I64);
+            }
+            V128Const(_) => self.push_val(V128),
+            I8x16Shuffle(lanes) => {
+                if let Some(lane) = lanes.iter().find(|lane| **lane >= 32) {
+                    bail!("invalid lane index {}", lane)
+                }
+                self.pop_vals(&[V128, V128])?;
+                self.push_val(V128);
+            }
+            Vector(op) => self.vector(*op, None, None)?,
+            VectorLane(op, lane) => self.vector(*op, None, Some(*lane))?,
+            VectorMem(op, memarg) => self.vector(*op, Some(memarg), None)?,
+            VectorMemLane(op, memarg, lane) => self.vector(*op, Some(memarg), Some(*lane))?,
+            AtomicFence => {}
+            Atomic(op, memarg) => self.atomic(*op, memarg)?,
+            Block(..) | Loop(..) | If(..) | TryTable(..) => {
+                unreachable!("validated as a nested expression")
+            }
+            Else | End | Void => bail!("unexpected {}", instr),
+        }
+        Ok(())
+    }
+}
+
+/// https://webassembly.github.io/gc/core/valid/instructions.html#aggregate-reference-instructions
+fn defaultable(FieldType(_, storage): FieldType) -> Result<()> {
+    if !storage.unpack().is_defaultable() {
+        bail!("field of {} has no default value", storage)
+    }
+    Ok(())
+}
+
+fn mutable(field: FieldType) -> Result<FieldType> {
+    if field.0 != Mut::Var {
+        bail!("field of {} is immutable", field.1)
+    }
+    Ok(field)
+}
+
+/// bytes of data segments are read as numbers
+fn numeric(field: FieldType) -> Result<FieldType> {
+    if matches!(field.1, StorageType::Val(ValType::Ref(_))) {
+        bail!("array of {} cannot be initialized by data", field.1)
+    }
+    Ok(field)
+}
+
+/// packed fields are read with their extensions, unlike the others
+fn unpacked(instr: &Instruction, FieldType(_, storage): FieldType, is_plain: bool) -> Result<()> {
+    let is_packed = matches!(storage, StorageType::Packed(_));
+    if is_packed == is_plain {
+        bail!("{} cannot read a field of {}", instr, storage)
+    }
+    Ok(())
+}
+
+fn type_list(types: &[ValType]) -> String {
+    let types: Vec<String> = types.iter().map(ValType::to_string).collect();
+    types.join(" ")
+}
+
+/// structured instructions are described by their keywords
+fn describe(instr: &Instruction) -> String {
+    match instr {
+        Instruction::Block(..) => "block".into(),
+        Instruction::Loop(..) => "loop".into(),
+        Instruction::If(..) => "if".into(),
+        Instruction::TryTable(..) => "try_table".into(),
+        _ => instr.to_string(),
+    }
+}